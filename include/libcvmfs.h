@@ -0,0 +1,45 @@
+/**
+ * C interface of the cvmfs Rust client, compatible with the reference
+ * libcvmfs. Link against libcvmfs.so produced by `cargo build`.
+ */
+#ifndef LIBCVMFS_H_
+#define LIBCVMFS_H_
+
+#include <stddef.h>
+#include <sys/stat.h>
+#include <sys/types.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define LIBCVMFS_FAIL_OK 0
+#define LIBCVMFS_FAIL_OPTIONS -3
+#define LIBCVMFS_FAIL_INITCACHE -5
+
+typedef struct CvmfsContext cvmfs_context;
+
+int cvmfs_init(char const *options);
+void cvmfs_fini();
+
+cvmfs_context *cvmfs_attach_repo(char const *options);
+void cvmfs_detach_repo(cvmfs_context *ctx);
+int cvmfs_remount(cvmfs_context *ctx);
+
+int cvmfs_open(cvmfs_context *ctx, const char *path);
+ssize_t cvmfs_pread(cvmfs_context *ctx, int fd, void *buf, size_t size,
+                    off_t off);
+int cvmfs_close(cvmfs_context *ctx, int fd);
+
+int cvmfs_readlink(cvmfs_context *ctx, const char *path, char *buf,
+                   size_t size);
+int cvmfs_stat(cvmfs_context *ctx, const char *path, struct stat *st);
+int cvmfs_lstat(cvmfs_context *ctx, const char *path, struct stat *st);
+int cvmfs_listdir(cvmfs_context *ctx, const char *path, char ***buf,
+                  size_t *buflen);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif  // LIBCVMFS_H_
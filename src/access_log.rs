@@ -0,0 +1,250 @@
+use std::ffi::CString;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Mutex, Once};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+
+use crate::common::{json_string, CvmfsError, CvmfsResult};
+
+/// Rotated access log files kept by default
+pub const DEFAULT_ACCESS_LOG_ROTATIONS: usize = 3;
+const SYSLOG_TARGET: &str = "syslog";
+
+static SYSLOG_OPEN: Once = Once::new();
+
+/// Where the access records are written to
+#[derive(Debug, Clone, PartialEq)]
+pub enum AccessLogTarget {
+    /// One JSON object per line
+    File(PathBuf),
+    Syslog,
+}
+
+impl FromStr for AccessLogTarget {
+    type Err = CvmfsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" => Err(CvmfsError::InvalidConfiguration(
+                "the access log path is empty".into(),
+            )),
+            SYSLOG_TARGET => Ok(AccessLogTarget::Syslog),
+            path => Ok(AccessLogTarget::File(PathBuf::from(path))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessLogConfig {
+    pub target: AccessLogTarget,
+    /// Records written per second at most, the rest are only counted
+    pub max_records_per_second: Option<u32>,
+    /// Size in bytes after which the log file is rotated
+    pub max_file_size: Option<u64>,
+    /// Rotated files kept, named `<file>.1` (the newest) to `<file>.<n>`
+    pub rotations: usize,
+}
+
+impl AccessLogConfig {
+    pub fn new(target: AccessLogTarget) -> Self {
+        Self {
+            target,
+            max_records_per_second: None,
+            max_file_size: None,
+            rotations: DEFAULT_ACCESS_LOG_ROTATIONS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccessOperation {
+    Lookup,
+    Open,
+}
+
+impl AccessOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccessOperation::Lookup => "lookup",
+            AccessOperation::Open => "open",
+        }
+    }
+}
+
+/// A file system operation on a path of the repository
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessRecord<'a> {
+    pub operation: AccessOperation,
+    pub path: &'a str,
+    pub size: u64,
+    /// Served without downloading anything from the server
+    pub cache_hit: bool,
+    pub latency: Duration,
+    /// Error returned to the caller, if the operation failed
+    pub errno: Option<i32>,
+}
+
+impl AccessRecord<'_> {
+    pub fn to_json(&self, time: DateTime<Utc>) -> String {
+        format!(
+            "{{\"time\":{},\"op\":\"{}\",\"path\":{},\"size\":{},\"cache_hit\":{},\"latency_us\":{},\"errno\":{}}}",
+            json_string(&time.to_rfc3339()),
+            self.operation.as_str(),
+            json_string(self.path),
+            self.size,
+            self.cache_hit,
+            self.latency.as_micros(),
+            self.errno.map_or("null".into(), |errno| errno.to_string()),
+        )
+    }
+}
+
+#[derive(Debug)]
+struct AccessLogState {
+    file: Option<File>,
+    size: u64,
+    window_start: Instant,
+    written: u32,
+    dropped: u64,
+}
+
+/// Log of the lookups and opens served by a mount, so that site operators
+/// can tell which files their jobs actually use
+#[derive(Debug)]
+pub struct AccessLog {
+    config: AccessLogConfig,
+    state: Mutex<AccessLogState>,
+}
+
+impl AccessLog {
+    pub fn open(config: AccessLogConfig) -> CvmfsResult<Self> {
+        let (file, size) = match &config.target {
+            AccessLogTarget::File(path) => {
+                let file = Self::open_file(path)?;
+                let size = file.metadata()?.len();
+                (Some(file), size)
+            }
+            AccessLogTarget::Syslog => {
+                SYSLOG_OPEN.call_once(|| unsafe {
+                    libc::openlog(c"cvmfs".as_ptr(), libc::LOG_PID, libc::LOG_DAEMON)
+                });
+                (None, 0)
+            }
+        };
+        Ok(Self {
+            config,
+            state: Mutex::new(AccessLogState {
+                file,
+                size,
+                window_start: Instant::now(),
+                written: 0,
+                dropped: 0,
+            }),
+        })
+    }
+
+    fn open_file(path: &Path) -> CvmfsResult<File> {
+        Ok(OpenOptions::new().create(true).append(true).open(path)?)
+    }
+
+    pub fn config(&self) -> &AccessLogConfig {
+        &self.config
+    }
+
+    /// Writes a record unless the rate limit of the current second is reached.
+    /// Failures are logged, since they must not fail the file system operation.
+    pub fn record(&self, record: &AccessRecord) {
+        if let Err(e) = self.try_record(record) {
+            log::warn!("Could not write the access log: {:?}", e);
+        }
+    }
+
+    fn try_record(&self, record: &AccessRecord) -> CvmfsResult<()> {
+        let mut state = self.state.lock().map_err(|_| CvmfsError::Sync)?;
+        let now = Utc::now();
+        if state.window_start.elapsed() >= Duration::from_secs(1) {
+            state.window_start = Instant::now();
+            state.written = 0;
+            if state.dropped > 0 {
+                let line = format!(
+                    "{{\"time\":{},\"dropped\":{}}}",
+                    json_string(&now.to_rfc3339()),
+                    state.dropped
+                );
+                state.dropped = 0;
+                self.write_line(&mut state, &line)?;
+            }
+        }
+        if let Some(limit) = self.config.max_records_per_second {
+            if state.written >= limit {
+                state.dropped += 1;
+                return Ok(());
+            }
+        }
+        state.written += 1;
+        self.write_line(&mut state, &record.to_json(now))
+    }
+
+    fn write_line(&self, state: &mut AccessLogState, line: &str) -> CvmfsResult<()> {
+        let AccessLogTarget::File(path) = &self.config.target else {
+            let message = CString::new(line).map_err(|_| CvmfsError::ParseError)?;
+            unsafe { libc::syslog(libc::LOG_INFO, c"%s".as_ptr(), message.as_ptr()) };
+            return Ok(());
+        };
+        let length = line.len() as u64 + 1;
+        if self
+            .config
+            .max_file_size
+            .is_some_and(|max_size| state.size > 0 && state.size + length > max_size)
+        {
+            state.file = None;
+            rotate_file(path, self.config.rotations)?;
+            state.file = Some(Self::open_file(path)?);
+            state.size = 0;
+        }
+        let file = match &mut state.file {
+            Some(file) => file,
+            None => state.file.insert(Self::open_file(path)?),
+        };
+        writeln!(file, "{}", line)?;
+        state.size += length;
+        Ok(())
+    }
+
+    /// Reopens the log file, after it was moved away by an external tool
+    /// such as logrotate
+    pub fn reopen(&self) -> CvmfsResult<()> {
+        let AccessLogTarget::File(path) = &self.config.target else {
+            return Ok(());
+        };
+        let file = Self::open_file(path)?;
+        let mut state = self.state.lock().map_err(|_| CvmfsError::Sync)?;
+        state.size = file.metadata()?.len();
+        state.file = Some(file);
+        Ok(())
+    }
+}
+
+/// Shifts `<file>.<n>` to `<file>.<n + 1>`, dropping the oldest one
+pub(crate) fn rotate_file(path: &Path, rotations: usize) -> CvmfsResult<()> {
+    let rotated = |index: usize| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    };
+    if rotations == 0 {
+        fs::remove_file(path)?;
+        return Ok(());
+    }
+    for index in (1..rotations).rev() {
+        if rotated(index).exists() {
+            fs::rename(rotated(index), rotated(index + 1))?;
+        }
+    }
+    fs::rename(path, rotated(1))?;
+    Ok(())
+}
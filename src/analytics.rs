@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::common::{json_string, CvmfsError, CvmfsResult};
+
+/// Paths reported by default as the most used ones
+pub const DEFAULT_TOP_PATHS: usize = 20;
+/// Path components grouping the accesses into subtrees, `/atlas/sw` for 2
+pub const DEFAULT_SUBTREE_DEPTH: usize = 2;
+/// Distinct paths tracked at most, so that a scan of the whole repository does
+/// not exhaust the memory. Later paths only count towards their subtree.
+pub const MAX_TRACKED_PATHS: usize = 100_000;
+
+/// Accesses to a path or a subtree
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PathUsage {
+    pub opens: u64,
+    /// Bytes read by the applications
+    pub bytes: u64,
+    /// Opens served without downloading anything
+    pub cache_hits: u64,
+}
+
+impl PathUsage {
+    /// Fraction of the opens served from the cache
+    pub fn cache_efficiency(&self) -> f64 {
+        if self.opens == 0 {
+            return 0.0;
+        }
+        self.cache_hits as f64 / self.opens as f64
+    }
+
+    fn to_json(self, path: &str) -> String {
+        format!(
+            "{{\"path\":{},\"opens\":{},\"bytes\":{},\"cache_hits\":{},\"cache_efficiency\":{:.3}}}",
+            json_string(path),
+            self.opens,
+            self.bytes,
+            self.cache_hits,
+            self.cache_efficiency()
+        )
+    }
+}
+
+/// Summary of the usage of a mount
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AnalyticsReport {
+    pub total: PathUsage,
+    pub tracked_paths: usize,
+    /// Most opened paths, the most opened first
+    pub top_paths: Vec<(String, PathUsage)>,
+    /// Usage of every subtree, the most opened first
+    pub subtrees: Vec<(String, PathUsage)>,
+}
+
+impl AnalyticsReport {
+    /// The report as a single line of JSON
+    pub fn to_json(&self) -> String {
+        let list = |entries: &[(String, PathUsage)]| {
+            entries
+                .iter()
+                .map(|(path, usage)| usage.to_json(path))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        format!(
+            "{{\"total\":{},\"tracked_paths\":{},\"top_paths\":[{}],\"subtrees\":[{}]}}",
+            self.total.to_json("/"),
+            self.tracked_paths,
+            list(&self.top_paths),
+            list(&self.subtrees)
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+struct AnalyticsState {
+    total: PathUsage,
+    paths: HashMap<String, PathUsage>,
+    subtrees: HashMap<String, PathUsage>,
+}
+
+/// Aggregates the opens and bytes served per path over the lifetime of a
+/// mount, so that sites can tell what is worth preloading or pinning
+#[derive(Debug)]
+pub struct Analytics {
+    subtree_depth: usize,
+    state: Mutex<AnalyticsState>,
+}
+
+impl Default for Analytics {
+    fn default() -> Self {
+        Self::new(DEFAULT_SUBTREE_DEPTH)
+    }
+}
+
+impl Analytics {
+    pub fn new(subtree_depth: usize) -> Self {
+        Self {
+            subtree_depth,
+            state: Default::default(),
+        }
+    }
+
+    /// Subtree a path is accounted to
+    pub fn subtree_of(&self, path: &str) -> String {
+        let components: Vec<&str> = path
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .collect();
+        if components.len() <= 1 {
+            return "/".into();
+        }
+        // files count towards their directory
+        let depth = self.subtree_depth.min(components.len() - 1);
+        format!("/{}", components[..depth].join("/"))
+    }
+
+    fn update(&self, path: &str, f: impl Fn(&mut PathUsage)) {
+        let subtree = self.subtree_of(path);
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        f(&mut state.total);
+        f(state.subtrees.entry(subtree).or_default());
+        if let Some(usage) = state.paths.get_mut(path) {
+            f(usage);
+        } else if state.paths.len() < MAX_TRACKED_PATHS {
+            f(state.paths.entry(path.into()).or_default());
+        }
+    }
+
+    pub fn record_open(&self, path: &str, cache_hit: bool) {
+        self.update(path, |usage| {
+            usage.opens += 1;
+            usage.cache_hits += cache_hit as u64;
+        });
+    }
+
+    pub fn record_read(&self, path: &str, bytes: u64) {
+        self.update(path, |usage| usage.bytes += bytes);
+    }
+
+    pub fn report(&self, top: usize) -> CvmfsResult<AnalyticsReport> {
+        let state = self.state.lock().map_err(|_| CvmfsError::Sync)?;
+        let sorted = |entries: &HashMap<String, PathUsage>, limit: usize| {
+            let mut entries: Vec<(String, PathUsage)> = entries
+                .iter()
+                .map(|(path, usage)| (path.clone(), *usage))
+                .collect();
+            entries.sort_by(|(a_path, a), (b_path, b)| {
+                (b.opens, b.bytes, a_path).cmp(&(a.opens, a.bytes, b_path))
+            });
+            entries.truncate(limit);
+            entries
+        };
+        Ok(AnalyticsReport {
+            total: state.total,
+            tracked_paths: state.paths.len(),
+            top_paths: sorted(&state.paths, top),
+            subtrees: sorted(&state.subtrees, usize::MAX),
+        })
+    }
+
+    /// Writes the report to a file, as done when the mount goes away
+    pub fn write_report(&self, path: &Path, top: usize) -> CvmfsResult<()> {
+        fs::write(path, self.report(top)?.to_json() + "\n")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,139 @@
+use std::sync::{Arc, LazyLock};
+
+use tokio::runtime::{Builder, Runtime};
+use tokio::sync::Semaphore;
+
+use crate::common::{CvmfsError, CvmfsResult};
+use crate::fetcher::Fetcher;
+
+/// Threads of the runtime driving the asynchronous downloads
+pub const ASYNC_WORKER_THREADS: usize = 4;
+
+static RUNTIME: LazyLock<std::io::Result<Runtime>> = LazyLock::new(|| {
+    Builder::new_multi_thread()
+        .worker_threads(ASYNC_WORKER_THREADS)
+        .thread_name("cvmfs-fetcher")
+        .enable_all()
+        .build()
+});
+
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(reqwest::Client::new);
+
+/// Runtime shared by the blocking callers of the asynchronous fetcher
+pub fn runtime() -> CvmfsResult<&'static Runtime> {
+    RUNTIME
+        .as_ref()
+        .map_err(|e| CvmfsError::IO(format!("{:?}", e)))
+}
+
+/// Fetcher transferring many objects at once over asynchronous connections,
+/// instead of a thread per download. Objects are decompressed, verified and
+/// cached as the blocking fetcher does, with the same mirrors and offline state.
+#[derive(Debug, Clone)]
+pub struct AsyncFetcher {
+    fetcher: Fetcher,
+}
+
+impl AsyncFetcher {
+    pub fn new(fetcher: Fetcher) -> Self {
+        Self { fetcher }
+    }
+
+    pub fn fetcher(&self) -> &Fetcher {
+        &self.fetcher
+    }
+
+    /// Retrieves a file from the cache if it exists, or from the repository
+    /// if it does not
+    pub async fn retrieve_file(&self, file_name: &str) -> CvmfsResult<String> {
+        if let Some(cached_file) = self.fetcher.cache.get(file_name) {
+            return Ok(cached_file.to_str().ok_or(CvmfsError::FileNotFound)?.into());
+        }
+        let (file_bytes, file_url) = self.download(file_name).await?;
+        let fetcher = self.fetcher.clone();
+        let file_name = file_name.to_string();
+        // decompressing, hashing and writing to the cache block
+        tokio::task::spawn_blocking(move || {
+            let content = fetcher.decode_object(&file_name, file_bytes, &file_url)?;
+            let cached_file = fetcher.cache.store(&file_name, &content)?;
+            Ok(cached_file.to_str().ok_or(CvmfsError::FileNotFound)?.into())
+        })
+        .await
+        .map_err(|_| CvmfsError::Sync)?
+    }
+
+    /// Retrieves several files with at most `concurrency` downloads in flight,
+    /// returning the outcome of each in the same order
+    pub async fn retrieve_files(
+        &self,
+        file_names: &[String],
+        concurrency: usize,
+    ) -> Vec<CvmfsResult<String>> {
+        let permits = Arc::new(Semaphore::new(concurrency.max(1)));
+        let tasks: Vec<_> = file_names
+            .iter()
+            .map(|file_name| {
+                let fetcher = self.clone();
+                let permits = permits.clone();
+                let file_name = file_name.clone();
+                tokio::spawn(async move {
+                    let _permit = permits.acquire().await.map_err(|_| CvmfsError::Sync)?;
+                    fetcher.retrieve_file(&file_name).await
+                })
+            })
+            .collect();
+        let mut results = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            results.push(task.await.unwrap_or(Err(CvmfsError::Sync)));
+        }
+        results
+    }
+
+    /// Same as `retrieve_files`, blocking on the shared runtime. Must not be
+    /// called from within a runtime.
+    pub fn retrieve_files_blocking(
+        &self,
+        file_names: &[String],
+        concurrency: usize,
+    ) -> CvmfsResult<Vec<CvmfsResult<String>>> {
+        Ok(runtime()?.block_on(self.retrieve_files(file_names, concurrency)))
+    }
+
+    /// Downloads a file from the first mirror able to serve it, failing over
+    /// and backing off as the blocking fetcher does
+    async fn download(&self, file_name: &str) -> CvmfsResult<(Arc<[u8]>, String)> {
+        // the configured proxies only have blocking clients
+        if self.fetcher.proxies.is_some() {
+            let fetcher = self.fetcher.clone();
+            let file_name = file_name.to_string();
+            return tokio::task::spawn_blocking(move || fetcher.download(&file_name))
+                .await
+                .map_err(|_| CvmfsError::Sync)?;
+        }
+        if self.fetcher.is_offline() {
+            return Err(CvmfsError::Offline(file_name.into()));
+        }
+        let mut errors = Vec::new();
+        for index in self.fetcher.mirrors.order() {
+            let file_url = self.fetcher.mirror_url(index, file_name)?;
+            let result = async {
+                CLIENT
+                    .get(&file_url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .bytes()
+                    .await
+            }
+            .await;
+            match result {
+                Ok(bytes) => {
+                    self.fetcher.record_download_success(index);
+                    return Ok((Arc::from(bytes.as_ref()), file_url));
+                }
+                Err(e) => errors.push(self.fetcher.record_download_failure(index, &file_url, e)),
+            }
+        }
+        Err(self.fetcher.download_failed(file_name, errors))
+    }
+}
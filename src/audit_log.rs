@@ -0,0 +1,104 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+
+use crate::common::{json_string, CvmfsError, CvmfsResult};
+
+/// Kind of content whose integrity was verified
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuditKind {
+    Manifest,
+    Whitelist,
+    Catalog,
+    Object,
+}
+
+impl AuditKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditKind::Manifest => "manifest",
+            AuditKind::Whitelist => "whitelist",
+            AuditKind::Catalog => "catalog",
+            AuditKind::Object => "object",
+        }
+    }
+
+    /// Kind of a content addressed object, from the suffix of its name
+    pub fn of_object(file_name: &str) -> Self {
+        if file_name.ends_with('C') {
+            AuditKind::Catalog
+        } else {
+            AuditKind::Object
+        }
+    }
+}
+
+/// Outcome of one verification
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditRecord<'a> {
+    pub kind: AuditKind,
+    /// Name of the file on the server
+    pub name: &'a str,
+    /// Hash the content was checked against
+    pub hash: &'a str,
+    /// Host the content was downloaded from
+    pub source: &'a str,
+    /// The error found, `None` if the content was trusted
+    pub error: Option<&'a CvmfsError>,
+}
+
+impl AuditRecord<'_> {
+    pub fn to_json(&self, time: DateTime<Utc>) -> String {
+        format!(
+            "{{\"time\":{},\"kind\":\"{}\",\"name\":{},\"hash\":{},\"source\":{},\"result\":\"{}\",\"error\":{}}}",
+            json_string(&time.to_rfc3339()),
+            self.kind.as_str(),
+            json_string(self.name),
+            json_string(self.hash),
+            json_string(self.source),
+            if self.error.is_none() { "ok" } else { "failed" },
+            self.error
+                .map_or("null".into(), |error| json_string(&error.to_string())),
+        )
+    }
+}
+
+/// Append-only record of every manifest, whitelist, catalog and object whose
+/// integrity was verified, one JSON object per line, so that security teams
+/// can tell afterwards which content was trusted by a client
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path) -> CvmfsResult<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            path: path.into(),
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends a record. Failures are logged, since they must not change the
+    /// outcome of the verification.
+    pub fn record(&self, record: &AuditRecord) {
+        let line = record.to_json(Utc::now());
+        let result = self
+            .file
+            .lock()
+            .map_err(|_| CvmfsError::Sync)
+            .and_then(|mut file| Ok(writeln!(file, "{}", line)?));
+        if let Err(e) = result {
+            log::warn!("Could not write the audit log {:?}: {:?}", self.path, e);
+        }
+    }
+}
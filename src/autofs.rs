@@ -0,0 +1,189 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::common::{CvmfsError, CvmfsResult};
+use crate::config::{is_enabled, ClientConfig};
+use crate::mount_config::{derive_fqrn, DEFAULT_DOMAIN};
+use crate::proxy::HTTP_PROXY_VARIABLE;
+
+/// File system type printed in the map entries, which makes mount(8) run
+/// the `mount.cvmfs` helper
+pub const AUTOFS_FSTYPE: &str = "cvmfs";
+/// Name the binary is installed or linked under to act as the mount helper
+pub const MOUNT_HELPER_NAME: &str = "mount.cvmfs";
+/// Url template of the servers, as in the reference client configuration
+pub const SERVER_URL_VARIABLE: &str = "CVMFS_SERVER_URL";
+/// Comma separated list of the repositories autofs may mount, any if unset
+pub const REPOSITORIES_VARIABLE: &str = "CVMFS_REPOSITORIES";
+pub const DEFAULT_DOMAIN_VARIABLE: &str = "CVMFS_DEFAULT_DOMAIN";
+pub const CACHE_BASE_VARIABLE: &str = "CVMFS_CACHE_BASE";
+/// Named snapshot mounted instead of the latest revision
+pub const REPOSITORY_TAG_VARIABLE: &str = "CVMFS_REPOSITORY_TAG";
+/// Servers of the files with external data, passed on as `--external-url`
+pub const EXTERNAL_URL_VARIABLE: &str = "CVMFS_EXTERNAL_URL";
+/// Set to `yes` to order the servers by proximity, passed on as `--use-geoapi`
+pub const USE_GEOAPI_VARIABLE: &str = "CVMFS_USE_GEOAPI";
+/// Time the mount helper waits for the mount to show up
+pub const MOUNT_TIMEOUT: Duration = Duration::from_secs(30);
+const MOUNTINFO_PATH: &str = "/proc/self/mountinfo";
+/// Generic mount options that mean nothing to FUSE
+const IGNORED_MOUNT_OPTIONS: [&str; 7] = [
+    "rw", "ro", "defaults", "auto", "noauto", "_netdev", "nouser",
+];
+
+/// Whether an autofs key can be a repository name. Keys like `.git` or
+/// `desktop.ini` probed by file managers are rejected early.
+pub fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && !key.starts_with('.')
+        && !key.contains("..")
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+}
+
+/// Entry of the program map for a key, `-fstype=cvmfs :<fqrn>`. Fails for
+/// keys that are not repositories, so that autofs reports them as missing.
+pub fn map_entry(key: &str, default_domain: &str, allowed: &[String]) -> CvmfsResult<String> {
+    if !is_valid_key(key) {
+        return Err(CvmfsError::InvalidConfiguration(format!(
+            "invalid repository name: {}",
+            key
+        )));
+    }
+    let fqrn = derive_fqrn(key, default_domain);
+    if !allowed.is_empty()
+        && !allowed
+            .iter()
+            .any(|name| derive_fqrn(name, default_domain) == fqrn)
+    {
+        return Err(CvmfsError::InvalidConfiguration(format!(
+            "{} is not in {}",
+            fqrn, REPOSITORIES_VARIABLE
+        )));
+    }
+    Ok(format!("-fstype={} :{}", AUTOFS_FSTYPE, fqrn))
+}
+
+/// Program map entry for a key, configured from the environment
+pub fn map_entry_from_env(key: &str) -> CvmfsResult<String> {
+    let default_domain = env::var(DEFAULT_DOMAIN_VARIABLE).unwrap_or(DEFAULT_DOMAIN.into());
+    let allowed: Vec<String> = env::var(REPOSITORIES_VARIABLE)
+        .unwrap_or_default()
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+    map_entry(key, &default_domain, &allowed)
+}
+
+/// Translates the `mount.cvmfs <fqrn> <mount point> [-o options]` call made
+/// by mount(8) into the arguments of the client
+pub fn helper_args(
+    args: &[String],
+    server_url: &str,
+    cache_directory: Option<&str>,
+) -> CvmfsResult<Vec<String>> {
+    let mut positionals = Vec::new();
+    let mut options = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => {
+                let value = args.next().ok_or_else(|| {
+                    CvmfsError::InvalidConfiguration("missing value for -o".into())
+                })?;
+                options.extend(value.split(',').map(String::from));
+            }
+            // fake, no mtab and verbose flags of mount(8)
+            "-f" | "-n" | "-s" | "-v" => {}
+            _ => positionals.push(arg.clone()),
+        }
+    }
+    let [fqrn, mount_point] = positionals.as_slice() else {
+        return Err(CvmfsError::InvalidConfiguration(format!(
+            "usage: {} <repository> <mount point> [-o options]",
+            MOUNT_HELPER_NAME
+        )));
+    };
+    let mut translated = vec![server_url.to_string(), mount_point.clone()];
+    translated.extend(cache_directory.map(String::from));
+    translated.extend(["--repository".into(), fqrn.clone()]);
+    let fuse_options: Vec<String> = std::iter::once("ro".to_string())
+        .chain(options.into_iter().filter(|option| {
+            !option.is_empty() && !IGNORED_MOUNT_OPTIONS.contains(&option.as_str())
+        }))
+        .collect();
+    translated.extend(["--fuse-options".into(), fuse_options.join(",")]);
+    Ok(translated)
+}
+
+/// Whether a mount point shows up in the contents of a mountinfo file
+pub fn is_mounted(mountinfo: &str, mount_point: &Path) -> bool {
+    mountinfo
+        .lines()
+        .filter_map(|line| line.split(' ').nth(4))
+        .any(|path| Path::new(&path.replace("\\040", " ")) == mount_point)
+}
+
+/// Acts as the mount helper: starts the client in the background and returns
+/// once the repository is mounted, as mount(8) expects
+pub fn run_mount_helper(args: &[String]) -> CvmfsResult<()> {
+    let server_url = match env::var(SERVER_URL_VARIABLE) {
+        Ok(server_url) => server_url,
+        Err(_) => {
+            let fqrn = args
+                .first()
+                .ok_or_else(|| CvmfsError::InvalidConfiguration("missing repository".into()))?;
+            ClientConfig::load_system(fqrn)?
+                .server_url()
+                .ok_or_else(|| {
+                    CvmfsError::InvalidConfiguration(format!("{} is not set", SERVER_URL_VARIABLE))
+                })?
+                .to_string()
+        }
+    };
+    let cache_directory = env::var(CACHE_BASE_VARIABLE).ok();
+    let mut args = helper_args(args, &server_url, cache_directory.as_deref())?;
+    if let Ok(proxy) = env::var(HTTP_PROXY_VARIABLE) {
+        args.extend(["--http-proxy".into(), proxy]);
+    }
+    if let Ok(tag) = env::var(REPOSITORY_TAG_VARIABLE) {
+        args.extend(["--tag".into(), tag]);
+    }
+    if let Ok(urls) = env::var(EXTERNAL_URL_VARIABLE) {
+        args.extend(["--external-url".into(), urls]);
+    }
+    if env::var(USE_GEOAPI_VARIABLE).is_ok_and(|value| is_enabled(&value)) {
+        args.push("--use-geoapi".into());
+    }
+    let mount_point = fs::canonicalize(&args[1])?;
+    let mut child = Command::new(env::current_exe()?)
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .spawn()?;
+    let started = Instant::now();
+    while started.elapsed() < MOUNT_TIMEOUT {
+        if is_mounted(&fs::read_to_string(MOUNTINFO_PATH)?, &mount_point) {
+            return Ok(());
+        }
+        if let Some(status) = child.try_wait()? {
+            return Err(CvmfsError::Generic(format!(
+                "the client exited with {} before mounting",
+                status
+            )));
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    let _ = child.kill();
+    Err(CvmfsError::Generic(format!(
+        "{} was not mounted after {:?}",
+        mount_point.display(),
+        MOUNT_TIMEOUT
+    )))
+}
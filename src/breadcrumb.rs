@@ -0,0 +1,64 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::common::CvmfsError;
+use crate::manifest::Manifest;
+
+pub const BREADCRUMB_PREFIX: &str = "cvmfschecksum.";
+
+/// Last known state of a repository, persisted in the cache so that restarts
+/// can mount from cached catalogs and detect revisions going backwards.
+/// It is stored in the same format as the reference client: `<hash>T<timestamp>R<revision>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breadcrumb {
+    pub catalog_hash: String,
+    pub timestamp: i64,
+    pub revision: u32,
+}
+
+impl Breadcrumb {
+    pub fn file_name(fqrn: &str) -> String {
+        format!("{}{}", BREADCRUMB_PREFIX, fqrn)
+    }
+}
+
+impl From<&Manifest> for Breadcrumb {
+    fn from(manifest: &Manifest) -> Self {
+        Self {
+            catalog_hash: manifest.root_catalog.clone(),
+            timestamp: manifest.last_modified.timestamp(),
+            revision: manifest.revision,
+        }
+    }
+}
+
+impl FromStr for Breadcrumb {
+    type Err = CvmfsError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let (catalog_hash, rest) = value.split_once('T').ok_or(CvmfsError::ParseError)?;
+        let (timestamp, revision) = match rest.split_once('R') {
+            Some((timestamp, revision)) => (timestamp, revision),
+            None => (rest, "0"),
+        };
+        if catalog_hash.is_empty() {
+            return Err(CvmfsError::ParseError);
+        }
+        Ok(Self {
+            catalog_hash: catalog_hash.into(),
+            timestamp: timestamp.parse().map_err(|_| CvmfsError::ParseError)?,
+            revision: revision.parse().map_err(|_| CvmfsError::ParseError)?,
+        })
+    }
+}
+
+impl Display for Breadcrumb {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}T{}R{}",
+            self.catalog_hash, self.timestamp, self.revision
+        )
+    }
+}
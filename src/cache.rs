@@ -1,11 +1,152 @@
-use std::fs::{create_dir_all, remove_dir_all};
+use std::ffi::{CStr, CString};
+use std::fs::{self, create_dir_all, remove_dir_all};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
 
-use crate::common::{CvmfsError, CvmfsResult};
+use chrono::{DateTime, Utc};
+use openssl::sha::sha256;
+
+use crate::breadcrumb::Breadcrumb;
+use crate::catalog_set::CatalogSet;
+use crate::common::{json_string, CvmfsError, CvmfsResult};
+use crate::metrics::metrics;
+use crate::quota::QuotaManager;
+
+const PINNED_TAG_PREFIX: &str = "cvmfspin.";
+/// Directory of the cache keeping the corrupt objects found
+pub const QUARANTINE_DIRECTORY: &str = "quarantine";
+/// Extended attribute holding the SHA-256 of a cached object as stored, since
+/// the hash in its name is the one of the compressed object
+pub const DIGEST_XATTR: &CStr = c"user.cvmfs.digest";
+const DIGEST_LENGTH: usize = 64;
+/// Marks the files still being written, named `<object>.partial.<pid>.<n>`
+const PARTIAL_MARKER: &str = ".partial";
+/// Files written so far by the process, making their temporary names unique
+static PARTIAL_FILES: AtomicU64 = AtomicU64::new(0);
+
+/// Whether a file of the cache is an object still being written
+pub fn is_partial_file(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().contains(PARTIAL_MARKER))
+}
+
+/// Hex SHA-256 of the content of a cached object
+pub fn content_digest(content: &[u8]) -> String {
+    hex::encode(sha256(content))
+}
+
+fn c_path(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|_| io::ErrorKind::InvalidInput.into())
+}
+
+/// Digest recorded for a cached object, if any
+pub fn stored_digest(path: &Path) -> Option<String> {
+    let path = c_path(path).ok()?;
+    let mut value = [0u8; DIGEST_LENGTH];
+    let length = unsafe {
+        libc::getxattr(
+            path.as_ptr(),
+            DIGEST_XATTR.as_ptr(),
+            value.as_mut_ptr().cast(),
+            value.len(),
+        )
+    };
+    if length != DIGEST_LENGTH as isize {
+        return None;
+    }
+    String::from_utf8(value.to_vec()).ok()
+}
+
+/// Records the digest of a cached object, failing on file systems without
+/// support for user extended attributes
+pub fn record_digest(path: &Path, digest: &str) -> io::Result<()> {
+    let path = c_path(path)?;
+    let result = unsafe {
+        libc::setxattr(
+            path.as_ptr(),
+            DIGEST_XATTR.as_ptr(),
+            digest.as_ptr().cast(),
+            digest.len(),
+            0,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// State of the failover to the fallback cache directory
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FailoverStatus {
+    /// New objects are being written to the fallback directory
+    pub active: bool,
+    /// Writes to the primary directory that failed for lack of space or permissions
+    pub primary_errors: u64,
+    pub fallback_writes: u64,
+}
+
+#[derive(Debug, Default)]
+struct FailoverState {
+    active: AtomicBool,
+    primary_errors: AtomicU64,
+    fallback_writes: AtomicU64,
+}
+
+/// Why an object was quarantined, written next to it as `<object>.json`
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuarantineRecord {
+    /// Where the object came from, the server url or its cache location
+    pub url: String,
+    pub expected_hash: String,
+    pub actual_hash: String,
+    pub time: DateTime<Utc>,
+}
+
+impl QuarantineRecord {
+    pub fn new(url: &str, expected_hash: &str, actual_hash: &str) -> Self {
+        Self {
+            url: url.into(),
+            expected_hash: expected_hash.into(),
+            actual_hash: actual_hash.into(),
+            time: Utc::now(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"url\":{},\"expected_hash\":{},\"actual_hash\":{},\"time\":{}}}",
+            json_string(&self.url),
+            json_string(&self.expected_hash),
+            json_string(&self.actual_hash),
+            json_string(&self.time.to_rfc3339()),
+        )
+    }
+}
+
+/// Errors meaning that the primary directory cannot take any more objects
+fn is_failover_error(error: &io::Error) -> bool {
+    matches!(
+        error.raw_os_error(),
+        Some(libc::ENOSPC | libc::EDQUOT | libc::EACCES | libc::EPERM | libc::EROFS)
+    )
+}
 
 #[derive(Debug, Clone)]
 pub struct Cache {
     pub cache_directory: String,
+    /// Directory taking the new objects once the primary one is full or
+    /// unwritable. Objects already in the primary directory keep being served.
+    pub fallback_directory: Option<String>,
+    /// Record the digest of the objects written, for the scrubber to check them
+    pub record_digests: bool,
+    /// Shared by the clones of the cache, so that every fetcher fails over at once
+    failover: Arc<FailoverState>,
+    /// Size limit of the primary directory, unbounded when `None`
+    quota: Option<Arc<QuotaManager>>,
 }
 
 impl Cache {
@@ -13,46 +154,324 @@ impl Cache {
         let path = Path::new(&cache_directory);
         Ok(Self {
             cache_directory: path.to_str().ok_or(CvmfsError::FileNotFound)?.into(),
+            fallback_directory: None,
+            record_digests: false,
+            failover: Default::default(),
+            quota: None,
         })
     }
 
+    /// Cache whose objects are kept under `limit` bytes, evicting the least
+    /// recently used ones
+    pub fn with_quota(cache_directory: String, limit: u64) -> CvmfsResult<Self> {
+        let mut cache = Self::new(cache_directory)?;
+        cache.set_quota(limit)?;
+        Ok(cache)
+    }
+
+    /// Limits the size of the objects in the primary directory
+    pub fn set_quota(&mut self, limit: u64) -> CvmfsResult<()> {
+        self.quota = Some(Arc::new(QuotaManager::open(
+            Path::new(&self.cache_directory),
+            limit,
+        )?));
+        Ok(())
+    }
+
+    pub fn quota(&self) -> Option<&QuotaManager> {
+        self.quota.as_deref()
+    }
+
+    pub fn with_fallback(cache_directory: String, fallback_directory: String) -> CvmfsResult<Self> {
+        let mut cache = Self::new(cache_directory)?;
+        cache.fallback_directory = Some(fallback_directory);
+        Ok(cache)
+    }
+
+    /// Creates the directory layout, failing over right away if the primary
+    /// directory cannot be written
     pub fn initialize(&self) -> CvmfsResult<()> {
-        let base_path = self.create_directory("data")?;
+        match Self::initialize_directory(Path::new(&self.cache_directory)) {
+            Err(e) if is_failover_error(&e) && self.fallback_directory.is_some() => {
+                self.fail_over(&e)
+            }
+            result => Ok(result?),
+        }
+    }
+
+    fn initialize_directory(directory: &Path) -> io::Result<()> {
+        let base_path = directory.join("data");
         for i in 0x00..=0xff {
-            let new_folder = format!("{:02x}", i);
-            let new_file = Path::join::<&Path>(base_path.as_ref(), new_folder.as_ref());
-            create_dir_all(new_file)?;
+            create_dir_all(base_path.join(format!("{:02x}", i)))?;
         }
         Ok(())
     }
 
-    fn create_directory(&self, path: &str) -> CvmfsResult<String> {
-        let cache_full_path = Path::new(&self.cache_directory).join(path);
-        create_dir_all(cache_full_path.clone())?;
-        cache_full_path
-            .into_os_string()
-            .into_string()
-            .map_err(|_| CvmfsError::FileNotFound)
+    fn fail_over(&self, error: &io::Error) -> CvmfsResult<()> {
+        let fallback = self
+            .fallback_directory
+            .as_ref()
+            .ok_or(CvmfsError::CacheDirectoryNotFound)?;
+        self.failover.primary_errors.fetch_add(1, Ordering::Relaxed);
+        if !self.failover.active.swap(true, Ordering::Relaxed) {
+            log::warn!(
+                "Cache directory {} unusable ({}), writing new objects to {}",
+                self.cache_directory,
+                error,
+                fallback
+            );
+        }
+        Ok(Self::initialize_directory(Path::new(fallback))?)
     }
 
+    pub fn failover_status(&self) -> FailoverStatus {
+        FailoverStatus {
+            active: self.failover.active.load(Ordering::Relaxed),
+            primary_errors: self.failover.primary_errors.load(Ordering::Relaxed),
+            fallback_writes: self.failover.fallback_writes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Goes back to writing new objects to the primary directory, e.g. once
+    /// space was freed
+    pub fn reset_failover(&self) {
+        self.failover.active.store(false, Ordering::Relaxed);
+    }
+
+    /// Directory new objects are written to
+    fn write_directory(&self) -> &str {
+        match &self.fallback_directory {
+            Some(fallback) if self.failover.active.load(Ordering::Relaxed) => fallback,
+            _ => &self.cache_directory,
+        }
+    }
+
+    /// Location a new file is written to
     pub fn add(&self, file_name: &str) -> PathBuf {
-        Path::join(self.cache_directory.as_ref(), file_name)
+        Path::join(self.write_directory().as_ref(), file_name)
     }
 
+    /// Location of a cached file, looking into the fallback directory too
     pub fn get(&self, file_name: &str) -> Option<PathBuf> {
+        let primary = Path::new(&self.cache_directory).join(file_name);
+        if primary.exists() {
+            if let Some(quota) = &self.quota {
+                if QuotaManager::is_tracked(file_name) {
+                    quota.touch(file_name);
+                }
+            }
+            return Some(primary);
+        }
+        let fallback = Path::new(self.fallback_directory.as_ref()?).join(file_name);
+        fallback.exists().then_some(fallback)
+    }
+
+    /// Writes a file next to its cache location and moves it into place, so
+    /// that a partially written file is never picked up. Writes the primary
+    /// directory cannot take go to the fallback directory.
+    pub fn store(&self, file_name: &str, content: &[u8]) -> CvmfsResult<PathBuf> {
+        let failed_over = self.failover.active.load(Ordering::Relaxed);
         let path = self.add(file_name);
-        if path.exists() || path.is_file() {
-            return Some(path);
+        let digest = (self.record_digests && file_name.starts_with("data/"))
+            .then(|| content_digest(content));
+        let digest = digest.as_deref();
+        match Self::write_atomically(&path, content, digest) {
+            Ok(()) => {
+                metrics().record_cache_write(content.len());
+                if failed_over {
+                    self.failover
+                        .fallback_writes
+                        .fetch_add(1, Ordering::Relaxed);
+                } else if let Some(quota) = &self.quota {
+                    if QuotaManager::is_tracked(file_name) {
+                        if let Err(e) = quota.insert(file_name, content.len() as u64) {
+                            log::warn!("Could not account {} in the quota: {:?}", file_name, e);
+                        }
+                    }
+                }
+                Ok(path)
+            }
+            Err(e)
+                if !failed_over && is_failover_error(&e) && self.fallback_directory.is_some() =>
+            {
+                self.fail_over(&e)?;
+                let path = self.add(file_name);
+                Self::write_atomically(&path, content, digest)?;
+                metrics().record_cache_write(content.len());
+                self.failover
+                    .fallback_writes
+                    .fetch_add(1, Ordering::Relaxed);
+                Ok(path)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write_atomically(path: &Path, content: &[u8], digest: Option<&str>) -> io::Result<()> {
+        // unique per writer, so that concurrent writers of an object, in this
+        // process or another one sharing the cache, never mix their content
+        let mut partial_file = path.as_os_str().to_owned();
+        partial_file.push(format!(
+            "{}.{}.{}",
+            PARTIAL_MARKER,
+            std::process::id(),
+            PARTIAL_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        if let Err(e) = fs::write(&partial_file, content) {
+            let _ = fs::remove_file(&partial_file);
+            return Err(e);
+        }
+        if let Some(digest) = digest {
+            if let Err(e) = record_digest(Path::new(&partial_file), digest) {
+                log::debug!("Could not record the digest of {:?}: {}", path, e);
+            }
         }
-        None
+        fs::rename(partial_file, path)
     }
 
+    /// Empties the primary and the fallback directories, going back to the
+    /// primary one for new objects
     pub fn evict(&self) -> CvmfsResult<()> {
-        let data_path = Path::new(&self.cache_directory).join("data");
-        if data_path.exists() && data_path.is_dir() {
-            remove_dir_all(data_path)?;
+        let mut evicted = false;
+        for directory in std::iter::once(&self.cache_directory).chain(&self.fallback_directory) {
+            let data_path = Path::new(directory).join("data");
+            if data_path.exists() && data_path.is_dir() {
+                remove_dir_all(data_path)?;
+                evicted = true;
+            }
+        }
+        if let Some(quota) = &self.quota {
+            quota.clear()?;
+        }
+        if evicted {
+            self.reset_failover();
             self.initialize()?;
         }
         Ok(())
     }
+
+    pub fn quarantine_directory(&self) -> PathBuf {
+        Path::new(&self.cache_directory).join(QUARANTINE_DIRECTORY)
+    }
+
+    /// Location of a quarantined object, unique per object and time
+    fn quarantine_path(&self, file_name: &str, record: &QuarantineRecord) -> CvmfsResult<PathBuf> {
+        let directory = self.quarantine_directory();
+        create_dir_all(&directory)?;
+        let name = file_name
+            .strip_prefix("data/")
+            .unwrap_or(file_name)
+            .replace('/', "");
+        Ok(directory.join(format!(
+            "{}-{}",
+            record.time.format("%Y%m%dT%H%M%S%.6f"),
+            name
+        )))
+    }
+
+    fn write_quarantine_record(path: &Path, record: &QuarantineRecord) -> CvmfsResult<()> {
+        let mut metadata = path.as_os_str().to_owned();
+        metadata.push(".json");
+        fs::write(metadata, record.to_json())?;
+        Ok(())
+    }
+
+    /// Keeps the content of a corrupt download for investigation
+    pub fn quarantine_content(
+        &self,
+        file_name: &str,
+        content: &[u8],
+        record: &QuarantineRecord,
+    ) -> CvmfsResult<PathBuf> {
+        let path = self.quarantine_path(file_name, record)?;
+        fs::write(&path, content)?;
+        Self::write_quarantine_record(&path, record)?;
+        log::warn!("Quarantined {} into {:?}", record.url, path);
+        Ok(path)
+    }
+
+    /// Moves a corrupt cached object out of the cache for investigation,
+    /// copying it when it lives in another file system
+    pub fn quarantine_object(
+        &self,
+        object: &Path,
+        record: &QuarantineRecord,
+    ) -> CvmfsResult<PathBuf> {
+        let file_name = object
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or(CvmfsError::FileNotFound)?;
+        let path = self.quarantine_path(file_name, record)?;
+        if fs::rename(object, &path).is_err() {
+            fs::copy(object, &path)?;
+            fs::remove_file(object)?;
+        }
+        Self::write_quarantine_record(&path, record)?;
+        log::warn!("Quarantined {:?} into {:?}", object, path);
+        Ok(path)
+    }
+
+    /// Objects currently in quarantine, without their metadata files
+    pub fn quarantined_objects(&self) -> CvmfsResult<Vec<PathBuf>> {
+        let directory = self.quarantine_directory();
+        if !directory.is_dir() {
+            return Ok(vec![]);
+        }
+        let mut objects = Vec::new();
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|extension| extension != "json") {
+                objects.push(path);
+            }
+        }
+        objects.sort();
+        Ok(objects)
+    }
+
+    /// Reads the last known state of a repository, if any
+    pub fn load_breadcrumb(&self, fqrn: &str) -> Option<Breadcrumb> {
+        let path = self.get(&Breadcrumb::file_name(fqrn))?;
+        fs::read_to_string(path).ok()?.parse().ok()
+    }
+
+    pub fn store_breadcrumb(&self, fqrn: &str, breadcrumb: &Breadcrumb) -> CvmfsResult<()> {
+        self.store(
+            &Breadcrumb::file_name(fqrn),
+            breadcrumb.to_string().as_bytes(),
+        )?;
+        Ok(())
+    }
+
+    /// Reads the catalogs opened by the last mount of a repository, if any
+    pub fn load_catalog_set(&self, fqrn: &str) -> Option<CatalogSet> {
+        let path = self.get(&CatalogSet::file_name(fqrn))?;
+        fs::read_to_string(path).ok()?.parse().ok()
+    }
+
+    pub fn store_catalog_set(&self, fqrn: &str, catalog_set: &CatalogSet) -> CvmfsResult<()> {
+        self.store(
+            &CatalogSet::file_name(fqrn),
+            catalog_set.to_string().as_bytes(),
+        )?;
+        Ok(())
+    }
+
+    /// Reads the tag a repository was pinned to, if any
+    pub fn load_pinned_tag(&self, fqrn: &str) -> Option<String> {
+        let path = self.get(&format!("{}{}", PINNED_TAG_PREFIX, fqrn))?;
+        let tag = fs::read_to_string(path).ok()?;
+        Some(tag.trim().to_string()).filter(|tag| !tag.is_empty())
+    }
+
+    pub fn store_pinned_tag(&self, fqrn: &str, tag: &str) -> CvmfsResult<()> {
+        self.store(&format!("{}{}", PINNED_TAG_PREFIX, fqrn), tag.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn remove_pinned_tag(&self, fqrn: &str) -> CvmfsResult<()> {
+        if let Some(path) = self.get(&format!("{}{}", PINNED_TAG_PREFIX, fqrn)) {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
 }
@@ -1,31 +1,40 @@
-use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use chrono::{DateTime, Utc};
 use rusqlite::Row;
 
-use crate::common::{canonicalize_path, split_md5, CvmfsError, CvmfsResult};
-use crate::database_object::DatabaseObject;
+use crate::common::{normalize_path, path_md5, split_md5, CvmfsError, CvmfsResult};
+use crate::database_object::{DatabaseObject, SqliteTuning};
 use crate::directory_entry::{DirectoryEntry, PathHash};
 
 pub const CATALOG_ROOT_PREFIX: &str = "C";
-const LISTING_QUERY: &str = "\
-SELECT md5path_1, md5path_2, parent_1, parent_2, hash, flags, size, mode, mtime, name, symlink \
-FROM catalog \
-WHERE parent_1 = ? AND parent_2 = ? \
-ORDER BY name ASC";
+/// Suffix of the micro catalogs, which only hold the root directory and its
+/// children
+pub const MICRO_CATALOG_SUFFIX: &str = "L";
+/// Suffix of the cached files with external data, whose hash is the one of
+/// their uncompressed content
+pub const EXTERNAL_SUFFIX: &str = "E";
+/// Catalogs with a schema below this one use the legacy table layout
+pub const LEGACY_SCHEMA: f32 = 1.0;
+const ENTRY_COLUMNS: &str =
+    "md5path_1, md5path_2, parent_1, parent_2, hash, flags, size, mode, mtime, name, symlink";
+/// Legacy catalogs may not store symlink targets in their own column
+const LEGACY_ENTRY_COLUMNS: &str =
+    "md5path_1, md5path_2, parent_1, parent_2, hash, flags, size, mode, mtime, name, NULL";
+const LISTING_CONDITION: &str = "WHERE parent_1 = ? AND parent_2 = ? ORDER BY name ASC";
 const NESTED_COUNT: &str = "SELECT count(*) FROM nested_catalogs;";
 const READ_CHUNK: &str = "\
 SELECT md5path_1, md5path_2, offset, size, hash \
 FROM chunks \
 WHERE md5path_1 = ? AND md5path_2 = ? \
 ORDER BY offset ASC";
-const FIND_MD5_PATH: &str = "SELECT md5path_1, md5path_2, parent_1, parent_2, hash, flags, size, mode, mtime, name, symlink \
-FROM catalog \
-WHERE md5path_1 = ? AND md5path_2 = ? \
-LIMIT 1;";
+const FIND_CONDITION: &str = "WHERE md5path_1 = ? AND md5path_2 = ? LIMIT 1;";
 const READ_STATISTICS: &str = "SELECT * FROM statistics ORDER BY counter;";
+/// Queries taking longer than this are logged along with what they looked for
+pub const DEFAULT_SLOW_QUERY_THRESHOLD: Duration = Duration::from_millis(100);
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CatalogReference {
     pub root_path: String,
     pub catalog_hash: String,
@@ -43,6 +52,102 @@ pub struct Catalog {
     pub hash: String,
     pub last_modified: DateTime<Utc>,
     pub root_prefix: String,
+    pub ttl: Option<u32>,
+    /// Old and minimal catalogs may lack some of the optional tables
+    pub has_statistics: bool,
+    pub has_chunks: bool,
+    pub has_nested_catalogs: bool,
+    /// Schema older than `LEGACY_SCHEMA`, with a different set of columns
+    pub is_legacy: bool,
+    /// Queries taking longer are logged as slow
+    pub slow_query_threshold: Duration,
+    listing_query: String,
+    find_query: String,
+    counters: Mutex<PerformanceCounters>,
+}
+
+/// Statements run against a catalog, timed separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CatalogQuery {
+    /// Lookup of a path
+    Find,
+    Listing,
+    Chunks,
+    Nested,
+    Statistics,
+}
+
+impl CatalogQuery {
+    pub const ALL: [Self; 5] = [
+        Self::Find,
+        Self::Listing,
+        Self::Chunks,
+        Self::Nested,
+        Self::Statistics,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Find => "find",
+            Self::Listing => "listing",
+            Self::Chunks => "chunks",
+            Self::Nested => "nested",
+            Self::Statistics => "statistics",
+        }
+    }
+}
+
+/// Number and duration of the runs of a statement
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct QueryStatistics {
+    pub count: u64,
+    pub total: Duration,
+    pub max: Duration,
+    /// Runs slower than the slow query threshold
+    pub slow: u64,
+}
+
+impl QueryStatistics {
+    pub fn mean(&self) -> Duration {
+        self.total
+            .checked_div(self.count as u32)
+            .unwrap_or_default()
+    }
+}
+
+/// Statistics of every statement run against a catalog
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PerformanceCounters {
+    queries: [QueryStatistics; CatalogQuery::ALL.len()],
+}
+
+impl PerformanceCounters {
+    pub fn get(&self, query: CatalogQuery) -> QueryStatistics {
+        self.queries[query as usize]
+    }
+
+    /// Statements run, whatever their kind
+    pub fn total_queries(&self) -> u64 {
+        self.queries.iter().map(|query| query.count).sum()
+    }
+
+    /// Adds the counters of another catalog
+    pub fn merge(&mut self, other: &Self) {
+        for (query, other) in self.queries.iter_mut().zip(&other.queries) {
+            query.count += other.count;
+            query.total += other.total;
+            query.max = query.max.max(other.max);
+            query.slow += other.slow;
+        }
+    }
+
+    fn record(&mut self, query: CatalogQuery, elapsed: Duration, slow: bool) {
+        let statistics = &mut self.queries[query as usize];
+        statistics.count += 1;
+        statistics.total += elapsed;
+        statistics.max = statistics.max.max(elapsed);
+        statistics.slow += slow as u64;
+    }
 }
 
 /// Statistics for the catalog and the whole file system.
@@ -66,7 +171,11 @@ unsafe impl Sync for Catalog {}
 
 impl Catalog {
     pub fn new(path: String, hash: String) -> CvmfsResult<Self> {
-        let database = DatabaseObject::new(&path)?;
+        Self::with_tuning(path, hash, &SqliteTuning::default())
+    }
+
+    pub fn with_tuning(path: String, hash: String, tuning: &SqliteTuning) -> CvmfsResult<Self> {
+        let database = DatabaseObject::with_tuning(&path, tuning)?;
         let properties = database.read_properties_table()?;
         let mut revision = 0;
         let mut previous_revision = String::new();
@@ -74,6 +183,7 @@ impl Catalog {
         let mut schema_revision = 0.0;
         let mut root_prefix = String::from("/");
         let mut last_modified = Default::default();
+        let mut ttl = None;
         for (key, value) in properties {
             match key.as_str() {
                 "revision" => revision = value.parse().map_err(|_| CvmfsError::ParseError)?,
@@ -93,12 +203,28 @@ impl Catalog {
                     root_prefix.clear();
                     root_prefix.push_str(&value)
                 }
+                "TTL" => ttl = Some(value.parse().map_err(|_| CvmfsError::ParseError)?),
                 _ => {}
             }
         }
         if revision == 0 || schema == 0.0 {
             return Err(CvmfsError::CatalogInitialization);
         }
+        let has_statistics = database.has_table("statistics")?;
+        let has_chunks = database.has_table("chunks")?;
+        let has_nested_catalogs = database.has_table("nested_catalogs")?;
+        let is_legacy = schema < LEGACY_SCHEMA;
+        let columns = if is_legacy && !database.has_column("catalog", "symlink")? {
+            LEGACY_ENTRY_COLUMNS
+        } else {
+            ENTRY_COLUMNS
+        };
+        let hardlinks = if database.has_column("catalog", "hardlinks")? {
+            "hardlinks"
+        } else {
+            "0"
+        };
+        let columns = format!("{}, {}", columns, hardlinks);
         Ok(Self {
             database,
             schema,
@@ -108,9 +234,54 @@ impl Catalog {
             last_modified,
             root_prefix,
             previous_revision,
+            ttl,
+            has_statistics,
+            has_chunks,
+            has_nested_catalogs,
+            is_legacy,
+            listing_query: format!("SELECT {} FROM catalog {}", columns, LISTING_CONDITION),
+            find_query: format!("SELECT {} FROM catalog {}", columns, FIND_CONDITION),
+            slow_query_threshold: DEFAULT_SLOW_QUERY_THRESHOLD,
+            counters: Default::default(),
         })
     }
 
+    /// Statistics of the statements run so far against the catalog
+    pub fn performance_counters(&self) -> PerformanceCounters {
+        self.counters
+            .lock()
+            .map(|counters| counters.clone())
+            .unwrap_or_default()
+    }
+
+    /// Runs a statement, recording how long it took and logging it with its
+    /// `subject` when slow
+    fn timed<R>(
+        &self,
+        query: CatalogQuery,
+        subject: impl FnOnce() -> String,
+        f: impl FnOnce() -> CvmfsResult<R>,
+    ) -> CvmfsResult<R> {
+        let started = Instant::now();
+        let result = f();
+        let elapsed = started.elapsed();
+        let slow = elapsed > self.slow_query_threshold;
+        if slow {
+            log::warn!(
+                "Slow {} query in catalog {} ({}) for {}: {:?}",
+                query.name(),
+                self.root_prefix,
+                self.hash,
+                subject(),
+                elapsed
+            );
+        }
+        if let Ok(mut counters) = self.counters.lock() {
+            counters.record(query, elapsed, slow);
+        }
+        result
+    }
+
     pub fn is_root(&self) -> bool {
         self.root_prefix.eq("/")
     }
@@ -121,56 +292,78 @@ impl Catalog {
 
     /// Returns the number of nested catalogs in the catalog
     pub fn nested_count(&self) -> CvmfsResult<u32> {
-        let mut result = self.database.create_prepared_statement(NESTED_COUNT)?;
-        let mut row = result.query([])?;
-        let next_row = row
-            .next()
-            .map_err(|e| CvmfsError::DatabaseError(format!("{:?}", e)))?
-            .ok_or(CvmfsError::DatabaseError("No rows found".to_string()))?;
-        Ok(next_row.get(0)?)
+        if !self.has_nested_catalogs {
+            return Ok(0);
+        }
+        self.timed(CatalogQuery::Nested, String::new, || {
+            let mut result = self.database.create_prepared_statement(NESTED_COUNT)?;
+            let mut row = result.query([])?;
+            let next_row = row
+                .next()
+                .map_err(|e| CvmfsError::DatabaseError(format!("{:?}", e)))?
+                .ok_or(CvmfsError::DatabaseError("No rows found".to_string()))?;
+            Ok(next_row.get(0)?)
+        })
     }
 
     /// List CatalogReferences to all contained nested catalogs
     pub fn list_nested(&self) -> CvmfsResult<Vec<CatalogReference>> {
-        let new_version = self.schema <= 1.2 && self.schema_revision > 0.0;
+        if !self.has_nested_catalogs {
+            return Ok(vec![]);
+        }
+        // legacy catalogs don't record the size of their nested catalogs
+        let new_version = !self.is_legacy && self.schema <= 1.2 && self.schema_revision > 0.0;
         let sql = if new_version {
             "SELECT path, sha1, size FROM nested_catalogs"
         } else {
             "SELECT path, sha1 FROM nested_catalogs"
         };
-        let mut result = self.database.create_prepared_statement(sql)?;
-        let iterator = result.query_map([], |row| {
-            Ok(CatalogReference {
-                root_path: row.get(0)?,
-                catalog_hash: row.get(1)?,
-                catalog_size: if new_version { row.get(2)? } else { 0 },
+        self.timed(CatalogQuery::Nested, String::new, || {
+            self.database.with_connection(|connection| {
+                let mut result = connection.prepare_cached(sql)?;
+                let iterator = result.query_map([], |row| {
+                    Ok(CatalogReference {
+                        root_path: row.get(0)?,
+                        catalog_hash: row.get(1)?,
+                        catalog_size: if new_version { row.get(2)? } else { 0 },
+                    })
+                })?;
+                Ok(iterator.collect::<Result<Vec<_>, _>>()?)
             })
-        })?;
-        Ok(iterator.collect::<Result<Vec<_>, _>>()?)
+        })
     }
 
     fn path_sanitized(needle_path: &str, catalog_path: &str) -> bool {
         needle_path.len() == catalog_path.len()
             || (needle_path.len() > catalog_path.len()
-                && needle_path.chars().collect::<Vec<char>>()[catalog_path.len()] == '/')
+                && needle_path.as_bytes()[catalog_path.len()] == b'/')
     }
 
     /// Find the best matching nested CatalogReference for a given path
     pub fn find_nested_for_path(&self, needle_path: &str) -> CvmfsResult<Option<CatalogReference>> {
         let catalog_refs = self.list_nested()?;
+        Ok(Self::best_nested_match(&catalog_refs, needle_path).cloned())
+    }
+
+    /// Finds the best matching reference for a path among the nested catalogs
+    /// already listed from a catalog
+    pub fn best_nested_match<'a>(
+        catalog_refs: &'a [CatalogReference],
+        needle_path: &str,
+    ) -> Option<&'a CatalogReference> {
         let mut best_match = None;
         let mut best_match_score = 0;
-        let real_needle_path = canonicalize_path(needle_path);
+        let needle_path = normalize_path(needle_path);
         for nested_catalog in catalog_refs {
-            if real_needle_path.starts_with(&nested_catalog.root_path)
+            if needle_path.starts_with(&nested_catalog.root_path)
                 && nested_catalog.root_path.len() > best_match_score
-                && Self::path_sanitized(needle_path, &nested_catalog.root_path)
+                && Self::path_sanitized(&needle_path, &nested_catalog.root_path)
             {
                 best_match_score = nested_catalog.root_path.len();
                 best_match = Some(nested_catalog);
             }
         }
-        Ok(best_match)
+        best_match
     }
 
     /// Create a directory listing of DirectoryEntry items based on MD5 path
@@ -179,41 +372,57 @@ impl Catalog {
         parent_1: i64,
         parent_2: i64,
     ) -> CvmfsResult<Vec<DirectoryEntry>> {
-        let mut statement = self.database.create_prepared_statement(LISTING_QUERY)?;
-        let mut result = Vec::new();
-        let mut rows = statement.query([parent_1, parent_2])?;
-        loop {
-            match rows.next() {
-                Ok(row) => {
-                    if let Some(row) = row {
-                        result.push(self.make_directory_entry(row)?);
-                    } else {
-                        break;
-                    }
+        self.map_directory_split_md5(parent_1, parent_2, |dirent| dirent)
+    }
+
+    /// Maps every entry of a directory while reading it from the database cursor,
+    /// so that callers don't need an intermediate listing
+    pub fn map_directory_split_md5<T>(
+        &self,
+        parent_1: i64,
+        parent_2: i64,
+        mut f: impl FnMut(DirectoryEntry) -> T,
+    ) -> CvmfsResult<Vec<T>> {
+        let subject = || format!("the directory {:x}{:x}", parent_1, parent_2);
+        self.timed(CatalogQuery::Listing, subject, || {
+            self.database.with_connection(|connection| {
+                let mut statement = connection.prepare_cached(&self.listing_query)?;
+                let mut result = Vec::new();
+                let mut rows = statement.query([parent_1, parent_2])?;
+                while let Some(row) = rows.next()? {
+                    result.push(f(self.make_directory_entry(row)?));
                 }
-                Err(e) => return Err(e.into()),
-            }
-        }
-        Ok(result)
+                Ok(result)
+            })
+        })
     }
 
     pub fn list_directory(&self, path: &str) -> CvmfsResult<Vec<DirectoryEntry>> {
-        let mut real_path = canonicalize_path(path);
-        if real_path.eq(Path::new("/")) {
-            real_path = PathBuf::new();
-        }
-        let md5_hash = md5::compute(
-            real_path
-                .to_str()
-                .ok_or(CvmfsError::FileNotFound)?
-                .bytes()
-                .collect::<Vec<u8>>(),
-        );
-        let parent_hash = split_md5(&md5_hash.0);
-        self.list_directory_split_md5(parent_hash.hash1, parent_hash.hash2)
+        self.map_directory(path, |dirent| dirent)
+    }
+
+    pub fn map_directory<T>(
+        &self,
+        path: &str,
+        f: impl FnMut(DirectoryEntry) -> T,
+    ) -> CvmfsResult<Vec<T>> {
+        let path = normalize_path(path);
+        let path = if path == "/" { "" } else { &path };
+        let parent_hash = split_md5(&path_md5(path));
+        self.map_directory_split_md5(parent_hash.hash1, parent_hash.hash2, f)
     }
 
+    /// Statistics of the catalog, all zero if the catalog does not keep them
     pub fn get_statistics(&self) -> CvmfsResult<Statistics> {
+        if !self.has_statistics {
+            return Ok(Statistics::default());
+        }
+        self.timed(CatalogQuery::Statistics, String::new, || {
+            self.read_statistics()
+        })
+    }
+
+    fn read_statistics(&self) -> CvmfsResult<Statistics> {
         let mut statement = self.database.create_prepared_statement(READ_STATISTICS)?;
         let mut rows = statement.query([])?;
         let mut statistics = Statistics::default();
@@ -237,43 +446,84 @@ impl Catalog {
         Ok(statistics)
     }
 
+    /// Chunks are not read here to keep metadata operations cheap, see `load_chunks`
     fn make_directory_entry(&self, row: &Row) -> CvmfsResult<DirectoryEntry> {
-        let mut directory_entry = DirectoryEntry::new(row)?;
-        self.read_chunks(&mut directory_entry)?;
-        Ok(directory_entry)
+        DirectoryEntry::new(row)
     }
 
-    /// Finds and adds the file chunk of a DirectoryEntry
-    fn read_chunks(&self, directory_entry: &mut DirectoryEntry) -> CvmfsResult<()> {
-        let mut statement = self.database.create_prepared_statement(READ_CHUNK)?;
+    /// Finds and adds the file chunks of a DirectoryEntry, needed to read its contents
+    pub fn load_chunks(&self, directory_entry: &mut DirectoryEntry) -> CvmfsResult<()> {
+        if !self.has_chunks || !directory_entry.is_file() || !directory_entry.has_chunks() {
+            return Ok(());
+        }
+        let name = directory_entry.name.clone();
         let path_hash = directory_entry.path_hash();
-        let iterator = statement.query([path_hash.hash1, path_hash.hash2])?;
-        directory_entry.add_chunks(iterator)?;
-        Ok(())
+        self.timed(
+            CatalogQuery::Chunks,
+            || name,
+            || {
+                self.database.with_connection(|connection| {
+                    let mut statement = connection.prepare_cached(READ_CHUNK)?;
+                    let iterator = statement.query([path_hash.hash1, path_hash.hash2])?;
+                    directory_entry.add_chunks(iterator)
+                })
+            },
+        )
     }
 
     pub fn find_directory_entry(&self, root_path: &str) -> CvmfsResult<DirectoryEntry> {
-        let real_path = canonicalize_path(root_path);
-        let md5_path = md5::compute(
-            real_path
-                .to_str()
-                .ok_or(CvmfsError::FileNotFound)?
-                .bytes()
-                .collect::<Vec<u8>>(),
+        let root_path = normalize_path(root_path);
+        let path_hash = split_md5(&path_md5(&root_path));
+        self.timed(
+            CatalogQuery::Find,
+            || root_path.to_string(),
+            || self.find_directory_entry_split_md5(path_hash),
         )
-        .0;
-        self.find_directory_entry_md5(&md5_path)
+    }
+
+    /// Finds the DirectoryEntry of several paths reusing a single prepared statement
+    pub fn find_directory_entries(&self, root_paths: &[&str]) -> Vec<CvmfsResult<DirectoryEntry>> {
+        let entries = self.database.with_connection(|connection| {
+            let mut statement = connection.prepare_cached(&self.find_query)?;
+            Ok(root_paths
+                .iter()
+                .map(|root_path| {
+                    let root_path = normalize_path(root_path);
+                    let path_hash = split_md5(&path_md5(&root_path));
+                    self.timed(
+                        CatalogQuery::Find,
+                        || root_path.to_string(),
+                        || {
+                            let mut rows = statement.query([path_hash.hash1, path_hash.hash2])?;
+                            let row = rows.next()?.ok_or(CvmfsError::FileNotFound)?;
+                            self.make_directory_entry(row)
+                        },
+                    )
+                })
+                .collect())
+        });
+        entries.unwrap_or_else(|e| root_paths.iter().map(|_| Err(e.clone())).collect())
     }
 
     pub fn find_directory_entry_md5(&self, md5_path: &[u8; 16]) -> CvmfsResult<DirectoryEntry> {
         let path_hash = split_md5(md5_path);
-        self.find_directory_entry_split_md5(path_hash)
+        let subject = || {
+            md5_path
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect()
+        };
+        self.timed(CatalogQuery::Find, subject, || {
+            self.find_directory_entry_split_md5(path_hash)
+        })
     }
 
     fn find_directory_entry_split_md5(&self, path_hash: PathHash) -> CvmfsResult<DirectoryEntry> {
-        let mut statement = self.database.create_prepared_statement(FIND_MD5_PATH)?;
-        let mut rows = statement.query([path_hash.hash1, path_hash.hash2])?;
-        let row = rows.next()?.ok_or(CvmfsError::FileNotFound)?;
-        self.make_directory_entry(row)
+        self.database.with_connection(|connection| {
+            let mut statement = connection.prepare_cached(&self.find_query)?;
+            let mut rows = statement.query([path_hash.hash1, path_hash.hash2])?;
+            let row = rows.next()?.ok_or(CvmfsError::FileNotFound)?;
+            self.make_directory_entry(row)
+        })
     }
 }
@@ -0,0 +1,44 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::common::CvmfsError;
+
+pub const CATALOG_SET_PREFIX: &str = "cvmfscatalogs.";
+
+/// Catalogs opened by a mount when it was unmounted, persisted in the cache so
+/// that the next mount of the same revision can pre-open them.
+/// It is stored as the root catalog hash followed by one catalog hash per line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CatalogSet {
+    pub root_hash: String,
+    pub catalog_hashes: Vec<String>,
+}
+
+impl CatalogSet {
+    pub fn file_name(fqrn: &str) -> String {
+        format!("{}{}", CATALOG_SET_PREFIX, fqrn)
+    }
+}
+
+impl FromStr for CatalogSet {
+    type Err = CvmfsError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut lines = value.lines().map(str::trim).filter(|line| !line.is_empty());
+        let root_hash = lines.next().ok_or(CvmfsError::ParseError)?;
+        Ok(Self {
+            root_hash: root_hash.into(),
+            catalog_hashes: lines.map(String::from).collect(),
+        })
+    }
+}
+
+impl Display for CatalogSet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.root_hash)?;
+        for catalog_hash in &self.catalog_hashes {
+            writeln!(f, "{}", catalog_hash)?;
+        }
+        Ok(())
+    }
+}
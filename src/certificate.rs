@@ -1,16 +1,71 @@
+use openssl::hash::{hash, MessageDigest};
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey, Public};
+use openssl::sign::Verifier;
+use openssl::x509::X509;
 use x509_certificate::X509Certificate;
 
 use crate::common::CvmfsError;
 
 pub const CERTIFICATE_ROOT_PREFIX: &str = "X";
 
-struct Certificate {
+/// Wrapper around the X509 certificate used to sign the repository manifest
+#[derive(Debug)]
+pub struct Certificate {
     pub openssl_certificate: X509Certificate,
 }
 
 impl Certificate {
-    pub fn verify(&self, _signature: &str, _message: &str) -> bool {
-        unimplemented!()
+    /// Parses a PEM encoded certificate, as stored in the repository
+    pub fn from_pem(bytes: &[u8]) -> Result<Self, CvmfsError> {
+        Ok(Self {
+            openssl_certificate: X509Certificate::from_pem(bytes)
+                .map_err(|_| CvmfsError::Certificate)?,
+        })
+    }
+
+    /// SHA-1 fingerprint of the certificate as listed in the whitelist,
+    /// uppercase hex bytes separated by colons
+    pub fn fingerprint(&self) -> Result<String, CvmfsError> {
+        let der = self
+            .openssl_certificate
+            .encode_der()
+            .map_err(|_| CvmfsError::Certificate)?;
+        Ok(hash(MessageDigest::sha1(), &der)
+            .map_err(|_| CvmfsError::Certificate)?
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<_>>()
+            .join(":"))
+    }
+
+    /// Verifies the signature of a message against the public key of the certificate.
+    /// RSA keys use PKCS#1 v1.5 with SHA-1, like the reference CernVM-FS signer,
+    /// while ECDSA keys use the digest matching the size of their curve.
+    pub fn verify(&self, signature: &[u8], message: &[u8]) -> bool {
+        let Some((digest, key)) = self.verification_key() else {
+            return false;
+        };
+        Verifier::new(digest, &key)
+            .and_then(|mut verifier| verifier.verify_oneshot(signature, message))
+            .unwrap_or(false)
+    }
+
+    /// Public key of the certificate and the digest it signs with, `None` for
+    /// the key types and sizes that are not accepted
+    fn verification_key(&self) -> Option<(MessageDigest, PKey<Public>)> {
+        let der = self.openssl_certificate.encode_der().ok()?;
+        let key = X509::from_der(&der).ok()?.public_key().ok()?;
+        let digest = match key.id() {
+            Id::RSA if (2048..=8192).contains(&key.bits()) => MessageDigest::sha1(),
+            Id::EC => match key.ec_key().ok()?.group().curve_name()? {
+                Nid::X9_62_PRIME256V1 => MessageDigest::sha256(),
+                Nid::SECP384R1 => MessageDigest::sha384(),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some((digest, key))
     }
 }
 
@@ -0,0 +1,389 @@
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::DateTime;
+use clap::{Args, Parser, Subcommand};
+
+use crate::common::{compose_object_path, CvmfsError, CvmfsResult};
+use crate::directory_entry::{DirectoryEntry, SpecialKind};
+use crate::export::ExportTarget;
+use crate::mount_config::{default_cache_directory, MountArgs, MountConfig};
+use crate::preload::PreloadSpec;
+use crate::repository::Repository;
+use crate::validation::ValidationMode;
+
+/// Command line of the client. Mounting is the default, so that the
+/// subcommand can be left out.
+#[derive(Debug, Parser)]
+#[command(name = "cvmfs", args_conflicts_with_subcommands = true)]
+#[command(subcommand_negates_reqs = true, arg_required_else_help = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    mount: Option<MountArgs>,
+}
+
+impl Cli {
+    pub fn into_command(self) -> Command {
+        match (self.command, self.mount) {
+            (Some(command), _) => command,
+            (None, Some(args)) => Command::Mount(Box::new(args)),
+            (None, None) => unreachable!("clap requires the mount arguments"),
+        }
+    }
+}
+
+/// Repository opened by the inspection commands, without mounting it
+#[derive(Debug, Clone, PartialEq, Args)]
+pub struct RepositoryArgs {
+    pub url: String,
+    #[arg(long = "cache-dir", default_value_t = default_cache_directory())]
+    pub cache_directory: String,
+    #[arg(long = "keys-dir")]
+    pub keys_directory: Option<PathBuf>,
+    /// Tag inspected instead of the latest revision
+    #[arg(long, conflicts_with = "revision")]
+    pub tag: Option<String>,
+    /// Revision inspected instead of the latest one
+    #[arg(long)]
+    pub revision: Option<u32>,
+    /// Skips the signature chain, for repositories without keys
+    #[arg(long)]
+    pub insecure: bool,
+}
+
+impl RepositoryArgs {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.into(),
+            cache_directory: default_cache_directory(),
+            keys_directory: None,
+            tag: None,
+            revision: None,
+            insecure: false,
+        }
+    }
+
+    /// Opens the repository with the same checks as a mount. Tags are not
+    /// pinned, so that the mounts sharing the cache are not affected.
+    pub fn open(&self) -> CvmfsResult<Repository> {
+        let mut config = MountConfig::new(&self.url, Path::new("/"), &self.cache_directory);
+        if let Some(keys_directory) = &self.keys_directory {
+            config.keys_directory = keys_directory.clone();
+        }
+        if self.insecure {
+            config.validation.signature = ValidationMode::Ignore;
+        }
+        config.revision = self.revision;
+        let mut repository = config.create_repository()?;
+        if let Some(tag) = &self.tag {
+            let revision = repository.get_tag_by_name(tag)?.revision;
+            repository.set_current_tag(revision as u32)?;
+        }
+        Ok(repository)
+    }
+}
+
+/// Subcommands of the client
+#[derive(Debug, Clone, PartialEq, Subcommand)]
+pub enum Command {
+    /// Mounts a repository
+    Mount(Box<MountArgs>),
+    /// Manifest and current revision of the repository
+    Info(RepositoryArgs),
+    /// Content hash and catalog flags of a path
+    Lookup {
+        #[command(flatten)]
+        repository: RepositoryArgs,
+        path: String,
+    },
+    /// Entries of a directory
+    #[command(name = "ls")]
+    List {
+        #[command(flatten)]
+        repository: RepositoryArgs,
+        #[arg(default_value = "/")]
+        path: String,
+        /// Mode, size and modification time of every entry
+        #[arg(short)]
+        long: bool,
+    },
+    /// Contents of a file, written to the output
+    Cat {
+        #[command(flatten)]
+        repository: RepositoryArgs,
+        path: String,
+    },
+    /// Attributes of a path
+    Stat {
+        #[command(flatten)]
+        repository: RepositoryArgs,
+        path: String,
+    },
+    /// Named snapshots of the repository
+    Tags(RepositoryArgs),
+    /// Entries added, removed or modified from the revision of one tag to the
+    /// one of another
+    Diff {
+        #[command(flatten)]
+        repository: RepositoryArgs,
+        old: String,
+        new: String,
+    },
+    /// Subtree written to a local directory, or a tar archive when the
+    /// destination ends in `.tar`
+    Export {
+        #[command(flatten)]
+        repository: RepositoryArgs,
+        path: String,
+        destination: PathBuf,
+    },
+    /// Catalogs and files of some paths downloaded into the cache, to be
+    /// served offline later
+    Preload {
+        #[command(flatten)]
+        repository: RepositoryArgs,
+        #[arg(required_unless_present = "spec_file")]
+        paths: Vec<String>,
+        /// File listing the paths in the `.cvmfsdirtab` format
+        #[arg(long = "spec")]
+        spec_file: Option<PathBuf>,
+    },
+    /// Reports the divergence between two servers, exiting with 1 when out
+    /// of sync
+    Compare {
+        first: String,
+        second: String,
+        cache_directory: Option<String>,
+    },
+    /// Program map for autofs, printing the map entry of a key
+    Automount { key: String },
+    /// Arguments given by mount(8) to the `mount.cvmfs` helper
+    MountHelper {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+}
+
+impl Command {
+    /// Runs an inspection command, writing its result to the output
+    pub fn run(&self, out: &mut dyn Write) -> CvmfsResult<()> {
+        match self {
+            Command::Info(args) => write_info(&mut args.open()?, out),
+            Command::Lookup { repository, path } => {
+                write_lookup(&repository.open()?.lookup(path)?, path, out)
+            }
+            Command::List {
+                repository,
+                path,
+                long,
+            } => {
+                for entry in repository.open()?.list_directory(path)? {
+                    if *long {
+                        writeln!(
+                            out,
+                            "{} {:>12} {} {}",
+                            mode_string(&entry),
+                            entry.size,
+                            format_time(entry.mtime),
+                            entry.name
+                        )?;
+                    } else {
+                        writeln!(out, "{}", entry.name)?;
+                    }
+                }
+                Ok(())
+            }
+            Command::Cat { repository, path } => {
+                let mut file = repository.open()?.get_file(path)?;
+                io::copy(&mut file, out)?;
+                Ok(())
+            }
+            Command::Stat { repository, path } => {
+                write_stat(&repository.open()?.lookup(path)?, path, out)
+            }
+            Command::Tags(args) => {
+                for tag in args.open()?.list_tags()? {
+                    writeln!(
+                        out,
+                        "{}\t{}\t{}\t{}\t{}",
+                        tag.name,
+                        tag.revision,
+                        format_time(tag.timestamp as i64),
+                        tag.hash,
+                        tag.description
+                    )?;
+                }
+                Ok(())
+            }
+            Command::Diff {
+                repository,
+                old,
+                new,
+            } => {
+                for change in repository.open()?.diff(old, new)? {
+                    writeln!(out, "{}", change)?;
+                }
+                Ok(())
+            }
+            Command::Export {
+                repository,
+                path,
+                destination,
+            } => {
+                let target = ExportTarget::from_path(destination);
+                let summary = repository.open()?.export(path, &target)?;
+                writeln!(
+                    out,
+                    "{} directories, {} files ({} bytes), {} symlinks, {} special files, {} skipped",
+                    summary.directories,
+                    summary.files,
+                    summary.bytes,
+                    summary.symlinks,
+                    summary.special_files,
+                    summary.skipped
+                )?;
+                Ok(())
+            }
+            Command::Preload {
+                repository,
+                paths,
+                spec_file,
+            } => {
+                let mut spec = PreloadSpec::from_paths(paths)?;
+                if let Some(spec_file) = spec_file {
+                    let from_file = PreloadSpec::load_file(spec_file)?;
+                    spec.include.extend(from_file.include);
+                    spec.exclude.extend(from_file.exclude);
+                }
+                let summary = repository.open()?.preload(&spec)?;
+                writeln!(
+                    out,
+                    "{} catalogs, {} files ({} bytes), {} objects of which {} were cached, {} skipped",
+                    summary.catalogs,
+                    summary.files,
+                    summary.bytes,
+                    summary.objects,
+                    summary.cached,
+                    summary.skipped
+                )?;
+                for object in &summary.missing {
+                    writeln!(out, "missing: {}", object)?;
+                }
+                if !summary.missing.is_empty() {
+                    return Err(CvmfsError::IO(format!(
+                        "{} objects could not be downloaded",
+                        summary.missing.len()
+                    )));
+                }
+                Ok(())
+            }
+            _ => Err(CvmfsError::InvalidConfiguration(
+                "not an inspection command".into(),
+            )),
+        }
+    }
+}
+
+fn write_info(repository: &mut Repository, out: &mut dyn Write) -> CvmfsResult<()> {
+    let manifest = &repository.manifest;
+    writeln!(out, "name: {}", manifest.repository_name)?;
+    writeln!(out, "revision: {}", manifest.revision)?;
+    writeln!(out, "published: {}", manifest.last_modified.to_rfc3339())?;
+    writeln!(out, "root catalog: {}", manifest.root_catalog)?;
+    writeln!(
+        out,
+        "history: {}",
+        manifest.history_database.as_deref().unwrap_or("none")
+    )?;
+    writeln!(out, "certificate: {}", manifest.certificate)?;
+    let tag = repository.current_tag()?;
+    writeln!(out, "tag: {} (revision {})", tag.name, tag.revision)?;
+    writeln!(out, "ttl: {}s", repository.get_ttl()?)?;
+    Ok(())
+}
+
+fn write_lookup(entry: &DirectoryEntry, path: &str, out: &mut dyn Write) -> CvmfsResult<()> {
+    writeln!(out, "path: {}", path)?;
+    writeln!(out, "type: {}", kind(entry))?;
+    if let Some(hash) = entry.content_hash_string() {
+        writeln!(out, "content hash: {}", hash)?;
+        writeln!(out, "object: {}", compose_object_path(&hash, "")?.display())?;
+    } else if entry.is_file() {
+        writeln!(out, "content hash: chunked")?;
+    }
+    if entry.is_nested_catalog_mountpoint() {
+        writeln!(out, "nested catalog: mountpoint")?;
+    }
+    if entry.is_nested_catalog_root() {
+        writeln!(out, "nested catalog: root")?;
+    }
+    Ok(())
+}
+
+fn write_stat(entry: &DirectoryEntry, path: &str, out: &mut dyn Write) -> CvmfsResult<()> {
+    writeln!(out, "path: {}", path)?;
+    writeln!(out, "type: {}", kind(entry))?;
+    writeln!(out, "size: {}", entry.size)?;
+    writeln!(
+        out,
+        "mode: {} ({:04o})",
+        mode_string(entry),
+        entry.mode & 0o7777
+    )?;
+    writeln!(out, "modified: {}", format_time(entry.mtime))?;
+    writeln!(out, "links: {}", entry.nlink())?;
+    writeln!(out, "inode: {}", entry.inode())?;
+    if let Some(SpecialKind::CharDevice | SpecialKind::BlockDevice) = entry.special_kind() {
+        writeln!(out, "device: {}", entry.rdev)?;
+    }
+    if let Some(target) = &entry.symlink {
+        writeln!(out, "symlink: {}", target)?;
+    }
+    Ok(())
+}
+
+fn kind(entry: &DirectoryEntry) -> &'static str {
+    if entry.is_directory() {
+        "directory"
+    } else if entry.is_symlink() {
+        "symlink"
+    } else {
+        match entry.special_kind() {
+            Some(SpecialKind::CharDevice) => "character device",
+            Some(SpecialKind::BlockDevice) => "block device",
+            Some(SpecialKind::NamedPipe) => "fifo",
+            Some(SpecialKind::Socket) => "socket",
+            None => "file",
+        }
+    }
+}
+
+/// Permissions of an entry as `ls -l` prints them, e.g. `drwxr-xr-x`
+pub fn mode_string(entry: &DirectoryEntry) -> String {
+    let kind = if entry.is_directory() {
+        'd'
+    } else if entry.is_symlink() {
+        'l'
+    } else {
+        match entry.special_kind() {
+            Some(SpecialKind::CharDevice) => 'c',
+            Some(SpecialKind::BlockDevice) => 'b',
+            Some(SpecialKind::NamedPipe) => 'p',
+            Some(SpecialKind::Socket) => 's',
+            None => '-',
+        }
+    };
+    std::iter::once(kind)
+        .chain((0..9).rev().map(|bit| match entry.mode & (1 << bit) {
+            0 => '-',
+            _ => ['x', 'w', 'r'][bit % 3],
+        }))
+        .collect()
+}
+
+fn format_time(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .map_or_else(|| timestamp.to_string(), |time| time.to_rfc3339())
+}
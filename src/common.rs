@@ -1,8 +1,12 @@
+use std::borrow::Cow;
 use std::fmt::Debug;
 use std::fs::File;
 use std::io::{ErrorKind, Read, Seek, SeekFrom};
 use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::fs::FileExt;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
 
 use crate::directory_entry::{Chunk, PathHash};
 use crate::fetcher::Fetcher;
@@ -14,11 +18,51 @@ pub const WHITELIST_NAME: &str = ".cvmfswhitelist";
 pub const MANIFEST_NAME: &str = ".cvmfspublished";
 pub const LAST_REPLICATION_NAME: &str = ".cvmfs_last_snapshot";
 pub const REPLICATING_NAME: &str = ".cvmfs_is_snapshotting";
+/// Default tolerance, in seconds, between the local clock and the server timestamps
+pub const DEFAULT_CLOCK_SKEW_TOLERANCE: i64 = 300;
 
 pub type CvmfsResult<R> = Result<R, CvmfsError>;
-pub trait FileLike: Debug + Read + Seek + AsRawFd + Send + Sync {}
+pub trait FileLike: Debug + Read + Seek + AsRawFd + Send + Sync {
+    /// Reads from an absolute position without moving the cursor, filling the
+    /// buffer unless the end of the file is reached.
+    /// Returns None for files that only support seeking.
+    fn read_at(&self, _buf: &mut [u8], _offset: u64) -> Option<std::io::Result<usize>> {
+        None
+    }
+}
+
+impl FileLike for File {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Option<std::io::Result<usize>> {
+        Some(read_fully_at(self, buf, offset))
+    }
+}
+
+/// Positional read retrying until the buffer is full or the end of the file
+fn read_fully_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    let mut total = 0;
+    while total < buf.len() {
+        match FileExt::read_at(file, &mut buf[total..], offset + total as u64) {
+            Ok(0) => break,
+            Ok(n) => total += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(total)
+}
+
+/// Chunks downloaded in the background ahead of sequential reads
+pub const DEFAULT_CHUNK_READ_AHEAD: usize = 4;
 
-impl FileLike for File {}
+/// Part of a read of a chunked file served by one of its chunks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChunkSpan {
+    /// Index of the chunk, in offset order
+    pub index: usize,
+    /// Position of the span inside the chunk
+    pub offset: u64,
+    pub len: usize,
+}
 
 #[derive(Debug)]
 pub struct ChunkedFile {
@@ -26,47 +70,118 @@ pub struct ChunkedFile {
     chunks: Vec<(String, Chunk)>,
     position: u64,
     fetcher: Fetcher,
+    read_ahead: usize,
+    /// Position the last read ended at, telling sequential reads apart
+    last_read_end: u64,
+    /// Chunks before this index were already handed to the prefetcher
+    prefetched_until: usize,
 }
 
 impl ChunkedFile {
-    pub(crate) fn new(chunks: Vec<(String, Chunk)>, size: u64, fetcher: Fetcher) -> Self {
+    /// Creates a file out of its chunks, given as pairs of object path and chunk
+    pub fn new(mut chunks: Vec<(String, Chunk)>, size: u64, fetcher: Fetcher) -> Self {
+        chunks.sort_by_key(|(_, chunk)| chunk.offset);
         Self {
             chunks,
             position: 0,
             size,
             fetcher,
+            read_ahead: DEFAULT_CHUNK_READ_AHEAD,
+            last_read_end: 0,
+            prefetched_until: 0,
+        }
+    }
+
+    /// Same file, prefetching up to `chunks` chunks ahead of sequential
+    /// reads. Read-ahead is disabled with 0.
+    pub fn with_read_ahead(mut self, chunks: usize) -> Self {
+        self.read_ahead = chunks;
+        self
+    }
+
+    /// Spans of the chunks covering `len` bytes from the current position, in
+    /// order. They stop at the end of the file and at a gap between chunks.
+    pub fn spans(&self, len: usize) -> Vec<ChunkSpan> {
+        let end = self.size.min(self.position.saturating_add(len as u64));
+        let mut position = self.position;
+        let mut index = self
+            .chunks
+            .partition_point(|(_, chunk)| chunk.offset + chunk.size <= position);
+        let mut spans = Vec::new();
+        while position < end {
+            let Some((_, chunk)) = self.chunks.get(index) else {
+                break;
+            };
+            if chunk.offset > position {
+                break;
+            }
+            let offset = position - chunk.offset;
+            let len = (chunk.size - offset).min(end - position);
+            if len > 0 {
+                spans.push(ChunkSpan {
+                    index,
+                    offset,
+                    len: len as usize,
+                });
+            }
+            position += len;
+            index += 1;
         }
+        spans
+    }
+
+    /// Starts downloading in the background the chunks following the one
+    /// being read, so that streaming a cold file does not wait for each chunk
+    /// in turn. Chunks being downloaded are awaited rather than fetched twice.
+    fn read_ahead(&mut self, index: usize) {
+        let start = (index + 1).max(self.prefetched_until);
+        let end = (index + 1 + self.read_ahead).min(self.chunks.len());
+        if start >= end {
+            return;
+        }
+        self.prefetched_until = end;
+        let paths: Vec<String> = self.chunks[start..end]
+            .iter()
+            .map(|(path, _)| path.clone())
+            .filter(|path| self.fetcher.cache.get(path).is_none())
+            .collect();
+        if paths.is_empty() {
+            return;
+        }
+        let fetcher = self.fetcher.clone();
+        thread::spawn(move || fetcher.prefetch_with_concurrency(&paths, paths.len()));
     }
 }
 
+/// Only the chunks covering the requested range are fetched, so reading the
+/// beginning of a huge file does not wait for the rest of it to be downloaded.
+/// Sequential reads also prefetch the following chunks.
 impl Read for ChunkedFile {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let sequential = self.position == self.last_read_end;
         let mut currently_read = 0;
-        let mut index = self.chunks.iter().position(|(_, chunk)| {
-            chunk.offset >= self.position && chunk.offset + chunk.size < self.position
-        }).unwrap_or_else(|| usize::MAX);
-        while currently_read < buf.len() && index < self.chunks.len() {
-            let (path, chunk) = &self.chunks[index];
-            let chunk_position = self.position - chunk.offset;
+        for span in self.spans(buf.len()) {
+            if sequential && self.read_ahead > 0 {
+                self.read_ahead(span.index);
+            }
             let local_path = self
                 .fetcher
-                .retrieve_file(path.as_str())
+                .retrieve_file(&self.chunks[span.index].0)
                 .map_err(|_| ErrorKind::Unsupported)?;
-            let mut file = File::open(local_path).map_err(|_| ErrorKind::NotFound)?;
-            file.seek(SeekFrom::Start(chunk_position))
-                .map_err(|_| ErrorKind::NotSeekable)?;
-            let mut chunk_bytes_read = 0;
-            while chunk_bytes_read < buf.len() {
-                let bytes_read = file.read(buf)?;
-                if bytes_read == 0 {
-                    break;
-                }
-                chunk_bytes_read += bytes_read;
+            let file = File::open(local_path).map_err(|_| ErrorKind::NotFound)?;
+            let bytes_read = read_fully_at(
+                &file,
+                &mut buf[currently_read..currently_read + span.len],
+                span.offset,
+            )?;
+            currently_read += bytes_read;
+            self.position += bytes_read as u64;
+            // a chunk shorter than the catalog says ends the read
+            if bytes_read < span.len {
+                break;
             }
-            currently_read += chunk_bytes_read;
-            index += 1;
         }
-        self.position += currently_read as u64;
+        self.last_read_end = self.position;
         Ok(currently_read)
     }
 }
@@ -92,17 +207,78 @@ impl AsRawFd for ChunkedFile {
             .chunks
             .iter()
             .fold(String::new(), |mut acc, (_, chunk)| {
-                acc.extend(chunk.content_hash.chars());
+                acc.push_str(&chunk.content_hash);
                 acc
             });
-        let hash = md5::compute(hash_concat.as_bytes()).0;
-        let (int_bytes, _) = hash.as_slice().split_at(size_of::<u64>());
-        u64::from_le_bytes(int_bytes.try_into().expect("Casting to u64 should work")) as RawFd
+        synthetic_fd(&hash_concat)
     }
 }
 
 impl FileLike for ChunkedFile {}
 
+/// Handle derived from an identifier, for files not backed by a descriptor
+fn synthetic_fd(id: &str) -> RawFd {
+    let hash = md5::compute(id.as_bytes()).0;
+    let (int_bytes, _) = hash.as_slice().split_at(size_of::<u64>());
+    u64::from_le_bytes(int_bytes.try_into().expect("Casting to u64 should work")) as RawFd
+}
+
+/// Object served from memory, e.g. while it is still being written to the cache
+#[derive(Debug)]
+pub struct MemoryFile {
+    name: String,
+    content: Arc<[u8]>,
+    position: u64,
+}
+
+impl MemoryFile {
+    pub fn new(name: &str, content: Arc<[u8]>) -> Self {
+        Self {
+            name: name.into(),
+            content,
+            position: 0,
+        }
+    }
+}
+
+impl Read for MemoryFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let bytes_read = FileLike::read_at(self, buf, self.position).unwrap_or(Ok(0))?;
+        self.position += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+impl Seek for MemoryFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position: i64 = match pos {
+            SeekFrom::Start(p) => p as i64,
+            SeekFrom::End(p) => self.content.len() as i64 + p,
+            SeekFrom::Current(p) => self.position as i64 + p,
+        };
+        if position < 0 {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        self.position = position as u64;
+        Ok(self.position)
+    }
+}
+
+impl AsRawFd for MemoryFile {
+    fn as_raw_fd(&self) -> RawFd {
+        synthetic_fd(&self.name)
+    }
+}
+
+impl FileLike for MemoryFile {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Option<std::io::Result<usize>> {
+        let start = (offset as usize).min(self.content.len());
+        let bytes_read = buf.len().min(self.content.len() - start);
+        buf[..bytes_read].copy_from_slice(&self.content[start..start + bytes_read]);
+        Some(Ok(bytes_read))
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, thiserror::Error)]
 pub enum CvmfsError {
     #[error("Invalid Certificate")]
@@ -139,6 +315,59 @@ pub enum CvmfsError {
     Generic(String),
     #[error("The path is not a file")]
     NotAFile,
+    #[error("Invalid mount point: {0}")]
+    InvalidMountPoint(String),
+    #[error("Mount point already in use: {0}")]
+    MountPointBusy(String),
+    #[error("Mount not found: {0}")]
+    MountNotFound(String),
+    #[error("Revision rollback detected: {0}")]
+    RevisionRollback(String),
+    #[error("The repository whitelist has expired")]
+    WhitelistExpired,
+    #[error("No master key found for {0}")]
+    MasterKeyNotFound(String),
+    #[error("Invalid whitelist signature")]
+    InvalidWhitelistSignature,
+    #[error("Invalid manifest signature")]
+    InvalidManifestSignature,
+    #[error("The local clock appears to be wrong: {0}")]
+    ClockSkew(String),
+    #[error("Content hash mismatch for {0}")]
+    ContentHashMismatch(String),
+    #[error("Invalid configuration: {0}")]
+    InvalidConfiguration(String),
+    #[error("Server unreachable: {0}")]
+    Unreachable(String),
+    #[error("Not available offline: {0}")]
+    Offline(String),
+    #[error("Certificate not listed in the whitelist: {0}")]
+    UntrustedCertificate(String),
+    #[error("Invalid manifest: {0}")]
+    InvalidManifest(String),
+    #[error("Repository name mismatch: {0}")]
+    RepositoryNameMismatch(String),
+    #[error("Unsupported history database schema: {0}")]
+    UnsupportedHistorySchema(String),
+    #[error("Invalid object hash: {0:?}")]
+    InvalidObjectHash(String),
+    #[error("Missing content hash for {0}")]
+    MissingContentHash(String),
+    #[error("No external url configured for {0}")]
+    MissingExternalUrl(String),
+    #[error("Invalid GeoAPI reply: {0:?}")]
+    InvalidGeoApiReply(String),
+    #[error("Corrupt chunk list: {0}")]
+    CorruptChunks(String),
+    #[error("Too many levels of symbolic links: {0}")]
+    SymlinkLoop(String),
+}
+
+impl CvmfsError {
+    /// Failures to reach the server, as opposed to errors in its answer
+    pub fn is_unreachable(&self) -> bool {
+        matches!(self, CvmfsError::Unreachable(_) | CvmfsError::Offline(_))
+    }
 }
 
 impl From<String> for CvmfsError {
@@ -154,13 +383,22 @@ impl From<&str> for CvmfsError {
 }
 
 impl From<CvmfsError> for i32 {
-    fn from(_: CvmfsError) -> Self {
-        libc::ENOSYS
+    fn from(e: CvmfsError) -> Self {
+        match e {
+            CvmfsError::FileNotFound => libc::ENOENT,
+            // integrity failures, unreachable or malformed servers and files
+            // with no way to download them surface as I/O errors, as in the
+            // official client
+            _ => libc::EIO,
+        }
     }
 }
 
 impl From<reqwest::Error> for CvmfsError {
     fn from(e: reqwest::Error) -> Self {
+        if e.is_connect() || e.is_timeout() {
+            return CvmfsError::Unreachable(format!("{:?}", e));
+        }
         CvmfsError::IO(format!("{:?}", e))
     }
 }
@@ -183,14 +421,40 @@ pub fn canonicalize_path(path: &str) -> PathBuf {
         .unwrap_or(PathBuf::from(path))
 }
 
+/// Lexically normalizes a repository path, removing empty and `.` components
+/// and the trailing slash. Paths already normalized are borrowed.
+pub fn normalize_path(path: &str) -> Cow<'_, str> {
+    let is_normal = !path.contains("//")
+        && !path.contains("/./")
+        && !path.ends_with("/.")
+        && (path.len() <= 1 || !path.ends_with('/'));
+    if is_normal {
+        return Cow::Borrowed(path);
+    }
+    let mut normalized = String::with_capacity(path.len());
+    for component in path.split('/').filter(|c| !c.is_empty() && *c != ".") {
+        normalized.push('/');
+        normalized.push_str(component);
+    }
+    if normalized.is_empty() && path.starts_with('/') {
+        normalized.push('/');
+    }
+    Cow::Owned(normalized)
+}
+
+/// MD5 digest of a path, as used to index the catalogs
+pub fn path_md5(path: &str) -> [u8; 16] {
+    md5::compute(path.as_bytes()).0
+}
+
 pub fn split_md5(md5_digest: &[u8; 16]) -> PathHash {
     let mut hi = 0;
     let mut lo = 0;
-    for i in 0..8 {
-        lo |= (md5_digest[i] as i64) << (i * 8);
+    for (i, byte) in md5_digest[..8].iter().enumerate() {
+        lo |= (*byte as i64) << (i * 8);
     }
-    for i in 8..16 {
-        hi |= (md5_digest[i] as i64) << ((i - 8) * 8)
+    for (i, byte) in md5_digest[8..].iter().enumerate() {
+        hi |= (*byte as i64) << (i * 8)
     }
     PathHash {
         hash1: lo,
@@ -198,9 +462,31 @@ pub fn split_md5(md5_digest: &[u8; 16]) -> PathHash {
     }
 }
 
-pub fn compose_object_path(object_hash: &str, hash_suffix: &str) -> PathBuf {
-    let (first, second) = object_hash.split_at(2);
-    Path::new("data")
+/// Quotes and escapes a string as a JSON string literal
+pub fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+pub fn compose_object_path(object_hash: &str, hash_suffix: &str) -> CvmfsResult<PathBuf> {
+    let (first, second) = object_hash
+        .split_at_checked(2)
+        .filter(|(first, _)| first.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| CvmfsError::InvalidObjectHash(object_hash.into()))?;
+    Ok(Path::new("data")
         .join(first)
-        .join(second.to_owned() + hash_suffix)
+        .join(second.to_owned() + hash_suffix))
 }
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use crate::autofs::{
+    CACHE_BASE_VARIABLE, DEFAULT_DOMAIN_VARIABLE, EXTERNAL_URL_VARIABLE, REPOSITORY_TAG_VARIABLE,
+    SERVER_URL_VARIABLE, USE_GEOAPI_VARIABLE,
+};
+use crate::common::{CvmfsError, CvmfsResult, REPO_CONFIG_PATH, SERVER_CONFIG_NAME};
+use crate::mount_config::{derive_fqrn, MountConfig, DEFAULT_DOMAIN};
+use crate::proxy::{ProxyConfig, HTTP_PROXY_VARIABLE};
+
+/// Directory of the client configuration, as in the reference client
+pub const CONFIG_DIRECTORY: &str = "/etc/cvmfs";
+/// Size limit of the cache in megabytes, unlimited when negative
+pub const QUOTA_LIMIT_VARIABLE: &str = "CVMFS_QUOTA_LIMIT";
+pub const KEYS_DIR_VARIABLE: &str = "CVMFS_KEYS_DIR";
+/// Url of the stratum-0, in the `server.conf` of the repositories published
+/// from this machine
+pub const STRATUM0_VARIABLE: &str = "CVMFS_STRATUM0";
+
+/// Settings of the reference client configuration files: `NAME=value` lines,
+/// where later files override the earlier ones
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientConfig {
+    values: HashMap<String, String>,
+}
+
+impl ClientConfig {
+    /// Parses the contents of a configuration file. Comments, blank lines and
+    /// anything but assignments are skipped, and the values may be quoted.
+    pub fn parse(content: &str) -> Self {
+        let values = content
+            .lines()
+            .filter_map(|line| {
+                let line = line.trim();
+                let line = line.strip_prefix("export ").unwrap_or(line);
+                let (name, value) = line.split_once('=')?;
+                let name = name.trim();
+                if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                    return None;
+                }
+                let value = value.split(" #").next().unwrap_or_default().trim();
+                let value = ['"', '\'']
+                    .iter()
+                    .find_map(|quote| value.strip_prefix(*quote)?.strip_suffix(*quote))
+                    .unwrap_or(value);
+                Some((name.to_string(), value.to_string()))
+            })
+            .collect();
+        Self { values }
+    }
+
+    /// Loads a configuration file given by the user, which must exist
+    pub fn load_file(path: &Path) -> CvmfsResult<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Loads the system configuration of a repository, see `load`
+    pub fn load_system(name: &str) -> CvmfsResult<Self> {
+        Self::load(
+            Path::new(CONFIG_DIRECTORY),
+            Path::new(REPO_CONFIG_PATH),
+            name,
+        )
+    }
+
+    /// Loads the configuration of a repository from its layers, each one
+    /// overriding the previous ones: the `server.conf` of the repository if
+    /// published from this machine, `default.conf`, `default.local`, then the
+    /// `.conf` and `.local` files of its domain in `domain.d` and of the
+    /// repository in `config.d`. Missing files are skipped.
+    pub fn load(directory: &Path, repositories_directory: &Path, name: &str) -> CvmfsResult<Self> {
+        let mut defaults = Self::default();
+        defaults.merge_file(&directory.join("default.conf"))?;
+        defaults.merge_file(&directory.join("default.local"))?;
+        let fqrn = derive_fqrn(
+            name,
+            defaults
+                .get(DEFAULT_DOMAIN_VARIABLE)
+                .unwrap_or(DEFAULT_DOMAIN),
+        );
+        let mut config = Self::default();
+        config.merge_file(&repositories_directory.join(&fqrn).join(SERVER_CONFIG_NAME))?;
+        config.values.extend(defaults.values);
+        if let Some((_, domain)) = fqrn.split_once('.') {
+            config.merge_layer(&directory.join("domain.d"), domain)?;
+        }
+        config.merge_layer(&directory.join("config.d"), &fqrn)?;
+        Ok(config)
+    }
+
+    /// Overrides the settings with the ones of `<name>.conf` and `<name>.local`
+    fn merge_layer(&mut self, directory: &Path, name: &str) -> CvmfsResult<()> {
+        self.merge_file(&directory.join(format!("{}.conf", name)))?;
+        self.merge_file(&directory.join(format!("{}.local", name)))?;
+        Ok(())
+    }
+
+    /// Overrides the settings with the ones of a file, if it exists
+    fn merge_file(&mut self, path: &Path) -> CvmfsResult<()> {
+        match fs::read_to_string(path) {
+            Ok(content) => {
+                log::debug!("Loading the configuration of {:?}", path);
+                self.values.extend(Self::parse(&content).values);
+                Ok(())
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    /// Servers of the repository, the stratum-0 when no stratum-1 is set
+    pub fn server_url(&self) -> Option<&str> {
+        self.get(SERVER_URL_VARIABLE)
+            .or_else(|| self.get(STRATUM0_VARIABLE))
+            .filter(|url| !url.is_empty())
+    }
+
+    /// Applies the settings to a mount. The servers are not, since the
+    /// repository url of a mount is always given.
+    pub fn apply(&self, config: &mut MountConfig) -> CvmfsResult<()> {
+        if let Some(domain) = self.get(DEFAULT_DOMAIN_VARIABLE) {
+            config.default_domain = domain.into();
+        }
+        if let Some(proxy) = self.get(HTTP_PROXY_VARIABLE) {
+            config.http_proxy = Some(ProxyConfig::parse(proxy)?);
+        }
+        if let Some(cache_directory) = self.get(CACHE_BASE_VARIABLE) {
+            config.cache_directory = cache_directory.into();
+        }
+        if let Some(quota) = self.get(QUOTA_LIMIT_VARIABLE) {
+            let megabytes: i64 = parse_value(QUOTA_LIMIT_VARIABLE, quota)?;
+            config.cache_quota = u64::try_from(megabytes)
+                .ok()
+                .map(|megabytes| megabytes << 20);
+        }
+        if let Some(tag) = self.get(REPOSITORY_TAG_VARIABLE) {
+            config.tag = Some(tag.into());
+        }
+        if let Some(urls) = self.get(EXTERNAL_URL_VARIABLE) {
+            config.external_url = Some(urls.into());
+        }
+        if let Some(keys_directory) = self.get(KEYS_DIR_VARIABLE) {
+            config.keys_directory = PathBuf::from(keys_directory);
+        }
+        if let Some(value) = self.get(USE_GEOAPI_VARIABLE) {
+            config.use_geo_api = is_enabled(value);
+        }
+        // the variant symlinks see every parameter, as in the reference client
+        for (name, value) in &self.values {
+            config.symlink_variables.set(name, value);
+        }
+        Ok(())
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: &str) -> CvmfsResult<T> {
+    value.parse().map_err(|_| {
+        CvmfsError::InvalidConfiguration(format!("invalid value for {}: {}", name, value))
+    })
+}
+
+/// Whether a boolean setting is switched on, as `yes`, `on` or `1`
+pub fn is_enabled(value: &str) -> bool {
+    ["yes", "on", "1", "true"]
+        .iter()
+        .any(|enabled| value.eq_ignore_ascii_case(enabled))
+}
@@ -0,0 +1,177 @@
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use crate::common::{CvmfsError, CvmfsResult};
+use crate::directory_entry::DirectoryEntry;
+use crate::repository::Repository;
+
+pub const DEFAULT_REGISTRY: &str = "registry.hub.docker.com";
+pub const DEFAULT_TAG: &str = "latest";
+pub const FLAT_ROOT: &str = "/.flat";
+pub const LAYERS_ROOT: &str = "/.layers";
+pub const PODMAN_STORE_ROOT: &str = "/.podman";
+const MOUNT_PREFIX: &str = "/cvmfs";
+
+/// Reference to a container image, as in `registry/namespace/name:tag`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageReference {
+    pub registry: String,
+    pub repository: String,
+    pub tag: Option<String>,
+    pub digest: Option<String>,
+}
+
+impl ImageReference {
+    /// Parses an image reference filling in the same defaults as docker does
+    pub fn parse(reference: &str) -> CvmfsResult<Self> {
+        if reference.is_empty() || reference.contains(char::is_whitespace) {
+            return Err(CvmfsError::ParseError);
+        }
+        let (name, digest) = match reference.split_once('@') {
+            Some((name, digest)) => (name, Some(digest.to_string())),
+            None => (reference, None),
+        };
+        let (name, tag) = match name.rsplit_once(':') {
+            Some((image, tag)) if !tag.contains('/') => (image, Some(tag.to_string())),
+            _ => (name, None),
+        };
+        let (registry, repository) = match name.split_once('/') {
+            Some((host, rest))
+                if host.contains('.') || host.contains(':') || host == "localhost" =>
+            {
+                (host.to_string(), rest.to_string())
+            }
+            _ => (DEFAULT_REGISTRY.to_string(), name.to_string()),
+        };
+        let repository = if registry == DEFAULT_REGISTRY && !repository.contains('/') {
+            format!("library/{}", repository)
+        } else {
+            repository
+        };
+        if repository.is_empty() || repository.split('/').any(|part| part.is_empty()) {
+            return Err(CvmfsError::ParseError);
+        }
+        let tag = if tag.is_none() && digest.is_none() {
+            Some(DEFAULT_TAG.to_string())
+        } else {
+            tag
+        };
+        Ok(Self {
+            registry,
+            repository,
+            tag,
+            digest,
+        })
+    }
+
+    /// Path of the image entry in an unpacked repository
+    pub fn repository_path(&self) -> String {
+        let version = match (&self.tag, &self.digest) {
+            (_, Some(digest)) => format!("@{}", digest),
+            (Some(tag), None) => format!(":{}", tag),
+            (None, None) => format!(":{}", DEFAULT_TAG),
+        };
+        format!("/{}/{}{}", self.registry, self.repository, version)
+    }
+}
+
+impl Display for ImageReference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.repository_path()[1..])
+    }
+}
+
+/// Exposes the layout of unpacked container image repositories, such as
+/// unpacked.cern.ch, to container runtimes.
+/// Images are published as symlinks named after the image reference pointing to
+/// a flattened root file system under `/.flat`, while the individual layers are
+/// kept under `/.layers` and a podman additional image store under `/.podman`.
+#[derive(Debug)]
+pub struct UnpackedRepository<'a> {
+    repository: &'a mut Repository,
+}
+
+impl<'a> UnpackedRepository<'a> {
+    pub fn new(repository: &'a mut Repository) -> Self {
+        Self { repository }
+    }
+
+    /// Resolves an image reference to the path of its flattened root file system
+    pub fn resolve_image(&mut self, image: &ImageReference) -> CvmfsResult<String> {
+        let image_path = image.repository_path();
+        let dirent = self.repository.lookup(&image_path)?;
+        if dirent.is_directory() {
+            return Ok(image_path);
+        }
+        if !dirent.is_symlink() {
+            return Err(CvmfsError::FileNotFound);
+        }
+        let target = dirent.symlink.ok_or(CvmfsError::FileNotFound)?;
+        let resolved = self.strip_mount_prefix(&target, &image_path);
+        if !self.repository.lookup(&resolved)?.is_directory() {
+            return Err(CvmfsError::FileNotFound);
+        }
+        Ok(resolved)
+    }
+
+    /// Lists the published versions (tags and digests) of an image
+    pub fn list_tags(&mut self, image: &ImageReference) -> CvmfsResult<Vec<String>> {
+        let image_path = image.repository_path();
+        let (parent, name) = image_path
+            .rsplit_once('/')
+            .ok_or(CvmfsError::FileNotFound)?;
+        let base_name = name
+            .split_once([':', '@'])
+            .map(|(base_name, _)| base_name)
+            .unwrap_or(name);
+        let entries = self.repository.list_directory(parent)?;
+        Ok(entries
+            .into_iter()
+            .filter_map(|dirent| {
+                dirent
+                    .name
+                    .strip_prefix(base_name)
+                    .filter(|version| version.starts_with([':', '@']))
+                    .map(|version| version[1..].to_string())
+            })
+            .collect())
+    }
+
+    /// Path of an unpacked layer, usable as an overlay lower directory
+    pub fn layer_path(&mut self, digest: &str) -> CvmfsResult<String> {
+        let digest = digest.strip_prefix("sha256:").unwrap_or(digest);
+        if digest.len() < 2 {
+            return Err(CvmfsError::ParseError);
+        }
+        let path = format!("{}/{}/{}/layerfs", LAYERS_ROOT, &digest[..2], digest);
+        self.expect_directory(&path)?;
+        Ok(path)
+    }
+
+    /// Root of the podman/containers-storage additional image store
+    pub fn additional_image_store(&mut self) -> CvmfsResult<String> {
+        self.expect_directory(PODMAN_STORE_ROOT)?;
+        Ok(PODMAN_STORE_ROOT.to_string())
+    }
+
+    fn expect_directory(&mut self, path: &str) -> CvmfsResult<DirectoryEntry> {
+        let dirent = self.repository.lookup(path)?;
+        if !dirent.is_directory() {
+            return Err(CvmfsError::FileNotFound);
+        }
+        Ok(dirent)
+    }
+
+    /// Symlinks are published with absolute `/cvmfs/<fqrn>` targets
+    fn strip_mount_prefix(&self, target: &str, link_path: &str) -> String {
+        let mount_point = format!("{}/{}", MOUNT_PREFIX, self.repository.fqrn);
+        if let Some(relative) = target.strip_prefix(&mount_point) {
+            return relative.to_string();
+        }
+        if target.starts_with('/') {
+            return target.to_string();
+        }
+        let parent = Path::new(link_path).parent().unwrap_or(Path::new("/"));
+        parent.join(target).to_string_lossy().into_owned()
+    }
+}
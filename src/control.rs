@@ -0,0 +1,153 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+
+use crate::analytics::DEFAULT_TOP_PATHS;
+use crate::common::{CvmfsError, CvmfsResult};
+use crate::repository::Repository;
+
+pub const CONTROL_SOCKET_PREFIX: &str = "cvmfs_io.";
+
+/// Commands accepted by the control socket, one per line
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlCommand {
+    /// Pins the mount to a tag, persisting it across remounts
+    Pin(String),
+    /// Goes back to following the latest revision
+    Unpin,
+    /// Reports the tag being served
+    Tag,
+    /// Reports whether the cache failed over to its fallback directory
+    Cache,
+    /// Reports the revision served, whether it is stale or frozen, and the
+    /// certificate rotations seen
+    Status,
+    /// Reports the usage of the mount as JSON, with the given number of most
+    /// opened paths
+    Analytics(usize),
+}
+
+impl ControlCommand {
+    pub fn parse(line: &str) -> CvmfsResult<Self> {
+        let mut words = line.split_whitespace();
+        let command = match (words.next(), words.next()) {
+            (Some("pin"), Some(tag)) => ControlCommand::Pin(tag.into()),
+            (Some("unpin"), None) => ControlCommand::Unpin,
+            (Some("tag"), None) => ControlCommand::Tag,
+            (Some("cache"), None) => ControlCommand::Cache,
+            (Some("status"), None) => ControlCommand::Status,
+            (Some("analytics"), top) => ControlCommand::Analytics(
+                top.map_or(Ok(DEFAULT_TOP_PATHS), str::parse)
+                    .map_err(|_| CvmfsError::ParseError)?,
+            ),
+            _ => return Err(CvmfsError::ParseError),
+        };
+        if words.next().is_some() {
+            return Err(CvmfsError::ParseError);
+        }
+        Ok(command)
+    }
+
+    /// Runs the command against the repository, returning the reply line
+    pub fn execute(&self, repository: &mut Repository) -> CvmfsResult<String> {
+        match self {
+            ControlCommand::Pin(tag) => {
+                repository.pin_tag(tag)?;
+                Ok("OK".into())
+            }
+            ControlCommand::Unpin => {
+                repository.unpin_tag()?;
+                Ok("OK".into())
+            }
+            ControlCommand::Tag => {
+                let tag = repository.current_tag()?;
+                Ok(match repository.pinned_tag() {
+                    Some(_) => format!("{} (pinned)", tag.name),
+                    None => tag.name.clone(),
+                })
+            }
+            ControlCommand::Cache => {
+                let status = repository.cache_failover_status();
+                let mut reply = format!(
+                    "failover={} primary_errors={} fallback_writes={}",
+                    status.active, status.primary_errors, status.fallback_writes
+                );
+                if let Some(quota) = repository.cache().quota() {
+                    reply += &format!(" quota_used={} quota_limit={}", quota.size(), quota.limit());
+                }
+                Ok(reply)
+            }
+            ControlCommand::Status => Ok(format!(
+                "revision={} stale={} offline_since={} degraded={} certificate={} certificate_rotations={}",
+                repository.manifest.revision,
+                repository.is_stale(),
+                repository
+                    .offline_since()
+                    .map_or("none".into(), |since| since.to_rfc3339()),
+                repository.degraded,
+                repository.certificate_hash().unwrap_or("none"),
+                repository.certificate_rotations
+            )),
+            ControlCommand::Analytics(top) => Ok(repository
+                .analytics
+                .as_ref()
+                .ok_or_else(|| {
+                    CvmfsError::InvalidConfiguration("the analytics are not collected".into())
+                })?
+                .report(*top)?
+                .to_json()),
+        }
+    }
+}
+
+/// Path of the control socket of a repository, inside its cache directory
+pub fn socket_path(cache_directory: &str, fqrn: &str) -> PathBuf {
+    Path::new(cache_directory).join(format!("{}{}", CONTROL_SOCKET_PREFIX, fqrn))
+}
+
+/// Listens for control commands on a unix socket in a background thread
+pub fn spawn(path: &Path, repository: Arc<RwLock<Repository>>) -> CvmfsResult<JoinHandle<()>> {
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(spawn_listener(UnixListener::bind(path)?, repository))
+}
+
+/// Serves control commands on an already listening socket, such as the one
+/// handed over by systemd socket activation
+pub fn spawn_listener(
+    listener: UnixListener,
+    repository: Arc<RwLock<Repository>>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(e) = serve(stream, &repository) {
+                        log::warn!("Control socket connection failed: {:?}", e);
+                    }
+                }
+                Err(e) => log::warn!("Control socket error: {:?}", e),
+            }
+        }
+    })
+}
+
+fn serve(stream: UnixStream, repository: &RwLock<Repository>) -> CvmfsResult<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        let reply = ControlCommand::parse(&line).and_then(|command| {
+            let mut repository = repository.write().map_err(|_| CvmfsError::Sync)?;
+            command.execute(&mut repository)
+        });
+        match reply {
+            Ok(reply) => writeln!(writer, "{}", reply)?,
+            Err(e) => writeln!(writer, "ERROR {}", e)?,
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::access_log::rotate_file;
+use crate::common::{CvmfsError, CvmfsResult};
+
+/// Rotated daemon log files kept by default
+pub const DEFAULT_LOG_ROTATIONS: usize = 3;
+/// Variable holding the log levels when none are configured, as with env_logger
+pub const LOG_LEVELS_VARIABLE: &str = "RUST_LOG";
+const CRATE_PREFIX: &str = "cvmfs::";
+
+/// Settings of the daemon log. Categories are the modules messages come from,
+/// with or without the crate prefix, such as `file_system` or `fetcher`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogConfig {
+    /// Level of the messages of the categories without their own level
+    pub level: LevelFilter,
+    /// Levels of single categories, overriding `level`
+    pub categories: Vec<(String, LevelFilter)>,
+    /// Messages written per second by each call site at most, the rest are
+    /// only counted
+    pub max_messages_per_second: Option<u32>,
+    /// File the messages are appended to, the standard error when `None`
+    pub file: Option<PathBuf>,
+    /// Size in bytes after which the log file is rotated
+    pub max_file_size: Option<u64>,
+    /// Rotated files kept, named `<file>.1` (the newest) to `<file>.<n>`
+    pub rotations: usize,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: LevelFilter::Error,
+            categories: Vec::new(),
+            max_messages_per_second: None,
+            file: None,
+            max_file_size: None,
+            rotations: DEFAULT_LOG_ROTATIONS,
+        }
+    }
+}
+
+impl LogConfig {
+    /// Configuration with the levels of `RUST_LOG`, if set and valid
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Ok(levels) = env::var(LOG_LEVELS_VARIABLE) {
+            if let Err(e) = config.set_levels(&levels) {
+                eprintln!("Ignoring {}: {}", LOG_LEVELS_VARIABLE, e);
+            }
+        }
+        config
+    }
+
+    /// Parses levels like `info,file_system=warn,fetcher=debug`
+    pub fn set_levels(&mut self, levels: &str) -> CvmfsResult<()> {
+        let parse = |level: &str| {
+            LevelFilter::from_str(level.trim()).map_err(|_| {
+                CvmfsError::InvalidConfiguration(format!("invalid log level: {}", level))
+            })
+        };
+        let mut categories = Vec::new();
+        for directive in levels.split(',').filter(|d| !d.trim().is_empty()) {
+            match directive.split_once('=') {
+                Some((category, level)) => {
+                    categories.push((category.trim().to_string(), parse(level)?))
+                }
+                None => self.level = parse(directive)?,
+            }
+        }
+        self.categories = categories;
+        Ok(())
+    }
+
+    /// Level of the messages of a target, given by its most specific category
+    pub fn level_of(&self, target: &str) -> LevelFilter {
+        let category = target.strip_prefix(CRATE_PREFIX).unwrap_or(target);
+        self.categories
+            .iter()
+            .filter(|(name, _)| {
+                [target, category].iter().any(|t| {
+                    t == name
+                        || t.strip_prefix(name.as_str())
+                            .is_some_and(|rest| rest.starts_with("::"))
+                })
+            })
+            .max_by_key(|(name, _)| name.len())
+            .map_or(self.level, |(_, level)| *level)
+    }
+
+    /// Most verbose level of any category
+    pub fn max_level(&self) -> LevelFilter {
+        self.categories
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.level, Ord::max)
+    }
+
+    /// Makes this the logger of the process
+    pub fn install(self) -> CvmfsResult<()> {
+        let max_level = self.max_level();
+        let logger: &'static DaemonLogger = Box::leak(Box::new(DaemonLogger::new(self)?));
+        log::set_logger(logger).map_err(|e| CvmfsError::Generic(e.to_string()))?;
+        log::set_max_level(max_level);
+        Ok(())
+    }
+}
+
+/// Messages of a call site in the current second
+#[derive(Debug)]
+struct SiteWindow {
+    start: Instant,
+    written: u32,
+    dropped: u64,
+}
+
+#[derive(Debug)]
+struct DaemonLogState {
+    file: Option<File>,
+    size: u64,
+    sites: HashMap<(String, u32), SiteWindow>,
+}
+
+/// Logger of the daemon, filtering the messages by category, rate limiting
+/// the repetitive ones, and rotating its file
+#[derive(Debug)]
+pub struct DaemonLogger {
+    config: LogConfig,
+    state: Mutex<DaemonLogState>,
+}
+
+impl DaemonLogger {
+    pub fn new(config: LogConfig) -> CvmfsResult<Self> {
+        let (file, size) = match &config.file {
+            Some(path) => {
+                let file = Self::open_file(path)?;
+                let size = file.metadata()?.len();
+                (Some(file), size)
+            }
+            None => (None, 0),
+        };
+        Ok(Self {
+            config,
+            state: Mutex::new(DaemonLogState {
+                file,
+                size,
+                sites: HashMap::new(),
+            }),
+        })
+    }
+
+    fn open_file(path: &Path) -> CvmfsResult<File> {
+        Ok(OpenOptions::new().create(true).append(true).open(path)?)
+    }
+
+    pub fn config(&self) -> &LogConfig {
+        &self.config
+    }
+
+    fn try_log(&self, record: &Record) -> CvmfsResult<()> {
+        let mut state = self.state.lock().map_err(|_| CvmfsError::Sync)?;
+        let time = Utc::now().to_rfc3339();
+        if let Some(limit) = self.config.max_messages_per_second {
+            let site = (
+                record.file().unwrap_or(record.target()).to_string(),
+                record.line().unwrap_or_default(),
+            );
+            let window = state.sites.entry(site.clone()).or_insert(SiteWindow {
+                start: Instant::now(),
+                written: 0,
+                dropped: 0,
+            });
+            let mut suppressed = 0;
+            if window.start.elapsed() >= Duration::from_secs(1) {
+                suppressed = window.dropped;
+                *window = SiteWindow {
+                    start: Instant::now(),
+                    written: 0,
+                    dropped: 0,
+                };
+            }
+            if window.written >= limit {
+                window.dropped += 1;
+                return Ok(());
+            }
+            window.written += 1;
+            if suppressed > 0 {
+                let line = format!(
+                    "[{} WARN {}] {} similar messages from {}:{} were suppressed",
+                    time,
+                    record.target(),
+                    suppressed,
+                    site.0,
+                    site.1
+                );
+                self.write_line(&mut state, &line)?;
+            }
+        }
+        let line = format!(
+            "[{} {} {}] {}",
+            time,
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        self.write_line(&mut state, &line)
+    }
+
+    fn write_line(&self, state: &mut DaemonLogState, line: &str) -> CvmfsResult<()> {
+        let Some(path) = &self.config.file else {
+            writeln!(io::stderr(), "{}", line)?;
+            return Ok(());
+        };
+        let length = line.len() as u64 + 1;
+        if self
+            .config
+            .max_file_size
+            .is_some_and(|max_size| state.size > 0 && state.size + length > max_size)
+        {
+            state.file = None;
+            rotate_file(path, self.config.rotations)?;
+            state.file = Some(Self::open_file(path)?);
+            state.size = 0;
+        }
+        let file = match &mut state.file {
+            Some(file) => file,
+            None => state.file.insert(Self::open_file(path)?),
+        };
+        writeln!(file, "{}", line)?;
+        state.size += length;
+        Ok(())
+    }
+}
+
+impl Log for DaemonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.config.level_of(metadata.target())
+    }
+
+    /// Failures go to the standard error, since logging them would recurse
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        if let Err(e) = self.try_log(record) {
+            eprintln!("Could not write the daemon log: {:?}", e);
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            if let Some(file) = &mut state.file {
+                let _ = file.flush();
+            }
+        }
+    }
+}
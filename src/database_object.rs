@@ -1,31 +1,135 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
-use rusqlite::{Connection, OpenFlags, Statement};
+use rusqlite::{ffi, Connection, OpenFlags, Statement};
 
 use crate::common::{CvmfsError, CvmfsResult};
 
+/// Where SQLite keeps its temporary tables and indices
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TempStore {
+    Default = 0,
+    File = 1,
+    Memory = 2,
+}
+
+/// SQLite settings applied to every database when it is opened
+#[derive(Debug, Clone, PartialEq)]
+pub struct SqliteTuning {
+    /// Page cache size per connection, in KiB
+    pub cache_size_kib: u32,
+    pub temp_store: TempStore,
+    /// Rejects any statement modifying the database
+    pub query_only: bool,
+}
+
+impl Default for SqliteTuning {
+    fn default() -> Self {
+        Self {
+            cache_size_kib: 8192,
+            temp_store: TempStore::Memory,
+            query_only: true,
+        }
+    }
+}
+
+/// Idle connections kept per database for concurrent queries
+pub const MAX_POOLED_CONNECTIONS: usize = 4;
+
 #[derive(Debug)]
 pub struct DatabaseObject {
     connection: Connection,
+    path: PathBuf,
+    tuning: SqliteTuning,
+    /// Extra read-only connections, so that several threads can query the same
+    /// database in parallel instead of serializing on a single connection
+    pool: Mutex<Vec<Connection>>,
 }
 
 unsafe impl Sync for DatabaseObject {}
 
 impl DatabaseObject {
     pub fn new(database_file: &str) -> CvmfsResult<Self> {
+        Self::with_tuning(database_file, &SqliteTuning::default())
+    }
+
+    pub fn with_tuning(database_file: &str, tuning: &SqliteTuning) -> CvmfsResult<Self> {
         let path = Path::new(database_file);
-        let connection = Self::open_database(path)?;
-        Ok(Self { connection })
+        let connection = Self::open_database(path, tuning)?;
+        Ok(Self {
+            connection,
+            path: path.into(),
+            tuning: tuning.clone(),
+            pool: Mutex::new(Vec::new()),
+        })
     }
 
-    fn open_database(path: &Path) -> CvmfsResult<Connection> {
+    fn open_database(path: &Path, tuning: &SqliteTuning) -> CvmfsResult<Connection> {
         let flags = OpenFlags::SQLITE_OPEN_READ_ONLY
             | OpenFlags::SQLITE_OPEN_NO_MUTEX
             | OpenFlags::SQLITE_OPEN_NO_MUTEX;
-        Ok(Connection::open_with_flags(path, flags)?)
+        let connection = Connection::open_with_flags(path, flags)?;
+        // negative cache sizes are expressed in KiB instead of pages
+        connection.pragma_update(None, "cache_size", -(tuning.cache_size_kib as i64))?;
+        connection.pragma_update(None, "temp_store", tuning.temp_store as i32)?;
+        connection.pragma_update(None, "query_only", tuning.query_only)?;
+        Ok(connection)
+    }
+
+    /// Runs a query on a pooled connection, opening a new one if all of them
+    /// are busy. Statements are cached per connection.
+    pub fn with_connection<R>(
+        &self,
+        f: impl FnOnce(&Connection) -> CvmfsResult<R>,
+    ) -> CvmfsResult<R> {
+        let pooled = self.pool.lock().map_err(|_| CvmfsError::Sync)?.pop();
+        let connection = match pooled {
+            Some(connection) => connection,
+            None => Self::open_database(&self.path, &self.tuning)?,
+        };
+        let result = f(&connection);
+        let mut pool = self.pool.lock().map_err(|_| CvmfsError::Sync)?;
+        if pool.len() < MAX_POOLED_CONNECTIONS {
+            pool.push(connection);
+        }
+        result
+    }
+
+    /// Number of idle connections in the pool
+    pub fn pooled_connections(&self) -> usize {
+        self.pool.lock().map(|pool| pool.len()).unwrap_or(0)
     }
 
-    pub fn create_prepared_statement(&self, sql: &str) -> CvmfsResult<Statement> {
+    /// Heap memory held by the page caches of the connections, in bytes
+    pub fn memory_used(&self) -> usize {
+        let pooled: usize = self
+            .pool
+            .lock()
+            .map(|pool| pool.iter().map(Self::cache_used).sum())
+            .unwrap_or(0);
+        Self::cache_used(&self.connection) + pooled
+    }
+
+    fn cache_used(connection: &Connection) -> usize {
+        let mut current = 0;
+        let mut highwater = 0;
+        let result = unsafe {
+            ffi::sqlite3_db_status(
+                connection.handle(),
+                ffi::SQLITE_DBSTATUS_CACHE_USED,
+                &mut current,
+                &mut highwater,
+                0,
+            )
+        };
+        if result == ffi::SQLITE_OK {
+            current as usize
+        } else {
+            0
+        }
+    }
+
+    pub fn create_prepared_statement(&self, sql: &str) -> CvmfsResult<Statement<'_>> {
         Ok(self.connection.prepare(sql)?)
     }
 
@@ -36,4 +140,20 @@ impl DatabaseObject {
             .collect::<Result<Vec<_>, _>>()
             .map_err(CvmfsError::from)
     }
+
+    pub fn has_table(&self, table: &str) -> CvmfsResult<bool> {
+        let mut statement = self.create_prepared_statement(
+            "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = ?;",
+        )?;
+        let count: u32 = statement.query_row([table], |row| row.get(0))?;
+        Ok(count > 0)
+    }
+
+    pub fn has_column(&self, table: &str, column: &str) -> CvmfsResult<bool> {
+        let mut statement = self.create_prepared_statement(
+            "SELECT count(*) FROM pragma_table_info(?) WHERE name = ?;",
+        )?;
+        let count: u32 = statement.query_row([table, column], |row| row.get(0))?;
+        Ok(count > 0)
+    }
 }
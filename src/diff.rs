@@ -0,0 +1,230 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+
+use crate::common::CvmfsResult;
+use crate::directory_entry::DirectoryEntry;
+use crate::repository::Repository;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+impl ChangeKind {
+    /// Letter of the change in the listings, as in `svn status`
+    pub fn symbol(&self) -> char {
+        match self {
+            ChangeKind::Added => 'A',
+            ChangeKind::Removed => 'R',
+            ChangeKind::Modified => 'M',
+        }
+    }
+}
+
+/// Entry differing between two revisions
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub kind: ChangeKind,
+    pub path: String,
+    /// Entry in the first revision, `None` when added
+    pub old: Option<DirectoryEntry>,
+    /// Entry in the second revision, `None` when removed
+    pub new: Option<DirectoryEntry>,
+    /// Attributes of a modified entry that changed, such as `size` or `chunks`
+    pub fields: Vec<&'static str>,
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.kind.symbol(), self.path)?;
+        if !self.fields.is_empty() {
+            write!(f, " ({})", self.fields.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares the trees of two revisions given by their root catalogs, in
+/// depth first order. Added and removed directories are reported along with
+/// all their contents, while nested catalogs left untouched are skipped
+/// without being read.
+pub fn diff_revisions(
+    repository: &Repository,
+    old_root_hash: &str,
+    new_root_hash: &str,
+) -> CvmfsResult<Vec<Change>> {
+    let differ = Differ {
+        repository,
+        old_root_hash,
+        new_root_hash,
+    };
+    let mut changes = Vec::new();
+    let old = repository.lookup_at(old_root_hash, "/")?;
+    let new = repository.lookup_at(new_root_hash, "/")?;
+    differ.compare("/", old, new, &mut changes)?;
+    Ok(changes)
+}
+
+struct Differ<'a> {
+    repository: &'a Repository,
+    old_root_hash: &'a str,
+    new_root_hash: &'a str,
+}
+
+impl Differ<'_> {
+    fn compare(
+        &self,
+        path: &str,
+        mut old: DirectoryEntry,
+        mut new: DirectoryEntry,
+        changes: &mut Vec<Change>,
+    ) -> CvmfsResult<()> {
+        if old.is_directory() != new.is_directory() {
+            self.subtree(ChangeKind::Removed, self.old_root_hash, path, changes)?;
+            return self.subtree(ChangeKind::Added, self.new_root_hash, path, changes);
+        }
+        let fields = self.changed_fields(path, &mut old, &mut new)?;
+        let is_directory = old.is_directory();
+        let nested = old.is_nested_catalog_mountpoint() && new.is_nested_catalog_mountpoint();
+        if !fields.is_empty() {
+            changes.push(Change {
+                kind: ChangeKind::Modified,
+                path: path.into(),
+                old: Some(old),
+                new: Some(new),
+                fields,
+            });
+        }
+        if !is_directory || nested && self.same_nested_catalog(path)? {
+            return Ok(());
+        }
+        self.compare_directories(path, changes)
+    }
+
+    fn compare_directories(&self, path: &str, changes: &mut Vec<Change>) -> CvmfsResult<()> {
+        let parent = if path == "/" { "" } else { path };
+        let mut old_entries = self
+            .repository
+            .read_directory_at(self.old_root_hash, path)?
+            .into_iter()
+            .peekable();
+        let mut new_entries = self
+            .repository
+            .read_directory_at(self.new_root_hash, path)?
+            .into_iter()
+            .peekable();
+        loop {
+            let order = match (old_entries.peek(), new_entries.peek()) {
+                (None, None) => return Ok(()),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(old), Some(new)) => old.name.cmp(&new.name),
+            };
+            match order {
+                Ordering::Less => {
+                    let old = old_entries.next().unwrap_or_else(|| unreachable!());
+                    let child = format!("{}/{}", parent, old.name);
+                    self.subtree(ChangeKind::Removed, self.old_root_hash, &child, changes)?;
+                }
+                Ordering::Greater => {
+                    let new = new_entries.next().unwrap_or_else(|| unreachable!());
+                    let child = format!("{}/{}", parent, new.name);
+                    self.subtree(ChangeKind::Added, self.new_root_hash, &child, changes)?;
+                }
+                Ordering::Equal => {
+                    let (Some(old), Some(new)) = (old_entries.next(), new_entries.next()) else {
+                        unreachable!()
+                    };
+                    let child = format!("{}/{}", parent, old.name);
+                    self.compare(&child, old, new, changes)?;
+                }
+            }
+        }
+    }
+
+    /// Reports an entry and everything below it as added or removed
+    fn subtree(
+        &self,
+        kind: ChangeKind,
+        root_hash: &str,
+        path: &str,
+        changes: &mut Vec<Change>,
+    ) -> CvmfsResult<()> {
+        for wrapper in self.repository.walk_at(root_hash, path)? {
+            let wrapper = wrapper?;
+            let entry = Some(wrapper.directory_entry);
+            let (old, new) = match kind {
+                ChangeKind::Removed => (entry, None),
+                _ => (None, entry),
+            };
+            changes.push(Change {
+                kind,
+                path: wrapper.path,
+                old,
+                new,
+                fields: vec![],
+            });
+        }
+        Ok(())
+    }
+
+    fn changed_fields(
+        &self,
+        path: &str,
+        old: &mut DirectoryEntry,
+        new: &mut DirectoryEntry,
+    ) -> CvmfsResult<Vec<&'static str>> {
+        let mut fields = Vec::new();
+        if old.content_hash != new.content_hash {
+            fields.push("content");
+        }
+        if old.is_file() && new.is_file() && old.has_chunks() && new.has_chunks() {
+            self.repository
+                .retrieve_catalog_for_path_at(self.old_root_hash, path)?
+                .load_chunks(old)?;
+            self.repository
+                .retrieve_catalog_for_path_at(self.new_root_hash, path)?
+                .load_chunks(new)?;
+            let chunk_key = |entry: &DirectoryEntry| -> Vec<(u64, u64, String)> {
+                entry
+                    .chunks
+                    .iter()
+                    .map(|chunk| (chunk.offset, chunk.size, chunk.content_hash.clone()))
+                    .collect()
+            };
+            if chunk_key(old) != chunk_key(new) {
+                fields.push("chunks");
+            }
+        }
+        if old.size != new.size {
+            fields.push("size");
+        }
+        if old.mode != new.mode {
+            fields.push("mode");
+        }
+        if old.mtime != new.mtime {
+            fields.push("mtime");
+        }
+        if old.symlink != new.symlink {
+            fields.push("symlink");
+        }
+        if old.flags != new.flags {
+            fields.push("flags");
+        }
+        Ok(fields)
+    }
+
+    /// Whether a nested catalog is the same in both revisions, in which case
+    /// nothing below its mountpoint changed
+    fn same_nested_catalog(&self, path: &str) -> CvmfsResult<bool> {
+        let old = self
+            .repository
+            .retrieve_catalog_for_path_at(self.old_root_hash, path)?;
+        let new = self
+            .repository
+            .retrieve_catalog_for_path_at(self.new_root_hash, path)?;
+        Ok(old.hash == new.hash)
+    }
+}
@@ -3,25 +3,43 @@ use std::ops::BitAnd;
 use hex::ToHex;
 use rusqlite::{Row, Rows};
 
-use crate::common::CvmfsResult;
+use crate::common::{CvmfsError, CvmfsResult};
+use crate::variant_symlink::SymlinkVariables;
+
+pub const RIPEMD160_SUFFIX: &str = "-rmd160";
+pub const SHAKE128_SUFFIX: &str = "-shake128";
 
 /// Enumeration of supported content hash types
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum ContentHashTypes {
     Unknown = -1,
     Sha1 = 1,
     Ripemd160 = 2,
-    UpperBound = 3,
+    Shake128 = 3,
+    UpperBound = 4,
 }
 
 impl ContentHashTypes {
     /// Figures out the hash suffix in CVMFS's CAS
     pub fn hash_suffix(obj: &Self) -> String {
         match obj {
-            ContentHashTypes::Ripemd160 => "-rmd160".into(),
+            ContentHashTypes::Ripemd160 => RIPEMD160_SUFFIX.into(),
+            ContentHashTypes::Shake128 => SHAKE128_SUFFIX.into(),
             _ => "".into(),
         }
     }
+
+    /// Algorithm of a hash written as in the manifest and the nested catalog
+    /// references, `<hex>[-<algorithm>]`, optionally followed by the object suffix
+    pub fn from_hash(hash: &str) -> Self {
+        let hash = hash.trim_end_matches(|c: char| c.is_ascii_uppercase());
+        match hash.find('-').map(|index| &hash[index..]) {
+            None => ContentHashTypes::Sha1,
+            Some(RIPEMD160_SUFFIX) => ContentHashTypes::Ripemd160,
+            Some(SHAKE128_SUFFIX) => ContentHashTypes::Shake128,
+            Some(_) => ContentHashTypes::Unknown,
+        }
+    }
 }
 
 impl From<u32> for ContentHashTypes {
@@ -29,21 +47,46 @@ impl From<u32> for ContentHashTypes {
         match value {
             1 => ContentHashTypes::Sha1,
             2 => ContentHashTypes::Ripemd160,
-            3 => ContentHashTypes::UpperBound,
+            3 => ContentHashTypes::Shake128,
+            4 => ContentHashTypes::UpperBound,
             _ => ContentHashTypes::Unknown,
         }
     }
 }
 
+/// Kind of a special file, given by the type bits of its mode
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SpecialKind {
+    CharDevice,
+    BlockDevice,
+    NamedPipe,
+    Socket,
+}
+
+impl SpecialKind {
+    pub fn from_mode(mode: u16) -> Option<Self> {
+        match u32::from(mode) & libc::S_IFMT {
+            libc::S_IFCHR => Some(Self::CharDevice),
+            libc::S_IFBLK => Some(Self::BlockDevice),
+            libc::S_IFIFO => Some(Self::NamedPipe),
+            libc::S_IFSOCK => Some(Self::Socket),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum Flags {
     Directory = 1,
     NestedCatalogMountpoint = 2,
     File = 4,
     Link = 8,
+    /// Device, FIFO or socket, see `DirectoryEntry::special_kind`
     FileStat = 16,
     NestedCatalogRoot = 32,
     FileChunk = 64,
+    /// Data stored out of the repository, see `DirectoryEntry::is_external`
+    FileExternal = 128,
     ContentHashTypes = 256 + 512 + 1024,
 }
 
@@ -115,6 +158,13 @@ pub struct DirectoryEntry {
     pub mtime: i64,
     pub name: String,
     pub symlink: Option<String>,
+    /// Device number of the device files
+    pub rdev: u64,
+    /// Links to the entry, zero when the catalog does not record them
+    pub link_count: u32,
+    /// Group of the hardlinks sharing the entry within its directory, zero
+    /// when the entry is not hardlinked
+    pub hardlink_group: u32,
     pub content_hash_type: ContentHashTypes,
     pub chunks: Vec<Chunk>,
 }
@@ -123,6 +173,14 @@ impl DirectoryEntry {
     pub fn new(row: &Row) -> CvmfsResult<Self> {
         let content_hash: Option<Vec<u8>> = row.get(4)?;
         let flags = row.get(5)?;
+        // the link count in the lower half, the hardlink group in the upper one
+        let hardlinks: i64 = row.get(11)?;
+        // special files store their device number in place of their size
+        let size: u64 = row.get(6)?;
+        let (size, rdev) = match flags & Flags::FileStat {
+            0 => (size, 0),
+            _ => (0, size),
+        };
         Ok(Self {
             md5_path_1: row.get(0)?,
             md5_path_2: row.get(1)?,
@@ -130,16 +188,54 @@ impl DirectoryEntry {
             parent_2: row.get(3)?,
             content_hash: content_hash.map(|value| value.encode_hex()),
             flags,
-            size: row.get(6)?,
+            size,
             mode: row.get(7)?,
             mtime: row.get(8)?,
             name: row.get(9)?,
             symlink: row.get(10)?,
+            rdev,
+            link_count: hardlinks as u32,
+            hardlink_group: (hardlinks >> 32) as u32,
             content_hash_type: Self::read_content_hash_type(flags),
             chunks: vec![],
         })
     }
 
+    /// Approximate memory held by the entry, in bytes
+    pub fn memory_size(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.name.capacity()
+            + self.symlink.as_ref().map_or(0, String::capacity)
+            + self.content_hash.as_ref().map_or(0, String::capacity)
+            + self
+                .chunks
+                .iter()
+                .map(|chunk| std::mem::size_of::<Chunk>() + chunk.content_hash.capacity())
+                .sum::<usize>()
+    }
+
+    /// Read-only directory that does not exist in any catalog
+    pub fn virtual_directory(name: &str, mtime: i64) -> Self {
+        Self {
+            md5_path_1: 0,
+            md5_path_2: 0,
+            parent_1: 0,
+            parent_2: 0,
+            content_hash: None,
+            flags: Flags::Directory as u32,
+            size: 4096,
+            mode: 0o40555,
+            mtime,
+            name: name.into(),
+            symlink: None,
+            rdev: 0,
+            link_count: 2,
+            hardlink_group: 0,
+            content_hash_type: ContentHashTypes::Unknown,
+            chunks: vec![],
+        }
+    }
+
     pub fn add_chunks(&mut self, mut rows: Rows) -> CvmfsResult<()> {
         self.chunks.clear();
         loop {
@@ -183,6 +279,60 @@ impl DirectoryEntry {
         self.flags & Flags::Link > 0
     }
 
+    /// Replaces the placeholders in the target of a variant symlink, whose
+    /// size becomes the one of the expanded target
+    pub fn expand_symlink(&mut self, variables: &SymlinkVariables) {
+        if !self.is_symlink() {
+            return;
+        }
+        if let Some(target) = &mut self.symlink {
+            *target = variables.expand(target);
+            self.size = target.len() as u64;
+        }
+    }
+
+    pub fn is_special(&self) -> bool {
+        self.flags & Flags::FileStat > 0
+    }
+
+    /// Kind of the device, FIFO or socket, `None` for any other entry
+    pub fn special_kind(&self) -> Option<SpecialKind> {
+        self.is_special()
+            .then(|| SpecialKind::from_mode(self.mode))
+            .flatten()
+    }
+
+    /// Whether the data of the file is served uncompressed by its path from
+    /// the external servers, instead of by its hash from the repository
+    pub fn is_external(&self) -> bool {
+        self.flags & Flags::FileExternal > 0
+    }
+
+    /// Number of links reported to `stat`, defaulting to the ones every
+    /// directory and file has when the catalog does not record them
+    pub fn nlink(&self) -> u32 {
+        match self.link_count {
+            0 if self.is_directory() => 2,
+            0 => 1,
+            link_count => link_count,
+        }
+    }
+
+    /// Inode number, stable across mounts and revisions. The hardlinks of a
+    /// group share it, derived from their directory since the groups are
+    /// numbered per directory.
+    pub fn inode(&self) -> u64 {
+        if self.hardlink_group == 0 {
+            return self.md5_path_1 as u64;
+        }
+        let mut context = md5::Context::new();
+        context.consume(self.parent_1.to_le_bytes());
+        context.consume(self.parent_2.to_le_bytes());
+        context.consume(self.hardlink_group.to_le_bytes());
+        let digest = context.compute().0;
+        u64::from_le_bytes(digest[..8].try_into().unwrap_or_default())
+    }
+
     pub fn path_hash(&self) -> PathHash {
         PathHash {
             hash1: self.md5_path_1,
@@ -197,6 +347,32 @@ impl DirectoryEntry {
         }
     }
 
+    /// Checks that the chunks follow each other from the start of the file
+    /// and cover exactly its size, so that a broken catalog never serves a
+    /// truncated or overlapping file
+    pub fn check_chunks(&self) -> CvmfsResult<()> {
+        let mut expected_offset = 0;
+        for chunk in &self.chunks {
+            if chunk.offset != expected_offset {
+                return Err(CvmfsError::CorruptChunks(format!(
+                    "{}: chunk at offset {}, expected {}",
+                    self.name, chunk.offset, expected_offset
+                )));
+            }
+            expected_offset += chunk.size;
+        }
+        if expected_offset != self.size {
+            return Err(CvmfsError::CorruptChunks(format!(
+                "{}: {} chunks cover {} bytes, the file has {}",
+                self.name,
+                self.chunks.len(),
+                expected_offset,
+                self.size
+            )));
+        }
+        Ok(())
+    }
+
     pub fn has_chunks(&self) -> bool {
         self.content_hash.is_none()
     }
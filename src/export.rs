@@ -0,0 +1,336 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::common::{CvmfsError, CvmfsResult};
+use crate::directory_entry::{DirectoryEntry, SpecialKind};
+use crate::repository::Repository;
+
+/// Extension of the destinations exported as a tar archive
+pub const TAR_EXTENSION: &str = "tar";
+const BLOCK_SIZE: usize = 512;
+/// Name of the GNU records holding the paths too long for a tar header
+const GNU_LONG_NAME: &str = "././@LongLink";
+
+/// Where a subtree is exported
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportTarget {
+    /// Local directory, created if missing
+    Directory(PathBuf),
+    /// Tar archive, replaced if it exists
+    Tar(PathBuf),
+}
+
+impl ExportTarget {
+    /// Archives for the paths ending in `.tar`, directories otherwise
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(extension) if extension == TAR_EXTENSION => Self::Tar(path.into()),
+            _ => Self::Directory(path.into()),
+        }
+    }
+}
+
+/// Entries written by an export
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExportSummary {
+    pub directories: u64,
+    pub files: u64,
+    pub symlinks: u64,
+    /// Device nodes, FIFOs and sockets
+    pub special_files: u64,
+    /// Bytes of file contents written
+    pub bytes: u64,
+    /// Special files that could not be exported: all of them in a directory,
+    /// which would need privileges to create them, and sockets in an archive
+    pub skipped: u64,
+}
+
+/// Writes a subtree of the current revision with the contents, symlinks,
+/// permissions and modification times of its entries
+pub fn export(
+    repository: &Repository,
+    path: &str,
+    target: &ExportTarget,
+) -> CvmfsResult<ExportSummary> {
+    let root_hash = repository.get_root_hash()?.to_string();
+    let mut summary = ExportSummary::default();
+    match target {
+        ExportTarget::Directory(destination) => {
+            let mut directories = Vec::new();
+            for wrapper in repository.walk_at(&root_hash, path)? {
+                let wrapper = wrapper?;
+                let local_path = destination.join(relative_path(path, &wrapper.path));
+                let entry = wrapper.directory_entry;
+                if entry.is_directory() {
+                    fs::create_dir_all(&local_path)?;
+                    summary.directories += 1;
+                    // permissions last, read-only directories could not be filled
+                    directories.push((local_path, entry));
+                    continue;
+                }
+                if local_path.symlink_metadata().is_ok() {
+                    fs::remove_file(&local_path)?;
+                }
+                if entry.is_symlink() {
+                    symlink(entry.symlink.as_deref().unwrap_or_default(), &local_path)?;
+                    summary.symlinks += 1;
+                } else if entry.special_kind().is_some() {
+                    log::warn!("Skipping the special file {}", wrapper.path);
+                    summary.skipped += 1;
+                } else {
+                    let mut file = File::create(&local_path)?;
+                    summary.bytes += copy_contents(
+                        repository,
+                        &root_hash,
+                        &wrapper.path,
+                        entry.clone(),
+                        &mut file,
+                    )?;
+                    file.set_modified(modification_time(&entry))?;
+                    drop(file);
+                    set_mode(&local_path, &entry)?;
+                    summary.files += 1;
+                }
+            }
+            for (local_path, entry) in directories.iter().rev() {
+                File::open(local_path)?.set_modified(modification_time(entry))?;
+                set_mode(local_path, entry)?;
+            }
+        }
+        ExportTarget::Tar(destination) => {
+            let partial = destination.with_extension("tar.partial");
+            let mut archive = TarWriter::new(io::BufWriter::new(File::create(&partial)?));
+            for wrapper in repository.walk_at(&root_hash, path)? {
+                let wrapper = wrapper?;
+                let name = relative_path(path, &wrapper.path);
+                let entry = wrapper.directory_entry;
+                if entry.is_directory() {
+                    summary.directories += 1;
+                    if !name.is_empty() {
+                        archive.append(&format!("{}/", name), &entry, TarKind::Directory, 0)?;
+                    }
+                } else if entry.is_symlink() {
+                    let target = entry.symlink.as_deref().unwrap_or_default();
+                    archive.append(&name, &entry, TarKind::Symlink(target), 0)?;
+                    summary.symlinks += 1;
+                } else if entry.special_kind() == Some(SpecialKind::Socket) {
+                    // tar has no socket type, and sockets cannot be restored anyway
+                    log::warn!("Skipping the socket {}", wrapper.path);
+                    summary.skipped += 1;
+                } else if let Some(kind) = entry.special_kind() {
+                    archive.append(&name, &entry, TarKind::Special(kind), 0)?;
+                    summary.special_files += 1;
+                } else {
+                    archive.append(&name, &entry, TarKind::File, entry.size)?;
+                    let written = copy_contents(
+                        repository,
+                        &root_hash,
+                        &wrapper.path,
+                        entry.clone(),
+                        &mut archive.out,
+                    )?;
+                    archive.pad(written)?;
+                    summary.bytes += written;
+                    summary.files += 1;
+                }
+            }
+            archive.finish()?.flush()?;
+            fs::rename(&partial, destination)?;
+        }
+    }
+    Ok(summary)
+}
+
+/// Path of an entry below the exported one, empty for the exported one
+fn relative_path(root: &str, path: &str) -> String {
+    let root = root.trim_end_matches('/');
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .trim_start_matches('/')
+        .to_string()
+}
+
+/// Writes the contents of a file, failing if they do not match its size
+fn copy_contents(
+    repository: &Repository,
+    root_hash: &str,
+    path: &str,
+    mut entry: DirectoryEntry,
+    out: &mut dyn Write,
+) -> CvmfsResult<u64> {
+    let size = entry.size;
+    repository
+        .retrieve_catalog_for_path_at(root_hash, path)?
+        .load_chunks(&mut entry)?;
+    let file = repository.retrieve_object(path, entry)?;
+    let written = io::copy(&mut file.take(size), out)?;
+    if written != size {
+        return Err(CvmfsError::IO(format!(
+            "{} is {} bytes long, only {} could be read",
+            path, size, written
+        )));
+    }
+    Ok(written)
+}
+
+fn modification_time(entry: &DirectoryEntry) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(entry.mtime.max(0) as u64)
+}
+
+fn set_mode(path: &Path, entry: &DirectoryEntry) -> CvmfsResult<()> {
+    let mode = u32::from(entry.mode) & 0o7777;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+enum TarKind<'a> {
+    File,
+    Directory,
+    Symlink(&'a str),
+    Special(SpecialKind),
+}
+
+/// Writer of ustar archives, with the GNU extension for long paths
+struct TarWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> TarWriter<W> {
+    fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    /// Writes the header of an entry, whose `size` bytes of contents follow
+    fn append(
+        &mut self,
+        name: &str,
+        entry: &DirectoryEntry,
+        kind: TarKind,
+        size: u64,
+    ) -> CvmfsResult<()> {
+        let (type_flag, link) = match kind {
+            TarKind::File => (b'0', ""),
+            TarKind::Directory => (b'5', ""),
+            TarKind::Symlink(target) => (b'2', target),
+            TarKind::Special(SpecialKind::CharDevice) => (b'3', ""),
+            TarKind::Special(SpecialKind::BlockDevice) => (b'4', ""),
+            TarKind::Special(SpecialKind::NamedPipe) => (b'6', ""),
+            TarKind::Special(SpecialKind::Socket) => {
+                return Err(CvmfsError::IO(format!(
+                    "cannot archive the socket {}",
+                    name
+                )))
+            }
+        };
+        if link.len() > 100 {
+            self.write_long_name(b'K', link)?;
+        }
+        let (prefix, short_name) = match split_name(name) {
+            Some(split) => split,
+            None => {
+                self.write_long_name(b'L', name)?;
+                ("", &name[name.len().saturating_sub(100)..])
+            }
+        };
+        let mut header = [0u8; BLOCK_SIZE];
+        put(&mut header[0..100], short_name.as_bytes());
+        put_octal(&mut header[100..108], u64::from(entry.mode) & 0o7777);
+        put_octal(&mut header[108..116], 0);
+        put_octal(&mut header[116..124], 0);
+        put_octal(&mut header[124..136], size);
+        put_octal(&mut header[136..148], entry.mtime.max(0) as u64);
+        header[156] = type_flag;
+        put(
+            &mut header[157..257],
+            &link.as_bytes()[..link.len().min(100)],
+        );
+        put(&mut header[257..265], b"ustar\x0000");
+        put(&mut header[265..269], b"root");
+        put(&mut header[297..301], b"root");
+        if type_flag == b'3' || type_flag == b'4' {
+            // the encoding of the Linux device numbers
+            let major = ((entry.rdev >> 8) & 0xfff) | ((entry.rdev >> 32) & !0xfff);
+            let minor = (entry.rdev & 0xff) | ((entry.rdev >> 12) & !0xff);
+            put_octal(&mut header[329..337], major);
+            put_octal(&mut header[337..345], minor);
+        }
+        put(&mut header[345..500], prefix.as_bytes());
+        self.write_header(header)
+    }
+
+    /// GNU record holding a path that does not fit in the next header
+    fn write_long_name(&mut self, type_flag: u8, name: &str) -> CvmfsResult<()> {
+        let mut header = [0u8; BLOCK_SIZE];
+        put(&mut header[0..100], GNU_LONG_NAME.as_bytes());
+        put_octal(&mut header[100..108], 0o644);
+        put_octal(&mut header[108..116], 0);
+        put_octal(&mut header[116..124], 0);
+        put_octal(&mut header[124..136], name.len() as u64 + 1);
+        put_octal(&mut header[136..148], 0);
+        header[156] = type_flag;
+        put(&mut header[257..265], b"ustar  \x00");
+        self.write_header(header)?;
+        self.out.write_all(name.as_bytes())?;
+        self.out.write_all(&[0])?;
+        self.pad(name.len() as u64 + 1)
+    }
+
+    fn write_header(&mut self, mut header: [u8; BLOCK_SIZE]) -> CvmfsResult<()> {
+        header[148..156].fill(b' ');
+        let checksum: u64 = header.iter().map(|byte| u64::from(*byte)).sum();
+        put(
+            &mut header[148..155],
+            format!("{:06o}\0", checksum).as_bytes(),
+        );
+        self.out.write_all(&header)?;
+        Ok(())
+    }
+
+    /// Fills the last block of contents of `length` bytes with zeros
+    fn pad(&mut self, length: u64) -> CvmfsResult<()> {
+        let remainder = (length % BLOCK_SIZE as u64) as usize;
+        if remainder > 0 {
+            self.out.write_all(&[0; BLOCK_SIZE][remainder..])?;
+        }
+        Ok(())
+    }
+
+    /// Ends the archive with two empty blocks
+    fn finish(mut self) -> CvmfsResult<W> {
+        self.out.write_all(&[0; 2 * BLOCK_SIZE])?;
+        Ok(self.out)
+    }
+}
+
+/// Splits a path into the prefix and name fields of a ustar header, `None`
+/// when it does not fit
+fn split_name(name: &str) -> Option<(&str, &str)> {
+    if name.len() <= 100 {
+        return Some(("", name));
+    }
+    let separators = name.trim_end_matches('/').match_indices('/');
+    separators
+        .map(|(index, _)| (&name[..index], &name[index + 1..]))
+        .find(|(prefix, name)| prefix.len() <= 155 && name.len() <= 100)
+}
+
+fn put(field: &mut [u8], value: &[u8]) {
+    field[..value.len()].copy_from_slice(value);
+}
+
+/// Zero padded octal number terminated by a NUL, or in base 256 as GNU tar
+/// does when it does not fit
+fn put_octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}\0", value, width = field.len() - 1);
+    if digits.len() <= field.len() {
+        put(field, digits.as_bytes());
+        return;
+    }
+    let bytes = value.to_be_bytes();
+    let start = field.len() - bytes.len();
+    field[start..].copy_from_slice(&bytes);
+    field[0] |= 0x80;
+}
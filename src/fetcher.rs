@@ -1,93 +1,786 @@
-use std::fs;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fs::File;
 use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Condvar, LazyLock, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
-use crate::cache::Cache;
-use crate::common::{CvmfsError, CvmfsResult};
-use compress::zlib;
+use flate2::read::ZlibDecoder;
+use openssl::hash::{Hasher, MessageDigest};
+use threadpool::ThreadPool;
 
-#[derive(Debug)]
+#[cfg(feature = "async")]
+use crate::async_fetcher::AsyncFetcher;
+use crate::audit_log::{AuditKind, AuditLog, AuditRecord};
+use crate::cache::{Cache, QuarantineRecord};
+use crate::common::{CvmfsError, CvmfsResult, FileLike, MemoryFile};
+use crate::directory_entry::ContentHashTypes;
+use crate::metrics::metrics;
+use crate::mirrors::{MirrorSet, MirrorStatus};
+use crate::proxy::{ProxyChain, DIRECT};
+use crate::validation::ValidationMode;
+
+/// Threads computing the digests of downloaded objects
+pub const VERIFICATION_THREADS: usize = 4;
+
+static VERIFICATION_POOL: OnceLock<Mutex<ThreadPool>> = OnceLock::new();
+/// Threads writing downloaded objects to the cache
+pub const WRITE_BACK_THREADS: usize = 2;
+
+/// Time the downloads fail right away once the servers were found
+/// unreachable, before the network is tried again
+pub const OFFLINE_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Path of the GeoAPI of the stratum-1 servers, relative to a mirror url
+pub const GEO_API_PATH: &str = "api/v1.0/geo";
+/// Proxy name given to the GeoAPI for direct connections
+const GEO_API_NO_PROXY: &str = "x";
+
+static WRITE_BACK_POOL: OnceLock<Mutex<ThreadPool>> = OnceLock::new();
+/// Bytes of downloaded objects waiting for the write-back pool. The objects
+/// downloaded beyond it are written to the cache before being returned.
+pub const MAX_PENDING_WRITE_BYTES: usize = 64 << 20;
+static PENDING_WRITES: LazyLock<Mutex<PendingWrites>> = LazyLock::new(Default::default);
+
+/// Objects served from memory until the write-back pool has cached them
+#[derive(Debug, Default)]
+struct PendingWrites {
+    objects: HashMap<PathBuf, Arc<[u8]>>,
+    /// Total size of the objects, see `MAX_PENDING_WRITE_BYTES`
+    bytes: usize,
+}
+
+impl PendingWrites {
+    /// Queues an object for the write-back pool, unless it would go over the
+    /// budget. Returns whether it was queued.
+    fn try_insert(&mut self, cached_file: PathBuf, content: Arc<[u8]>) -> bool {
+        if self.bytes + content.len() > MAX_PENDING_WRITE_BYTES {
+            return false;
+        }
+        self.bytes += content.len();
+        if let Some(previous) = self.objects.insert(cached_file, content) {
+            self.bytes -= previous.len();
+        }
+        true
+    }
+
+    fn remove(&mut self, cached_file: &Path) {
+        if let Some(content) = self.objects.remove(cached_file) {
+            self.bytes -= content.len();
+        }
+    }
+}
+
+/// Location of an object in the cache, and whether its content is verified
+type DownloadKey = (PathBuf, bool);
+/// Downloads in flight, so that the mounts sharing a cache download common
+/// objects once
+static IN_FLIGHT: LazyLock<Mutex<HashMap<DownloadKey, Arc<InFlight>>>> =
+    LazyLock::new(Default::default);
+/// Downloads served by waiting for the same object being downloaded by another fetcher
+static DEDUPLICATED_DOWNLOADS: AtomicU64 = AtomicU64::new(0);
+
+/// Download of an object awaited by several fetchers
+#[derive(Debug, Default)]
+struct InFlight {
+    result: Mutex<Option<CvmfsResult<Arc<[u8]>>>>,
+    done: Condvar,
+}
+
+impl InFlight {
+    fn wait(&self) -> CvmfsResult<Arc<[u8]>> {
+        let mut result = self.result.lock().map_err(|_| CvmfsError::Sync)?;
+        while result.is_none() {
+            result = self.done.wait(result).map_err(|_| CvmfsError::Sync)?;
+        }
+        result.clone().ok_or(CvmfsError::Sync)?
+    }
+
+    fn finish(&self, result: CvmfsResult<Arc<[u8]>>) {
+        if let Ok(mut slot) = self.result.lock() {
+            *slot = Some(result);
+        }
+        self.done.notify_all();
+    }
+}
+
+thread_local! {
+    /// Objects downloaded by each thread, to tell apart operations served from the cache
+    static DOWNLOADS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Algorithm a content addressed object is hashed with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DigestAlgorithm {
+    Sha1,
+    Sha256,
+    Ripemd160,
+    /// SHAKE128 truncated to 160 bits, as published by CernVM-FS
+    Shake128,
+}
+
+impl DigestAlgorithm {
+    /// Hex digest of some content, `None` if the algorithm is not available in
+    /// the linked OpenSSL (RIPEMD-160 needs its legacy provider)
+    pub fn digest(&self, content: &[u8]) -> Option<String> {
+        match self {
+            DigestAlgorithm::Sha1 => Some(hex::encode(openssl::sha::sha1(content))),
+            DigestAlgorithm::Sha256 => Some(hex::encode(openssl::sha::sha256(content))),
+            DigestAlgorithm::Ripemd160 => openssl::hash::hash(MessageDigest::ripemd160(), content)
+                .ok()
+                .map(hex::encode),
+            DigestAlgorithm::Shake128 => {
+                let mut hasher = Hasher::new(MessageDigest::shake_128()).ok()?;
+                hasher.update(content).ok()?;
+                let mut output = [0u8; 20];
+                hasher.finish_xof(&mut output).ok()?;
+                Some(hex::encode(output))
+            }
+        }
+    }
+}
+
+/// Parses the reply of the GeoAPI: the positions, starting at 1, of the
+/// queried hosts from the closest to the farthest, separated by commas
+pub fn parse_geo_reply(reply: &str, hosts: usize) -> CvmfsResult<Vec<usize>> {
+    let invalid = || CvmfsError::InvalidGeoApiReply(reply.into());
+    let ranking = reply
+        .trim()
+        .split(',')
+        .map(|position| match position.trim().parse::<usize>() {
+            Ok(position) if (1..=hosts).contains(&position) => Ok(position - 1),
+            _ => Err(invalid()),
+        })
+        .collect::<CvmfsResult<Vec<_>>>()?;
+    let mut ranked = ranking.clone();
+    ranked.sort_unstable();
+    ranked.dedup();
+    if ranked.len() != hosts || ranking.len() != hosts {
+        return Err(invalid());
+    }
+    Ok(ranking)
+}
+
+/// Digest algorithm and expected hex digest of a content addressed object,
+/// derived from its path in the repository (`data/<2>/<rest>[-<algorithm>]<suffix>`).
+/// Hashes are lowercase, while the suffix telling the object type is uppercase.
+/// The algorithm comes from the same suffix the catalogs and the manifest use,
+/// so objects of repositories migrating between algorithms are all verified.
+pub fn expected_digest(file_name: &str) -> Option<(DigestAlgorithm, String)> {
+    let object = file_name.strip_prefix("data/")?;
+    let (prefix, rest) = object.split_once('/')?;
+    let hash: String = prefix
+        .chars()
+        .chain(rest.chars())
+        .take_while(|c| c.is_ascii_digit() || ('a'..='f').contains(c))
+        .collect();
+    let algorithm = match (ContentHashTypes::from_hash(rest), hash.len()) {
+        (ContentHashTypes::Sha1, 40) => DigestAlgorithm::Sha1,
+        (ContentHashTypes::Sha1, 64) => DigestAlgorithm::Sha256,
+        (ContentHashTypes::Ripemd160, 40) => DigestAlgorithm::Ripemd160,
+        (ContentHashTypes::Shake128, 40) => DigestAlgorithm::Shake128,
+        _ => return None,
+    };
+    Some((algorithm, hash))
+}
+
+#[derive(Debug, Clone)]
 pub struct Fetcher {
     pub cache: Cache,
+    /// Url of the first mirror
     pub source: String,
+    /// Every mirror of the repository, with their health shared by the clones
+    pub mirrors: Arc<MirrorSet>,
+    /// Proxies the downloads go through, the ones of the environment when
+    /// `None`
+    pub proxies: Option<Arc<ProxyChain>>,
+    /// Handling of downloaded objects whose digest does not match their name
+    pub content_validation: ValidationMode,
+    /// Record of the verified content, disabled when `None`
+    pub audit_log: Option<Arc<AuditLog>>,
+    /// Connectivity of the repository, shared by the clones
+    offline: Arc<OfflineState>,
+    /// Time the network is not tried after finding it unreachable
+    pub offline_retry_interval: Duration,
+}
+
+#[derive(Debug, Default)]
+struct OfflineState {
+    /// Disconnected mode, the network is never tried
+    forced: AtomicBool,
+    /// Last time every mirror was unreachable
+    unreachable_at: Mutex<Option<Instant>>,
 }
 
 impl Fetcher {
     pub fn new(source: &str, cache_directory: &str, initialize: bool) -> CvmfsResult<Self> {
+        let cache = Cache::new(cache_directory.into())?;
+        if initialize {
+            cache.initialize()?;
+        }
+        Ok(Self::with_cache(source, cache))
+    }
+
+    /// Fetcher failing over between several mirrors of the repository
+    pub fn new_with_mirrors(
+        sources: &[&str],
+        cache_directory: &str,
+        initialize: bool,
+    ) -> CvmfsResult<Self> {
+        let cache = Cache::new(cache_directory.into())?;
+        if initialize {
+            cache.initialize()?;
+        }
+        Self::with_mirrors(sources, cache)
+    }
+
+    /// Fetcher over an already initialized cache
+    pub fn with_cache(source: &str, cache: Cache) -> Self {
+        Self::with_mirror_set(MirrorSet::single(Self::source_url(source)), cache)
+    }
+
+    /// Fetcher over an already initialized cache, failing over between
+    /// several mirrors of the repository
+    pub fn with_mirrors(sources: &[&str], cache: Cache) -> CvmfsResult<Self> {
+        let mirrors = MirrorSet::new(sources.iter().map(|s| Self::source_url(s)).collect())?;
+        Ok(Self::with_mirror_set(mirrors, cache))
+    }
+
+    /// Fetcher downloading from the given mirrors, whose backoff may have
+    /// been tuned
+    pub fn with_mirror_set(mirrors: MirrorSet, cache: Cache) -> Self {
+        Self {
+            cache,
+            source: mirrors.urls()[0].clone(),
+            mirrors: Arc::new(mirrors),
+            proxies: None,
+            content_validation: ValidationMode::Ignore,
+            audit_log: None,
+            offline: Default::default(),
+            offline_retry_interval: OFFLINE_RETRY_INTERVAL,
+        }
+    }
+
+    /// Fetcher of the external data of the repository, downloading from other
+    /// servers with the same cache, proxies and validation. Its connectivity
+    /// is tracked apart from the one of the repository servers.
+    pub fn for_external_data(&self, urls: &[&str]) -> CvmfsResult<Self> {
+        let mut fetcher = Self::with_mirrors(urls, self.cache.clone())?;
+        fetcher.proxies = self.proxies.clone();
+        fetcher.content_validation = self.content_validation;
+        fetcher.audit_log = self.audit_log.clone();
+        fetcher.offline_retry_interval = self.offline_retry_interval;
+        fetcher.set_offline(self.offline.forced.load(Ordering::Relaxed));
+        Ok(fetcher)
+    }
+
+    fn source_url(source: &str) -> String {
         let path = Path::new(source);
-        let source = if path.exists() && path.is_dir() {
+        if path.exists() && path.is_dir() {
             format!("{}{}", "file://", source)
         } else {
             source.into()
+        }
+    }
+
+    /// Health of every proxy, empty when not going through configured ones
+    pub fn proxy_status(&self) -> Vec<MirrorStatus> {
+        self.proxies
+            .as_ref()
+            .map_or_else(Vec::new, |proxies| proxies.status())
+    }
+
+    /// Switches the disconnected mode, in which only the cached objects are
+    /// served and the network is never tried
+    pub fn set_offline(&self, offline: bool) {
+        self.offline.forced.store(offline, Ordering::Relaxed);
+    }
+
+    /// Whether downloads currently fail without trying the network, either in
+    /// disconnected mode or shortly after the servers were found unreachable
+    pub fn is_offline(&self) -> bool {
+        self.offline.forced.load(Ordering::Relaxed)
+            || self
+                .offline
+                .unreachable_at
+                .lock()
+                .is_ok_and(|unreachable_at| {
+                    unreachable_at.is_some_and(|at| at.elapsed() < self.offline_retry_interval)
+                })
+    }
+
+    fn record_connectivity(&self, reachable: bool) {
+        let Ok(mut unreachable_at) = self.offline.unreachable_at.lock() else {
+            return;
         };
-        let cache = Cache::new(cache_directory.into())?;
-        if initialize {
-            cache.initialize()?;
+        match (reachable, unreachable_at.is_some()) {
+            (true, true) => log::info!("Repository servers reachable again"),
+            (false, false) => {
+                log::warn!("Repository servers unreachable, serving cached data only")
+            }
+            _ => {}
         }
-        Ok(Self { cache, source })
+        *unreachable_at = (!reachable).then(Instant::now);
+    }
+
+    /// Health of every mirror
+    pub fn mirror_status(&self) -> Vec<MirrorStatus> {
+        self.mirrors.status()
+    }
+
+    /// Records the outcome of a verification in the audit log, if enabled
+    pub fn audit(&self, kind: AuditKind, name: &str, hash: &str, error: Option<&CvmfsError>) {
+        self.audit_from(&self.source, kind, name, hash, error);
+    }
+
+    fn audit_from(
+        &self,
+        source: &str,
+        kind: AuditKind,
+        name: &str,
+        hash: &str,
+        error: Option<&CvmfsError>,
+    ) {
+        let Some(audit_log) = &self.audit_log else {
+            return;
+        };
+        let host = reqwest::Url::parse(source)
+            .ok()
+            .and_then(|url| url.host_str().map(String::from));
+        audit_log.record(&AuditRecord {
+            kind,
+            name,
+            hash,
+            source: host.as_deref().unwrap_or(source),
+            error,
+        });
     }
 
     /// Method to retrieve a file from the cache if exists, or from
     /// the repository if it doesn't. In case it has to be retrieved from
     /// the repository it won't be decompressed.
     pub fn retrieve_raw_file(&self, file_name: &str) -> CvmfsResult<String> {
-        let cache_file = self.cache.add(file_name);
-        let file_url = self.make_file_url(file_name);
-        Self::download_content_and_store(
-            cache_file.to_str().ok_or(CvmfsError::FileNotFound)?,
-            file_url.to_str().ok_or(CvmfsError::FileNotFound)?,
-        )?;
-        Ok(self
-            .cache
-            .get(file_name)
-            .ok_or(CvmfsError::FileNotFound)?
-            .to_str()
-            .ok_or(CvmfsError::FileNotFound)?
-            .into())
+        let (bytes, _) = self.download(file_name)?;
+        let cached_file = self.cache.store(file_name, &bytes)?;
+        Ok(cached_file.to_str().ok_or(CvmfsError::FileNotFound)?.into())
     }
 
     pub fn retrieve_file(&self, file_name: &str) -> CvmfsResult<String> {
-        if let Some(cached_file) = self.cache.get(file_name) {
+        let cached_file = self.cache.get(file_name);
+        metrics().record_cache_lookup(cached_file.is_some());
+        if let Some(cached_file) = cached_file {
             return Ok(cached_file.to_str().ok_or(CvmfsError::FileNotFound)?.into());
         }
         self.retrieve_file_from_source(file_name)
     }
 
-    fn make_file_url(&self, file_name: &str) -> PathBuf {
-        Path::join(self.source.as_ref(), file_name)
+    /// Downloads several files concurrently into the cache, skipping the ones
+    /// already cached. Failures are only logged, since prefetching is speculative.
+    pub fn prefetch(&self, file_names: &[String]) {
+        self.prefetch_with_concurrency(file_names, file_names.len());
+    }
+
+    /// Same as `prefetch`, with at most `concurrency` downloads in flight
+    #[cfg(not(feature = "async"))]
+    pub fn prefetch_with_concurrency(&self, file_names: &[String], concurrency: usize) {
+        use std::sync::atomic::AtomicUsize;
+        use std::thread;
+
+        let next = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            for _ in 0..concurrency.clamp(1, file_names.len().max(1)) {
+                scope.spawn(|| {
+                    while let Some(file_name) = file_names.get(next.fetch_add(1, Ordering::Relaxed))
+                    {
+                        if self.cache.get(file_name).is_some() {
+                            continue;
+                        }
+                        if let Err(e) = self.retrieve_file_from_source(file_name) {
+                            log::debug!("Could not prefetch {}: {:?}", file_name, e);
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    /// Same as `prefetch`, with at most `concurrency` downloads in flight on
+    /// the asynchronous runtime
+    #[cfg(feature = "async")]
+    pub fn prefetch_with_concurrency(&self, file_names: &[String], concurrency: usize) {
+        let fetcher = AsyncFetcher::new(self.clone());
+        match fetcher.retrieve_files_blocking(file_names, concurrency) {
+            Ok(results) => {
+                for (file_name, result) in file_names.iter().zip(results) {
+                    if let Err(e) = result {
+                        log::debug!("Could not prefetch {}: {:?}", file_name, e);
+                    }
+                }
+            }
+            Err(e) => log::debug!("Could not prefetch {} files: {:?}", file_names.len(), e),
+        }
+    }
+
+    /// Downloads a file from the first mirror able to serve it. Mirrors that
+    /// cannot be reached or fail with a server error are backed off from,
+    /// while the ones missing the file are only skipped. When no mirror can
+    /// be reached the fetcher goes offline for a while.
+    pub(crate) fn download(&self, file_name: &str) -> CvmfsResult<(Arc<[u8]>, String)> {
+        if self.is_offline() {
+            return Err(CvmfsError::Offline(file_name.into()));
+        }
+        let mut errors = Vec::new();
+        for index in self.mirrors.order() {
+            let file_url = self.mirror_url(index, file_name)?;
+            match self.get(&file_url) {
+                Ok(bytes) => {
+                    self.record_download_success(index);
+                    metrics().record_download(bytes.len());
+                    return Ok((Arc::from(bytes), file_url));
+                }
+                Err(e) => errors.push(self.record_download_failure(index, &file_url, e)),
+            }
+        }
+        Err(self.download_failed(file_name, errors))
+    }
+
+    /// Gets a url through the proxies, or directly without them
+    fn get(&self, url: &str) -> reqwest::Result<Vec<u8>> {
+        match &self.proxies {
+            Some(proxies) => proxies.get(url),
+            None => reqwest::blocking::get(url)
+                .and_then(|response| response.error_for_status())
+                .and_then(|response| response.bytes())
+                .map(|bytes| bytes.to_vec()),
+        }
+    }
+
+    /// Asks the GeoAPI of the mirrors, in turn until one answers, for their
+    /// order from the closest to the farthest from the client, or from its
+    /// proxy when going through one
+    pub fn geo_order(&self) -> CvmfsResult<Vec<usize>> {
+        let hosts = self
+            .mirrors
+            .urls()
+            .iter()
+            .map(|url| {
+                reqwest::Url::parse(url)
+                    .ok()
+                    .and_then(|parsed| parsed.host_str().map(String::from))
+                    .ok_or_else(|| {
+                        CvmfsError::InvalidConfiguration(format!("no host in mirror url {}", url))
+                    })
+            })
+            .collect::<CvmfsResult<Vec<_>>>()?;
+        let proxy = self
+            .proxies
+            .as_ref()
+            .map(|proxies| proxies.current())
+            .filter(|proxy| *proxy != DIRECT)
+            .and_then(|proxy| Some(reqwest::Url::parse(proxy).ok()?.host_str()?.to_string()))
+            .unwrap_or_else(|| GEO_API_NO_PROXY.into());
+        let query = format!("{}/{}/{}", GEO_API_PATH, proxy, hosts.join(","));
+        let mut last_error = None;
+        for index in self.mirrors.order() {
+            let url = self.mirror_url(index, &query)?;
+            match self.get(&url) {
+                Ok(reply) => return parse_geo_reply(&String::from_utf8_lossy(&reply), hosts.len()),
+                Err(e) => {
+                    log::debug!("Could not query {}: {:?}", url, e);
+                    last_error = Some(e.into());
+                }
+            }
+        }
+        Err(last_error.unwrap_or(CvmfsError::FileNotFound))
+    }
+
+    /// Makes the downloads try the closest mirrors first, according to the
+    /// GeoAPI. The mirrors keep their order when it cannot be queried.
+    pub fn sort_mirrors_by_geo(&self) -> CvmfsResult<()> {
+        if self.mirrors.urls().len() < 2 {
+            return Ok(());
+        }
+        let ranking = self.geo_order()?;
+        log::info!(
+            "Mirrors ordered by proximity: {}",
+            ranking
+                .iter()
+                .map(|index| self.mirrors.urls()[*index].as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        self.mirrors.set_ranking(ranking)
+    }
+
+    /// Url of a file on one of the mirrors
+    pub(crate) fn mirror_url(&self, index: usize, file_name: &str) -> CvmfsResult<String> {
+        let file_url = Path::join(self.mirrors.urls()[index].as_ref(), file_name);
+        Ok(file_url.to_str().ok_or(CvmfsError::FileNotFound)?.into())
+    }
+
+    pub(crate) fn record_download_success(&self, index: usize) {
+        self.mirrors.record_success(index);
+        self.record_connectivity(true);
+    }
+
+    /// Backs off from a mirror that cannot be reached or fails with a server
+    /// error, leaving the ones missing the file alone
+    pub(crate) fn record_download_failure(
+        &self,
+        index: usize,
+        file_url: &str,
+        error: reqwest::Error,
+    ) -> CvmfsError {
+        if error.status().is_none_or(|status| status.is_server_error()) {
+            self.mirrors.record_failure(index);
+        }
+        log::debug!("Could not download {}: {:?}", file_url, error);
+        error.into()
+    }
+
+    /// Error of a download no mirror could serve, going offline when none of
+    /// them could be reached
+    pub(crate) fn download_failed(&self, file_name: &str, errors: Vec<CvmfsError>) -> CvmfsError {
+        let reachable = errors.iter().any(|error| !error.is_unreachable());
+        self.record_connectivity(reachable);
+        let error = match errors.into_iter().last() {
+            Some(_) if !reachable => CvmfsError::Offline(file_name.into()),
+            error => error.unwrap_or(CvmfsError::FileNotFound),
+        };
+        metrics().record_error(&error);
+        error
+    }
+
+    /// Opens an object of the repository. On a cache miss the whole object is
+    /// downloaded and verified in memory, and served from there. Writing it to
+    /// the cache is left to the write-back pool while the queued objects fit in
+    /// `MAX_PENDING_WRITE_BYTES`, and done before returning otherwise, so that
+    /// a slow cache bounds the memory held by the queue.
+    pub fn retrieve_object(&self, file_name: &str) -> CvmfsResult<Box<dyn FileLike>> {
+        // keyed by the primary location, which does not change on failover
+        let cached_file = Path::new(&self.cache.cache_directory).join(file_name);
+        if let Some(content) = pending_write(&cached_file) {
+            metrics().record_cache_lookup(true);
+            return Ok(Box::new(MemoryFile::new(file_name, content)));
+        }
+        let path = self.cache.get(file_name);
+        metrics().record_cache_lookup(path.is_some());
+        if let Some(path) = path {
+            return Ok(Box::new(File::open(path)?));
+        }
+        let content = self.download_shared(file_name)?;
+        let queued = PENDING_WRITES
+            .lock()
+            .map_err(|_| CvmfsError::Sync)?
+            .try_insert(cached_file.clone(), content.clone());
+        if !queued {
+            if let Err(e) = self.cache.store(file_name, &content) {
+                log::warn!("Could not cache {}: {:?}", file_name, e);
+            }
+            return Ok(Box::new(MemoryFile::new(file_name, content)));
+        }
+        let pending = content.clone();
+        let cache = self.cache.clone();
+        let object_name = file_name.to_string();
+        WRITE_BACK_POOL
+            .get_or_init(|| Mutex::new(ThreadPool::new(WRITE_BACK_THREADS)))
+            .lock()
+            .map_err(|_| CvmfsError::Sync)?
+            .execute(move || {
+                if let Err(e) = cache.store(&object_name, &pending) {
+                    log::warn!("Could not cache {}: {:?}", object_name, e);
+                }
+                if let Ok(mut pending_writes) = PENDING_WRITES.lock() {
+                    pending_writes.remove(&cached_file);
+                }
+            });
+        Ok(Box::new(MemoryFile::new(file_name, content)))
+    }
+
+    /// Opens a file stored out of the content addressable storage, which the
+    /// external servers serve uncompressed at its path in the repository. It
+    /// is verified and cached as `object_name`, derived from its content hash.
+    pub fn retrieve_external(
+        &self,
+        path: &str,
+        object_name: &str,
+    ) -> CvmfsResult<Box<dyn FileLike>> {
+        let cached_file = self.cache.get(object_name);
+        metrics().record_cache_lookup(cached_file.is_some());
+        if let Some(cached_file) = cached_file {
+            return Ok(Box::new(File::open(cached_file)?));
+        }
+        let (file_bytes, file_url) = self.download(path.trim_start_matches('/'))?;
+        DOWNLOADS.with(|downloads| downloads.set(downloads.get() + 1));
+        if let Some((algorithm, expected)) =
+            expected_digest(object_name).filter(|_| self.content_validation.is_enabled())
+        {
+            let actual = algorithm.digest(&file_bytes);
+            self.check_digest(
+                object_name,
+                &file_bytes,
+                &file_url,
+                algorithm,
+                &expected,
+                actual,
+            )?;
+        }
+        self.cache.store(object_name, &file_bytes)?;
+        Ok(Box::new(MemoryFile::new(object_name, file_bytes)))
+    }
+
+    /// Number of objects downloaded so far by the calling thread
+    pub fn thread_downloads() -> u64 {
+        DOWNLOADS.with(Cell::get)
+    }
+
+    /// Number of downloads avoided so far because another fetcher sharing the
+    /// cache was already downloading the same object
+    pub fn deduplicated_downloads() -> u64 {
+        DEDUPLICATED_DOWNLOADS.load(Ordering::Relaxed)
+    }
+
+    /// Blocks until every object handed to the write-back pool is in the cache
+    pub fn flush_write_back() {
+        if let Some(pool) = WRITE_BACK_POOL.get() {
+            if let Ok(pool) = pool.lock() {
+                pool.join();
+            }
+        }
     }
 
     fn retrieve_file_from_source(&self, file_name: &str) -> CvmfsResult<String> {
-        let file_url = self.make_file_url(file_name);
-        let cached_file = self.cache.add(file_name);
-        Self::download_content_and_decompress(
-            cached_file.to_str().ok_or(CvmfsError::FileNotFound)?,
-            file_url.to_str().ok_or(CvmfsError::FileNotFound)?,
-        )?;
-        match self.cache.get(file_name) {
-            None => Err(CvmfsError::FileNotFound),
-            Some(file) => Ok(file.to_str().ok_or(CvmfsError::FileNotFound)?.into()),
+        let content = self.download_shared(file_name)?;
+        let cached_file = self.cache.store(file_name, &content)?;
+        Ok(cached_file.to_str().ok_or(CvmfsError::FileNotFound)?.into())
+    }
+
+    /// Downloads an object, unless a fetcher sharing the cache is already
+    /// downloading it, in which case its outcome is awaited instead. Objects
+    /// are content addressed, so the repository they come from is irrelevant.
+    fn download_shared(&self, file_name: &str) -> CvmfsResult<Arc<[u8]>> {
+        let key = (
+            Path::new(&self.cache.cache_directory).join(file_name),
+            self.content_validation.is_enabled(),
+        );
+        let (in_flight, leader) = {
+            let mut downloads = IN_FLIGHT.lock().map_err(|_| CvmfsError::Sync)?;
+            match downloads.get(&key) {
+                Some(in_flight) => (in_flight.clone(), false),
+                None => {
+                    let in_flight = Arc::new(InFlight::default());
+                    downloads.insert(key.clone(), in_flight.clone());
+                    (in_flight, true)
+                }
+            }
+        };
+        if !leader {
+            DEDUPLICATED_DOWNLOADS.fetch_add(1, Ordering::Relaxed);
+            return in_flight.wait();
         }
+        let result = self.download_object(file_name).map(Arc::from);
+        if let Ok(mut downloads) = IN_FLIGHT.lock() {
+            downloads.remove(&key);
+        }
+        in_flight.finish(result.clone());
+        result
     }
 
-    fn download_content_and_decompress(cached_file: &str, file_url: &str) -> CvmfsResult<()> {
-        let response = reqwest::blocking::get(file_url)?;
-        let file_bytes = response.bytes()?;
-        Self::decompress(file_bytes.as_ref(), cached_file)?;
-        Ok(())
+    /// Downloads and decompresses an object
+    fn download_object(&self, file_name: &str) -> CvmfsResult<Vec<u8>> {
+        let (file_bytes, file_url) = self.download(file_name)?;
+        DOWNLOADS.with(|downloads| downloads.set(downloads.get() + 1));
+        self.decode_object(file_name, file_bytes, &file_url)
     }
 
-    fn download_content_and_store(cached_file: &str, file_url: &str) -> CvmfsResult<()> {
-        let response = reqwest::blocking::get(file_url)?;
-        let content = response.bytes()?.to_vec();
-        fs::write(cached_file, content)?;
+    /// Decompresses a downloaded object. When verification is enabled the
+    /// compressed object is hashed on the verification pool while it is being
+    /// decompressed, and checked against its name with the validation mode.
+    pub(crate) fn decode_object(
+        &self,
+        file_name: &str,
+        file_bytes: Arc<[u8]>,
+        file_url: &str,
+    ) -> CvmfsResult<Vec<u8>> {
+        let Some((algorithm, expected)) =
+            expected_digest(file_name).filter(|_| self.content_validation.is_enabled())
+        else {
+            return Self::decompress(file_bytes.as_ref());
+        };
+        let (sender, receiver) = mpsc::channel();
+        let bytes = file_bytes.clone();
+        VERIFICATION_POOL
+            .get_or_init(|| Mutex::new(ThreadPool::new(VERIFICATION_THREADS)))
+            .lock()
+            .map_err(|_| CvmfsError::Sync)?
+            .execute(move || {
+                let _ = sender.send(algorithm.digest(&bytes));
+            });
+        let decompressed = Self::decompress(file_bytes.as_ref());
+        let actual = receiver.recv().map_err(|_| CvmfsError::Sync)?;
+        self.check_digest(
+            file_name,
+            &file_bytes,
+            file_url,
+            algorithm,
+            &expected,
+            actual,
+        )?;
+        decompressed
+    }
+
+    /// Checks the digest of a downloaded object against its name with the
+    /// validation mode, quarantining the object if they differ
+    fn check_digest(
+        &self,
+        file_name: &str,
+        file_bytes: &[u8],
+        file_url: &str,
+        algorithm: DigestAlgorithm,
+        expected: &str,
+        actual: Option<String>,
+    ) -> CvmfsResult<()> {
+        let kind = AuditKind::of_object(file_name);
+        let Some(actual) = actual else {
+            log::debug!(
+                "Cannot verify {}, {:?} is not available",
+                file_name,
+                algorithm
+            );
+            return Ok(());
+        };
+        if actual != expected {
+            let record = QuarantineRecord::new(file_url, expected, &actual);
+            if let Err(e) = self
+                .cache
+                .quarantine_content(file_name, file_bytes, &record)
+            {
+                log::warn!("Could not quarantine {}: {:?}", file_url, e);
+            }
+            let error = CvmfsError::ContentHashMismatch(file_url.into());
+            self.audit_from(file_url, kind, file_name, expected, Some(&error));
+            self.content_validation.apply(Err(error))?;
+        } else {
+            self.audit_from(file_url, kind, file_name, expected, None);
+        }
         Ok(())
     }
 
-    fn decompress(compressed_bytes: &[u8], cached_file: &str) -> CvmfsResult<()> {
+    fn decompress(compressed_bytes: &[u8]) -> CvmfsResult<Vec<u8>> {
         let mut decompressed = Vec::new();
-        zlib::Decoder::new(compressed_bytes).read_to_end(&mut decompressed)?;
-        fs::write(cached_file, decompressed)?;
-        Ok(())
+        ZlibDecoder::new(compressed_bytes).read_to_end(&mut decompressed)?;
+        Ok(decompressed)
     }
 }
+
+/// Content of an object still being written to the cache, if any
+fn pending_write(cached_file: &Path) -> Option<Arc<[u8]>> {
+    PENDING_WRITES
+        .lock()
+        .ok()?
+        .objects
+        .get(cached_file)
+        .cloned()
+}
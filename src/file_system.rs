@@ -1,57 +1,251 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::ffi::{OsStr, OsString};
 use std::io::{Read, Seek, SeekFrom};
-use std::path::Path;
-use std::sync::RwLock;
-use std::time::{Duration, SystemTime};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
 use chrono::{DateTime, Utc};
 use fuse_mt::{
     CallbackResult, FileAttr, FileType, FilesystemMT, RequestInfo, ResultData, ResultEmpty,
-    ResultEntry, ResultOpen, ResultReaddir, ResultSlice, ResultXattr,
+    ResultEntry, ResultOpen, ResultReaddir, ResultSlice, ResultXattr, Xattr,
 };
 use fuse_mt::{DirectoryEntry as FuseDirectoryEntry, ResultStatfs, Statfs};
 use rand::Rng;
 
-use crate::common::{CvmfsError, CvmfsResult, FileLike};
-use crate::directory_entry::DirectoryEntry;
-use crate::repository::Repository;
+use crate::access_log::{AccessLog, AccessOperation, AccessRecord};
+use crate::analytics::{Analytics, DEFAULT_TOP_PATHS};
+use crate::catalog::Statistics;
+use crate::common::{normalize_path, CvmfsError, CvmfsResult, FileLike};
+use crate::directory_entry::{DirectoryEntry, SpecialKind};
+use crate::fetcher::Fetcher;
+use crate::lru::LruCache;
+use crate::metrics::metrics;
+use crate::refresher::{self, RefresherHandle};
+use crate::repository::{MemoryUsage, Repository};
+use crate::revision_tag::RevisionTag;
+use crate::scrubber::ScrubberHandle;
+use crate::systemd::Notifier;
+use crate::variant_symlink::SymlinkVariables;
+use crate::xattr::XattrPolicy;
 
 const TTL: Duration = Duration::from_secs(1);
+/// Capacity kept by the per-thread read buffer between reads
+const MAX_POOLED_READ_BUFFER: usize = 1 << 20;
+pub const CONTROL_DIRECTORY: &str = "/.cvmfs";
+pub const SNAPSHOTS_DIRECTORY: &str = "/.cvmfs/snapshots";
+/// Daemon log category of the FUSE callbacks, which log every call at the
+/// debug and trace levels, e.g. `file_system::fuse=trace`
+pub const FUSE_LOG_TARGET: &str = "cvmfs::file_system::fuse";
+/// Paths whose lookup is remembered, see `CernvmFileSystem::cached_lookup`
+pub const DEFAULT_ATTRIBUTE_CACHE_SIZE: usize = 16384;
+
+/// Outcome of a lookup, `None` for a missing path, along with the root catalog
+/// of the revision it was looked up in
+type CachedLookup = (Arc<str>, Option<DirectoryEntry>);
+
+/// Location of a path of the mount point, which can belong to the current
+/// revision or to the snapshot of a tag under `/.cvmfs/snapshots/<tag>`
+#[derive(Debug, PartialEq)]
+pub enum VirtualPath<'a> {
+    Current(&'a str),
+    /// One of the directories above the snapshots
+    Directory(&'a str),
+    Snapshot {
+        tag: &'a str,
+        path: &'a str,
+    },
+}
+
+impl<'a> VirtualPath<'a> {
+    pub fn parse(path: &'a str) -> Self {
+        if path == CONTROL_DIRECTORY || path == SNAPSHOTS_DIRECTORY {
+            return VirtualPath::Directory(path);
+        }
+        let Some(snapshot) = path
+            .strip_prefix(SNAPSHOTS_DIRECTORY)
+            .and_then(|rest| rest.strip_prefix('/'))
+        else {
+            return VirtualPath::Current(path);
+        };
+        match snapshot.find('/') {
+            Some(index) => VirtualPath::Snapshot {
+                tag: &snapshot[..index],
+                path: &snapshot[index..],
+            },
+            None => VirtualPath::Snapshot {
+                tag: snapshot,
+                path: "/",
+            },
+        }
+    }
+}
 
 fn map_dirent_type_to_fs_kind(dirent: &DirectoryEntry) -> FileType {
     if dirent.is_directory() {
         FileType::Directory
     } else if dirent.is_symlink() {
         FileType::Symlink
-    } else if dirent.is_file() {
-        FileType::RegularFile
     } else {
-        FileType::RegularFile
+        match dirent.special_kind() {
+            Some(SpecialKind::CharDevice) => FileType::CharDevice,
+            Some(SpecialKind::BlockDevice) => FileType::BlockDevice,
+            Some(SpecialKind::NamedPipe) => FileType::NamedPipe,
+            Some(SpecialKind::Socket) => FileType::Socket,
+            None => FileType::RegularFile,
+        }
     }
 }
 
+/// Extended attributes exposed on the mount point root, describing the tag
+/// being served
+pub fn tag_xattrs(tag: &RevisionTag) -> Vec<(&'static str, String)> {
+    vec![
+        ("user.tag", tag.name.clone()),
+        ("user.tag_hash", tag.hash.clone()),
+        ("user.tag_revision", tag.revision.to_string()),
+        ("user.tag_timestamp", tag.timestamp.to_string()),
+        ("user.tag_channel", tag.channel.to_string()),
+        ("user.tag_description", tag.description.clone()),
+    ]
+}
+
+/// Value of `user.catalogue_counters`, the statistics of the catalog serving
+/// a path in the `cvmfs_talk` format
+pub fn catalogue_counters(statistics: &Statistics) -> String {
+    [
+        ("regular", statistics.regular),
+        ("symlink", statistics.symlink),
+        ("special", statistics.special),
+        ("dir", statistics.dir),
+        ("nested", statistics.nested),
+        ("chunked", statistics.chunked),
+        ("chunks", statistics.chunks),
+        ("file_size", statistics.file_size),
+        ("chunked_size", statistics.chunked_size),
+        ("xattr", statistics.xattr),
+        ("external", statistics.external),
+        ("external_file_size", statistics.external_file_size),
+    ]
+    .iter()
+    .map(|(name, value)| format!("{}: {}\n", name, value))
+    .collect()
+}
+
+fn xattr_reply(data: Vec<u8>, size: u32) -> ResultXattr {
+    if size == 0 {
+        Ok(Xattr::Size(data.len() as u32))
+    } else if data.len() > size as usize {
+        Err(libc::ERANGE)
+    } else {
+        Ok(Xattr::Data(data))
+    }
+}
+
+/// File opened for a path with the given handle
+fn find_opened<'a>(
+    opened_files: &'a HashMap<String, Vec<OpenedFile>>,
+    path: &str,
+    fh: u64,
+) -> Option<&'a OpenedFile> {
+    opened_files
+        .get(path)?
+        .iter()
+        .find(|opened| opened.handle == fh)
+}
+
+thread_local! {
+    /// Scratch buffer reused by the reads served on each FUSE thread
+    static READ_BUFFER: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A file opened by one or more handles of the same revision, which share it.
+/// The file has a lock of its own, taken exclusively only by the reads that
+/// need to seek, so that they don't block the other files.
+#[derive(Debug)]
+struct OpenedFile {
+    file: RwLock<Box<dyn FileLike>>,
+    handles: usize,
+    /// Root catalog hash of the revision the file was opened in
+    revision: String,
+    /// Handle returned to FUSE, the descriptor of the file
+    handle: u64,
+}
+
 #[derive(Debug)]
 pub struct CernvmFileSystem {
-    repository: RwLock<Repository>,
-    opened_files: RwLock<HashMap<String, Box<dyn FileLike>>>,
+    repository: Arc<RwLock<Repository>>,
+    /// Files opened by path, one per revision still in use
+    opened_files: RwLock<HashMap<String, Vec<OpenedFile>>>,
+    access_log: Option<AccessLog>,
+    /// Repository directory exposed as the root of the mount, see `set_subpath`
+    subpath: Option<String>,
+    xattr_policy: XattrPolicy,
+    /// Recent lookups of the mount point paths, see `cached_lookup`
+    attribute_cache: Mutex<LruCache<String, CachedLookup>>,
+    /// Background scrubber of the cache, stopped with the file system
+    scrubber: Option<ScrubberHandle>,
+    /// Background refresh of the revision, stopped with the file system
+    refresher: Option<RefresherHandle>,
+    /// Service manager told when the mount is ready and when it stops
+    notifier: Option<Arc<Notifier>>,
+    /// Usage collected over the lifetime of the mount, and the file its report
+    /// is written to on unmount
+    analytics: Option<(Arc<Analytics>, Option<PathBuf>)>,
+    /// Values of the placeholders of the variant symlinks
+    symlink_variables: SymlinkVariables,
 }
 
 impl FilesystemMT for CernvmFileSystem {
+    fn init(&self, _req: RequestInfo) -> ResultEmpty {
+        if let Some(notifier) = &self.notifier {
+            if let Err(e) = notifier.ready() {
+                log::warn!("Could not notify systemd of the mount: {:?}", e);
+            }
+        }
+        Ok(())
+    }
+
     fn destroy(&self) {
+        if let Some(notifier) = &self.notifier {
+            let _ = notifier.stopping();
+        }
         if let Ok(mut f) = self.opened_files.write() {
             f.drain();
         };
+        Fetcher::flush_write_back();
+        if let Ok(repo) = self.repository.read() {
+            if let Err(e) = repo.store_catalog_set() {
+                log::warn!("Could not persist the opened catalogs: {:?}", e);
+            }
+        }
+        if let Some((analytics, Some(report))) = &self.analytics {
+            if let Err(e) = analytics.write_report(report, DEFAULT_TOP_PATHS) {
+                log::warn!("Could not write the analytics report {:?}: {:?}", report, e);
+            }
+        }
     }
 
     fn getattr(&self, _req: RequestInfo, path: &Path, _fh: Option<u64>) -> ResultEntry {
+        let _timer = metrics().time_request("getattr");
         let path = path.to_str().ok_or(CvmfsError::FileNotFound)?;
-        log::info!("Getting attribute of path: {path}");
-        let mut repo = self
-            .repository
-            .write()
-            .map_err(|e| CvmfsError::Generic(format!("{:?}", e)))?;
-        let result = repo.lookup(path)?;
+        log::trace!(target: FUSE_LOG_TARGET, "Getting attribute of path: {path}");
+        let started = Instant::now();
+        let downloads = Fetcher::thread_downloads();
+        let result = self.cached_lookup(path);
+        self.log_access(
+            AccessOperation::Lookup,
+            path,
+            (started, downloads),
+            result
+                .as_ref()
+                .map(|dirent| dirent.size)
+                .map_err(|e| e.clone().into()),
+        );
+        let result = result?;
         let date_time: DateTime<Utc> =
             DateTime::from_timestamp(result.mtime, 0).ok_or(CvmfsError::InvalidTimestamp)?;
         let time = SystemTime::from(date_time);
@@ -64,20 +258,20 @@ impl FilesystemMT for CernvmFileSystem {
             crtime: time,
             kind: map_dirent_type_to_fs_kind(&result),
             perm: result.mode & 0o7777,
-            nlink: 0,
+            nlink: result.nlink(),
             uid: 0,
             gid: 0,
-            rdev: 1,
+            rdev: result.rdev as u32,
             flags: result.flags,
         };
         Ok((TTL, file_attr))
     }
 
     fn readlink(&self, _req: RequestInfo, path: &Path) -> ResultData {
+        let _timer = metrics().time_request("readlink");
         let path = path.to_str().ok_or(CvmfsError::FileNotFound)?;
-        log::info!("Reading link: {path}");
-        let mut repo = self.repository.write().map_err(|_| CvmfsError::Sync)?;
-        let result = repo.lookup(path)?;
+        log::trace!(target: FUSE_LOG_TARGET, "Reading link: {path}");
+        let result = self.cached_lookup(path)?;
         if !result.is_symlink() {
             return Err(libc::ENOLINK);
         }
@@ -85,73 +279,88 @@ impl FilesystemMT for CernvmFileSystem {
     }
 
     fn open(&self, _req: RequestInfo, path: &Path, _flags: u32) -> ResultOpen {
+        let _timer = metrics().time_request("open");
         let path = path.to_str().ok_or(CvmfsError::FileNotFound)?;
-        log::info!("Opening file: {path}");
-        let mut repo = self.repository.write().map_err(|_| CvmfsError::Sync)?;
-        let result = repo.lookup(path)?;
-        if !result.is_file() {
-            return Err(libc::ENOENT);
+        log::debug!(target: FUSE_LOG_TARGET, "Opening file: {path}");
+        let started = Instant::now();
+        let downloads = Fetcher::thread_downloads();
+        let repo = self.repository.read().map_err(|_| CvmfsError::Sync)?;
+        let result = self.open_file(&repo, path);
+        self.log_access(
+            AccessOperation::Open,
+            path,
+            (started, downloads),
+            result.as_ref().map(|(size, _)| *size).map_err(|e| *e),
+        );
+        let (_, file) = result?;
+        if let Some((analytics, _)) = &self.analytics {
+            analytics.record_open(path, Fetcher::thread_downloads() == downloads);
         }
-        let file = repo.get_file(path)?;
-        let fd = file.as_raw_fd() as u64;
-        self.opened_files
-            .write()
-            .map_err(|_| CvmfsError::Sync)?
-            .insert(path.into(), file);
-        Ok((fd, 0))
+        let (root_hash, resolved) = self.resolve(&repo, path)?;
+        if let Err(e) = repo.prefetch_siblings_at(&root_hash, &resolved) {
+            log::debug!("Could not prefetch the siblings of {}: {:?}", resolved, e);
+        }
+        let mut opened_files = self.opened_files.write().map_err(|_| CvmfsError::Sync)?;
+        let revisions = opened_files.entry(path.into()).or_default();
+        // handles opened before a revision switch keep reading their own file
+        let index = match revisions
+            .iter()
+            .position(|opened| opened.revision == root_hash)
+        {
+            Some(index) => index,
+            None => {
+                revisions.push(OpenedFile {
+                    handle: file.as_raw_fd() as u64,
+                    file: RwLock::new(file),
+                    handles: 0,
+                    revision: root_hash,
+                });
+                revisions.len() - 1
+            }
+        };
+        revisions[index].handles += 1;
+        let fh = revisions[index].handle;
+        metrics().set_open_files(opened_files.values().map(Vec::len).sum());
+        Ok((fh, 0))
     }
 
     fn read(
         &self,
         _req: RequestInfo,
         path: &Path,
-        _fh: u64,
+        fh: u64,
         offset: u64,
         size: u32,
         callback: impl FnOnce(ResultSlice<'_>) -> CallbackResult,
     ) -> CallbackResult {
+        let _timer = metrics().time_request("read");
         let path = match path.to_str() {
             Some(p) => p,
             None => return callback(Err(libc::ENOENT)),
         };
-        log::info!("Reading file: {path}");
-        let mut opened_files = match self.opened_files.write() {
-            Ok(guard) => guard,
-            Err(e) => {
-                log::error!("{:?}", e);
-                return callback(Err(libc::EIO));
-            }
-        };
-        let file = match opened_files.get_mut(path) {
-            Some(f) => f,
-            None => return callback(Err(libc::ENOENT)),
-        };
-
-        let mut data = vec![0u8; size as usize];
-        if let Err(e) = file.seek(SeekFrom::Start(offset)) {
-            log::error!("{:?}", e);
-            return callback(Err(match e.raw_os_error() {
-                Some(code) => code,
-                None => libc::EIO,
-            }));
-        }
-        let bytes_read = match file.read(&mut data) {
-            Ok(n) => n,
-            Err(e) => {
-                log::error!("{:?}", e);
-                return callback(Err(match e.raw_os_error() {
-                    Some(code) => code,
-                    None => libc::EIO,
-                }));
-            }
-        };
-
-        callback(Ok(&data[0..bytes_read]))
+        log::trace!(target: FUSE_LOG_TARGET, "Reading file: {path}");
+        READ_BUFFER.with(|buffer| {
+            let mut data = buffer.borrow_mut();
+            data.clear();
+            data.resize(size as usize, 0);
+            let result = match self.read_into(path, fh, offset, &mut data) {
+                Ok(bytes_read) => {
+                    if let Some((analytics, _)) = &self.analytics {
+                        analytics.record_read(path, bytes_read as u64);
+                    }
+                    callback(Ok(&data[0..bytes_read]))
+                }
+                Err(code) => callback(Err(code)),
+            };
+            data.truncate(0);
+            data.shrink_to(MAX_POOLED_READ_BUFFER);
+            result
+        })
     }
 
     fn flush(&self, _req: RequestInfo, path: &Path, _fh: u64, _lock_owner: u64) -> ResultEmpty {
         let path = path.to_str().ok_or(libc::ENOENT)?;
-        log::info!("Flushing file: {path}");
+        log::trace!(target: FUSE_LOG_TARGET, "Flushing file: {path}");
         Ok(())
     }
 
@@ -159,38 +368,46 @@ impl FilesystemMT for CernvmFileSystem {
         &self,
         _req: RequestInfo,
         path: &Path,
-        _fh: u64,
+        fh: u64,
         _flags: u32,
         _lock_owner: u64,
         _flush: bool,
     ) -> ResultEmpty {
+        let _timer = metrics().time_request("release");
         let path = path.to_str().ok_or(libc::ENOENT)?;
-        log::info!("Releasing: {path}");
-        match self
-            .opened_files
-            .write()
-            .map_err(|e| {
-                log::error!("{:?}", e);
-                libc::EIO
-            })?
-            .remove(path)
-        {
-            None => Err(libc::ENOENT),
-            Some(_) => Ok(()),
+        log::debug!(target: FUSE_LOG_TARGET, "Releasing: {path}");
+        let mut opened_files = self.opened_files.write().map_err(|e| {
+            log::error!("{:?}", e);
+            libc::EIO
+        })?;
+        let revisions = opened_files.get_mut(path).ok_or(libc::ENOENT)?;
+        let index = revisions
+            .iter()
+            .position(|opened| opened.handle == fh)
+            .ok_or(libc::EBADF)?;
+        revisions[index].handles -= 1;
+        if revisions[index].handles == 0 {
+            revisions.swap_remove(index);
+            if revisions.is_empty() {
+                opened_files.remove(path);
+            }
+            metrics().set_open_files(opened_files.values().map(Vec::len).sum());
         }
+        Ok(())
     }
 
     fn opendir(&self, _req: RequestInfo, path: &Path, _flags: u32) -> ResultOpen {
+        let _timer = metrics().time_request("opendir");
         let path = path.to_str().ok_or(libc::ENOENT)?;
-        log::info!("Opening directory: {path}");
-        let mut repo = match self.repository.write() {
+        log::debug!(target: FUSE_LOG_TARGET, "Opening directory: {path}");
+        let repo = match self.repository.read() {
             Ok(repo) => repo,
             Err(e) => {
                 log::error!("{:?}", e);
                 return Err(libc::EIO);
             }
         };
-        let result = repo.lookup(path)?;
+        let result = self.lookup(&repo, path)?;
         if !result.is_directory() {
             return Err(libc::ENOENT);
         }
@@ -201,27 +418,23 @@ impl FilesystemMT for CernvmFileSystem {
     }
 
     fn readdir(&self, _req: RequestInfo, path: &Path, _fh: u64) -> ResultReaddir {
+        let _timer = metrics().time_request("readdir");
         let path = path.to_str().ok_or(libc::ENOENT)?;
-        log::info!("Reading directory: {path}");
-        let mut repo = self.repository.write().map_err(|_| libc::EIO)?;
-        let result = repo.lookup(path)?;
+        log::debug!(target: FUSE_LOG_TARGET, "Reading directory: {path}");
+        let repo = self.repository.read().map_err(|_| libc::EIO)?;
+        let result = self.lookup(&repo, path)?;
         if !result.is_directory() {
             log::error!("Path '{path}' is not a directory");
             return Err(libc::ENOENT);
         }
-        match repo.list_directory(path) {
-            Ok(entries) => Ok(entries
-                .into_iter()
-                .map(|dirent| FuseDirectoryEntry {
-                    kind: map_dirent_type_to_fs_kind(&dirent),
-                    name: OsString::from(dirent.name),
-                })
-                .collect()),
-            Err(e) => {
-                log::error!("Could not list directory {path}: {:?}", e);
-                Err(e.into())
-            }
-        }
+        let to_fuse_entry = |dirent: DirectoryEntry| FuseDirectoryEntry {
+            kind: map_dirent_type_to_fs_kind(&dirent),
+            name: OsString::from(dirent.name),
+        };
+        self.map_directory(&repo, path, to_fuse_entry).map_err(|e| {
+            log::error!("Could not list directory {path}: {:?}", e);
+            e.into()
+        })
     }
 
     fn releasedir(&self, _req: RequestInfo, _path: &Path, _fh: u64, _flags: u32) -> ResultEmpty {
@@ -229,8 +442,9 @@ impl FilesystemMT for CernvmFileSystem {
     }
 
     fn statfs(&self, _req: RequestInfo, _path: &Path) -> ResultStatfs {
-        log::info!("Getting FS statistics");
-        let mut repo = self.repository.write().map_err(|_| libc::EIO)?;
+        let _timer = metrics().time_request("statfs");
+        log::debug!(target: FUSE_LOG_TARGET, "Getting FS statistics");
+        let repo = self.repository.read().map_err(|_| libc::EIO)?;
         let statistics = repo.get_statistics()?;
         Ok(Statfs {
             blocks: 1 + statistics.file_size / 512,
@@ -244,23 +458,405 @@ impl FilesystemMT for CernvmFileSystem {
         })
     }
 
-    fn getxattr(&self, _req: RequestInfo, _path: &Path, _name: &OsStr, _size: u32) -> ResultXattr {
-        Err(libc::ENODATA)
+    fn getxattr(&self, _req: RequestInfo, path: &Path, name: &OsStr, size: u32) -> ResultXattr {
+        let _timer = metrics().time_request("getxattr");
+        if let Some(value) = name
+            .to_str()
+            .and_then(|name| self.xattr_policy.resolve(name))
+        {
+            return xattr_reply(value?, size);
+        }
+        let path = path.to_str().ok_or(libc::ENOENT)?;
+        let repo = self.repository.read().map_err(|_| libc::EIO)?;
+        let value = self
+            .user_xattrs(&repo, path)?
+            .into_iter()
+            .find(|(attribute, _)| name == OsStr::new(attribute))
+            .map(|(_, value)| value)
+            .ok_or(libc::ENODATA)?;
+        xattr_reply(value.into_bytes(), size)
+    }
+
+    fn listxattr(&self, _req: RequestInfo, path: &Path, size: u32) -> ResultXattr {
+        let _timer = metrics().time_request("listxattr");
+        let mut names = Vec::new();
+        for attribute in self.xattr_policy.listed() {
+            names.extend_from_slice(attribute.as_bytes());
+            names.push(0);
+        }
+        let path = path.to_str().ok_or(libc::ENOENT)?;
+        let repo = self.repository.read().map_err(|_| libc::EIO)?;
+        for (attribute, _) in self.user_xattrs(&repo, path)? {
+            names.extend_from_slice(attribute.as_bytes());
+            names.push(0);
+        }
+        xattr_reply(names, size)
     }
 
     fn access(&self, _req: RequestInfo, path: &Path, _mask: u32) -> ResultEmpty {
+        let _timer = metrics().time_request("access");
         let path = path.to_str().ok_or(libc::ENOENT)?;
-        log::info!("Accessing: {path}");
-        let mut repo = self.repository.write().map_err(|_| libc::EIO)?;
-        repo.lookup(path).map(|_| Ok(()))?
+        log::trace!(target: FUSE_LOG_TARGET, "Accessing: {path}");
+        self.cached_lookup(path).map(|_| Ok(()))?
     }
 }
 
 impl CernvmFileSystem {
     pub fn new(repository: Repository) -> CvmfsResult<Self> {
-        Ok(Self {
-            repository: RwLock::new(repository),
+        let file_system = Self {
+            repository: Arc::new(RwLock::new(repository)),
             opened_files: Default::default(),
+            access_log: None,
+            subpath: None,
+            xattr_policy: Default::default(),
+            attribute_cache: Mutex::new(LruCache::new(DEFAULT_ATTRIBUTE_CACHE_SIZE)),
+            scrubber: None,
+            refresher: None,
+            notifier: None,
+            analytics: None,
+            symlink_variables: Default::default(),
+        };
+        file_system.spawn_warm_start();
+        Ok(file_system)
+    }
+
+    /// Records the lookups and opens served from now on
+    pub fn set_access_log(&mut self, access_log: AccessLog) {
+        self.access_log = Some(access_log);
+    }
+
+    /// Collects the usage of the mount, writing its report to a file on
+    /// unmount if given. The report is also available from the control socket.
+    pub fn set_analytics(
+        &mut self,
+        analytics: Arc<Analytics>,
+        report: Option<PathBuf>,
+    ) -> CvmfsResult<()> {
+        self.repository
+            .write()
+            .map_err(|_| CvmfsError::Sync)?
+            .analytics = Some(analytics.clone());
+        self.analytics = Some((analytics, report));
+        Ok(())
+    }
+
+    pub fn set_notifier(&mut self, notifier: Arc<Notifier>) {
+        self.notifier = Some(notifier);
+    }
+
+    pub fn set_scrubber(&mut self, scrubber: ScrubberHandle) {
+        self.scrubber = Some(scrubber);
+    }
+
+    /// Switches to the new revisions of the repository as their TTL expires
+    pub fn spawn_refresher(&mut self) {
+        self.refresher = Some(refresher::spawn(self.repository.clone()));
+    }
+
+    /// Changes the number of mount point paths whose lookup is remembered
+    pub fn set_attribute_cache_size(&mut self, size: usize) {
+        self.attribute_cache = Mutex::new(LruCache::new(size));
+    }
+
+    /// Changes the answers to the `security.*` and `system.*` attribute queries
+    pub fn set_xattr_policy(&mut self, xattr_policy: XattrPolicy) {
+        self.xattr_policy = xattr_policy;
+    }
+
+    /// Changes the values of the placeholders of the variant symlinks, which
+    /// are otherwise taken from the environment
+    pub fn set_symlink_variables(&mut self, symlink_variables: SymlinkVariables) {
+        self.symlink_variables = symlink_variables;
+    }
+
+    /// Exposes only a subtree of the repository, which becomes the root of
+    /// the mount. Snapshots are restricted to the same subtree.
+    pub fn set_subpath(&mut self, subpath: &str) -> CvmfsResult<()> {
+        let subpath = normalize_path(subpath);
+        if subpath == "/" || subpath.is_empty() {
+            self.subpath = None;
+            return Ok(());
+        }
+        if !subpath.starts_with('/') {
+            return Err(CvmfsError::InvalidConfiguration(format!(
+                "the subpath {} is not absolute",
+                subpath
+            )));
+        }
+        let repo = self.repository.read().map_err(|_| CvmfsError::Sync)?;
+        if !repo.lookup(&subpath)?.is_directory() {
+            return Err(CvmfsError::InvalidConfiguration(format!(
+                "the subpath {} is not a directory",
+                subpath
+            )));
+        }
+        self.subpath = Some(subpath.into_owned());
+        Ok(())
+    }
+
+    /// Path in the repository of a path of the mount point
+    fn scoped<'a>(&self, path: &'a str) -> Cow<'a, str> {
+        match &self.subpath {
+            None => Cow::Borrowed(path),
+            Some(subpath) if path == "/" => Cow::Owned(subpath.clone()),
+            Some(subpath) => Cow::Owned(format!("{}{}", subpath, path)),
+        }
+    }
+
+    /// Writes an operation to the access log, if any. It is a cache hit when
+    /// the thread did not download anything since the operation started.
+    fn log_access(
+        &self,
+        operation: AccessOperation,
+        path: &str,
+        (started, downloads): (Instant, u64),
+        result: Result<u64, i32>,
+    ) {
+        let Some(access_log) = &self.access_log else {
+            return;
+        };
+        access_log.record(&AccessRecord {
+            operation,
+            path,
+            size: *result.as_ref().unwrap_or(&0),
+            cache_hit: Fetcher::thread_downloads() == downloads,
+            latency: started.elapsed(),
+            errno: result.err(),
+        });
+    }
+
+    /// Pre-opens in the background the cached catalogs used by the last mount,
+    /// taking the lock once per catalog so that requests are not blocked
+    fn spawn_warm_start(&self) {
+        let repository = self.repository.clone();
+        thread::spawn(move || {
+            let Some(catalog_set) = repository
+                .read()
+                .ok()
+                .and_then(|repo| repo.load_catalog_set())
+            else {
+                return;
+            };
+            log::info!("Pre-opening {} catalogs", catalog_set.catalog_hashes.len());
+            for catalog_hash in catalog_set.catalog_hashes {
+                let Ok(repo) = repository.read() else {
+                    return;
+                };
+                if let Err(e) = repo.open_cached_catalog(&catalog_hash) {
+                    log::debug!("Could not pre-open catalog {}: {:?}", catalog_hash, e);
+                }
+            }
+        });
+    }
+
+    /// Memory used by the repository state and the number of opened files
+    pub fn memory_usage(&self) -> CvmfsResult<MemoryUsage> {
+        let mut usage = self
+            .repository
+            .read()
+            .map_err(|_| CvmfsError::Sync)?
+            .memory_usage();
+        usage.open_files = self
+            .opened_files
+            .read()
+            .map_err(|_| CvmfsError::Sync)?
+            .values()
+            .map(Vec::len)
+            .sum();
+        Ok(usage)
+    }
+
+    /// Reads from the file opened with the given handle into the buffer,
+    /// returning the errno on failure. Plain files are read with pread under
+    /// the shared lock of the file, while chunked files need to seek and
+    /// therefore its exclusive lock. FUSE reads are served with it.
+    pub fn read_into(
+        &self,
+        path: &str,
+        fh: u64,
+        offset: u64,
+        data: &mut [u8],
+    ) -> Result<usize, i32> {
+        let opened_files = self.opened_files.read().map_err(|e| {
+            log::error!("{:?}", e);
+            libc::EIO
+        })?;
+        let opened = find_opened(&opened_files, path, fh).ok_or(libc::ENOENT)?;
+        let positional = opened
+            .file
+            .read()
+            .map_err(|e| {
+                log::error!("{:?}", e);
+                libc::EIO
+            })?
+            .read_at(data, offset);
+        let result = match positional {
+            Some(result) => result,
+            None => {
+                let mut file = opened.file.write().map_err(|e| {
+                    log::error!("{:?}", e);
+                    libc::EIO
+                })?;
+                file.seek(SeekFrom::Start(offset))
+                    .and_then(|_| file.read(data))
+            }
+        };
+        result.map_err(|e| {
+            log::error!("{:?}", e);
+            e.raw_os_error().unwrap_or(libc::EIO)
         })
     }
+
+    /// Shared handle to the repository, for components living next to the mount
+    pub fn repository(&self) -> Arc<RwLock<Repository>> {
+        self.repository.clone()
+    }
+
+    /// Root catalog hash of the revision serving a path, along with the path
+    /// inside that revision, below the subpath if any
+    fn resolve<'a>(&self, repo: &Repository, path: &'a str) -> CvmfsResult<(String, Cow<'a, str>)> {
+        match VirtualPath::parse(path) {
+            VirtualPath::Current(path) => {
+                Ok((repo.get_root_hash()?.to_string(), self.scoped(path)))
+            }
+            VirtualPath::Snapshot { tag, path } => {
+                Ok((repo.get_tag_by_name(tag)?.hash, self.scoped(path)))
+            }
+            VirtualPath::Directory(_) => Err(CvmfsError::FileNotFound),
+        }
+    }
+
+    fn lookup(&self, repo: &Repository, path: &str) -> CvmfsResult<DirectoryEntry> {
+        if let VirtualPath::Directory(path) = VirtualPath::parse(path) {
+            let name = path.rsplit('/').next().unwrap_or_default();
+            return Ok(DirectoryEntry::virtual_directory(
+                name,
+                repo.manifest.last_modified.timestamp(),
+            ));
+        }
+        let (root_hash, path) = self.resolve(repo, path)?;
+        repo.lookup_at(&root_hash, &path)
+    }
+
+    /// Virtual `user.*` attributes of a path: the revision and the catalog
+    /// serving it, and the content of regular files. The root also describes
+    /// the tag being served.
+    fn user_xattrs(
+        &self,
+        repo: &Repository,
+        path: &str,
+    ) -> CvmfsResult<Vec<(&'static str, String)>> {
+        let mut entry = self.lookup(repo, path)?;
+        let tag = match VirtualPath::parse(path) {
+            VirtualPath::Snapshot { tag, .. } => repo.get_tag_by_name(tag)?,
+            _ => repo.current_tag()?.clone(),
+        };
+        let mut xattrs = if path == "/" {
+            tag_xattrs(&tag)
+        } else {
+            vec![("user.tag", tag.name.clone())]
+        };
+        xattrs.extend([
+            ("user.revision", tag.revision.to_string()),
+            ("user.root_hash", tag.hash),
+            (
+                "user.expires",
+                (repo.time_to_refresh().as_secs() / 60).to_string(),
+            ),
+            ("user.host", repo.host().to_string()),
+        ]);
+        if let VirtualPath::Directory(_) = VirtualPath::parse(path) {
+            return Ok(xattrs);
+        }
+        let (root_hash, path) = self.resolve(repo, path)?;
+        let path = if path == "/" { "" } else { &path };
+        let catalog = repo.retrieve_catalog_for_path_at(&root_hash, path)?;
+        xattrs.push((
+            "user.catalogue_counters",
+            catalogue_counters(&catalog.get_statistics()?),
+        ));
+        if entry.is_file() {
+            catalog.load_chunks(&mut entry)?;
+            if let Some(hash) = entry.content_hash_string() {
+                xattrs.push(("user.hash", hash));
+            }
+            xattrs.push(("user.chunks", entry.chunks.len().max(1).to_string()));
+        }
+        Ok(xattrs)
+    }
+
+    /// Looks up a path, answering the repeated lookups of the same revision
+    /// from memory, the failed ones included. A new revision or tag
+    /// invalidates the remembered ones.
+    fn cached_lookup(&self, path: &str) -> CvmfsResult<DirectoryEntry> {
+        let repo = self.repository.read().map_err(|_| CvmfsError::Sync)?;
+        let root_hash: Arc<str> = repo.get_root_hash()?.into();
+        let key = path.to_string();
+        let cached = self
+            .attribute_cache
+            .lock()
+            .map_err(|_| CvmfsError::Sync)?
+            .get(&key);
+        if let Some((revision, entry)) = cached {
+            if revision == root_hash {
+                return entry.ok_or(CvmfsError::FileNotFound);
+            }
+        }
+        let result = self.lookup(&repo, path).map(|mut entry| {
+            entry.expand_symlink(&self.symlink_variables);
+            entry
+        });
+        let entry = match &result {
+            Ok(entry) => Some(entry.clone()),
+            Err(CvmfsError::FileNotFound) => None,
+            Err(_) => return result,
+        };
+        self.attribute_cache
+            .lock()
+            .map_err(|_| CvmfsError::Sync)?
+            .insert(key, (root_hash, entry));
+        result
+    }
+
+    fn map_directory<T>(
+        &self,
+        repo: &Repository,
+        path: &str,
+        mut f: impl FnMut(DirectoryEntry) -> T,
+    ) -> CvmfsResult<Vec<T>> {
+        let mtime = repo.manifest.last_modified.timestamp();
+        match VirtualPath::parse(path) {
+            VirtualPath::Directory(CONTROL_DIRECTORY) => Ok(vec![f(
+                DirectoryEntry::virtual_directory("snapshots", mtime),
+            )]),
+            VirtualPath::Directory(_) => Ok(repo
+                .list_tags()?
+                .into_iter()
+                .map(|tag| {
+                    f(DirectoryEntry::virtual_directory(
+                        &tag.name,
+                        tag.timestamp as i64,
+                    ))
+                })
+                .collect()),
+            _ => {
+                let (root_hash, path) = self.resolve(repo, path)?;
+                repo.map_directory_at(&root_hash, &path, f)
+            }
+        }
+    }
+
+    /// Size and contents of a regular file
+    fn open_file(&self, repo: &Repository, path: &str) -> Result<(u64, Box<dyn FileLike>), i32> {
+        let result = self.lookup(repo, path)?;
+        if !result.is_file() {
+            return Err(libc::ENOENT);
+        }
+        Ok((result.size, self.get_file(repo, path)?))
+    }
+
+    fn get_file(&self, repo: &Repository, path: &str) -> CvmfsResult<Box<dyn FileLike>> {
+        let (root_hash, path) = self.resolve(repo, path)?;
+        repo.get_file_at(&root_hash, &path)
+    }
 }
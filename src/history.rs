@@ -1,12 +1,36 @@
-use crate::common::CvmfsResult;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io::Write;
+
+use crate::common::{json_string, CvmfsError, CvmfsResult};
 use crate::database_object::DatabaseObject;
-use crate::revision_tag::{RevisionTag, SQL_QUERY_DATE, SQL_QUERY_NAME, SQL_QUERY_REVISION};
+use crate::revision_tag::{
+    Branch, RevisionTag, DEFAULT_BRANCH, SQL_QUERY_ALL, SQL_QUERY_ALL_PAGED, SQL_QUERY_BRANCHES,
+    SQL_QUERY_BRANCH_TAGS, SQL_QUERY_DATE, SQL_QUERY_NAME, SQL_QUERY_NAME_BRANCH, SQL_QUERY_RANGE,
+    SQL_QUERY_REVISION,
+};
+
+const TAG_PAGE_SIZE: i64 = 100;
+/// Major version of the history schemas that can be read. Minor revisions
+/// only add tables and columns, which the queries do not depend on.
+const SCHEMA_MAJOR: u32 = 1;
+const CSV_HEADER: &str = "kind,name,hash,revision,timestamp,channel,description,branch,parent";
+
+/// Formats in which the history can be exported
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Json,
+    /// One row per tag or branch, told apart by the `kind` column
+    Csv,
+}
 
 #[derive(Debug)]
 pub struct History {
     pub database_object: DatabaseObject,
     pub schema: String,
     pub fqrn: String,
+    /// Whether the database contains the branches table and the branch column of tags
+    pub has_branches: bool,
 }
 
 unsafe impl Sync for History {}
@@ -24,18 +48,32 @@ impl History {
                 _ => {}
             }
         }
-        if schema.ne("1.0") {
-            panic!("Invalid schema {}", schema);
+        if !is_supported_schema(&schema) {
+            return Err(CvmfsError::UnsupportedHistorySchema(schema));
         }
+        let has_branches = database_object.has_table("branches")?
+            && database_object.has_column("tags", "branch")?;
         Ok(Self {
             database_object,
             schema,
             fqrn,
+            has_branches,
         })
     }
 
+    /// Adds the branch column to a tag query when the database has it
+    fn tag_query<'a>(&self, query: &'a str) -> Cow<'a, str> {
+        if self.has_branches {
+            Cow::Owned(query.replacen("description FROM tags", "description, branch FROM tags", 1))
+        } else {
+            Cow::Borrowed(query)
+        }
+    }
+
     fn get_tag_by_query(&self, query: &str, param: &str) -> CvmfsResult<Option<RevisionTag>> {
-        let mut statement = self.database_object.create_prepared_statement(query)?;
+        let mut statement = self
+            .database_object
+            .create_prepared_statement(&self.tag_query(query))?;
         let mut rows = statement.query([param])?;
         match rows.next()? {
             None => Ok(None),
@@ -47,11 +85,248 @@ impl History {
         self.get_tag_by_query(SQL_QUERY_NAME, name)
     }
 
+    /// Gets a tag of a branch. Databases without branches only have the
+    /// default branch.
+    pub fn get_tag_by_name_and_branch(
+        &self,
+        name: &str,
+        branch: &str,
+    ) -> CvmfsResult<Option<RevisionTag>> {
+        if !self.has_branches {
+            return match branch {
+                DEFAULT_BRANCH => self.get_tag_by_name(name),
+                _ => Ok(None),
+            };
+        }
+        let mut statement = self
+            .database_object
+            .create_prepared_statement(&self.tag_query(SQL_QUERY_NAME_BRANCH))?;
+        let mut rows = statement.query([name, branch])?;
+        match rows.next()? {
+            None => Ok(None),
+            Some(row) => Ok(Some(RevisionTag::new(row)?)),
+        }
+    }
+
     pub fn get_tag_by_revision(&self, revision: u32) -> CvmfsResult<Option<RevisionTag>> {
         self.get_tag_by_query(SQL_QUERY_REVISION, revision.to_string().as_str())
     }
 
+    /// Gets the tag that was active at the given time, that is, the latest
+    /// tag published at or before it
     pub fn get_tag_by_date(&self, timestamp: u64) -> CvmfsResult<Option<RevisionTag>> {
         self.get_tag_by_query(SQL_QUERY_DATE, timestamp.to_string().as_str())
     }
+
+    /// Lists all the tags, the most recent first
+    pub fn list_tags(&self) -> CvmfsResult<Vec<RevisionTag>> {
+        let mut statement = self
+            .database_object
+            .create_prepared_statement(&self.tag_query(SQL_QUERY_ALL))?;
+        let mut rows = statement.query([])?;
+        let mut tags = Vec::new();
+        while let Some(row) = rows.next()? {
+            tags.push(RevisionTag::new(row)?);
+        }
+        Ok(tags)
+    }
+
+    /// Lazily iterates over all the tags, the most recent first, reading them
+    /// from the database in pages
+    pub fn tags(&self) -> TagIterator<'_> {
+        TagIterator {
+            history: self,
+            offset: 0,
+            buffer: VecDeque::new(),
+            exhausted: false,
+        }
+    }
+
+    /// Lists the tags published between two tags, both included, in
+    /// chronological order. The tags can be given in any order.
+    pub fn tags_between(&self, from: &str, to: &str) -> CvmfsResult<Vec<RevisionTag>> {
+        let from = self.get_tag_by_name(from)?.ok_or(CvmfsError::TagNotFound)?;
+        let to = self.get_tag_by_name(to)?.ok_or(CvmfsError::TagNotFound)?;
+        let (start, end) = if from.timestamp <= to.timestamp {
+            (from.timestamp, to.timestamp)
+        } else {
+            (to.timestamp, from.timestamp)
+        };
+        let mut statement = self
+            .database_object
+            .create_prepared_statement(&self.tag_query(SQL_QUERY_RANGE))?;
+        let mut rows = statement.query([start as i64, end as i64])?;
+        let mut tags = Vec::new();
+        while let Some(row) = rows.next()? {
+            tags.push(RevisionTag::new(row)?);
+        }
+        Ok(tags)
+    }
+
+    /// Dumps all the tags and branches of the history
+    pub fn export<W: Write>(&self, writer: &mut W, format: ExportFormat) -> CvmfsResult<()> {
+        let tags = self.list_tags()?;
+        let branches = self.list_branches()?;
+        match format {
+            ExportFormat::Json => {
+                let tags: Vec<String> = tags
+                    .iter()
+                    .map(|tag| {
+                        format!(
+                            "{{\"name\":{},\"hash\":{},\"revision\":{},\"timestamp\":{},\"channel\":{},\"description\":{},\"branch\":{}}}",
+                            json_string(&tag.name),
+                            json_string(&tag.hash),
+                            tag.revision,
+                            tag.timestamp,
+                            tag.channel,
+                            json_string(&tag.description),
+                            json_option(tag.branch.as_deref()),
+                        )
+                    })
+                    .collect();
+                let branches: Vec<String> = branches
+                    .iter()
+                    .map(|branch| {
+                        format!(
+                            "{{\"name\":{},\"parent\":{},\"initial_revision\":{}}}",
+                            json_string(&branch.name),
+                            json_option(branch.parent.as_deref()),
+                            branch.initial_revision,
+                        )
+                    })
+                    .collect();
+                writeln!(
+                    writer,
+                    "{{\"fqrn\":{},\"schema\":{},\"tags\":[{}],\"branches\":[{}]}}",
+                    json_string(&self.fqrn),
+                    json_string(&self.schema),
+                    tags.join(","),
+                    branches.join(",")
+                )?;
+            }
+            ExportFormat::Csv => {
+                writeln!(writer, "{}", CSV_HEADER)?;
+                for tag in &tags {
+                    writeln!(
+                        writer,
+                        "tag,{},{},{},{},{},{},{},",
+                        csv_field(&tag.name),
+                        csv_field(&tag.hash),
+                        tag.revision,
+                        tag.timestamp,
+                        tag.channel,
+                        csv_field(&tag.description),
+                        csv_field(tag.branch.as_deref().unwrap_or_default()),
+                    )?;
+                }
+                for branch in &branches {
+                    writeln!(
+                        writer,
+                        "branch,{},,{},,,,,{}",
+                        csv_field(&branch.name),
+                        branch.initial_revision,
+                        csv_field(branch.parent.as_deref().unwrap_or_default()),
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Lists the publishing branches, which is empty for databases without branches
+    pub fn list_branches(&self) -> CvmfsResult<Vec<Branch>> {
+        if !self.has_branches {
+            return Ok(vec![]);
+        }
+        let mut statement = self
+            .database_object
+            .create_prepared_statement(SQL_QUERY_BRANCHES)?;
+        let mut rows = statement.query([])?;
+        let mut branches = Vec::new();
+        while let Some(row) = rows.next()? {
+            branches.push(Branch::new(row)?);
+        }
+        Ok(branches)
+    }
+
+    /// Lists the tags of a branch, the most recent revision first.
+    /// Databases without branches only have the default branch.
+    pub fn list_tags_by_branch(&self, branch: &str) -> CvmfsResult<Vec<RevisionTag>> {
+        if !self.has_branches {
+            return match branch {
+                DEFAULT_BRANCH => self.list_tags(),
+                _ => Ok(vec![]),
+            };
+        }
+        let mut statement = self
+            .database_object
+            .create_prepared_statement(&self.tag_query(SQL_QUERY_BRANCH_TAGS))?;
+        let mut rows = statement.query([branch])?;
+        let mut tags = Vec::new();
+        while let Some(row) = rows.next()? {
+            tags.push(RevisionTag::new(row)?);
+        }
+        Ok(tags)
+    }
+
+    fn read_tag_page(&self, offset: i64) -> CvmfsResult<Vec<RevisionTag>> {
+        let mut statement = self
+            .database_object
+            .create_prepared_statement(&self.tag_query(SQL_QUERY_ALL_PAGED))?;
+        let mut rows = statement.query([TAG_PAGE_SIZE, offset])?;
+        let mut tags = Vec::new();
+        while let Some(row) = rows.next()? {
+            tags.push(RevisionTag::new(row)?);
+        }
+        Ok(tags)
+    }
+}
+
+/// Iterator over the tags of a history database
+#[derive(Debug)]
+pub struct TagIterator<'a> {
+    history: &'a History,
+    offset: i64,
+    buffer: VecDeque<RevisionTag>,
+    exhausted: bool,
+}
+
+impl Iterator for TagIterator<'_> {
+    type Item = CvmfsResult<RevisionTag>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buffer.is_empty() && !self.exhausted {
+            match self.history.read_tag_page(self.offset) {
+                Ok(tags) => {
+                    self.exhausted = (tags.len() as i64) < TAG_PAGE_SIZE;
+                    self.offset += tags.len() as i64;
+                    self.buffer.extend(tags);
+                }
+                Err(e) => {
+                    self.exhausted = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.buffer.pop_front().map(Ok)
+    }
+}
+
+/// Whether a schema is a `1.x` version
+fn is_supported_schema(schema: &str) -> bool {
+    let mut parts = schema.trim().split('.');
+    let major = parts.next().and_then(|major| major.parse::<u32>().ok());
+    major == Some(SCHEMA_MAJOR) && parts.all(|part| part.parse::<u32>().is_ok())
+}
+
+fn json_option(value: Option<&str>) -> String {
+    value.map(json_string).unwrap_or("null".into())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.into()
+    }
 }
@@ -1,13 +1,47 @@
+pub mod access_log;
+pub mod analytics;
+#[cfg(feature = "async")]
+pub mod async_fetcher;
+pub mod audit_log;
+pub mod autofs;
+pub mod breadcrumb;
 pub mod cache;
 pub mod catalog;
+pub mod catalog_set;
 pub mod certificate;
+pub mod cli;
 pub mod common;
+pub mod config;
+pub mod container;
+pub mod control;
+pub mod daemon_log;
 pub mod database_object;
+pub mod diff;
 pub mod directory_entry;
+pub mod export;
 pub mod fetcher;
 pub mod file_system;
 pub mod history;
+pub mod libcvmfs;
+pub mod lru;
 pub mod manifest;
+pub mod master_key;
+pub mod metrics;
+pub mod mirrors;
+pub mod mount_config;
+pub mod mount_manager;
+pub mod preload;
+pub mod proxy;
+pub mod quota;
+pub mod refresher;
+pub mod replica;
 pub mod repository;
 pub mod revision_tag;
 pub mod rootfile;
+pub mod scrubber;
+pub mod systemd;
+pub mod user_mount;
+pub mod validation;
+pub mod variant_symlink;
+pub mod whitelist;
+pub mod xattr;
@@ -0,0 +1,553 @@
+//! Compatibility layer for applications linked against the reference `libcvmfs`.
+//!
+//! The functions in this module follow the signatures and the option string
+//! format of `libcvmfs.h`, so programs such as parrot can load this crate as a
+//! drop-in replacement. Options are passed as comma separated `key=value`
+//! pairs, where a literal comma can be escaped with a backslash.
+//! Errors are reported the same way as in the reference library: by returning
+//! `-1` (or a null pointer) and setting `errno`.
+
+use std::collections::HashMap;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::common::{CvmfsError, CvmfsResult, FileLike};
+use crate::directory_entry::DirectoryEntry;
+use crate::mount_config::MountConfig;
+use crate::repository::Repository;
+use crate::variant_symlink::SymlinkVariables;
+
+pub const LIBCVMFS_FAIL_OK: c_int = 0;
+pub const LIBCVMFS_FAIL_OPTIONS: c_int = -3;
+pub const LIBCVMFS_FAIL_INITCACHE: c_int = -5;
+
+const DEFAULT_CACHE_DIRECTORY: &str = "/tmp/cvmfs";
+/// Names of the cache directory option, the reference one first
+const CACHE_DIRECTORY_OPTIONS: [&str; 2] = ["cachedir", "cache_directory"];
+/// Symlinks followed while resolving a path, as `MAXSYMLINKS` on Linux
+const MAX_SYMLINK_DEPTH: usize = 40;
+/// Prefix of the absolute symlinks that point inside the repository
+const MOUNT_PREFIX: &str = "/cvmfs/";
+
+static GLOBAL_OPTIONS: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+/// Opaque handle returned by `cvmfs_attach_repo`
+#[derive(Debug)]
+pub struct CvmfsContext {
+    repository: Mutex<Repository>,
+    opened_files: Mutex<HashMap<c_int, Box<dyn FileLike>>>,
+    next_fd: Mutex<c_int>,
+    /// Settings the repository is opened with, again on remount
+    config: MountConfig,
+}
+
+impl CvmfsContext {
+    fn new(config: MountConfig) -> CvmfsResult<Self> {
+        Ok(Self {
+            repository: Mutex::new(config.create_repository()?),
+            opened_files: Default::default(),
+            next_fd: Mutex::new(0),
+            config,
+        })
+    }
+
+    /// Entry of a path, with the variant symlinks expanded from the
+    /// environment of the process
+    fn lookup(&self, path: &str) -> CvmfsResult<DirectoryEntry> {
+        let mut dirent = self
+            .repository
+            .lock()
+            .map_err(|_| CvmfsError::Sync)?
+            .lookup(path)?;
+        dirent.expand_symlink(&SymlinkVariables::default());
+        Ok(dirent)
+    }
+
+    /// Same as `lookup`, following the symlinks that stay inside the
+    /// repository
+    fn lookup_following(&self, path: &str) -> CvmfsResult<DirectoryEntry> {
+        let fqrn = self
+            .repository
+            .lock()
+            .map_err(|_| CvmfsError::Sync)?
+            .fqrn
+            .clone();
+        let mut path = path.to_string();
+        for _ in 0..MAX_SYMLINK_DEPTH {
+            let dirent = self.lookup(&path)?;
+            let Some(target) = dirent.symlink.as_deref().filter(|_| dirent.is_symlink()) else {
+                return Ok(dirent);
+            };
+            path = resolve_symlink(&fqrn, &path, target).ok_or(CvmfsError::FileNotFound)?;
+        }
+        Err(CvmfsError::SymlinkLoop(path))
+    }
+
+    fn open(&self, path: &str) -> CvmfsResult<c_int> {
+        let file = self
+            .repository
+            .lock()
+            .map_err(|_| CvmfsError::Sync)?
+            .get_file(path)?;
+        let mut next_fd = self.next_fd.lock().map_err(|_| CvmfsError::Sync)?;
+        let fd = *next_fd;
+        *next_fd += 1;
+        self.opened_files
+            .lock()
+            .map_err(|_| CvmfsError::Sync)?
+            .insert(fd, file);
+        Ok(fd)
+    }
+
+    fn remount(&self) -> CvmfsResult<()> {
+        let repository = self.config.create_repository()?;
+        *self.repository.lock().map_err(|_| CvmfsError::Sync)? = repository;
+        Ok(())
+    }
+}
+
+/// Cache directory set by the options, under any of its accepted names
+pub fn cache_directory_option(options: &HashMap<String, String>) -> Option<&String> {
+    CACHE_DIRECTORY_OPTIONS
+        .iter()
+        .find_map(|name| options.get(*name))
+}
+
+/// Path of the repository a symlink points to, or `None` when it leaves the
+/// repository. Absolute targets are only followed below `/cvmfs/<fqrn>`.
+pub fn resolve_symlink(fqrn: &str, link: &str, target: &str) -> Option<String> {
+    let joined = if target.starts_with('/') {
+        let inside = target.strip_prefix(MOUNT_PREFIX)?.strip_prefix(fqrn)?;
+        if !inside.is_empty() && !inside.starts_with('/') {
+            return None;
+        }
+        inside.to_string()
+    } else {
+        let parent = link.rsplit_once('/').map_or("", |(parent, _)| parent);
+        format!("{}/{}", parent, target)
+    };
+    let mut components = Vec::new();
+    for component in joined.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop()?;
+            }
+            component => components.push(component),
+        }
+    }
+    Some(format!("/{}", components.join("/")))
+}
+
+/// Splits a libcvmfs option string into its key/value pairs
+pub fn parse_options(options: &str) -> HashMap<String, String> {
+    let mut result = HashMap::new();
+    let mut current = String::new();
+    let mut tokens = Vec::new();
+    let mut escaped = false;
+    for character in options.chars() {
+        match character {
+            _ if escaped => {
+                current.push(character);
+                escaped = false;
+            }
+            '\\' => escaped = true,
+            ',' => tokens.push(std::mem::take(&mut current)),
+            _ => current.push(character),
+        }
+    }
+    tokens.push(current);
+    for token in tokens.into_iter().filter(|token| !token.is_empty()) {
+        match token.split_once('=') {
+            Some((key, value)) => result.insert(key.trim().into(), value.into()),
+            None => result.insert(token.trim().into(), String::new()),
+        };
+    }
+    result
+}
+
+fn errno_for(error: &CvmfsError) -> c_int {
+    match error {
+        CvmfsError::FileNotFound | CvmfsError::CatalogNotFound => libc::ENOENT,
+        CvmfsError::NotAFile => libc::EISDIR,
+        CvmfsError::SymlinkLoop(_) => libc::ELOOP,
+        _ => libc::EIO,
+    }
+}
+
+fn set_errno(code: c_int) {
+    unsafe { *libc::__errno_location() = code };
+}
+
+unsafe fn read_c_str<'a>(value: *const c_char) -> Option<&'a str> {
+    if value.is_null() {
+        return None;
+    }
+    CStr::from_ptr(value).to_str().ok()
+}
+
+unsafe fn context<'a>(ctx: *mut CvmfsContext) -> Option<&'a CvmfsContext> {
+    ctx.as_ref()
+}
+
+fn fill_stat(dirent: &DirectoryEntry, st: &mut libc::stat) {
+    *st = unsafe { std::mem::zeroed() };
+    st.st_mode = dirent.mode as libc::mode_t;
+    st.st_size = dirent.size as libc::off_t;
+    st.st_nlink = dirent.nlink() as libc::nlink_t;
+    st.st_blksize = 4096;
+    st.st_blocks = (1 + dirent.size / 512) as libc::blkcnt_t;
+    st.st_mtime = dirent.mtime;
+    st.st_atime = dirent.mtime;
+    st.st_ctime = dirent.mtime;
+    st.st_ino = dirent.inode() as libc::ino_t;
+    st.st_rdev = dirent.rdev as libc::dev_t;
+}
+
+/// Initializes the library with the global options, such as `cachedir`.
+///
+/// # Safety
+/// `options` must be null or point to a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn cvmfs_init(options: *const c_char) -> c_int {
+    let Some(options) = read_c_str(options).map(parse_options) else {
+        return LIBCVMFS_FAIL_OPTIONS;
+    };
+    let mut global = match GLOBAL_OPTIONS.lock() {
+        Ok(global) => global,
+        Err(_) => return LIBCVMFS_FAIL_INITCACHE,
+    };
+    *global = Some(options);
+    LIBCVMFS_FAIL_OK
+}
+
+/// Releases the global state set up by `cvmfs_init`
+#[no_mangle]
+pub extern "C" fn cvmfs_fini() {
+    if let Ok(mut global) = GLOBAL_OPTIONS.lock() {
+        global.take();
+    }
+}
+
+/// Attaches a repository, returning a null pointer on failure.
+///
+/// # Safety
+/// `options` must be null or point to a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn cvmfs_attach_repo(options: *const c_char) -> *mut CvmfsContext {
+    let Some(options) = read_c_str(options).map(parse_options) else {
+        set_errno(libc::EINVAL);
+        return std::ptr::null_mut();
+    };
+    let source = match options.get("url") {
+        Some(url) => url.clone(),
+        None => {
+            log::error!("Missing 'url' option when attaching the repository");
+            set_errno(libc::EINVAL);
+            return std::ptr::null_mut();
+        }
+    };
+    let global_cache = GLOBAL_OPTIONS
+        .lock()
+        .ok()
+        .and_then(|global| cache_directory_option(global.as_ref()?).cloned());
+    let cache_directory = cache_directory_option(&options)
+        .cloned()
+        .or(global_cache)
+        .unwrap_or(DEFAULT_CACHE_DIRECTORY.into());
+    let config = MountConfig::new(&source, Path::new("/"), &cache_directory);
+    match CvmfsContext::new(config) {
+        Ok(context) => Box::into_raw(Box::new(context)),
+        Err(e) => {
+            log::error!("Could not attach the repository: {:?}", e);
+            set_errno(errno_for(&e));
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Detaches a repository previously returned by `cvmfs_attach_repo`.
+///
+/// # Safety
+/// `ctx` must be null or a pointer obtained from `cvmfs_attach_repo` that was
+/// not detached yet.
+#[no_mangle]
+pub unsafe extern "C" fn cvmfs_detach_repo(ctx: *mut CvmfsContext) {
+    if !ctx.is_null() {
+        drop(Box::from_raw(ctx));
+    }
+}
+
+/// Reloads the manifest of the repository, picking up new revisions.
+///
+/// # Safety
+/// `ctx` must be null or a valid pointer obtained from `cvmfs_attach_repo`.
+#[no_mangle]
+pub unsafe extern "C" fn cvmfs_remount(ctx: *mut CvmfsContext) -> c_int {
+    let Some(ctx) = context(ctx) else {
+        set_errno(libc::EINVAL);
+        return -1;
+    };
+    match ctx.remount() {
+        Ok(_) => 0,
+        Err(e) => {
+            set_errno(errno_for(&e));
+            -1
+        }
+    }
+}
+
+/// Opens a regular file, returning a file descriptor or `-1`.
+///
+/// # Safety
+/// `ctx` must be a valid context and `path` a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn cvmfs_open(ctx: *mut CvmfsContext, path: *const c_char) -> c_int {
+    let (Some(ctx), Some(path)) = (context(ctx), read_c_str(path)) else {
+        set_errno(libc::EINVAL);
+        return -1;
+    };
+    match ctx.open(path) {
+        Ok(fd) => fd,
+        Err(e) => {
+            set_errno(errno_for(&e));
+            -1
+        }
+    }
+}
+
+/// Reads up to `size` bytes at offset `off` of an opened file.
+///
+/// # Safety
+/// `ctx` must be a valid context and `buf` must be writable for `size` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn cvmfs_pread(
+    ctx: *mut CvmfsContext,
+    fd: c_int,
+    buf: *mut c_void,
+    size: libc::size_t,
+    off: libc::off_t,
+) -> libc::ssize_t {
+    let Some(ctx) = context(ctx) else {
+        set_errno(libc::EINVAL);
+        return -1;
+    };
+    if buf.is_null() || off < 0 {
+        set_errno(libc::EINVAL);
+        return -1;
+    }
+    let Ok(mut opened_files) = ctx.opened_files.lock() else {
+        set_errno(libc::EIO);
+        return -1;
+    };
+    let Some(file) = opened_files.get_mut(&fd) else {
+        set_errno(libc::EBADF);
+        return -1;
+    };
+    let buffer = std::slice::from_raw_parts_mut(buf as *mut u8, size);
+    if let Err(e) = file.seek(SeekFrom::Start(off as u64)) {
+        set_errno(e.raw_os_error().unwrap_or(libc::EIO));
+        return -1;
+    }
+    let mut total = 0;
+    while total < buffer.len() {
+        match file.read(&mut buffer[total..]) {
+            Ok(0) => break,
+            Ok(bytes_read) => total += bytes_read,
+            Err(e) => {
+                set_errno(e.raw_os_error().unwrap_or(libc::EIO));
+                return -1;
+            }
+        }
+    }
+    total as libc::ssize_t
+}
+
+/// Closes a file descriptor returned by `cvmfs_open`.
+///
+/// # Safety
+/// `ctx` must be null or a valid pointer obtained from `cvmfs_attach_repo`.
+#[no_mangle]
+pub unsafe extern "C" fn cvmfs_close(ctx: *mut CvmfsContext, fd: c_int) -> c_int {
+    let Some(ctx) = context(ctx) else {
+        set_errno(libc::EINVAL);
+        return -1;
+    };
+    match ctx.opened_files.lock().map(|mut files| files.remove(&fd)) {
+        Ok(Some(_)) => 0,
+        Ok(None) => {
+            set_errno(libc::EBADF);
+            -1
+        }
+        Err(_) => {
+            set_errno(libc::EIO);
+            -1
+        }
+    }
+}
+
+/// Copies the NUL-terminated target of a symlink into `buf`.
+///
+/// # Safety
+/// `ctx` must be a valid context, `path` a valid NUL-terminated string and
+/// `buf` writable for `size` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn cvmfs_readlink(
+    ctx: *mut CvmfsContext,
+    path: *const c_char,
+    buf: *mut c_char,
+    size: libc::size_t,
+) -> c_int {
+    let (Some(ctx), Some(path)) = (context(ctx), read_c_str(path)) else {
+        set_errno(libc::EINVAL);
+        return -1;
+    };
+    if buf.is_null() || size == 0 {
+        set_errno(libc::EINVAL);
+        return -1;
+    }
+    let dirent = match ctx.lookup(path) {
+        Ok(dirent) => dirent,
+        Err(e) => {
+            set_errno(errno_for(&e));
+            return -1;
+        }
+    };
+    let is_symlink = dirent.is_symlink();
+    let Some(target) = dirent.symlink.filter(|_| is_symlink) else {
+        set_errno(libc::EINVAL);
+        return -1;
+    };
+    let length = target.len().min(size - 1);
+    std::ptr::copy_nonoverlapping(target.as_ptr() as *const c_char, buf, length);
+    *buf.add(length) = 0;
+    0
+}
+
+/// Fills `st` with the attributes of `path`, following the symlinks that stay
+/// inside the repository.
+///
+/// # Safety
+/// `ctx` must be a valid context, `path` a valid NUL-terminated string and
+/// `st` a valid pointer to a `struct stat`.
+#[no_mangle]
+pub unsafe extern "C" fn cvmfs_stat(
+    ctx: *mut CvmfsContext,
+    path: *const c_char,
+    st: *mut libc::stat,
+) -> c_int {
+    let (Some(ctx), Some(path), Some(st)) = (context(ctx), read_c_str(path), st.as_mut()) else {
+        set_errno(libc::EINVAL);
+        return -1;
+    };
+    stat_result(ctx.lookup_following(path), st)
+}
+
+/// Same as `cvmfs_stat`, describing the symlinks themselves.
+///
+/// # Safety
+/// Same requirements as `cvmfs_stat`.
+#[no_mangle]
+pub unsafe extern "C" fn cvmfs_lstat(
+    ctx: *mut CvmfsContext,
+    path: *const c_char,
+    st: *mut libc::stat,
+) -> c_int {
+    let (Some(ctx), Some(path), Some(st)) = (context(ctx), read_c_str(path), st.as_mut()) else {
+        set_errno(libc::EINVAL);
+        return -1;
+    };
+    stat_result(ctx.lookup(path), st)
+}
+
+fn stat_result(lookup: CvmfsResult<DirectoryEntry>, st: &mut libc::stat) -> c_int {
+    match lookup {
+        Ok(dirent) => {
+            fill_stat(&dirent, st);
+            0
+        }
+        Err(e) => {
+            set_errno(errno_for(&e));
+            -1
+        }
+    }
+}
+
+/// Lists a directory into a NULL-terminated, `malloc`-allocated array of
+/// strings. `*buf` may point to an existing array of `*buflen` slots, which
+/// is grown as needed. The caller owns the result and its strings.
+///
+/// # Safety
+/// `ctx` must be a valid context, `path` a valid NUL-terminated string, and
+/// `buf`/`buflen` valid pointers to a `malloc`-allocated array and its size.
+#[no_mangle]
+pub unsafe extern "C" fn cvmfs_listdir(
+    ctx: *mut CvmfsContext,
+    path: *const c_char,
+    buf: *mut *mut *mut c_char,
+    buflen: *mut libc::size_t,
+) -> c_int {
+    let (Some(ctx), Some(path)) = (context(ctx), read_c_str(path)) else {
+        set_errno(libc::EINVAL);
+        return -1;
+    };
+    if buf.is_null() || buflen.is_null() {
+        set_errno(libc::EINVAL);
+        return -1;
+    }
+    let listing = ctx
+        .repository
+        .lock()
+        .map_err(|_| CvmfsError::Sync)
+        .and_then(|repository| repository.list_directory(path));
+    let entries = match listing {
+        Ok(entries) => entries,
+        Err(e) => {
+            set_errno(errno_for(&e));
+            return -1;
+        }
+    };
+    let names = [".".to_string(), "..".to_string()]
+        .into_iter()
+        .chain(entries.into_iter().map(|dirent| dirent.name));
+    let mut position = 0;
+    for name in names {
+        let Ok(name) = CString::new(name) else {
+            continue;
+        };
+        if !append_string(buf, buflen, position, libc::strdup(name.as_ptr())) {
+            set_errno(libc::ENOMEM);
+            return -1;
+        }
+        position += 1;
+    }
+    if !append_string(buf, buflen, position, std::ptr::null_mut()) {
+        set_errno(libc::ENOMEM);
+        return -1;
+    }
+    0
+}
+
+unsafe fn append_string(
+    buf: *mut *mut *mut c_char,
+    buflen: *mut libc::size_t,
+    position: usize,
+    value: *mut c_char,
+) -> bool {
+    if position >= *buflen || (*buf).is_null() {
+        let new_length = ((*buflen).max(8) * 2).max(position + 1);
+        let resized = libc::realloc(
+            *buf as *mut c_void,
+            new_length * std::mem::size_of::<*mut c_char>(),
+        ) as *mut *mut c_char;
+        if resized.is_null() {
+            return false;
+        }
+        *buf = resized;
+        *buflen = new_length;
+    }
+    *(*buf).add(position) = value;
+    true
+}
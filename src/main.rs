@@ -1,39 +1,139 @@
 use std::env;
-use std::ffi::OsStr;
-use std::path::PathBuf;
+use std::io;
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
 
-use cvmfs::fetcher::Fetcher;
-use cvmfs::file_system::CernvmFileSystem;
-use cvmfs::repository::Repository;
+use clap::Parser;
+use cvmfs::autofs::{self, MOUNT_HELPER_NAME};
+use cvmfs::cli::{Cli, Command};
+use cvmfs::control;
+use cvmfs::daemon_log::LogConfig;
+use cvmfs::metrics::{self, DEFAULT_METRICS_INTERVAL};
+use cvmfs::mount_config::{default_cache_directory, MountArgs};
+use cvmfs::replica;
+use cvmfs::systemd::{self, Notifier};
 
 fn main() {
-    env_logger::init();
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 3 {
-        panic!("Please specify url of the repository and the mount point");
-    }
-    let repo_url = &args[1];
-    let mountpoint = PathBuf::from(&args[2]);
-    if !mountpoint.exists() {
-        panic!("Mount point does not exist");
-    }
-    if !mountpoint.is_dir() {
-        panic!("Mount point is not a directory");
-    }
-    let repo_cache = if args.len() > 3 {
-        args[3].clone()
-    } else {
-        "/tmp/cvmfs".into()
-    };
-    let fetcher = Fetcher::new(repo_url, &repo_cache, true).expect("Failure creating the fetcher");
-    let repository = Repository::new(fetcher).expect("Failure creating the repository");
-    let file_system = CernvmFileSystem::new(repository).expect("Failure creating the file system");
-
-    let fuse_args = [OsStr::new("-o"), OsStr::new("fsname=cernvmfs")];
+    let program = env::args().next().unwrap_or_default();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let program = Path::new(&program).file_name();
+    if [MOUNT_HELPER_NAME, systemd::MOUNT_HELPER_NAME]
+        .iter()
+        .any(|name| program == Some(name.as_ref()))
+    {
+        mount_helper(&args);
+    }
+    match Cli::parse().into_command() {
+        Command::Mount(args) => mount(args),
+        Command::Compare {
+            first,
+            second,
+            cache_directory,
+        } => compare(&first, &second, cache_directory),
+        Command::Automount { key } => automount(&key),
+        Command::MountHelper { args } => mount_helper(&args),
+        command => inspect(&command),
+    }
+}
+
+/// Runs one of the commands inspecting a repository without mounting it
+fn inspect(command: &Command) -> ! {
+    let _ = LogConfig::from_env().install();
+    if let Err(e) = command.run(&mut io::stdout().lock()) {
+        eprintln!("cvmfs: {}", e);
+        process::exit(1)
+    }
+    process::exit(0)
+}
+
+fn mount(args: Box<MountArgs>) {
+    let config = args.into_config().unwrap_or_else(|e| panic!("{}", e));
+    config
+        .log
+        .clone()
+        .install()
+        .unwrap_or_else(|e| panic!("Could not open the daemon log: {}", e));
+    config.validate().unwrap_or_else(|e| panic!("{}", e));
+    let repository = config
+        .create_repository()
+        .unwrap_or_else(|e| panic!("Failure creating the repository: {}", e));
+    let socket_path = control::socket_path(&config.cache_directory, &repository.fqrn);
+    let mut file_system = config
+        .create_file_system(repository)
+        .unwrap_or_else(|e| panic!("Failure creating the file system: {}", e));
+    match systemd::take_control_listener() {
+        Some(listener) => {
+            control::spawn_listener(listener, file_system.repository());
+        }
+        None => {
+            if let Err(e) = control::spawn(&socket_path, file_system.repository()) {
+                log::warn!("Could not open the control socket: {:?}", e);
+            }
+        }
+    }
+    if let Some(address) = &config.metrics_address {
+        if let Err(e) = metrics::spawn_endpoint(address) {
+            log::warn!("Could not serve the metrics on {}: {:?}", address, e);
+        }
+    }
+    if let Some(path) = &config.metrics_file {
+        metrics::spawn_file_writer(path.clone(), DEFAULT_METRICS_INTERVAL);
+    }
+    match Notifier::from_env() {
+        Ok(Some(notifier)) => {
+            let notifier = Arc::new(notifier);
+            let repository = file_system.repository();
+            notifier
+                .clone()
+                .spawn_watchdog(move || !repository.is_poisoned());
+            file_system.set_notifier(notifier);
+        }
+        Ok(None) => {}
+        Err(e) => log::warn!("Could not connect to systemd: {:?}", e),
+    }
+
     fuse_mt::mount(
-        fuse_mt::FuseMT::new(file_system, 5),
-        mountpoint.to_str().expect("Invalid mount point string"),
-        &fuse_args[..],
+        fuse_mt::FuseMT::new(file_system, config.threads),
+        &config.mount_point,
+        &config.fuse_args(),
     )
     .expect("Could not mount the file system in the mountpoint");
 }
+
+/// Reports the divergence between two servers, exiting with 1 when out of sync
+fn compare(first: &str, second: &str, cache_directory: Option<String>) -> ! {
+    let _ = LogConfig::from_env().install();
+    let cache_directory = cache_directory.unwrap_or_else(default_cache_directory);
+    let comparison = replica::compare_replicas(first, second, &cache_directory)
+        .unwrap_or_else(|e| panic!("Could not compare the replicas: {}", e));
+    print!("{}", comparison);
+    process::exit(if comparison.is_in_sync() { 0 } else { 1 })
+}
+
+/// Program map for autofs: prints the map entry of a key, exiting with 1
+/// and printing nothing when the key is not a repository
+fn automount(key: &str) -> ! {
+    let _ = LogConfig::from_env().install();
+    match autofs::map_entry_from_env(key) {
+        Ok(entry) => {
+            println!("{}", entry);
+            process::exit(0)
+        }
+        Err(e) => {
+            log::info!("No map entry for {}: {}", key, e);
+            process::exit(1)
+        }
+    }
+}
+
+/// Mount helper called by mount(8) for the `cvmfs` and `cvmfs-rust` file
+/// system types
+fn mount_helper(args: &[String]) -> ! {
+    let _ = LogConfig::from_env().install();
+    if let Err(e) = autofs::run_mount_helper(args) {
+        eprintln!("{}: {}", MOUNT_HELPER_NAME, e);
+        process::exit(32)
+    }
+    process::exit(0)
+}
@@ -1,6 +1,10 @@
+use std::collections::HashMap;
+
+use crate::certificate::Certificate;
 use crate::common::{CvmfsError, CvmfsResult};
+use crate::directory_entry::ContentHashTypes;
 use crate::rootfile::RootFile;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeDelta, Utc};
 
 /// Wraps information from .cvmfspublished
 #[derive(Debug)]
@@ -18,20 +22,66 @@ pub struct Manifest {
     pub micro_catalog: String,
     pub garbage_collectable: bool,
     pub allows_alternative_name: bool,
+    pub meta_info: Option<String>,
+    pub reflog_hash: Option<String>,
+    pub unknown_keys: HashMap<char, String>,
 }
 
 impl Manifest {
     pub fn has_history(&self) -> bool {
         self.history_database.is_some()
     }
+
+    pub fn has_meta_info(&self) -> bool {
+        self.meta_info.is_some()
+    }
+
+    /// Algorithm the current revision is hashed with, from the root catalog
+    /// hash. Older objects may still use another one while a repository
+    /// migrates, so objects are resolved with their own algorithm.
+    pub fn hash_algorithm(&self) -> ContentHashTypes {
+        ContentHashTypes::from_hash(&self.root_catalog)
+    }
+
+    /// A manifest published in the future means the local clock is behind
+    pub fn validate_timestamp(&self, now: DateTime<Utc>, tolerance: TimeDelta) -> CvmfsResult<()> {
+        if self.last_modified - tolerance > now {
+            return Err(CvmfsError::ClockSkew(format!(
+                "revision {} of {} was published on {} but the local time is {}",
+                self.revision, self.repository_name, self.last_modified, now
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks that the manifest was signed with the key of a certificate.
+    /// As for the whitelist, the signed message is the checksum line.
+    pub fn verify_signature(&self, certificate: &Certificate) -> CvmfsResult<()> {
+        let checksum = self
+            .root_file
+            .checksum()
+            .ok_or(CvmfsError::IncompleteRootFileSignature)?;
+        let signature = self
+            .root_file
+            .signature()
+            .ok_or(CvmfsError::IncompleteRootFileSignature)?;
+        if certificate.verify(signature, checksum.as_bytes()) {
+            Ok(())
+        } else {
+            Err(CvmfsError::InvalidManifestSignature)
+        }
+    }
 }
 
 impl Manifest {
-    fn parse_boolean(value: &str) -> bool {
+    fn parse_boolean(key: char, value: &str) -> CvmfsResult<bool> {
         match value {
-            "yes" => true,
-            "no" => false,
-            _ => panic!("Invalid boolean value: {}", value),
+            "yes" => Ok(true),
+            "no" => Ok(false),
+            _ => Err(CvmfsError::InvalidManifest(format!(
+                "invalid boolean value for {}: {}",
+                key, value
+            ))),
         }
     }
 
@@ -48,10 +98,13 @@ impl Manifest {
         let mut micro_catalog = String::new();
         let mut garbage_collectable = false;
         let mut allows_alternative_name = false;
+        let mut meta_info = None;
+        let mut reflog_hash = None;
+        let mut unknown_keys = HashMap::new();
 
         for line in root_file.lines() {
             if let Some(key) = line.chars().next() {
-                let value = &line[1..];
+                let value = &line[key.len_utf8()..];
                 match key {
                     'C' => root_catalog = value.into(),
                     'R' => root_hash = value.into(),
@@ -68,9 +121,13 @@ impl Manifest {
                     'S' => revision = value.parse().map_err(|_| CvmfsError::ParseError)?,
                     'N' => repository_name = value.into(),
                     'L' => micro_catalog = value.into(),
-                    'G' => garbage_collectable = Self::parse_boolean(value),
-                    'A' => allows_alternative_name = Self::parse_boolean(value),
-                    _ => {}
+                    'G' => garbage_collectable = Self::parse_boolean(key, value)?,
+                    'A' => allows_alternative_name = Self::parse_boolean(key, value)?,
+                    'M' => meta_info = Some(value.into()),
+                    'Y' => reflog_hash = Some(value.into()),
+                    _ => {
+                        unknown_keys.insert(key, value.into());
+                    }
                 }
             }
         }
@@ -89,6 +146,9 @@ impl Manifest {
             micro_catalog,
             garbage_collectable,
             allows_alternative_name,
+            meta_info,
+            reflog_hash,
+            unknown_keys,
         })
     }
 }
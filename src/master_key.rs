@@ -0,0 +1,77 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use openssl::pkey::Public;
+use openssl::rsa::{Padding, Rsa};
+
+use crate::common::{CvmfsError, CvmfsResult};
+
+pub const KEYS_DIRECTORY: &str = "/etc/cvmfs/keys";
+const KEY_EXTENSION: &str = "pub";
+
+/// Repository master public key, used to sign the whitelist
+#[derive(Debug)]
+pub struct MasterKey {
+    pub path: PathBuf,
+    key: Rsa<Public>,
+}
+
+impl MasterKey {
+    pub fn from_pem(bytes: &[u8], path: &Path) -> CvmfsResult<Self> {
+        Ok(Self {
+            path: path.into(),
+            key: Rsa::public_key_from_pem(bytes).map_err(|_| CvmfsError::Certificate)?,
+        })
+    }
+
+    pub fn load(path: &Path) -> CvmfsResult<Self> {
+        Self::from_pem(&fs::read(path)?, path)
+    }
+
+    /// Verifies a raw RSA signature, as produced by the reference server tools
+    /// when signing the whitelist: the signature decrypts to the message itself.
+    pub fn verify(&self, signature: &[u8], message: &[u8]) -> bool {
+        let mut decrypted = vec![0u8; self.key.size() as usize];
+        match self
+            .key
+            .public_decrypt(signature, &mut decrypted, Padding::PKCS1)
+        {
+            Ok(length) => decrypted[..length].eq(message),
+            Err(_) => false,
+        }
+    }
+
+    /// Loads the master keys applying to a repository from a keys directory.
+    /// Following the reference client layout, the keys are looked up in
+    /// `<fqrn>.pub`, `<domain>.pub` and every key in the `<domain>/` subdirectory,
+    /// where the domain is the FQRN without its first label.
+    pub fn load_for_repository(keys_directory: &Path, fqrn: &str) -> CvmfsResult<Vec<Self>> {
+        let mut candidates = vec![keys_directory.join(format!("{}.{}", fqrn, KEY_EXTENSION))];
+        if let Some((_, domain)) = fqrn.split_once('.') {
+            candidates.push(keys_directory.join(format!("{}.{}", domain, KEY_EXTENSION)));
+            if let Ok(entries) = fs::read_dir(keys_directory.join(domain)) {
+                let mut domain_keys: Vec<PathBuf> = entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.extension().is_some_and(|ext| ext == KEY_EXTENSION))
+                    .collect();
+                domain_keys.sort();
+                candidates.extend(domain_keys);
+            }
+        }
+        let keys: Vec<Self> = candidates
+            .into_iter()
+            .filter(|path| path.is_file())
+            .filter_map(|path| match Self::load(&path) {
+                Ok(key) => Some(key),
+                Err(e) => {
+                    log::warn!("Ignoring invalid master key {}: {:?}", path.display(), e);
+                    None
+                }
+            })
+            .collect();
+        if keys.is_empty() {
+            return Err(CvmfsError::MasterKeyNotFound(fqrn.into()));
+        }
+        Ok(keys)
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::common::{CvmfsError, CvmfsResult};
+
+/// Time between two writes of the metrics file
+pub const DEFAULT_METRICS_INTERVAL: Duration = Duration::from_secs(15);
+/// Upper bounds in seconds of the latency histogram buckets
+pub const LATENCY_BUCKETS: [f64; 10] =
+    [0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+/// Content type of the Prometheus text exposition format
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Default::default);
+
+/// Metrics of the running client, shared by all its components
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+/// Distribution of durations over `LATENCY_BUCKETS`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Histogram {
+    /// Observations up to each bound, not cumulative
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    pub fn observe(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Writes the histogram in the Prometheus text format, with the given
+    /// labels on every sample
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(self.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{}le=\"{}\"}} {}",
+                name, labels, bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{}le=\"+Inf\"}} {}",
+            name, labels, self.count
+        );
+        let labels = labels.trim_end_matches(',');
+        let braces = |labels: &str| match labels {
+            "" => String::new(),
+            labels => format!("{{{}}}", labels),
+        };
+        let _ = writeln!(out, "{}_sum{} {}", name, braces(labels), self.sum);
+        let _ = writeln!(out, "{}_count{} {}", name, braces(labels), self.count);
+    }
+}
+
+/// Counters of the downloads, the cache, the lookups and the FUSE requests,
+/// exposed in the Prometheus text format
+#[derive(Debug, Default)]
+pub struct Metrics {
+    downloads: AtomicU64,
+    downloaded_bytes: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    cache_writes: AtomicU64,
+    cache_written_bytes: AtomicU64,
+    open_files: AtomicU64,
+    lookups: Mutex<Histogram>,
+    /// Duration of the FUSE requests per operation
+    requests: Mutex<BTreeMap<&'static str, Histogram>>,
+    /// Failures per type of error
+    errors: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Metrics {
+    pub fn record_download(&self, bytes: usize) {
+        self.downloads.fetch_add(1, Ordering::Relaxed);
+        self.downloaded_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Records whether an object was served from the cache
+    pub fn record_cache_lookup(&self, hit: bool) {
+        match hit {
+            true => self.cache_hits.fetch_add(1, Ordering::Relaxed),
+            false => self.cache_misses.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    pub fn record_cache_write(&self, bytes: usize) {
+        self.cache_writes.fetch_add(1, Ordering::Relaxed);
+        self.cache_written_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn set_open_files(&self, open_files: usize) {
+        self.open_files.store(open_files as u64, Ordering::Relaxed);
+    }
+
+    pub fn observe_lookup(&self, duration: Duration) {
+        if let Ok(mut lookups) = self.lookups.lock() {
+            lookups.observe(duration);
+        }
+    }
+
+    pub fn observe_request(&self, operation: &'static str, duration: Duration) {
+        if let Ok(mut requests) = self.requests.lock() {
+            requests.entry(operation).or_default().observe(duration);
+        }
+    }
+
+    /// Counts a failure under the name of its error variant
+    pub fn record_error(&self, error: &CvmfsError) {
+        let debug = format!("{:?}", error);
+        let kind = debug.split(['(', ' ', '{']).next().unwrap_or_default();
+        if let Ok(mut errors) = self.errors.lock() {
+            *errors.entry(kind.into()).or_default() += 1;
+        }
+    }
+
+    /// Observes the duration of a FUSE request until the returned guard is
+    /// dropped
+    pub fn time_request(&self, operation: &'static str) -> RequestTimer {
+        RequestTimer {
+            operation,
+            started: Instant::now(),
+        }
+    }
+
+    /// Lookups observed so far
+    pub fn lookups(&self) -> u64 {
+        self.lookups.lock().map_or(0, |lookups| lookups.count())
+    }
+
+    /// Failures of the given type so far
+    pub fn errors(&self, kind: &str) -> u64 {
+        self.errors
+            .lock()
+            .map_or(0, |errors| errors.get(kind).copied().unwrap_or_default())
+    }
+
+    /// All the metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let counters = [
+            (
+                "cvmfs_downloads_total",
+                "Objects downloaded from the servers",
+                &self.downloads,
+            ),
+            (
+                "cvmfs_downloaded_bytes_total",
+                "Bytes downloaded from the servers",
+                &self.downloaded_bytes,
+            ),
+            (
+                "cvmfs_cache_hits_total",
+                "Objects served from the cache",
+                &self.cache_hits,
+            ),
+            (
+                "cvmfs_cache_misses_total",
+                "Objects missing from the cache",
+                &self.cache_misses,
+            ),
+            (
+                "cvmfs_cache_writes_total",
+                "Objects written to the cache",
+                &self.cache_writes,
+            ),
+            (
+                "cvmfs_cache_written_bytes_total",
+                "Bytes written to the cache",
+                &self.cache_written_bytes,
+            ),
+        ];
+        for (name, help, counter) in counters {
+            header(&mut out, name, help, "counter");
+            let _ = writeln!(out, "{} {}", name, counter.load(Ordering::Relaxed));
+        }
+        header(
+            &mut out,
+            "cvmfs_open_files",
+            "Files currently opened",
+            "gauge",
+        );
+        let _ = writeln!(
+            out,
+            "cvmfs_open_files {}",
+            self.open_files.load(Ordering::Relaxed)
+        );
+        header(
+            &mut out,
+            "cvmfs_lookup_duration_seconds",
+            "Duration of the path lookups in the catalogs",
+            "histogram",
+        );
+        if let Ok(lookups) = self.lookups.lock() {
+            lookups.render(&mut out, "cvmfs_lookup_duration_seconds", "");
+        }
+        header(
+            &mut out,
+            "cvmfs_fuse_request_duration_seconds",
+            "Duration of the FUSE requests",
+            "histogram",
+        );
+        if let Ok(requests) = self.requests.lock() {
+            for (operation, histogram) in requests.iter() {
+                histogram.render(
+                    &mut out,
+                    "cvmfs_fuse_request_duration_seconds",
+                    &format!("operation=\"{}\",", operation),
+                );
+            }
+        }
+        header(
+            &mut out,
+            "cvmfs_errors_total",
+            "Failures per type of error",
+            "counter",
+        );
+        if let Ok(errors) = self.errors.lock() {
+            for (kind, count) in errors.iter() {
+                let _ = writeln!(out, "cvmfs_errors_total{{type=\"{}\"}} {}", kind, count);
+            }
+        }
+        out
+    }
+
+    /// Writes the metrics to a file, replacing it at once so that collectors
+    /// never read a partial file
+    pub fn write_file(&self, path: &Path) -> CvmfsResult<()> {
+        let partial = path.with_extension("partial");
+        fs::write(&partial, self.render())?;
+        fs::rename(&partial, path)?;
+        Ok(())
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Duration of a FUSE request in flight, see `Metrics::time_request`
+#[derive(Debug)]
+pub struct RequestTimer {
+    operation: &'static str,
+    started: Instant,
+}
+
+impl Drop for RequestTimer {
+    fn drop(&mut self) {
+        metrics().observe_request(self.operation, self.started.elapsed());
+    }
+}
+
+/// Writes the metrics to a file in a background thread, every `interval`
+pub fn spawn_file_writer(path: PathBuf, interval: Duration) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        if let Err(e) = metrics().write_file(&path) {
+            log::warn!("Could not write the metrics to {:?}: {:?}", path, e);
+        }
+        thread::sleep(interval);
+    })
+}
+
+/// Serves the metrics over HTTP in a background thread, whatever the path
+/// requested. Returns the address listened on, useful with port 0.
+pub fn spawn_endpoint(address: &str) -> CvmfsResult<SocketAddr> {
+    let listener = TcpListener::bind(address)?;
+    let address = listener.local_addr()?;
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(e) = serve(stream) {
+                        log::debug!("Metrics request failed: {:?}", e);
+                    }
+                }
+                Err(e) => log::warn!("Metrics endpoint error: {:?}", e),
+            }
+        }
+    });
+    log::info!("Serving the metrics on http://{}/metrics", address);
+    Ok(address)
+}
+
+fn serve(mut stream: TcpStream) -> CvmfsResult<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    // the request itself does not matter, only its headers are drained
+    let mut request = [0u8; 4096];
+    let _ = stream.read(&mut request)?;
+    let body = metrics().render();
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        CONTENT_TYPE,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
@@ -0,0 +1,184 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::common::{CvmfsError, CvmfsResult};
+
+/// Time a mirror is skipped after its first failure, doubled on every further
+/// consecutive failure
+pub const DEFAULT_MIRROR_BACKOFF: Duration = Duration::from_secs(5);
+/// Longest time a failing mirror is skipped
+pub const MAX_MIRROR_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Health of a mirror, as seen by the downloads so far
+#[derive(Debug, Clone, PartialEq)]
+pub struct MirrorStatus {
+    pub url: String,
+    /// Whether the mirror is tried before the ones backing off
+    pub healthy: bool,
+    /// Consecutive failures, reset by the first success
+    pub failures: u32,
+    pub successes: u64,
+}
+
+#[derive(Debug, Default)]
+struct MirrorHealth {
+    failures: u32,
+    successes: u64,
+    backoff_until: Option<Instant>,
+}
+
+impl MirrorHealth {
+    fn is_healthy(&self, now: Instant) -> bool {
+        self.backoff_until.is_none_or(|until| until <= now)
+    }
+}
+
+/// Stratum-1 servers replicating the same repository. Downloads start at the
+/// next mirror in round-robin order, or at the closest one once ranked, and
+/// fail over to the others, trying the ones backing off after a failure last.
+#[derive(Debug)]
+pub struct MirrorSet {
+    urls: Vec<String>,
+    health: Vec<Mutex<MirrorHealth>>,
+    next: AtomicUsize,
+    /// Preferred order of the mirrors, see `set_ranking`
+    ranking: Mutex<Option<Vec<usize>>>,
+    backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl MirrorSet {
+    pub fn new(urls: Vec<String>) -> CvmfsResult<Self> {
+        if urls.is_empty() {
+            return Err(CvmfsError::InvalidConfiguration(
+                "no mirror to download from".into(),
+            ));
+        }
+        let mut mirrors = Self::single(urls[0].clone());
+        mirrors.health = urls.iter().map(|_| Default::default()).collect();
+        mirrors.urls = urls;
+        Ok(mirrors)
+    }
+
+    pub fn single(url: String) -> Self {
+        Self {
+            urls: vec![url],
+            health: vec![Default::default()],
+            next: AtomicUsize::new(0),
+            ranking: Mutex::new(None),
+            backoff: DEFAULT_MIRROR_BACKOFF,
+            max_backoff: MAX_MIRROR_BACKOFF,
+        }
+    }
+
+    /// Same mirrors, backing off for `backoff` after a first failure and for
+    /// `max_backoff` at most
+    pub fn with_backoff(mut self, backoff: Duration, max_backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    pub fn urls(&self) -> &[String] {
+        &self.urls
+    }
+
+    /// Indexes of the mirrors in the order a download tries them
+    pub fn order(&self) -> Vec<usize> {
+        let ranking = self.ranking();
+        let candidates = ranking.unwrap_or_else(|| {
+            let start = self.next.fetch_add(1, Ordering::Relaxed) % self.urls.len();
+            (start..self.urls.len()).chain(0..start).collect()
+        });
+        let now = Instant::now();
+        let mut healthy = Vec::new();
+        let mut backing_off = Vec::new();
+        for index in candidates {
+            match self.health[index].lock() {
+                Ok(health) if !health.is_healthy(now) => {
+                    backing_off.push((health.backoff_until, index))
+                }
+                _ => healthy.push(index),
+            }
+        }
+        // the mirror recovering soonest is the most likely to work again
+        backing_off.sort();
+        healthy.extend(backing_off.into_iter().map(|(_, index)| index));
+        healthy
+    }
+
+    /// Preferred order of the mirrors, `None` while they are used in turn
+    pub fn ranking(&self) -> Option<Vec<usize>> {
+        self.ranking.lock().ok()?.clone()
+    }
+
+    /// Tries the mirrors in the given order, e.g. from the closest to the
+    /// farthest, instead of in turn. Every mirror must be ranked once.
+    pub fn set_ranking(&self, ranking: Vec<usize>) -> CvmfsResult<()> {
+        let mut sorted = ranking.clone();
+        sorted.sort_unstable();
+        if !sorted.iter().copied().eq(0..self.urls.len()) {
+            return Err(CvmfsError::InvalidConfiguration(format!(
+                "{:?} does not rank the {} mirrors",
+                ranking,
+                self.urls.len()
+            )));
+        }
+        *self.ranking.lock().map_err(|_| CvmfsError::Sync)? = Some(ranking);
+        Ok(())
+    }
+
+    /// Whether a mirror is not backing off after a failure
+    pub fn is_healthy(&self, index: usize) -> bool {
+        self.health[index]
+            .lock()
+            .map_or(true, |health| health.is_healthy(Instant::now()))
+    }
+
+    pub fn record_success(&self, index: usize) {
+        if let Ok(mut health) = self.health[index].lock() {
+            if health.failures > 0 {
+                log::info!("Mirror {} is back", self.urls[index]);
+            }
+            health.failures = 0;
+            health.successes += 1;
+            health.backoff_until = None;
+        }
+    }
+
+    /// Backs off from a mirror, exponentially in its consecutive failures
+    pub fn record_failure(&self, index: usize) {
+        if let Ok(mut health) = self.health[index].lock() {
+            health.failures += 1;
+            let backoff = self
+                .backoff
+                .saturating_mul(1 << (health.failures - 1).min(16))
+                .min(self.max_backoff);
+            log::warn!(
+                "Mirror {} failed {} times in a row, skipping it for {:?}",
+                self.urls[index],
+                health.failures,
+                backoff
+            );
+            health.backoff_until = Some(Instant::now() + backoff);
+        }
+    }
+
+    pub fn status(&self) -> Vec<MirrorStatus> {
+        let now = Instant::now();
+        self.urls
+            .iter()
+            .zip(&self.health)
+            .filter_map(|(url, health)| {
+                let health = health.lock().ok()?;
+                Some(MirrorStatus {
+                    url: url.clone(),
+                    healthy: health.is_healthy(now),
+                    failures: health.failures,
+                    successes: health.successes,
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,668 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::TimeDelta;
+use clap::Parser;
+
+use crate::access_log::{AccessLog, AccessLogConfig, AccessLogTarget};
+use crate::analytics::Analytics;
+use crate::audit_log::AuditLog;
+use crate::cache::Cache;
+use crate::common::{CvmfsError, CvmfsResult, DEFAULT_CHUNK_READ_AHEAD};
+use crate::config::ClientConfig;
+use crate::daemon_log::LogConfig;
+use crate::fetcher::Fetcher;
+use crate::file_system::CernvmFileSystem;
+use crate::master_key::KEYS_DIRECTORY;
+use crate::proxy::{ProxyChain, ProxyConfig};
+use crate::repository::{
+    Repository, SiblingPrefetch, TrustSettings, DEFAULT_GEO_SORT_INTERVAL,
+    DEFAULT_MAX_OPENED_CATALOGS,
+};
+use crate::scrubber::{Scrubber, ScrubberConfig};
+use crate::user_mount;
+use crate::validation::{ValidationMode, ValidationPolicy};
+use crate::variant_symlink::SymlinkVariables;
+use crate::whitelist::ExpiryPolicy;
+use crate::xattr::XattrPolicy;
+
+pub const DEFAULT_CACHE_DIRECTORY: &str = "/tmp/cvmfs";
+pub const DEFAULT_FUSE_THREADS: usize = 5;
+pub const DEFAULT_FSNAME_OPTION: &str = "fsname=cernvmfs";
+pub const DEFAULT_REPOSITORY_TYPE: &str = "stratum1";
+/// Domain appended to repository names given without one
+pub const DEFAULT_DOMAIN: &str = "cern.ch";
+pub const FQRN_PLACEHOLDER: &str = "@fqrn@";
+pub const ORG_PLACEHOLDER: &str = "@org@";
+/// Cache directory used when none is given: the system wide one for root
+/// and the per-user XDG cache directory for everyone else
+pub fn default_cache_directory() -> String {
+    if user_mount::is_unprivileged() {
+        if let Some(directory) = user_mount::user_cache_directory() {
+            return directory.to_string_lossy().into_owned();
+        }
+    }
+    DEFAULT_CACHE_DIRECTORY.into()
+}
+
+/// Fully qualified repository name of a repository name, which may omit the
+/// domain (`atlas` becomes `atlas.cern.ch`)
+pub fn derive_fqrn(name: &str, default_domain: &str) -> String {
+    if name.contains('.') || default_domain.is_empty() {
+        name.into()
+    } else {
+        format!("{}.{}", name, default_domain)
+    }
+}
+
+/// Expands a server url template the way `CVMFS_SERVER_URL` does: `@fqrn@` is
+/// replaced by the repository name and `@org@` by its first label
+pub fn expand_server_url(template: &str, fqrn: &str) -> String {
+    let org = fqrn.split('.').next().unwrap_or(fqrn);
+    template
+        .replace(FQRN_PLACEHOLDER, fqrn)
+        .replace(ORG_PLACEHOLDER, org)
+}
+
+/// Everything needed to mount a repository, shared by the command line and
+/// the embedders of the library
+#[derive(Debug, Clone, PartialEq)]
+pub struct MountConfig {
+    /// Url of the repository, or a template like `http://host/cvmfs/@fqrn@`.
+    /// Several mirrors can be separated by `;`, the fetcher failing over to
+    /// the next one when a mirror can't be reached (see `server_urls`).
+    pub repository_url: String,
+    /// Name of the repository, derived from the mount point when not given
+    pub repository_name: Option<String>,
+    pub default_domain: String,
+    pub mount_point: PathBuf,
+    pub cache_directory: String,
+    /// Takes the new objects when the cache directory is full or unwritable
+    pub fallback_cache_directory: Option<String>,
+    /// Options passed to FUSE with `-o`
+    pub fuse_options: Vec<String>,
+    pub threads: usize,
+    pub repository_type: String,
+    /// Directory holding the public master keys of the repositories
+    pub keys_directory: PathBuf,
+    /// Directory of the repository exposed as the root of the mount
+    pub subpath: Option<String>,
+    /// Tag the mount gets pinned to
+    pub tag: Option<String>,
+    /// Revision mounted instead of the latest one, for the current mount only
+    pub revision: Option<u32>,
+    pub sibling_prefetch: Option<SiblingPrefetch>,
+    /// Chunks prefetched ahead of sequential reads, disabled with 0
+    pub chunk_read_ahead: usize,
+    /// Handling of failed signature, digest and whitelist expiry checks
+    pub validation: ValidationPolicy,
+    /// Log of the lookups and opens, disabled when `None`
+    pub access_log: Option<AccessLogConfig>,
+    /// Mounting as a regular user through the setuid fusermount helper
+    pub unprivileged: bool,
+    /// Answers to the `security.*` and `system.*` extended attributes
+    pub xattr_policy: XattrPolicy,
+    /// Seconds the cached revision is served while the server is unreachable,
+    /// without limit when `None`
+    pub max_staleness: Option<u64>,
+    /// Background re-hashing of the cached objects, disabled when `None`
+    pub scrub: Option<ScrubberConfig>,
+    /// File recording every verified manifest, whitelist, catalog and object
+    pub audit_log: Option<PathBuf>,
+    /// Levels, rate limit and file of the daemon log
+    pub log: LogConfig,
+    /// File the usage analytics are written to on unmount, not collected when
+    /// `None`
+    pub analytics_report: Option<PathBuf>,
+    /// Proxies of the downloads, as in `CVMFS_HTTP_PROXY`, the ones of the
+    /// environment when `None`
+    pub http_proxy: Option<ProxyConfig>,
+    /// Servers of the files with external data, as in `CVMFS_EXTERNAL_URL`,
+    /// with the same placeholders as the server url
+    pub external_url: Option<String>,
+    /// Behavior once the whitelist expires, when its expiry is fatal
+    pub whitelist_expiry_policy: ExpiryPolicy,
+    /// Size limit in bytes of the cached objects, unbounded when `None`
+    pub cache_quota: Option<u64>,
+    /// Catalogs kept open at once, the least recently used ones are closed
+    pub max_opened_catalogs: usize,
+    /// Memory in bytes the opened catalogs may use, unbounded when `None`
+    pub catalog_memory_limit: Option<usize>,
+    /// Serves the cached data only, without ever contacting the servers
+    pub offline: bool,
+    /// Switches to the new revisions as the TTL of the current one expires
+    pub auto_refresh: bool,
+    /// Orders the mirrors by proximity with the GeoAPI, at mount time and
+    /// then periodically
+    pub use_geo_api: bool,
+    /// File the Prometheus metrics are periodically written to
+    pub metrics_file: Option<PathBuf>,
+    /// Address of the HTTP endpoint serving the Prometheus metrics
+    pub metrics_address: Option<String>,
+    /// Values of the placeholders of the variant symlinks, on top of the
+    /// environment
+    pub symlink_variables: SymlinkVariables,
+}
+
+impl MountConfig {
+    pub fn new(repository_url: &str, mount_point: &Path, cache_directory: &str) -> Self {
+        Self {
+            repository_url: repository_url.into(),
+            repository_name: None,
+            default_domain: DEFAULT_DOMAIN.into(),
+            mount_point: mount_point.into(),
+            cache_directory: cache_directory.into(),
+            fallback_cache_directory: None,
+            fuse_options: vec![DEFAULT_FSNAME_OPTION.into()],
+            threads: DEFAULT_FUSE_THREADS,
+            repository_type: DEFAULT_REPOSITORY_TYPE.into(),
+            keys_directory: PathBuf::from(KEYS_DIRECTORY),
+            subpath: None,
+            tag: None,
+            revision: None,
+            sibling_prefetch: None,
+            chunk_read_ahead: DEFAULT_CHUNK_READ_AHEAD,
+            validation: Default::default(),
+            access_log: None,
+            unprivileged: user_mount::is_unprivileged(),
+            xattr_policy: Default::default(),
+            max_staleness: None,
+            scrub: None,
+            audit_log: None,
+            log: LogConfig::from_env(),
+            analytics_report: None,
+            http_proxy: None,
+            external_url: None,
+            whitelist_expiry_policy: Default::default(),
+            cache_quota: None,
+            max_opened_catalogs: DEFAULT_MAX_OPENED_CATALOGS,
+            catalog_memory_limit: None,
+            offline: false,
+            auto_refresh: true,
+            use_geo_api: false,
+            metrics_file: None,
+            metrics_address: None,
+            symlink_variables: Default::default(),
+        }
+    }
+
+    /// Parses `<url> <mount point> [cache directory]` followed or preceded by
+    /// the options of [`MountArgs`], the program name excluded
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> CvmfsResult<Self> {
+        MountArgs::try_parse_from(std::iter::once("mount".to_string()).chain(args))
+            .map_err(|e| CvmfsError::InvalidConfiguration(e.to_string()))?
+            .into_config()
+    }
+
+    /// Checks the settings that would otherwise fail deep inside the mount
+    pub fn validate(&self) -> CvmfsResult<()> {
+        self.server_url()?;
+        if !self.mount_point.is_dir() {
+            return Err(CvmfsError::InvalidMountPoint(
+                self.mount_point.to_string_lossy().into_owned(),
+            ));
+        }
+        if self.cache_directory.is_empty() {
+            return Err(CvmfsError::InvalidConfiguration(
+                "the cache directory is empty".into(),
+            ));
+        }
+        if self
+            .subpath
+            .as_ref()
+            .is_some_and(|subpath| !subpath.starts_with('/'))
+        {
+            return Err(CvmfsError::InvalidConfiguration(
+                "the subpath must be absolute".into(),
+            ));
+        }
+        if self.threads == 0 {
+            return Err(CvmfsError::InvalidConfiguration(
+                "at least one FUSE thread is needed".into(),
+            ));
+        }
+        if self.unprivileged {
+            user_mount::check_user_mount()?;
+        }
+        if self
+            .sibling_prefetch
+            .as_ref()
+            .is_some_and(|settings| settings.concurrency == 0)
+        {
+            return Err(CvmfsError::InvalidConfiguration(
+                "the prefetch concurrency must be positive".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Fully qualified name of the repository, from the configured name or
+    /// else from the last component of the mount point, as in `/cvmfs/<fqrn>`
+    pub fn fqrn(&self) -> Option<String> {
+        let name = match &self.repository_name {
+            Some(name) => name.as_str(),
+            None => self.mount_point.file_name()?.to_str()?,
+        };
+        Some(derive_fqrn(name, &self.default_domain))
+    }
+
+    /// Url of the first mirror of the repository, with the template
+    /// placeholders expanded
+    pub fn server_url(&self) -> CvmfsResult<String> {
+        Ok(self.server_urls()?.remove(0))
+    }
+
+    /// Urls of every mirror of the repository, separated by `;` as in
+    /// `CVMFS_SERVER_URL`, with the template placeholders expanded
+    pub fn server_urls(&self) -> CvmfsResult<Vec<String>> {
+        let templates: Vec<&str> = self
+            .repository_url
+            .split(';')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .collect();
+        if templates.is_empty() {
+            return Err(CvmfsError::InvalidConfiguration(
+                "the repository url is empty".into(),
+            ));
+        }
+        templates
+            .into_iter()
+            .map(|template| {
+                if !template.contains(FQRN_PLACEHOLDER) && !template.contains(ORG_PLACEHOLDER) {
+                    return Ok(template.into());
+                }
+                let fqrn = self.fqrn().ok_or_else(|| {
+                    CvmfsError::InvalidConfiguration(format!(
+                        "no repository name to expand {}",
+                        template
+                    ))
+                })?;
+                Ok(expand_server_url(template, &fqrn))
+            })
+            .collect()
+    }
+
+    pub fn create_fetcher(&self) -> CvmfsResult<Fetcher> {
+        let mut cache = match &self.fallback_cache_directory {
+            Some(fallback) => Cache::with_fallback(self.cache_directory.clone(), fallback.clone())?,
+            None => Cache::new(self.cache_directory.clone())?,
+        };
+        cache.record_digests = self.scrub.is_some();
+        cache.initialize()?;
+        if let Some(limit) = self.cache_quota {
+            cache.set_quota(limit)?;
+        }
+        let urls = self.server_urls()?;
+        let urls: Vec<&str> = urls.iter().map(String::as_str).collect();
+        let mut fetcher = Fetcher::with_mirrors(&urls, cache)?;
+        if let Some(proxy) = &self.http_proxy {
+            fetcher.proxies = Some(Arc::new(ProxyChain::new(proxy)?));
+        }
+        if let Some(path) = &self.audit_log {
+            fetcher.audit_log = Some(Arc::new(AuditLog::open(path)?));
+        }
+        fetcher.set_offline(self.offline);
+        if self.use_geo_api && !self.offline {
+            if let Err(e) = fetcher.sort_mirrors_by_geo() {
+                log::warn!("Could not order the mirrors by proximity: {:?}", e);
+            }
+        }
+        Ok(fetcher)
+    }
+
+    /// Opens the repository with the settings of the configuration applied,
+    /// pinning it to the configured tag or revision if any
+    pub fn create_repository(&self) -> CvmfsResult<Repository> {
+        let expected_fqrn = self
+            .repository_name
+            .as_ref()
+            .map(|name| derive_fqrn(name, &self.default_domain));
+        let trust = TrustSettings {
+            validation: self.validation.clone(),
+            keys_directory: self.keys_directory.clone(),
+            expected_fqrn: expected_fqrn.clone(),
+            whitelist_expiry_policy: self.whitelist_expiry_policy,
+        };
+        let mut repository = Repository::with_trust(self.create_fetcher()?, trust)?;
+        if let Some(fqrn) = expected_fqrn.filter(|fqrn| *fqrn != repository.fqrn) {
+            return Err(CvmfsError::InvalidConfiguration(format!(
+                "expected repository {} but the server provides {}",
+                fqrn, repository.fqrn
+            )));
+        }
+        repository.repo_type = self.repository_type.clone();
+        repository.sibling_prefetch = self.sibling_prefetch.clone();
+        repository.chunk_read_ahead = self.chunk_read_ahead;
+        repository.max_opened_catalogs = self.max_opened_catalogs;
+        repository.memory_limits.catalogs = self.catalog_memory_limit;
+        if self.use_geo_api {
+            repository.geo_sort_interval = Some(DEFAULT_GEO_SORT_INTERVAL);
+        }
+        repository.max_staleness = self
+            .max_staleness
+            .map(|seconds| TimeDelta::seconds(seconds as i64));
+        if let Some(urls) = &self.external_url {
+            repository.set_external_urls(&expand_server_url(urls, &repository.fqrn))?;
+        }
+        if let Some(tag) = &self.tag {
+            repository.pin_tag(tag)?;
+        }
+        if let Some(revision) = self.revision {
+            repository.set_current_tag(revision)?;
+            log::info!("Mounting revision {} of {}", revision, repository.fqrn);
+        }
+        Ok(repository)
+    }
+
+    /// Creates the file system serving the repository, restricted to the
+    /// configured subpath
+    pub fn create_file_system(&self, repository: Repository) -> CvmfsResult<CernvmFileSystem> {
+        let scrubber = self
+            .scrub
+            .clone()
+            .map(|config| Scrubber::new(repository.cache().clone(), config))
+            .transpose()?;
+        let mut file_system = CernvmFileSystem::new(repository)?;
+        if let Some(scrubber) = scrubber {
+            file_system.set_scrubber(scrubber.spawn());
+        }
+        if self.auto_refresh {
+            file_system.spawn_refresher();
+        }
+        if let Some(subpath) = &self.subpath {
+            file_system.set_subpath(subpath)?;
+        }
+        if let Some(access_log) = self.create_access_log()? {
+            file_system.set_access_log(access_log);
+        }
+        file_system.set_xattr_policy(self.xattr_policy.clone());
+        file_system.set_symlink_variables(self.symlink_variables.clone());
+        if let Some(report) = &self.analytics_report {
+            file_system.set_analytics(Arc::new(Analytics::default()), Some(report.clone()))?;
+        }
+        Ok(file_system)
+    }
+
+    /// Opens the access log, if enabled
+    pub fn create_access_log(&self) -> CvmfsResult<Option<AccessLog>> {
+        self.access_log.clone().map(AccessLog::open).transpose()
+    }
+
+    /// Arguments handed to FUSE when mounting. Regular users lose the options
+    /// the mount helper would refuse, which would otherwise fail the mount.
+    pub fn fuse_args(&self) -> Vec<&OsStr> {
+        let allow_other = !self.unprivileged || user_mount::user_allow_other();
+        self.fuse_options
+            .iter()
+            .filter(|option| {
+                let permitted = user_mount::is_user_fuse_option(option, allow_other);
+                if !permitted {
+                    log::warn!(
+                        "Ignoring the {} option, user_allow_other is not set in {}",
+                        option,
+                        user_mount::FUSE_CONF_PATH
+                    );
+                }
+                permitted
+            })
+            .flat_map(|option| [OsStr::new("-o"), OsStr::new(option)])
+            .collect()
+    }
+}
+
+/// Arguments of the mount, applied on top of the configuration files
+#[derive(Debug, Clone, PartialEq, Parser)]
+#[command(args_override_self = true)]
+pub struct MountArgs {
+    /// Url of the repository, or a template like `http://host/cvmfs/@fqrn@`,
+    /// with the mirrors to fail over to separated by `;`
+    pub repository_url: String,
+    pub mount_point: PathBuf,
+    pub cache_directory: Option<String>,
+    /// Configuration file read instead of the system ones
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+    #[arg(long)]
+    pub cache_dir: Option<String>,
+    /// Takes the new objects when the cache directory is full or unwritable
+    #[arg(long)]
+    pub fallback_cache_dir: Option<String>,
+    /// Size limit in bytes of the cached objects
+    #[arg(long)]
+    pub cache_quota: Option<u64>,
+    /// Catalogs kept open at once
+    #[arg(long)]
+    pub max_catalogs: Option<usize>,
+    /// Memory in bytes the opened catalogs may use
+    #[arg(long)]
+    pub catalog_memory_limit: Option<usize>,
+    /// Name of the repository, derived from the mount point when not given
+    #[arg(long)]
+    pub repository: Option<String>,
+    #[arg(long)]
+    pub default_domain: Option<String>,
+    #[arg(long)]
+    pub keys_dir: Option<PathBuf>,
+    /// Tag the mount gets pinned to
+    #[arg(long, conflicts_with = "revision")]
+    pub tag: Option<String>,
+    /// Revision mounted instead of the latest one
+    #[arg(long)]
+    pub revision: Option<u32>,
+    #[arg(long)]
+    pub external_url: Option<String>,
+    /// Seconds the cached revision is served while the server is unreachable
+    #[arg(long)]
+    pub max_staleness: Option<u64>,
+    /// Directory of the repository exposed as the root of the mount
+    #[arg(long)]
+    pub subpath: Option<String>,
+    #[arg(long)]
+    pub selinux_context: Option<String>,
+    #[arg(long)]
+    pub threads: Option<usize>,
+    #[arg(long)]
+    pub repo_type: Option<String>,
+    /// Options passed to FUSE, separated by commas
+    #[arg(long, value_delimiter = ',')]
+    pub fuse_options: Vec<String>,
+    /// Chunks prefetched ahead of sequential reads, disabled with 0
+    #[arg(long)]
+    pub chunk_read_ahead: Option<usize>,
+    /// Prefetches the files up to this size, in bytes, of the opened
+    /// directories
+    #[arg(long)]
+    pub prefetch_siblings: Option<u64>,
+    #[arg(long, requires = "prefetch_siblings")]
+    pub prefetch_concurrency: Option<usize>,
+    #[arg(long)]
+    pub validation: Option<ValidationPolicy>,
+    /// File, or `syslog`, the lookups and opens are logged to
+    #[arg(long)]
+    pub access_log: Option<AccessLogTarget>,
+    #[arg(long, requires = "access_log")]
+    pub access_log_rate: Option<u32>,
+    #[arg(long, requires = "access_log")]
+    pub access_log_max_size: Option<u64>,
+    #[arg(long, requires = "access_log")]
+    pub access_log_rotations: Option<usize>,
+    /// Bytes of the cache re-hashed per second
+    #[arg(long)]
+    pub scrub_rate: Option<u64>,
+    /// Seconds between two scrubs of the cache
+    #[arg(long, requires = "scrub_rate")]
+    pub scrub_interval: Option<u64>,
+    #[arg(long)]
+    pub audit_log: Option<PathBuf>,
+    /// File the usage analytics are written to on unmount
+    #[arg(long)]
+    pub analytics: Option<PathBuf>,
+    #[arg(long)]
+    pub metrics_file: Option<PathBuf>,
+    #[arg(long)]
+    pub metrics_listen: Option<String>,
+    /// Value of a placeholder of the variant symlinks, as `NAME=value`
+    #[arg(long)]
+    pub symlink_variable: Vec<String>,
+    /// Skips the whole signature chain, for repositories without keys
+    #[arg(long)]
+    pub insecure: bool,
+    #[arg(long)]
+    pub offline: bool,
+    #[arg(long)]
+    pub no_auto_refresh: bool,
+    #[arg(long)]
+    pub use_geoapi: bool,
+    #[arg(long)]
+    pub whitelist_expiry_policy: Option<ExpiryPolicy>,
+    /// Proxies of the downloads, as in `CVMFS_HTTP_PROXY`
+    #[arg(long)]
+    pub http_proxy: Option<String>,
+    #[arg(long)]
+    pub log_levels: Option<String>,
+    #[arg(long)]
+    pub log_rate: Option<u32>,
+    #[arg(long)]
+    pub log_file: Option<PathBuf>,
+    #[arg(long, requires = "log_file")]
+    pub log_max_size: Option<u64>,
+    #[arg(long, requires = "log_file")]
+    pub log_rotations: Option<usize>,
+}
+
+impl MountArgs {
+    /// Configuration of the mount: the configuration files come first, so
+    /// that the arguments override them
+    pub fn into_config(self) -> CvmfsResult<MountConfig> {
+        let mut config = MountConfig::new(
+            &self.repository_url,
+            &self.mount_point,
+            &default_cache_directory(),
+        );
+        let client_config = match &self.config {
+            Some(path) => ClientConfig::load_file(path)?,
+            None => match self
+                .repository
+                .clone()
+                .or_else(|| Some(self.mount_point.file_name()?.to_str()?.to_string()))
+            {
+                Some(name) => ClientConfig::load_system(&name)?,
+                None => ClientConfig::default(),
+            },
+        };
+        client_config.apply(&mut config)?;
+        if let Some(cache_directory) = self.cache_dir.or(self.cache_directory) {
+            config.cache_directory = cache_directory;
+        }
+        config.fallback_cache_directory = self.fallback_cache_dir;
+        if let Some(quota) = self.cache_quota {
+            config.cache_quota = Some(quota);
+        }
+        if let Some(max_catalogs) = self.max_catalogs {
+            config.max_opened_catalogs = max_catalogs;
+        }
+        config.catalog_memory_limit = self.catalog_memory_limit;
+        if let Some(name) = self.repository {
+            config.repository_name = Some(name);
+        }
+        if let Some(domain) = self.default_domain {
+            config.default_domain = domain;
+        }
+        if let Some(keys_directory) = self.keys_dir {
+            config.keys_directory = keys_directory;
+        }
+        if let Some(tag) = self.tag {
+            config.tag = Some(tag);
+        }
+        config.revision = self.revision;
+        if config.tag.is_some() && config.revision.is_some() {
+            return Err(CvmfsError::InvalidConfiguration(
+                "--tag and --revision are mutually exclusive".into(),
+            ));
+        }
+        if let Some(url) = self.external_url {
+            config.external_url = Some(url);
+        }
+        config.max_staleness = self.max_staleness;
+        config.subpath = self.subpath;
+        if let Some(context) = self.selinux_context {
+            config.xattr_policy = XattrPolicy::with_selinux_context(&context)?;
+        }
+        if let Some(threads) = self.threads {
+            config.threads = threads;
+        }
+        if let Some(repository_type) = self.repo_type {
+            config.repository_type = repository_type;
+        }
+        config.fuse_options.extend(self.fuse_options);
+        if let Some(chunks) = self.chunk_read_ahead {
+            config.chunk_read_ahead = chunks;
+        }
+        config.sibling_prefetch = self.prefetch_siblings.map(|max_file_size| {
+            let defaults = SiblingPrefetch::default();
+            SiblingPrefetch {
+                max_file_size,
+                concurrency: self.prefetch_concurrency.unwrap_or(defaults.concurrency),
+            }
+        });
+        if let Some(validation) = self.validation {
+            config.validation = validation;
+        }
+        if self.insecure {
+            config.validation.signature = ValidationMode::Ignore;
+        }
+        config.access_log = self.access_log.map(|target| {
+            let mut access_log = AccessLogConfig::new(target);
+            access_log.max_records_per_second = self.access_log_rate;
+            access_log.max_file_size = self.access_log_max_size;
+            if let Some(rotations) = self.access_log_rotations {
+                access_log.rotations = rotations;
+            }
+            access_log
+        });
+        config.scrub = self.scrub_rate.map(|bytes_per_second| {
+            let mut scrub = ScrubberConfig::new(bytes_per_second);
+            if let Some(seconds) = self.scrub_interval {
+                scrub.interval = Duration::from_secs(seconds);
+            }
+            scrub
+        });
+        config.audit_log = self.audit_log;
+        config.analytics_report = self.analytics;
+        config.metrics_file = self.metrics_file;
+        config.metrics_address = self.metrics_listen;
+        for assignment in &self.symlink_variable {
+            config.symlink_variables.set_assignment(assignment)?;
+        }
+        config.offline = self.offline;
+        config.auto_refresh = !self.no_auto_refresh;
+        if self.use_geoapi {
+            config.use_geo_api = true;
+        }
+        if let Some(policy) = self.whitelist_expiry_policy {
+            config.whitelist_expiry_policy = policy;
+        }
+        if let Some(proxy) = self.http_proxy {
+            config.http_proxy = Some(ProxyConfig::parse(&proxy)?);
+        }
+        if let Some(levels) = self.log_levels {
+            config.log.set_levels(&levels)?;
+        }
+        if let Some(rate) = self.log_rate {
+            config.log.max_messages_per_second = Some(rate);
+        }
+        if let Some(file) = self.log_file {
+            config.log.file = Some(file);
+        }
+        if let Some(size) = self.log_max_size {
+            config.log.max_file_size = Some(size);
+        }
+        if let Some(rotations) = self.log_rotations {
+            config.log.rotations = rotations;
+        }
+        Ok(config)
+    }
+}
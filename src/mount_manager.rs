@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use fuser::BackgroundSession;
+
+use crate::common::{CvmfsError, CvmfsResult};
+use crate::mount_config::MountConfig;
+
+pub use crate::mount_config::DEFAULT_FUSE_THREADS;
+
+/// Description of a repository mount requested by an embedder
+pub type MountSpec = MountConfig;
+
+/// Time a mount has to list its root before it is reported unresponsive
+pub const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Result of a health check on a managed mount
+#[derive(Debug, Clone, PartialEq)]
+pub enum MountHealth {
+    Healthy,
+    Unresponsive(String),
+    Terminated,
+}
+
+/// Public view of a managed mount
+#[derive(Debug, Clone)]
+pub struct MountInfo {
+    pub spec: MountSpec,
+    pub fqrn: String,
+    pub mounted_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+struct ManagedMount {
+    info: MountInfo,
+    session: BackgroundSession,
+}
+
+/// Creates, tracks and destroys repository mounts inside a long running process,
+/// such as a Kubernetes CSI driver.
+/// All the operations are idempotent: mounting an already mounted spec returns
+/// the existing mount, and unmounting an unknown mount point succeeds.
+#[derive(Debug, Default)]
+pub struct MountManager {
+    mounts: Mutex<HashMap<PathBuf, ManagedMount>>,
+}
+
+impl MountManager {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn mount(&self, spec: MountSpec) -> CvmfsResult<MountInfo> {
+        let mount_point = Self::validate_mount_point(&spec.mount_point)?;
+        if let Some(existing) = self.existing_mount(&mount_point, &spec)? {
+            return Ok(existing);
+        }
+        log::info!(
+            "Mounting {} on {}",
+            spec.repository_url,
+            mount_point.display()
+        );
+        spec.validate()?;
+        // opening the repository downloads from the server, so it is done
+        // without blocking the other mounts
+        let repository = spec.create_repository()?;
+        let fqrn = repository.fqrn.clone();
+        let file_system = spec.create_file_system(repository)?;
+        let mut mounts = self.mounts.lock().map_err(|_| CvmfsError::Sync)?;
+        // a concurrent call may have mounted the same spec in the meantime
+        if let Some(existing) = mounts.get(&mount_point) {
+            return Self::check_existing(&mount_point, existing, &spec);
+        }
+        let session = fuse_mt::spawn_mount(
+            fuse_mt::FuseMT::new(file_system, spec.threads),
+            &mount_point,
+            &spec.fuse_args(),
+        )?;
+        let info = MountInfo {
+            spec,
+            fqrn,
+            mounted_at: Utc::now(),
+        };
+        mounts.insert(
+            mount_point,
+            ManagedMount {
+                info: info.clone(),
+                session,
+            },
+        );
+        Ok(info)
+    }
+
+    fn existing_mount(
+        &self,
+        mount_point: &Path,
+        spec: &MountSpec,
+    ) -> CvmfsResult<Option<MountInfo>> {
+        let mounts = self.mounts.lock().map_err(|_| CvmfsError::Sync)?;
+        mounts
+            .get(mount_point)
+            .map(|existing| Self::check_existing(mount_point, existing, spec))
+            .transpose()
+    }
+
+    fn check_existing(
+        mount_point: &Path,
+        existing: &ManagedMount,
+        spec: &MountSpec,
+    ) -> CvmfsResult<MountInfo> {
+        if existing.info.spec.repository_url != spec.repository_url {
+            return Err(CvmfsError::MountPointBusy(
+                mount_point.to_string_lossy().into_owned(),
+            ));
+        }
+        Ok(existing.info.clone())
+    }
+
+    pub fn unmount(&self, mount_point: &Path) -> CvmfsResult<()> {
+        let mount_point = canonical_mount_point(mount_point);
+        let removed = self
+            .mounts
+            .lock()
+            .map_err(|_| CvmfsError::Sync)?
+            .remove(&mount_point);
+        if let Some(mount) = removed {
+            log::info!("Unmounting {}", mount_point.display());
+            // dropping the session unmounts the file system
+            drop(mount.session);
+        }
+        Ok(())
+    }
+
+    pub fn list(&self) -> CvmfsResult<Vec<MountInfo>> {
+        let mounts = self.mounts.lock().map_err(|_| CvmfsError::Sync)?;
+        Ok(mounts.values().map(|mount| mount.info.clone()).collect())
+    }
+
+    pub fn get(&self, mount_point: &Path) -> CvmfsResult<MountInfo> {
+        let mount_point = canonical_mount_point(mount_point);
+        let mounts = self.mounts.lock().map_err(|_| CvmfsError::Sync)?;
+        mounts
+            .get(&mount_point)
+            .map(|mount| mount.info.clone())
+            .ok_or(CvmfsError::MountNotFound(
+                mount_point.to_string_lossy().into_owned(),
+            ))
+    }
+
+    /// Checks that a mount still answers, giving up on it after
+    /// `HEALTH_CHECK_TIMEOUT`
+    pub fn health_check(&self, mount_point: &Path) -> CvmfsResult<MountHealth> {
+        let mount_point = canonical_mount_point(mount_point);
+        let terminated = {
+            let mounts = self.mounts.lock().map_err(|_| CvmfsError::Sync)?;
+            let mount = mounts.get(&mount_point).ok_or(CvmfsError::MountNotFound(
+                mount_point.to_string_lossy().into_owned(),
+            ))?;
+            mount.session.guard.is_finished()
+        };
+        if terminated {
+            return Ok(MountHealth::Terminated);
+        }
+        Ok(wait_for_probe(
+            probe(mount_point),
+            Instant::now() + HEALTH_CHECK_TIMEOUT,
+        ))
+    }
+
+    /// Checks all the mounts at once
+    pub fn health_check_all(&self) -> CvmfsResult<Vec<(PathBuf, MountHealth)>> {
+        let snapshot: Vec<(PathBuf, bool)> = {
+            let mounts = self.mounts.lock().map_err(|_| CvmfsError::Sync)?;
+            mounts
+                .iter()
+                .map(|(mount_point, mount)| {
+                    (mount_point.clone(), mount.session.guard.is_finished())
+                })
+                .collect()
+        };
+        let probes: Vec<_> = snapshot
+            .into_iter()
+            .map(|(mount_point, terminated)| {
+                let probe = (!terminated).then(|| probe(mount_point.clone()));
+                (mount_point, probe)
+            })
+            .collect();
+        let deadline = Instant::now() + HEALTH_CHECK_TIMEOUT;
+        Ok(probes
+            .into_iter()
+            .map(|(mount_point, probe)| {
+                let health = match probe {
+                    Some(probe) => wait_for_probe(probe, deadline),
+                    None => MountHealth::Terminated,
+                };
+                (mount_point, health)
+            })
+            .collect())
+    }
+
+    fn validate_mount_point(mount_point: &Path) -> CvmfsResult<PathBuf> {
+        let invalid = || CvmfsError::InvalidMountPoint(mount_point.to_string_lossy().into_owned());
+        if !mount_point.is_absolute() {
+            return Err(invalid());
+        }
+        let metadata = fs::metadata(mount_point).map_err(|_| invalid())?;
+        if !metadata.is_dir() {
+            return Err(invalid());
+        }
+        Ok(canonical_mount_point(mount_point))
+    }
+}
+
+/// Lists a mount point on a worker thread, so that a hung mount can't block
+/// the caller
+fn probe(mount_point: PathBuf) -> Receiver<MountHealth> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let health = match fs::read_dir(&mount_point) {
+            Ok(_) => MountHealth::Healthy,
+            Err(e) => MountHealth::Unresponsive(format!("{:?}", e)),
+        };
+        let _ = sender.send(health);
+    });
+    receiver
+}
+
+fn wait_for_probe(probe: Receiver<MountHealth>, deadline: Instant) -> MountHealth {
+    let timeout = deadline.saturating_duration_since(Instant::now());
+    probe.recv_timeout(timeout).unwrap_or_else(|_| {
+        MountHealth::Unresponsive(format!("no answer within {:?}", HEALTH_CHECK_TIMEOUT))
+    })
+}
+
+fn canonical_mount_point(mount_point: &Path) -> PathBuf {
+    mount_point
+        .canonicalize()
+        .unwrap_or(mount_point.to_path_buf())
+}
@@ -0,0 +1,223 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crate::common::{compose_object_path, normalize_path, CvmfsError, CvmfsResult};
+use crate::repository::Repository;
+
+/// Objects downloaded at once while preloading
+pub const DEFAULT_PRELOAD_CONCURRENCY: usize = 16;
+
+/// Paths to preload, in the format of the `.cvmfsdirtab` files: one absolute
+/// path per line, whose components may contain `*` and `?` wildcards, with
+/// `!` in front of the paths to leave out. Comments start with `#`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PreloadSpec {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl PreloadSpec {
+    pub fn parse(content: &str) -> CvmfsResult<Self> {
+        let mut spec = Self::default();
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            match line.strip_prefix('!') {
+                Some(excluded) => spec.exclude.push(absolute_pattern(excluded.trim())?),
+                None => spec.include.push(absolute_pattern(line)?),
+            }
+        }
+        Ok(spec)
+    }
+
+    pub fn load_file(path: &Path) -> CvmfsResult<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Spec preloading whole subtrees
+    pub fn from_paths(paths: &[String]) -> CvmfsResult<Self> {
+        Ok(Self {
+            include: paths
+                .iter()
+                .map(|path| absolute_pattern(path))
+                .collect::<CvmfsResult<_>>()?,
+            exclude: vec![],
+        })
+    }
+
+    /// Whether a path or one of its ancestors is excluded
+    pub fn is_excluded(&self, path: &str) -> bool {
+        self.exclude.iter().any(|pattern| {
+            let mut components = path.split('/').skip(1);
+            pattern.split('/').skip(1).all(|wanted| {
+                components
+                    .next()
+                    .is_some_and(|name| glob_match(wanted, name))
+            })
+        })
+    }
+}
+
+/// What a preload found and downloaded
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PreloadSummary {
+    /// Catalogs serving the preloaded subtrees
+    pub catalogs: usize,
+    pub files: u64,
+    /// Size of the preloaded files
+    pub bytes: u64,
+    /// Objects of the files, chunks counted one by one
+    pub objects: usize,
+    /// Objects that were already in the cache
+    pub cached: usize,
+    /// Files with external data, which are not cached
+    pub skipped: u64,
+    /// Objects that could not be downloaded
+    pub missing: Vec<String>,
+}
+
+/// Fills the cache with the catalogs and the objects of the files matched by
+/// a spec in the current revision, so that they can be served offline
+pub fn preload(
+    repository: &Repository,
+    spec: &PreloadSpec,
+    concurrency: usize,
+) -> CvmfsResult<PreloadSummary> {
+    let root_hash = repository.get_root_hash()?.to_string();
+    let mut summary = PreloadSummary::default();
+    let mut paths = Vec::new();
+    for pattern in &spec.include {
+        paths.extend(expand_pattern(repository, &root_hash, pattern)?);
+    }
+    let mut object_names = BTreeSet::new();
+    for path in paths.iter().filter(|path| !spec.is_excluded(path)) {
+        summary.catalogs += repository.preload_catalogs(path)?;
+        for wrapper in repository.walk_at(&root_hash, path)? {
+            let wrapper = wrapper?;
+            let mut entry = wrapper.directory_entry;
+            if !entry.is_file() || entry.is_symlink() || spec.is_excluded(&wrapper.path) {
+                continue;
+            }
+            if entry.is_external() {
+                summary.skipped += 1;
+                continue;
+            }
+            summary.files += 1;
+            summary.bytes += entry.size;
+            if entry.has_chunks() {
+                repository
+                    .retrieve_catalog_for_path_at(&root_hash, &wrapper.path)?
+                    .load_chunks(&mut entry)?;
+                for chunk in &entry.chunks {
+                    object_names.insert(object_name(&chunk.content_hash_string())?);
+                }
+            } else if let Some(hash) = entry.content_hash_string() {
+                object_names.insert(object_name(&hash)?);
+            }
+        }
+    }
+    summary.objects = object_names.len();
+    let missing: Vec<String> = object_names
+        .into_iter()
+        .filter(|name| repository.cache().get(name).is_none())
+        .collect();
+    summary.cached = summary.objects - missing.len();
+    log::info!("Preloading {} objects", missing.len());
+    repository.prefetch_objects(&missing, concurrency);
+    summary.missing = missing
+        .into_iter()
+        .filter(|name| repository.cache().get(name).is_none())
+        .collect();
+    Ok(summary)
+}
+
+fn object_name(hash: &str) -> CvmfsResult<String> {
+    Ok(compose_object_path(hash, "")?
+        .to_str()
+        .ok_or(CvmfsError::FileNotFound)?
+        .to_string())
+}
+
+fn absolute_pattern(path: &str) -> CvmfsResult<String> {
+    let path = normalize_path(path);
+    if !path.starts_with('/') {
+        return Err(CvmfsError::InvalidConfiguration(format!(
+            "the preload path {} is not absolute",
+            path
+        )));
+    }
+    match path.trim_end_matches('/') {
+        "" => Ok("/".into()),
+        path => Ok(path.into()),
+    }
+}
+
+/// Paths of the current revision matched by a pattern, listing the
+/// directories of its components with wildcards
+fn expand_pattern(
+    repository: &Repository,
+    root_hash: &str,
+    pattern: &str,
+) -> CvmfsResult<Vec<String>> {
+    let mut paths = vec![String::new()];
+    for component in pattern.split('/').skip(1) {
+        if !component.contains(['*', '?']) {
+            paths.iter_mut().for_each(|path| {
+                path.push('/');
+                path.push_str(component);
+            });
+            continue;
+        }
+        let mut matched = Vec::new();
+        for parent in &paths {
+            let directory = if parent.is_empty() { "/" } else { parent };
+            let Ok(entries) = repository.list_directory_at(root_hash, directory) else {
+                continue;
+            };
+            matched.extend(
+                entries
+                    .iter()
+                    .filter(|entry| glob_match(component, &entry.name))
+                    .map(|entry| format!("{}/{}", parent, entry.name)),
+            );
+        }
+        paths = matched;
+    }
+    Ok(paths
+        .into_iter()
+        .map(|path| if path.is_empty() { "/".into() } else { path })
+        .collect())
+}
+
+/// Matches a name against a pattern where `*` stands for any characters and
+/// `?` for a single one
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
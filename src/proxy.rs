@@ -0,0 +1,162 @@
+use std::time::Duration;
+
+use reqwest::blocking::Client;
+
+use crate::common::{CvmfsError, CvmfsResult};
+use crate::mirrors::{MirrorSet, MirrorStatus};
+
+/// Proxies of the reference client configuration
+pub const HTTP_PROXY_VARIABLE: &str = "CVMFS_HTTP_PROXY";
+/// Stands for downloading without a proxy
+pub const DIRECT: &str = "DIRECT";
+/// Time a failed proxy is skipped, as `CVMFS_PROXY_RESET_AFTER` defaults to
+pub const DEFAULT_PROXY_RESET_AFTER: Duration = Duration::from_secs(300);
+
+/// Proxies downloads go through, in the syntax of `CVMFS_HTTP_PROXY`: groups
+/// separated by `;` are tried in order, while the proxies of a group,
+/// separated by `|`, share the load
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProxyConfig {
+    pub groups: Vec<Vec<String>>,
+}
+
+impl ProxyConfig {
+    pub fn parse(value: &str) -> CvmfsResult<Self> {
+        let mut groups = Vec::new();
+        for group in value.split(';').map(str::trim).filter(|g| !g.is_empty()) {
+            let mut proxies = Vec::new();
+            for proxy in group.split('|').map(str::trim).filter(|p| !p.is_empty()) {
+                proxies.push(match proxy {
+                    "auto" => {
+                        return Err(CvmfsError::InvalidConfiguration(
+                            "automatic proxy discovery is not supported".into(),
+                        ))
+                    }
+                    _ if proxy.eq_ignore_ascii_case(DIRECT) => DIRECT.to_string(),
+                    _ if proxy.contains("://") => proxy.to_string(),
+                    _ => format!("http://{}", proxy),
+                });
+            }
+            if !proxies.is_empty() {
+                groups.push(proxies);
+            }
+        }
+        if groups.is_empty() {
+            return Err(CvmfsError::InvalidConfiguration(format!(
+                "no proxy in {:?}, use {} to download without one",
+                value, DIRECT
+            )));
+        }
+        Ok(Self { groups })
+    }
+}
+
+/// Proxies with their health, failing over within a group first and then to
+/// the next group. A failed proxy is tried again once its backoff expires, so
+/// that the downloads return to the first group.
+#[derive(Debug)]
+pub struct ProxyChain {
+    groups: Vec<(MirrorSet, Vec<Client>)>,
+}
+
+impl ProxyChain {
+    pub fn new(config: &ProxyConfig) -> CvmfsResult<Self> {
+        if config.groups.is_empty() {
+            return Err(CvmfsError::InvalidConfiguration(
+                "no proxy configured".into(),
+            ));
+        }
+        let mut groups = Vec::new();
+        for proxies in &config.groups {
+            let clients = proxies
+                .iter()
+                .map(|proxy| {
+                    let builder = Client::builder();
+                    let builder = if proxy == DIRECT {
+                        builder.no_proxy()
+                    } else {
+                        builder.proxy(reqwest::Proxy::all(proxy)?)
+                    };
+                    Ok(builder.build()?)
+                })
+                .collect::<CvmfsResult<_>>()?;
+            let health = MirrorSet::new(proxies.clone())?
+                .with_backoff(DEFAULT_PROXY_RESET_AFTER, DEFAULT_PROXY_RESET_AFTER);
+            groups.push((health, clients));
+        }
+        Ok(Self { groups })
+    }
+
+    /// Same proxies, skipping the failed ones for `backoff`
+    pub fn with_backoff(mut self, backoff: Duration) -> Self {
+        self.groups = self
+            .groups
+            .into_iter()
+            .map(|(health, clients)| (health.with_backoff(backoff, backoff), clients))
+            .collect();
+        self
+    }
+
+    /// Proxies in the order a download tries them, as group and proxy
+    /// indexes. The failed ones come last.
+    fn order(&self) -> Vec<(usize, usize)> {
+        let (healthy, failed): (Vec<_>, Vec<_>) = self
+            .groups
+            .iter()
+            .enumerate()
+            .flat_map(|(group, (health, _))| {
+                health.order().into_iter().map(move |index| (group, index))
+            })
+            .partition(|(group, index)| self.groups[*group].0.is_healthy(*index));
+        healthy.into_iter().chain(failed).collect()
+    }
+
+    /// Proxy the downloads go through first: the first healthy one, or the
+    /// first one of all when every proxy is failing
+    pub fn current(&self) -> &str {
+        self.groups
+            .iter()
+            .flat_map(|(health, _)| {
+                (0..health.urls().len())
+                    .filter(|index| health.is_healthy(*index))
+                    .map(|index| health.urls()[index].as_str())
+            })
+            .next()
+            .unwrap_or(&self.groups[0].0.urls()[0])
+    }
+
+    /// Gets a url through the first proxy able to connect. Errors of the
+    /// server are returned right away, since other proxies would get them too.
+    pub fn get(&self, url: &str) -> reqwest::Result<Vec<u8>> {
+        let mut last_error = None;
+        for (group, index) in self.order() {
+            let (health, clients) = &self.groups[group];
+            let result = clients[index]
+                .get(url)
+                .send()
+                .and_then(|response| response.error_for_status())
+                .and_then(|response| response.bytes());
+            match result {
+                Ok(bytes) => {
+                    health.record_success(index);
+                    return Ok(bytes.to_vec());
+                }
+                Err(e) if (e.is_connect() || e.is_timeout()) && health.urls()[index] != DIRECT => {
+                    log::debug!("Proxy {} failed for {}: {:?}", health.urls()[index], url, e);
+                    health.record_failure(index);
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(last_error.expect("a proxy chain has at least one proxy"))
+    }
+
+    /// Health of every proxy, group after group
+    pub fn status(&self) -> Vec<MirrorStatus> {
+        self.groups
+            .iter()
+            .flat_map(|(health, _)| health.status())
+            .collect()
+    }
+}
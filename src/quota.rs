@@ -0,0 +1,205 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::cache::is_partial_file;
+use crate::common::{CvmfsError, CvmfsResult};
+
+/// Index of the cached objects, in the cache directory
+pub const QUOTA_DATABASE: &str = "quota.db";
+/// Fraction of the limit a full cache is cleaned up to, as the reference
+/// client does, so that eviction does not run on every download
+pub const CLEANUP_RATIO: f64 = 0.5;
+/// Only the objects are accounted, the metadata of the cache is tiny
+const DATA_PREFIX: &str = "data/";
+
+#[derive(Debug)]
+struct QuotaState {
+    connection: Connection,
+    /// Bytes of the objects in the index
+    size: u64,
+    /// Access sequence number of the last used object
+    sequence: i64,
+}
+
+/// Keeps the objects of a cache directory under a size limit, evicting the
+/// least recently used ones. Sizes and access order are kept in a SQLite
+/// index, so that they survive remounts.
+#[derive(Debug)]
+pub struct QuotaManager {
+    directory: PathBuf,
+    limit: u64,
+    state: Mutex<QuotaState>,
+}
+
+impl QuotaManager {
+    /// Opens the index of a cache directory, building it from the objects
+    /// already cached when it does not exist yet
+    pub fn open(directory: &Path, limit: u64) -> CvmfsResult<Self> {
+        fs::create_dir_all(directory)?;
+        let connection = Connection::open(directory.join(QUOTA_DATABASE))?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.pragma_update(None, "synchronous", "OFF")?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS objects (path TEXT PRIMARY KEY, size INTEGER, \
+             sequence INTEGER);
+             CREATE INDEX IF NOT EXISTS objects_sequence ON objects (sequence);",
+        )?;
+        let (count, size, sequence): (i64, i64, i64) = connection.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(size), 0), COALESCE(MAX(sequence), 0) FROM objects",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        let quota = Self {
+            directory: directory.into(),
+            limit,
+            state: Mutex::new(QuotaState {
+                connection,
+                size: size as u64,
+                sequence,
+            }),
+        };
+        if count == 0 {
+            quota.rebuild()?;
+        }
+        Ok(quota)
+    }
+
+    /// Indexes the objects found in the cache directory, the least recently
+    /// modified first
+    fn rebuild(&self) -> CvmfsResult<()> {
+        let mut objects = Vec::new();
+        let Ok(prefixes) = fs::read_dir(self.directory.join(DATA_PREFIX)) else {
+            return Ok(());
+        };
+        for prefix in prefixes.flatten() {
+            for entry in fs::read_dir(prefix.path())?.flatten() {
+                if is_partial_file(&entry.path()) {
+                    continue;
+                }
+                let name = entry.file_name();
+                let Some(name) = name.to_str() else {
+                    continue;
+                };
+                let metadata = entry.metadata()?;
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                let prefix = prefix.file_name();
+                let path = format!("{}{}/{}", DATA_PREFIX, prefix.to_string_lossy(), name);
+                objects.push((modified, path, metadata.len()));
+            }
+        }
+        objects.sort();
+        for (_, path, size) in objects {
+            self.insert(&path, size)?;
+        }
+        Ok(())
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Bytes of the cached objects
+    pub fn size(&self) -> u64 {
+        self.state.lock().map_or(0, |state| state.size)
+    }
+
+    /// Whether a cache file is accounted by the quota
+    pub fn is_tracked(file_name: &str) -> bool {
+        file_name.starts_with(DATA_PREFIX)
+    }
+
+    /// Accounts a newly cached object, cleaning up the cache if it grew over
+    /// the limit
+    pub fn insert(&self, file_name: &str, size: u64) -> CvmfsResult<()> {
+        let over_limit = {
+            let mut state = self.state.lock().map_err(|_| CvmfsError::Sync)?;
+            state.sequence += 1;
+            let previous: Option<i64> = state
+                .connection
+                .prepare_cached("SELECT size FROM objects WHERE path = ?")?
+                .query_row([file_name], |row| row.get(0))
+                .optional()?;
+            state
+                .connection
+                .prepare_cached("INSERT OR REPLACE INTO objects VALUES (?, ?, ?)")?
+                .execute(params![file_name, size as i64, state.sequence])?;
+            state.size = state.size - previous.unwrap_or(0) as u64 + size;
+            state.size > self.limit
+        };
+        if over_limit {
+            self.cleanup((self.limit as f64 * CLEANUP_RATIO) as u64)?;
+        }
+        Ok(())
+    }
+
+    /// Marks an object as the most recently used one
+    pub fn touch(&self, file_name: &str) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.sequence += 1;
+        let result = state
+            .connection
+            .prepare_cached("UPDATE objects SET sequence = ? WHERE path = ?")
+            .and_then(|mut statement| statement.execute(params![state.sequence, file_name]));
+        if let Err(e) = result {
+            log::debug!("Could not record the use of {}: {:?}", file_name, e);
+        }
+    }
+
+    /// Evicts the least recently used objects until the cache holds at most
+    /// `target` bytes, returning the bytes freed. Files still open keep being
+    /// readable, since they are only unlinked.
+    pub fn cleanup(&self, target: u64) -> CvmfsResult<u64> {
+        let mut state = self.state.lock().map_err(|_| CvmfsError::Sync)?;
+        let initial_size = state.size;
+        while state.size > target {
+            let oldest: Vec<(String, i64)> = state
+                .connection
+                .prepare_cached("SELECT path, size FROM objects ORDER BY sequence LIMIT 64")?
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<_, _>>()?;
+            if oldest.is_empty() {
+                break;
+            }
+            for (path, size) in oldest {
+                if state.size <= target {
+                    break;
+                }
+                match fs::remove_file(self.directory.join(&path)) {
+                    Ok(()) => {}
+                    // evicted by other means, e.g. quarantined by the scrubber
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e.into()),
+                }
+                state
+                    .connection
+                    .prepare_cached("DELETE FROM objects WHERE path = ?")?
+                    .execute([&path])?;
+                state.size = state.size.saturating_sub(size as u64);
+            }
+        }
+        let freed = initial_size - state.size;
+        if freed > 0 {
+            log::info!(
+                "Evicted {} bytes from the cache {:?}, {} bytes left",
+                freed,
+                self.directory,
+                state.size
+            );
+        }
+        Ok(freed)
+    }
+
+    /// Forgets every object, once the cache was emptied
+    pub fn clear(&self) -> CvmfsResult<()> {
+        let mut state = self.state.lock().map_err(|_| CvmfsError::Sync)?;
+        state.connection.execute("DELETE FROM objects", [])?;
+        state.size = 0;
+        Ok(())
+    }
+}
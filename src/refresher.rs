@@ -0,0 +1,75 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+
+use crate::repository::Repository;
+use crate::scrubber::sleep_unless_stopped;
+
+/// Picks up the new revisions of a repository in the background, checking
+/// the server each time the TTL of the current revision expires. Only the
+/// switch to a new revision takes the exclusive lock of the repository.
+pub fn spawn(repository: Arc<RwLock<Repository>>) -> RefresherHandle {
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = stop.clone();
+    let thread = thread::spawn(move || {
+        while !stopped.load(Ordering::Relaxed) {
+            let Ok(wait) = repository.read().map(|repo| repo.time_to_refresh()) else {
+                return;
+            };
+            sleep_unless_stopped(wait, &stopped);
+            if stopped.load(Ordering::Relaxed) {
+                return;
+            }
+            // the mirrors are ordered and the new revision downloaded under
+            // the shared lock, so that the current one keeps being served
+            let prepared = {
+                let Ok(repo) = repository.read() else {
+                    return;
+                };
+                if !repo.time_to_refresh().is_zero() {
+                    continue;
+                }
+                repo.sort_mirrors_if_due();
+                repo.prepare_refresh()
+            };
+            let Ok(mut repo) = repository.write() else {
+                return;
+            };
+            match repo.apply_refresh(prepared) {
+                Ok(true) => log::info!(
+                    "{} switched to revision {}",
+                    repo.fqrn,
+                    repo.manifest.revision
+                ),
+                Ok(false) => {}
+                Err(e) => log::error!("Could not refresh {}: {:?}", repo.fqrn, e),
+            }
+        }
+    });
+    RefresherHandle {
+        stop,
+        thread: Some(thread),
+    }
+}
+
+/// Control over a refresher running in the background
+#[derive(Debug)]
+pub struct RefresherHandle {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RefresherHandle {
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for RefresherHandle {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::common::CvmfsResult;
+use crate::fetcher::Fetcher;
+use crate::repository::Repository;
+use crate::revision_tag::RevisionTag;
+
+/// State published by one of the servers of a repository
+#[derive(Debug, Clone)]
+pub struct ReplicaStatus {
+    pub url: String,
+    pub fqrn: String,
+    pub revision: u32,
+    pub root_catalog: String,
+    pub last_modified: DateTime<Utc>,
+    pub last_replication: Option<DateTime<Utc>>,
+    pub replicating: bool,
+    pub tags: Vec<RevisionTag>,
+}
+
+impl ReplicaStatus {
+    /// Reads the manifest and the tags of a repository
+    pub fn from_repository(url: &str, repository: &Repository) -> CvmfsResult<Self> {
+        let tags = repository.list_tags()?;
+        Ok(Self {
+            url: url.into(),
+            fqrn: repository.fqrn.clone(),
+            revision: repository.manifest.revision,
+            root_catalog: repository.manifest.root_catalog.clone(),
+            last_modified: repository.manifest.last_modified,
+            last_replication: repository.last_replication,
+            replicating: repository.replicating,
+            tags,
+        })
+    }
+}
+
+/// Tag published by both servers pointing to different root catalogs
+#[derive(Debug, Clone, PartialEq)]
+pub struct DivergentTag {
+    pub name: String,
+    pub first_hash: String,
+    pub second_hash: String,
+}
+
+/// Differences between two servers of the same repository, typically a
+/// stratum-0 and one of its stratum-1 replicas
+#[derive(Debug, Clone)]
+pub struct ReplicaComparison {
+    pub first: ReplicaStatus,
+    pub second: ReplicaStatus,
+    /// Tags only published by the first server
+    pub missing_in_second: Vec<String>,
+    /// Tags only published by the second server
+    pub missing_in_first: Vec<String>,
+    pub divergent_tags: Vec<DivergentTag>,
+}
+
+impl ReplicaComparison {
+    pub fn new(first: ReplicaStatus, second: ReplicaStatus) -> Self {
+        let first_tags: HashMap<&str, &str> = first
+            .tags
+            .iter()
+            .map(|tag| (tag.name.as_str(), tag.hash.as_str()))
+            .collect();
+        let second_tags: HashMap<&str, &str> = second
+            .tags
+            .iter()
+            .map(|tag| (tag.name.as_str(), tag.hash.as_str()))
+            .collect();
+        let mut missing_in_second = Vec::new();
+        let mut divergent_tags = Vec::new();
+        for tag in &first.tags {
+            match second_tags.get(tag.name.as_str()) {
+                None => missing_in_second.push(tag.name.clone()),
+                Some(hash) if *hash != tag.hash => divergent_tags.push(DivergentTag {
+                    name: tag.name.clone(),
+                    first_hash: tag.hash.clone(),
+                    second_hash: hash.to_string(),
+                }),
+                _ => {}
+            }
+        }
+        let missing_in_first = second
+            .tags
+            .iter()
+            .filter(|tag| !first_tags.contains_key(tag.name.as_str()))
+            .map(|tag| tag.name.clone())
+            .collect();
+        Self {
+            missing_in_second,
+            missing_in_first,
+            divergent_tags,
+            first,
+            second,
+        }
+    }
+
+    /// Revisions the second server is behind the first one, negative if ahead
+    pub fn revision_lag(&self) -> i64 {
+        self.first.revision as i64 - self.second.revision as i64
+    }
+
+    /// Publication delay of the second server with respect to the first one
+    pub fn time_lag(&self) -> TimeDelta {
+        self.first.last_modified - self.second.last_modified
+    }
+
+    /// Both servers publish a different root catalog for the same revision
+    pub fn root_catalog_diverged(&self) -> bool {
+        self.first.revision == self.second.revision
+            && self.first.root_catalog != self.second.root_catalog
+    }
+
+    pub fn is_in_sync(&self) -> bool {
+        self.revision_lag() == 0
+            && !self.root_catalog_diverged()
+            && self.missing_in_first.is_empty()
+            && self.missing_in_second.is_empty()
+            && self.divergent_tags.is_empty()
+    }
+}
+
+impl Display for ReplicaComparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for status in [&self.first, &self.second] {
+            writeln!(
+                f,
+                "{}: {} revision {} ({}) published {}{}",
+                status.url,
+                status.fqrn,
+                status.revision,
+                status.root_catalog,
+                status.last_modified,
+                if status.replicating {
+                    ", replicating"
+                } else {
+                    ""
+                }
+            )?;
+        }
+        if self.is_in_sync() {
+            return writeln!(f, "In sync");
+        }
+        writeln!(
+            f,
+            "Lag: {} revisions, {} seconds",
+            self.revision_lag(),
+            self.time_lag().num_seconds()
+        )?;
+        if self.root_catalog_diverged() {
+            writeln!(f, "Root catalogs diverged for the same revision")?;
+        }
+        for name in &self.missing_in_second {
+            writeln!(f, "Tag {} missing in {}", name, self.second.url)?;
+        }
+        for name in &self.missing_in_first {
+            writeln!(f, "Tag {} missing in {}", name, self.first.url)?;
+        }
+        for tag in &self.divergent_tags {
+            writeln!(
+                f,
+                "Tag {} diverged: {} vs {}",
+                tag.name, tag.first_hash, tag.second_hash
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares two servers of a repository. Each server gets its own directory
+/// inside the cache, so that the rollback protection of one does not reject
+/// the manifest of the other.
+pub fn compare_replicas(
+    first_url: &str,
+    second_url: &str,
+    cache_directory: &str,
+) -> CvmfsResult<ReplicaComparison> {
+    let mut statuses = Vec::with_capacity(2);
+    for (index, url) in [first_url, second_url].into_iter().enumerate() {
+        let cache = Path::new(cache_directory).join(format!("replica{}", index + 1));
+        let fetcher = Fetcher::new(url, &cache.to_string_lossy(), true)?;
+        let repository = Repository::new(fetcher)?;
+        statuses.push(ReplicaStatus::from_repository(url, &repository)?);
+    }
+    let second = statuses.pop().expect("Two replicas were read");
+    let first = statuses.pop().expect("Two replicas were read");
+    Ok(ReplicaComparison::new(first, second))
+}
@@ -1,89 +1,354 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Debug, Formatter};
 use std::fs;
 use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeDelta, Utc};
 
-use crate::catalog::{Catalog, Statistics, CATALOG_ROOT_PREFIX};
+use crate::analytics::Analytics;
+use crate::audit_log::AuditKind;
+use crate::breadcrumb::Breadcrumb;
+use crate::cache::{Cache, FailoverStatus};
+use crate::catalog::{
+    Catalog, CatalogReference, PerformanceCounters, Statistics, CATALOG_ROOT_PREFIX,
+    EXTERNAL_SUFFIX, MICRO_CATALOG_SUFFIX,
+};
+use crate::catalog_set::CatalogSet;
+use crate::certificate::{Certificate, CERTIFICATE_ROOT_PREFIX};
 use crate::common::{
-    compose_object_path, ChunkedFile, CvmfsError, CvmfsResult, FileLike, LAST_REPLICATION_NAME,
-    MANIFEST_NAME, REPLICATING_NAME,
+    compose_object_path, normalize_path, ChunkedFile, CvmfsError, CvmfsResult, FileLike,
+    DEFAULT_CHUNK_READ_AHEAD, DEFAULT_CLOCK_SKEW_TOLERANCE, LAST_REPLICATION_NAME, MANIFEST_NAME,
+    REPLICATING_NAME, WHITELIST_NAME,
 };
-use crate::directory_entry::{Chunk, DirectoryEntry};
+use crate::database_object::SqliteTuning;
+use crate::diff::{diff_revisions, Change};
+use crate::directory_entry::{Chunk, DirectoryEntry, DirectoryEntryWrapper};
+use crate::export::{self, ExportSummary, ExportTarget};
 use crate::fetcher::Fetcher;
 use crate::history::History;
+use crate::lru::LruCache;
 use crate::manifest::Manifest;
+use crate::master_key::{MasterKey, KEYS_DIRECTORY};
+use crate::metrics::metrics;
+use crate::mount_config::DEFAULT_REPOSITORY_TYPE;
+use crate::preload::{self, PreloadSpec, PreloadSummary, DEFAULT_PRELOAD_CONCURRENCY};
 use crate::revision_tag::RevisionTag;
 use crate::rootfile::RootFile;
+use crate::validation::{ValidationMode, ValidationPolicy};
+use crate::whitelist::{ExpiryPolicy, Whitelist};
+
+pub const DEFAULT_LOOKUP_CACHE_SIZE: usize = 16384;
+pub const DEFAULT_CATALOG_CACHE_SIZE: usize = 16384;
+/// Directory listings kept in memory, see `Repository::map_directory_at`
+pub const DEFAULT_LISTING_CACHE_SIZE: usize = 1024;
+/// Nested catalogs downloaded at once while resolving a path
+pub const MAX_PARALLEL_CATALOG_FETCHES: usize = 8;
+/// Directories remembered as already prefetched, see `SiblingPrefetch`
+pub const PREFETCHED_DIRECTORIES_CACHE_SIZE: usize = 1024;
+/// Shortest time between two checks for a new revision, whatever the TTL
+pub const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+/// Time between two orderings of the mirrors by proximity, see
+/// `Repository::geo_sort_interval`
+pub const DEFAULT_GEO_SORT_INTERVAL: Duration = Duration::from_secs(4 * 3600);
+/// Catalogs kept open at once, see `Repository::max_opened_catalogs`
+pub const DEFAULT_MAX_OPENED_CATALOGS: usize = 1024;
+
+/// Memory consumed by the in-memory state of a mount, in bytes
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemoryUsage {
+    /// Page caches of the opened catalog databases
+    pub catalogs: usize,
+    pub lookup_cache: usize,
+    pub catalog_cache: usize,
+    pub listing_cache: usize,
+    /// Number of files currently opened through the file system
+    pub open_files: usize,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.catalogs + self.lookup_cache + self.catalog_cache + self.listing_cache
+    }
+}
+
+/// Ceilings on the memory usage, in bytes. Going over the catalog limit
+/// closes the least recently used catalogs until the usage is below it, while
+/// going over the cache limit empties the lookup caches.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemoryLimits {
+    pub catalogs: Option<usize>,
+    pub caches: Option<usize>,
+}
+
+/// Trust chain of a repository, verified when it is opened: the whitelist
+/// signed by a master key lists the certificate whose key signed the manifest
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrustSettings {
+    /// Handling of the failed checks, an ignored signature skipping the chain
+    pub validation: ValidationPolicy,
+    /// Directory holding the public master keys of the repositories
+    pub keys_directory: PathBuf,
+    /// Name the whitelist and the manifest have to agree on, the name of the
+    /// first manifest when `None`
+    pub expected_fqrn: Option<String>,
+    pub whitelist_expiry_policy: ExpiryPolicy,
+}
+
+impl Default for TrustSettings {
+    fn default() -> Self {
+        Self {
+            validation: Default::default(),
+            keys_directory: PathBuf::from(KEYS_DIRECTORY),
+            expected_fqrn: None,
+            whitelist_expiry_policy: Default::default(),
+        }
+    }
+}
+
+/// Opening a file downloads in the background the small files next to it,
+/// which are likely to be opened soon (e.g. the libraries of a `lib` directory).
+/// Each directory is only prefetched once per revision.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SiblingPrefetch {
+    /// Files bigger than this, in bytes, are left to be fetched on demand
+    pub max_file_size: u64,
+    /// Downloads in flight at once
+    pub concurrency: usize,
+}
+
+impl Default for SiblingPrefetch {
+    fn default() -> Self {
+        Self {
+            max_file_size: 1 << 20,
+            concurrency: 4,
+        }
+    }
+}
+
+/// Catalog kept open along with its last use, see `close_oldest_catalog`
+#[derive(Debug)]
+struct OpenedCatalog {
+    catalog: Arc<Catalog>,
+    last_used: AtomicU64,
+}
+
+type RevisionCallback = Box<dyn Fn(&RevisionTag) + Send + Sync>;
+
+/// Subscribers notified when a new revision of the repository is detected
+#[derive(Default)]
+struct RevisionCallbacks(Vec<RevisionCallback>);
+
+impl Debug for RevisionCallbacks {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RevisionCallbacks({})", self.0.len())
+    }
+}
+
+/// State of the server checked by `Repository::prepare_refresh`, which
+/// `Repository::apply_refresh` switches to
+#[derive(Debug)]
+pub struct PreparedRefresh {
+    /// Whether the whitelist expired and the current revision is kept
+    degraded: bool,
+    new_revision: Option<NewRevision>,
+}
+
+/// Revision downloaded and validated, but not switched to yet
+#[derive(Debug)]
+struct NewRevision {
+    manifest: Manifest,
+    /// Content of the manifest, cached once it is switched to
+    content: Arc<[u8]>,
+    tag: RevisionTag,
+}
 
-/// Wrapper around a CVMFS repository representation
+/// Wrapper around a CVMFS repository representation. Lookups, listings and
+/// file retrievals take `&self`, so that they can be served concurrently.
 #[derive(Debug)]
 pub struct Repository {
-    pub opened_catalogs: HashMap<String, Catalog>,
+    /// Catalogs opened so far, shared by the concurrent lookups
+    opened_catalogs: RwLock<HashMap<String, OpenedCatalog>>,
+    /// Catalogs kept open at once. The least recently used ones are closed
+    /// beyond it, and opened again from the cache when needed.
+    pub max_opened_catalogs: usize,
     pub manifest: Manifest,
     pub fqrn: String,
     pub repo_type: String,
     pub replicating_since: Option<DateTime<Utc>>,
     pub last_replication: Option<DateTime<Utc>>,
     pub replicating: bool,
+    pub whitelist_expiry_policy: ExpiryPolicy,
+    pub keys_directory: PathBuf,
+    /// Name the repository was mounted as, which the whitelist and the
+    /// manifest have to agree on; defaults to the name of the first manifest
+    pub expected_fqrn: Option<String>,
+    pub clock_skew_tolerance: TimeDelta,
+    /// Settings applied to the catalog databases when they are opened
+    pub sqlite_tuning: SqliteTuning,
+    pub memory_limits: MemoryLimits,
+    /// Prefetching of the siblings of opened files, disabled when `None`
+    pub sibling_prefetch: Option<SiblingPrefetch>,
+    /// Chunks prefetched ahead of sequential reads of chunked files
+    pub chunk_read_ahead: usize,
+    /// Set while the repository is frozen on a cached revision
+    pub degraded: bool,
+    /// Time the last known-good revision may be served while the server is
+    /// unreachable, without limit when `None`
+    pub max_staleness: Option<TimeDelta>,
+    /// Since when the server could not be reached, see `refresh`
+    offline_since: Option<DateTime<Utc>>,
+    /// When the server was last checked for a new revision, see `refresh_if_due`
+    last_refresh: Instant,
+    /// Time between two orderings of the mirrors by the GeoAPI, on refresh.
+    /// Disabled when `None`.
+    pub geo_sort_interval: Option<Duration>,
+    /// When the mirrors were last ordered, see `sort_mirrors_by_geo`
+    last_geo_sort: Mutex<Instant>,
+    /// Certificates replaced by a new one published in the manifest
+    pub certificate_rotations: u64,
+    /// Usage of the mount, reported by the control socket when collected
+    pub analytics: Option<Arc<Analytics>>,
+    /// Hash of the certificate last checked against the whitelist
+    certificate_hash: Option<String>,
+    /// Clock ticking on every use of a catalog, see `close_oldest_catalog`
+    catalog_clock: AtomicU64,
     fetcher: Fetcher,
+    /// Downloads of the files with external data, see `set_external_urls`
+    external_fetcher: Option<Fetcher>,
+    /// Handling of failed integrity checks, see `set_validation_policy`
+    validation: ValidationPolicy,
     tag: Option<RevisionTag>,
+    pinned_tag: Option<String>,
+    /// Directory entries already looked up, see `revision_path_key`
+    lookup_cache: Mutex<LruCache<[u8; 16], DirectoryEntry>>,
+    /// Hash of the catalog serving each resolved path, see `revision_path_key`
+    catalog_cache: Mutex<LruCache<[u8; 16], Arc<str>>>,
+    /// Entries of the directories listed recently, see `revision_path_key`
+    listing_cache: Mutex<LruCache<[u8; 16], Arc<[DirectoryEntry]>>>,
+    /// Directories whose siblings were already prefetched, see `revision_path_key`
+    prefetched_directories: Mutex<LruCache<[u8; 16], ()>>,
+    revision_callbacks: RevisionCallbacks,
 }
 
 impl Repository {
+    /// Opens the repository with the default trust settings, which refuse
+    /// the repositories not signed by the master keys of `/etc/cvmfs/keys`
     pub fn new(fetcher: Fetcher) -> CvmfsResult<Self> {
-        let manifest = Self::read_manifest(&fetcher)?;
+        Self::with_trust(fetcher, TrustSettings::default())
+    }
+
+    /// Opens the repository, verifying its trust chain and the expiry of its
+    /// whitelist before anything else is read from it
+    pub fn with_trust(fetcher: Fetcher, trust: TrustSettings) -> CvmfsResult<Self> {
+        let (manifest, offline) = Self::read_manifest_or_cached(&fetcher)?;
+        manifest
+            .validate_timestamp(Utc::now(), TimeDelta::seconds(DEFAULT_CLOCK_SKEW_TOLERANCE))?;
         let last_replication =
             Self::try_to_get_last_replication_timestamp(&fetcher).unwrap_or(None);
         let replicating_since = Self::try_to_get_replication_state(&fetcher).unwrap_or(None);
         let mut obj = Self {
-            opened_catalogs: HashMap::new(),
+            opened_catalogs: Default::default(),
+            max_opened_catalogs: DEFAULT_MAX_OPENED_CATALOGS,
             fqrn: manifest.repository_name.clone(),
             manifest,
-            repo_type: "stratum1".to_string(),
+            repo_type: DEFAULT_REPOSITORY_TYPE.into(),
             replicating_since,
             last_replication,
             replicating: replicating_since.is_some(),
+            whitelist_expiry_policy: trust.whitelist_expiry_policy,
+            keys_directory: trust.keys_directory,
+            expected_fqrn: trust.expected_fqrn,
+            clock_skew_tolerance: TimeDelta::seconds(DEFAULT_CLOCK_SKEW_TOLERANCE),
+            sqlite_tuning: Default::default(),
+            memory_limits: Default::default(),
+            sibling_prefetch: None,
+            chunk_read_ahead: DEFAULT_CHUNK_READ_AHEAD,
+            degraded: false,
+            max_staleness: None,
+            offline_since: offline.then(Utc::now),
+            last_refresh: Instant::now(),
+            geo_sort_interval: None,
+            last_geo_sort: Mutex::new(Instant::now()),
+            certificate_rotations: 0,
+            analytics: None,
+            certificate_hash: None,
+            catalog_clock: AtomicU64::new(0),
             fetcher,
+            external_fetcher: None,
+            validation: Default::default(),
             tag: None,
+            pinned_tag: None,
+            lookup_cache: Mutex::new(LruCache::new(DEFAULT_LOOKUP_CACHE_SIZE)),
+            catalog_cache: Mutex::new(LruCache::new(DEFAULT_CATALOG_CACHE_SIZE)),
+            listing_cache: Mutex::new(LruCache::new(DEFAULT_LISTING_CACHE_SIZE)),
+            prefetched_directories: Mutex::new(LruCache::new(PREFETCHED_DIRECTORIES_CACHE_SIZE)),
+            revision_callbacks: Default::default(),
         };
-        obj.tag = Some(obj.get_last_tag()?.clone());
+        if !trust.validation.signature.is_enabled() {
+            log::warn!("The signatures of {} are not verified", obj.fqrn);
+        }
+        obj.set_validation_policy(trust.validation);
+        obj.check_whitelist_expiry()?;
+        obj.check_certificate()?;
+        obj.tag = Some(obj.get_last_tag()?);
+        obj.store_breadcrumb();
+        if let Some(pinned_tag) = obj.fetcher.cache.load_pinned_tag(&obj.fqrn) {
+            log::info!("{} is pinned to tag {}", obj.fqrn, pinned_tag);
+            obj.tag = Some(obj.get_tag_by_name(&pinned_tag)?);
+            obj.pinned_tag = Some(pinned_tag);
+        }
         Ok(obj)
     }
 
-    /// Retrieves an object from the content addressable storage
-    pub fn retrieve_object(&self, dirent: &DirectoryEntry) -> CvmfsResult<Box<dyn FileLike>> {
+    /// Retrieves an object from the content addressable storage.
+    /// The entry is consumed so that its chunk list can be moved into the file.
+    /// Files with external data are downloaded by their path instead.
+    pub fn retrieve_object(
+        &self,
+        path: &str,
+        dirent: DirectoryEntry,
+    ) -> CvmfsResult<Box<dyn FileLike>> {
+        if dirent.is_external() {
+            let fetcher = self
+                .external_fetcher
+                .as_ref()
+                .ok_or_else(|| CvmfsError::MissingExternalUrl(path.into()))?;
+            let hash = dirent
+                .content_hash_string()
+                .ok_or_else(|| CvmfsError::MissingContentHash(dirent.name.clone()))?;
+            let object_name = compose_object_path(&hash, EXTERNAL_SUFFIX)?;
+            return fetcher
+                .retrieve_external(path, object_name.to_str().ok_or(CvmfsError::FileNotFound)?);
+        }
         if dirent.has_chunks() {
-            let chunks: CvmfsResult<Vec<(String, Chunk)>> = dirent
-                .clone()
+            dirent.check_chunks()?;
+            let chunks = dirent
                 .chunks
                 .into_iter()
                 .map(|chunk| -> CvmfsResult<(String, Chunk)> {
-                    let path = compose_object_path(chunk.content_hash_string().as_str(), "")
+                    let path = compose_object_path(chunk.content_hash_string().as_str(), "")?
                         .to_str()
                         .ok_or(CvmfsError::FileNotFound)?
                         .to_string();
                     Ok((path, chunk))
                 })
-                .collect();
-            Ok(Box::new(ChunkedFile::new(
-                chunks?,
-                dirent.size,
-                Fetcher::new(
-                    self.fetcher.source.as_str(),
-                    self.fetcher.cache.cache_directory.as_str(),
-                    false,
-                )?,
-            )))
+                .collect::<CvmfsResult<Vec<_>>>()?;
+            Ok(Box::new(
+                ChunkedFile::new(chunks, dirent.size, self.fetcher.clone())
+                    .with_read_ahead(self.chunk_read_ahead),
+            ))
         } else {
-            let path = self.retrieve_object_with_suffix(
-                dirent
-                    .content_hash_string()
-                    .expect("Content hash must be present if no chunks")
-                    .as_str(),
-                "",
-            )?;
-            Ok(Box::new(File::open(path)?))
+            let hash = dirent
+                .content_hash_string()
+                .ok_or_else(|| CvmfsError::MissingContentHash(dirent.name.clone()))?;
+            let path = compose_object_path(&hash, "")?;
+            self.fetcher
+                .retrieve_object(path.to_str().ok_or(CvmfsError::FileNotFound)?)
         }
     }
 
@@ -92,26 +357,207 @@ impl Repository {
         object_hash: &str,
         hash_suffix: &str,
     ) -> CvmfsResult<String> {
-        let path = compose_object_path(object_hash, hash_suffix);
+        let path = compose_object_path(object_hash, hash_suffix)?;
         self.fetcher
             .retrieve_file(path.to_str().ok_or(CvmfsError::FileNotFound)?)
     }
 
+    /// Whether new objects go to the fallback cache directory, and how often
+    /// the primary one failed
+    pub fn cache(&self) -> &Cache {
+        &self.fetcher.cache
+    }
+
+    /// Sets the servers of the files with external data, as in
+    /// `CVMFS_EXTERNAL_URL`, separated by semicolons
+    pub fn set_external_urls(&mut self, urls: &str) -> CvmfsResult<()> {
+        let urls: Vec<&str> = urls.split(';').filter(|url| !url.is_empty()).collect();
+        self.external_fetcher = Some(self.fetcher.for_external_data(&urls)?);
+        Ok(())
+    }
+
+    /// Server tried first by the next download, the first one not backing off
+    pub fn host(&self) -> &str {
+        let mirrors = &self.fetcher.mirrors;
+        (0..mirrors.urls().len())
+            .find(|&index| mirrors.is_healthy(index))
+            .map_or(&self.fetcher.source, |index| &mirrors.urls()[index])
+    }
+
+    pub fn cache_failover_status(&self) -> FailoverStatus {
+        self.fetcher.cache.failover_status()
+    }
+
+    /// Opens a catalog only if it is already in the cache, returning whether it
+    /// is opened
+    pub fn open_cached_catalog(&self, catalog_hash: &str) -> CvmfsResult<bool> {
+        if self.is_catalog_opened(catalog_hash) {
+            return Ok(true);
+        }
+        let path = compose_object_path(catalog_hash, CATALOG_ROOT_PREFIX)?;
+        if self
+            .fetcher
+            .cache
+            .get(path.to_str().ok_or(CvmfsError::FileNotFound)?)
+            .is_none()
+        {
+            return Ok(false);
+        }
+        self.retrieve_and_open_catalog(catalog_hash)?;
+        Ok(true)
+    }
+
+    /// Persists the opened catalogs, to be pre-opened by the next mount
+    pub fn store_catalog_set(&self) -> CvmfsResult<()> {
+        let catalog_set = CatalogSet {
+            root_hash: self.get_root_hash()?.into(),
+            catalog_hashes: self.opened_catalog_hashes(),
+        };
+        self.fetcher
+            .cache
+            .store_catalog_set(&self.fqrn, &catalog_set)
+    }
+
+    /// Catalogs persisted by the last mount, if it served the current revision
+    pub fn load_catalog_set(&self) -> Option<CatalogSet> {
+        let catalog_set = self.fetcher.cache.load_catalog_set(&self.fqrn)?;
+        if catalog_set.root_hash != self.get_root_hash().ok()? {
+            log::info!(
+                "Persisted catalogs of {} belong to another revision",
+                self.fqrn
+            );
+            return None;
+        }
+        Some(catalog_set)
+    }
+
+    /// Hashes of the catalogs currently opened
+    pub fn opened_catalog_hashes(&self) -> Vec<String> {
+        self.opened_catalogs
+            .read()
+            .map(|opened_catalogs| opened_catalogs.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Whether a catalog is opened, without touching it
+    pub fn is_catalog_opened(&self, catalog_hash: &str) -> bool {
+        self.opened_catalogs
+            .read()
+            .is_ok_and(|opened_catalogs| opened_catalogs.contains_key(catalog_hash))
+    }
+
+    /// Closes all the opened catalogs, to be opened again from the cache
+    pub fn close_catalogs(&self) {
+        if let Ok(mut opened_catalogs) = self.opened_catalogs.write() {
+            opened_catalogs.clear();
+        }
+    }
+
     /// Download and open a catalog from the repository
-    pub fn retrieve_catalog(&mut self, catalog_hash: &str) -> CvmfsResult<&Catalog> {
-        if self.opened_catalogs.contains_key(catalog_hash) {
-            return Ok(&self.opened_catalogs[catalog_hash]);
+    pub fn retrieve_catalog(&self, catalog_hash: &str) -> CvmfsResult<Arc<Catalog>> {
+        let opened_catalogs = self.opened_catalogs.read().map_err(|_| CvmfsError::Sync)?;
+        if let Some(opened) = opened_catalogs.get(catalog_hash) {
+            opened.last_used.store(self.tick(), Ordering::Relaxed);
+            return Ok(opened.catalog.clone());
         }
+        drop(opened_catalogs);
         self.retrieve_and_open_catalog(catalog_hash)
     }
 
-    pub fn retrieve_and_open_catalog(&mut self, catalog_hash: &str) -> CvmfsResult<&Catalog> {
-        let catalog_file = self.retrieve_object_with_suffix(catalog_hash, CATALOG_ROOT_PREFIX)?;
-        let catalog = Catalog::new(catalog_file, catalog_hash.into())?;
-        self.opened_catalogs.insert(catalog_hash.into(), catalog);
-        self.opened_catalogs
-            .get(catalog_hash)
-            .ok_or(CvmfsError::CatalogNotFound)
+    /// Opens a catalog, downloading it if needed. Concurrent openings of the
+    /// same catalog keep the first one to finish.
+    pub fn retrieve_and_open_catalog(&self, catalog_hash: &str) -> CvmfsResult<Arc<Catalog>> {
+        self.open_catalog(catalog_hash, CATALOG_ROOT_PREFIX)
+    }
+
+    fn open_catalog(&self, catalog_hash: &str, suffix: &str) -> CvmfsResult<Arc<Catalog>> {
+        self.enforce_memory_limits();
+        let catalog_file = self.retrieve_object_with_suffix(catalog_hash, suffix)?;
+        let catalog = Catalog::with_tuning(catalog_file, catalog_hash.into(), &self.sqlite_tuning)?;
+        let mut opened_catalogs = self.opened_catalogs.write().map_err(|_| CvmfsError::Sync)?;
+        if !opened_catalogs.contains_key(catalog_hash) {
+            while opened_catalogs.len() >= self.max_opened_catalogs
+                && self.close_oldest_catalog(&mut opened_catalogs).is_some()
+            {}
+        }
+        let opened = opened_catalogs
+            .entry(catalog_hash.into())
+            .or_insert_with(|| OpenedCatalog {
+                catalog: Arc::new(catalog),
+                last_used: AtomicU64::new(0),
+            });
+        opened.last_used.store(self.tick(), Ordering::Relaxed);
+        Ok(opened.catalog.clone())
+    }
+
+    /// Micro catalog of the latest revision, which can answer lookups of the
+    /// root directory and its children without downloading the root catalog.
+    /// Only used until the root catalog is opened, and skipped on failure.
+    fn micro_catalog(&self, root_hash: &str) -> Option<Arc<Catalog>> {
+        let hash = &self.manifest.micro_catalog;
+        if hash.is_empty() || root_hash != self.manifest.root_catalog {
+            return None;
+        }
+        let opened_catalogs = self.opened_catalogs.read().ok()?;
+        if opened_catalogs.contains_key(root_hash) {
+            return None;
+        }
+        if let Some(opened) = opened_catalogs.get(hash) {
+            opened.last_used.store(self.tick(), Ordering::Relaxed);
+            return Some(opened.catalog.clone());
+        }
+        drop(opened_catalogs);
+        match self.open_catalog(hash, MICRO_CATALOG_SUFFIX) {
+            Ok(catalog) => Some(catalog),
+            Err(error) => {
+                log::warn!("Cannot open the micro catalog {}: {}", hash, error);
+                None
+            }
+        }
+    }
+
+    /// Looks up the root directory or one of its children in the micro
+    /// catalog, `None` when the full catalogs are needed
+    fn find_top_level(&self, root_hash: &str, path: &str) -> CvmfsResult<Option<DirectoryEntry>> {
+        if path.rfind('/').unwrap_or(0) != 0 {
+            return Ok(None);
+        }
+        let Some(micro_catalog) = self.micro_catalog(root_hash) else {
+            return Ok(None);
+        };
+        // the micro catalog may lag behind, so the root catalog answers for
+        // the entries it lacks
+        let dirent = match micro_catalog.find_directory_entry(path) {
+            Ok(dirent) => dirent,
+            Err(CvmfsError::FileNotFound) => return Ok(None),
+            Err(e) => {
+                log::debug!("Could not look up {} in the micro catalog: {:?}", path, e);
+                return Ok(None);
+            }
+        };
+        // mountpoints are looked up as the root of their nested catalog
+        Ok((!dirent.is_nested_catalog_mountpoint()).then_some(dirent))
+    }
+
+    fn tick(&self) -> u64 {
+        self.catalog_clock.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Closes the least recently used catalog, sparing the root catalog of the
+    /// current revision. Returns the closed catalog, if any.
+    fn close_oldest_catalog(
+        &self,
+        opened_catalogs: &mut HashMap<String, OpenedCatalog>,
+    ) -> Option<Arc<Catalog>> {
+        let root_hash = self.tag.as_ref().map(|tag| tag.hash.as_str());
+        let oldest = opened_catalogs
+            .iter()
+            .filter(|(hash, _)| Some(hash.as_str()) != root_hash)
+            .min_by_key(|(_, opened)| opened.last_used.load(Ordering::Relaxed))?
+            .0
+            .clone();
+        log::debug!("Closing catalog {}", oldest);
+        opened_catalogs.remove(&oldest).map(|opened| opened.catalog)
     }
 
     pub fn has_history(&self) -> bool {
@@ -119,16 +565,16 @@ impl Repository {
     }
 
     pub fn retrieve_history(&self) -> CvmfsResult<History> {
-        if !self.has_history() {
-            return Err(CvmfsError::HistoryNotFound);
-        }
-        let history_db = self.retrieve_object_with_suffix(
-            self.manifest
-                .history_database
-                .as_ref()
-                .ok_or(CvmfsError::HistoryNotFound)?,
-            "H",
-        )?;
+        self.retrieve_history_of(&self.manifest)
+    }
+
+    /// History database of a manifest, which may not be the current one yet
+    fn retrieve_history_of(&self, manifest: &Manifest) -> CvmfsResult<History> {
+        let history_database = manifest
+            .history_database
+            .as_ref()
+            .ok_or(CvmfsError::HistoryNotFound)?;
+        let history_db = self.retrieve_object_with_suffix(history_database, "H")?;
         History::new(&history_db)
     }
 
@@ -141,6 +587,20 @@ impl Repository {
         }
     }
 
+    pub fn get_tag_by_name(&self, name: &str) -> CvmfsResult<RevisionTag> {
+        self.retrieve_history()?
+            .get_tag_by_name(name)?
+            .ok_or(CvmfsError::TagNotFound)
+    }
+
+    /// Every tag of the history, empty for the repositories without one
+    pub fn list_tags(&self) -> CvmfsResult<Vec<RevisionTag>> {
+        if !self.has_history() {
+            return Ok(vec![]);
+        }
+        self.retrieve_history()?.list_tags()
+    }
+
     pub fn current_tag(&self) -> CvmfsResult<&RevisionTag> {
         self.tag.as_ref().ok_or(CvmfsError::TagNotFound)
     }
@@ -154,11 +614,470 @@ impl Repository {
         self.get_tag(self.manifest.revision)
     }
 
-    fn read_manifest(fetcher: &Fetcher) -> CvmfsResult<Manifest> {
-        let manifest_file = fetcher.retrieve_raw_file(MANIFEST_NAME)?;
-        let file = File::open(&manifest_file)?;
-        let root_file = RootFile::new(&file)?;
-        Manifest::new(root_file)
+    /// Pins the repository to a tag. The pin is persisted in the cache, so it
+    /// survives remounts and refreshes until `unpin_tag` is called.
+    pub fn pin_tag(&mut self, name: &str) -> CvmfsResult<()> {
+        let tag = self.get_tag_by_name(name)?;
+        self.fetcher.cache.store_pinned_tag(&self.fqrn, name)?;
+        self.tag = Some(tag);
+        self.pinned_tag = Some(name.into());
+        Ok(())
+    }
+
+    /// Removes the pin and goes back to following the latest revision
+    pub fn unpin_tag(&mut self) -> CvmfsResult<()> {
+        self.fetcher.cache.remove_pinned_tag(&self.fqrn)?;
+        self.pinned_tag = None;
+        self.tag = Some(self.get_last_tag()?);
+        Ok(())
+    }
+
+    pub fn pinned_tag(&self) -> Option<&str> {
+        self.pinned_tag.as_deref()
+    }
+
+    /// Registers a callback fired every time a new revision is published
+    pub fn on_new_revision<F>(&mut self, callback: F)
+    where
+        F: Fn(&RevisionTag) + Send + Sync + 'static,
+    {
+        self.revision_callbacks.0.push(Box::new(callback));
+    }
+
+    /// Re-reads the manifest and notifies the subscribers if a new revision was
+    /// published. The current tag only moves forward if it was following the
+    /// latest revision and is not pinned. Returns whether a new revision was found.
+    /// While the server is unreachable the current revision keeps being served,
+    /// until it gets older than `max_staleness`.
+    pub fn refresh(&mut self) -> CvmfsResult<bool> {
+        self.sort_mirrors_if_due();
+        let prepared = self.prepare_refresh();
+        self.apply_refresh(prepared)
+    }
+
+    /// Orders the mirrors by distance once `geo_sort_interval` elapsed. The
+    /// ranking is shared by the fetcher, so this only needs a shared lock.
+    pub fn sort_mirrors_if_due(&self) {
+        let due = self.geo_sort_interval.is_some_and(|interval| {
+            self.last_geo_sort
+                .lock()
+                .is_ok_and(|last_geo_sort| last_geo_sort.elapsed() >= interval)
+        });
+        if due {
+            if let Err(e) = self.sort_mirrors_by_geo() {
+                log::warn!("Could not order the mirrors of {}: {:?}", self.fqrn, e);
+            }
+        }
+    }
+
+    /// Downloads and validates the latest revision published on the server,
+    /// without changing the state of the repository, so that it can run under
+    /// a shared lock while the current revision keeps being served
+    pub fn prepare_refresh(&self) -> CvmfsResult<PreparedRefresh> {
+        if !self.whitelist_allows_updates()? {
+            return Ok(PreparedRefresh {
+                degraded: true,
+                new_revision: None,
+            });
+        }
+        let up_to_date = PreparedRefresh {
+            degraded: false,
+            new_revision: None,
+        };
+        let (manifest, content) = Self::read_manifest(&self.fetcher)?;
+        manifest.validate_timestamp(Utc::now(), self.clock_skew_tolerance)?;
+        if manifest.revision <= self.manifest.revision {
+            return Ok(up_to_date);
+        }
+        if self.validation.signature.is_enabled() {
+            self.validation
+                .signature
+                .apply(self.verify_manifest(&manifest))?;
+        }
+        let root_catalog =
+            self.retrieve_object_with_suffix(&manifest.root_catalog, CATALOG_ROOT_PREFIX)?;
+        Catalog::with_tuning(
+            root_catalog,
+            manifest.root_catalog.clone(),
+            &self.sqlite_tuning,
+        )?;
+        let tag = self
+            .retrieve_history_of(&manifest)?
+            .get_tag_by_revision(manifest.revision)?
+            .ok_or(CvmfsError::RevisionNotFound)?;
+        Self::check_breadcrumb(&self.fetcher, &manifest)?;
+        Ok(PreparedRefresh {
+            new_revision: Some(NewRevision {
+                manifest,
+                content,
+                tag,
+            }),
+            ..up_to_date
+        })
+    }
+
+    /// Switches to the revision prepared by `prepare_refresh`, which only
+    /// touches the local state. Returns whether a new revision was found.
+    pub fn apply_refresh(&mut self, prepared: CvmfsResult<PreparedRefresh>) -> CvmfsResult<bool> {
+        self.last_refresh = Instant::now();
+        let prepared = match prepared {
+            Err(error) if error.is_unreachable() => return self.serve_stale(error),
+            result => result?,
+        };
+        if self.offline_since.take().is_some() {
+            log::info!("{} is reachable again", self.fqrn);
+        }
+        self.degraded = prepared.degraded;
+        let Some(NewRevision {
+            manifest,
+            content,
+            tag,
+        }) = prepared.new_revision
+        else {
+            return Ok(false);
+        };
+        // another refresh may have switched in the meantime
+        if manifest.revision <= self.manifest.revision {
+            return Ok(false);
+        }
+        let following_latest = self.pinned_tag.is_none()
+            && self.get_revision_number()? == self.manifest.revision as i32;
+        log::info!("New revision {} found for {}", manifest.revision, self.fqrn);
+        if self
+            .certificate_hash
+            .as_ref()
+            .is_some_and(|hash| *hash != manifest.certificate)
+        {
+            self.rotate_certificate(&manifest);
+        }
+        Self::store_manifest(&self.fetcher, &content);
+        self.manifest = manifest;
+        // the catalogs of the previous revision are only needed by a pinned tag
+        if following_latest {
+            self.close_catalogs();
+            self.tag = Some(tag.clone());
+        }
+        self.clear_caches();
+        self.enforce_memory_limits();
+        self.store_breadcrumb();
+        for callback in &self.revision_callbacks.0 {
+            callback(&tag);
+        }
+        Ok(true)
+    }
+
+    /// Orders the mirrors from the closest to the farthest, as told by the
+    /// GeoAPI of the stratum-1 servers
+    pub fn sort_mirrors_by_geo(&self) -> CvmfsResult<()> {
+        *self.last_geo_sort.lock().map_err(|_| CvmfsError::Sync)? = Instant::now();
+        self.fetcher.sort_mirrors_by_geo()
+    }
+
+    /// Time left until the TTL of the current revision expires and the server
+    /// is checked for a new one
+    pub fn time_to_refresh(&self) -> Duration {
+        let ttl = self.get_ttl().unwrap_or(self.manifest.ttl);
+        Duration::from_secs(ttl.into())
+            .max(MIN_REFRESH_INTERVAL)
+            .saturating_sub(self.last_refresh.elapsed())
+    }
+
+    /// Refreshes the repository once the TTL of the current revision expired.
+    /// Returns whether a new revision was found.
+    pub fn refresh_if_due(&mut self) -> CvmfsResult<bool> {
+        if !self.time_to_refresh().is_zero() {
+            return Ok(false);
+        }
+        self.refresh()
+    }
+
+    /// Since when the server could not be reached, `None` when it is online
+    pub fn offline_since(&self) -> Option<DateTime<Utc>> {
+        self.offline_since
+    }
+
+    /// Whether a cached revision is being served because the server is unreachable
+    pub fn is_stale(&self) -> bool {
+        self.offline_since.is_some()
+    }
+
+    fn serve_stale(&mut self, error: CvmfsError) -> CvmfsResult<bool> {
+        let offline_since = *self.offline_since.get_or_insert_with(Utc::now);
+        if self
+            .max_staleness
+            .is_some_and(|max_staleness| Utc::now() - offline_since > max_staleness)
+        {
+            log::error!(
+                "{} unreachable since {}, giving up on revision {}",
+                self.fqrn,
+                offline_since,
+                self.manifest.revision
+            );
+            return Err(error);
+        }
+        log::warn!(
+            "{} unreachable since {} ({:?}), serving cached revision {}",
+            self.fqrn,
+            offline_since,
+            error,
+            self.manifest.revision
+        );
+        Ok(false)
+    }
+
+    /// Retrieves the whitelist, falling back to the cached copy while the
+    /// server is unreachable
+    pub fn retrieve_whitelist(&self) -> CvmfsResult<Whitelist> {
+        let whitelist_file = match self.fetcher.retrieve_raw_file(WHITELIST_NAME) {
+            Err(error) if error.is_unreachable() => {
+                let cached_file = self.fetcher.cache.get(WHITELIST_NAME).ok_or(error)?;
+                cached_file.to_str().ok_or(CvmfsError::FileNotFound)?.into()
+            }
+            result => result?,
+        };
+        let file = File::open(&whitelist_file)?;
+        Whitelist::new(RootFile::new(&file)?)
+    }
+
+    /// Retrieves the whitelist and verifies its signature with the master keys
+    /// of the repository found in the keys directory
+    pub fn verify_whitelist(&self) -> CvmfsResult<Whitelist> {
+        let whitelist = self.retrieve_whitelist()?;
+        let result = MasterKey::load_for_repository(&self.keys_directory, &self.fqrn)
+            .and_then(|master_keys| whitelist.verify_signature(&master_keys));
+        self.fetcher.audit(
+            AuditKind::Whitelist,
+            WHITELIST_NAME,
+            whitelist.root_file.checksum().unwrap_or_default(),
+            result.as_ref().err(),
+        );
+        result?;
+        Ok(whitelist)
+    }
+
+    pub fn validation_policy(&self) -> &ValidationPolicy {
+        &self.validation
+    }
+
+    /// Changes how failed integrity checks are handled, for the repository and
+    /// the objects downloaded from now on
+    pub fn set_validation_policy(&mut self, policy: ValidationPolicy) {
+        self.fetcher.content_validation = policy.content_hash;
+        if let Some(fetcher) = &mut self.external_fetcher {
+            fetcher.content_validation = policy.content_hash;
+        }
+        self.validation = policy;
+    }
+
+    /// Verifies the whitelist signature according to the validation policy
+    pub fn check_whitelist_signature(&self) -> CvmfsResult<()> {
+        let mode = self.validation.signature;
+        if !mode.is_enabled() {
+            return Ok(());
+        }
+        mode.apply(self.verify_whitelist().map(|_| ()))
+    }
+
+    /// Retrieves the certificate a manifest was signed with. Certificates are
+    /// content addressed, so each one is cached under its own hash.
+    pub fn retrieve_certificate(&self, manifest: &Manifest) -> CvmfsResult<Certificate> {
+        let path =
+            self.retrieve_object_with_suffix(&manifest.certificate, CERTIFICATE_ROOT_PREFIX)?;
+        Certificate::from_pem(&fs::read(path)?)
+    }
+
+    /// Checks that the certificate of a manifest is listed in the whitelist,
+    /// itself signed by one of the master keys
+    pub fn verify_certificate(&self, manifest: &Manifest) -> CvmfsResult<Certificate> {
+        let certificate = self.retrieve_certificate(manifest)?;
+        let fingerprint = certificate.fingerprint()?;
+        let whitelist = self.verify_whitelist()?;
+        self.check_repository_name(&whitelist, manifest)?;
+        if !whitelist.contains_fingerprint(&fingerprint) {
+            return Err(CvmfsError::UntrustedCertificate(fingerprint));
+        }
+        Ok(certificate)
+    }
+
+    /// Checks that the whitelist and the manifest belong to the repository
+    /// that was mounted, so that another signed repository can't stand in
+    fn check_repository_name(&self, whitelist: &Whitelist, manifest: &Manifest) -> CvmfsResult<()> {
+        let expected = self.expected_fqrn.as_deref().unwrap_or(&self.fqrn);
+        if whitelist.repository_name != expected || manifest.repository_name != expected {
+            return Err(CvmfsError::RepositoryNameMismatch(format!(
+                "expected {}, the whitelist names {} and the manifest {}",
+                expected, whitelist.repository_name, manifest.repository_name
+            )));
+        }
+        Ok(())
+    }
+
+    /// Verifies the whole trust chain of a manifest: the whitelist signed by a
+    /// master key lists its certificate, whose key signed the manifest
+    pub fn verify_manifest(&self, manifest: &Manifest) -> CvmfsResult<()> {
+        let result = self
+            .verify_certificate(manifest)
+            .and_then(|certificate| manifest.verify_signature(&certificate));
+        self.fetcher.audit(
+            AuditKind::Manifest,
+            MANIFEST_NAME,
+            manifest.root_file.checksum().unwrap_or_default(),
+            result.as_ref().err(),
+        );
+        result
+    }
+
+    /// Verifies the signature of the current manifest according to the
+    /// validation policy, remembering its certificate to detect rotations
+    pub fn check_certificate(&mut self) -> CvmfsResult<()> {
+        let mode = self.validation.signature;
+        if !mode.is_enabled() {
+            return Ok(());
+        }
+        mode.apply(self.verify_manifest(&self.manifest))?;
+        self.certificate_hash = Some(self.manifest.certificate.clone());
+        Ok(())
+    }
+
+    /// Hash of the certificate last checked against the whitelist
+    pub fn certificate_hash(&self) -> Option<&str> {
+        self.certificate_hash.as_deref()
+    }
+
+    /// Remembers the new certificate referenced by a verified manifest
+    fn rotate_certificate(&mut self, manifest: &Manifest) {
+        log::info!(
+            "Certificate of {} rotated from {} to {} in revision {}",
+            self.fqrn,
+            self.certificate_hash.as_deref().unwrap_or_default(),
+            manifest.certificate,
+            manifest.revision
+        );
+        self.certificate_hash = Some(manifest.certificate.clone());
+        self.certificate_rotations += 1;
+    }
+
+    /// Checks the expiry of the whitelist according to the validation policy.
+    /// An expired whitelist fails with the `Unmount` expiry policy, while
+    /// `ServeFromCache` keeps the current revision without updates.
+    pub fn check_whitelist_expiry(&mut self) -> CvmfsResult<()> {
+        self.degraded = !self.whitelist_allows_updates()?;
+        if self.degraded {
+            self.warn_serving_without_updates();
+        }
+        Ok(())
+    }
+
+    fn warn_serving_without_updates(&self) {
+        log::warn!(
+            "Serving revision {} of {} without updates until the whitelist is renewed",
+            self.manifest.revision,
+            self.fqrn
+        );
+    }
+
+    /// Checks the expiry of the whitelist, applying the expiry policy when it
+    /// has expired and the check is fatal. Returns whether new revisions may
+    /// be picked up.
+    fn whitelist_allows_updates(&self) -> CvmfsResult<bool> {
+        let mode = self.validation.whitelist_expiry;
+        if !mode.is_enabled() {
+            return Ok(true);
+        }
+        let whitelist = self.retrieve_whitelist()?;
+        match whitelist.validate_timestamps(Utc::now(), self.clock_skew_tolerance) {
+            Ok(_) => return Ok(true),
+            Err(CvmfsError::WhitelistExpired) => {}
+            Err(e) => return Err(e),
+        }
+        log::warn!(
+            "The whitelist of {} expired on {}",
+            self.fqrn,
+            whitelist.expires
+        );
+        if mode != ValidationMode::Fatal {
+            return Ok(true);
+        }
+        match self.whitelist_expiry_policy {
+            ExpiryPolicy::ServeFromCache => Ok(false),
+            ExpiryPolicy::Unmount => Err(CvmfsError::WhitelistExpired),
+        }
+    }
+
+    /// Reads the manifest from the server, falling back to the cached copy when
+    /// the server is unreachable and the cache holds a breadcrumb for it.
+    /// Returns whether the cached copy was used.
+    fn read_manifest_or_cached(fetcher: &Fetcher) -> CvmfsResult<(Manifest, bool)> {
+        match Self::read_manifest(fetcher) {
+            Ok((manifest, content)) => {
+                Self::check_breadcrumb(fetcher, &manifest)?;
+                Self::store_manifest(fetcher, &content);
+                Ok((manifest, false))
+            }
+            Err(error) => {
+                let manifest = fetcher
+                    .cache
+                    .get(MANIFEST_NAME)
+                    .and_then(|path| File::open(path).ok())
+                    .and_then(|file| RootFile::new(&file).ok())
+                    .and_then(|root_file| Manifest::new(root_file).ok())
+                    .ok_or(error.clone())?;
+                match fetcher.cache.load_breadcrumb(&manifest.repository_name) {
+                    Some(breadcrumb) if breadcrumb.catalog_hash == manifest.root_catalog => {
+                        log::warn!(
+                            "Could not fetch the manifest ({:?}), mounting cached revision {}",
+                            error,
+                            manifest.revision
+                        );
+                        Ok((manifest, true))
+                    }
+                    _ => Err(error),
+                }
+            }
+        }
+    }
+
+    /// Refuses manifests older than the last revision seen by this cache
+    fn check_breadcrumb(fetcher: &Fetcher, manifest: &Manifest) -> CvmfsResult<()> {
+        if let Some(breadcrumb) = fetcher.cache.load_breadcrumb(&manifest.repository_name) {
+            if manifest.revision < breadcrumb.revision {
+                return Err(CvmfsError::RevisionRollback(format!(
+                    "{} went from revision {} to {}",
+                    manifest.repository_name, breadcrumb.revision, manifest.revision
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn store_breadcrumb(&self) {
+        let breadcrumb = Breadcrumb::from(&self.manifest);
+        if let Err(e) = self.fetcher.cache.store_breadcrumb(&self.fqrn, &breadcrumb) {
+            log::warn!("Could not store the breadcrumb of {}: {:?}", self.fqrn, e);
+        }
+    }
+
+    /// Downloads the manifest, returning it along with its content, which is
+    /// only stored in the cache once the manifest passed the checks
+    fn read_manifest(fetcher: &Fetcher) -> CvmfsResult<(Manifest, Arc<[u8]>)> {
+        let (content, _) = fetcher.download(MANIFEST_NAME)?;
+        let root_file = RootFile::from_bytes(&content);
+        if fetcher.audit_log.is_some() {
+            let checksum = root_file.as_ref().ok().and_then(|r| r.checksum());
+            fetcher.audit(
+                AuditKind::Manifest,
+                MANIFEST_NAME,
+                checksum.unwrap_or_default(),
+                root_file.as_ref().err(),
+            );
+        }
+        Ok((Manifest::new(root_file?)?, content))
+    }
+
+    fn store_manifest(fetcher: &Fetcher, content: &[u8]) {
+        if let Err(e) = fetcher.cache.store(MANIFEST_NAME, content) {
+            log::warn!("Could not cache the manifest: {:?}", e);
+        }
     }
 
     fn get_replication_date(
@@ -200,54 +1119,601 @@ impl Repository {
         Ok(self.current_tag()?.timestamp)
     }
 
-    pub fn retrieve_current_root_catalog(&mut self) -> CvmfsResult<&Catalog> {
-        let root_hash = self.current_tag()?.hash.to_string();
-        self.retrieve_catalog(&root_hash)
+    /// Time to live of the current revision in seconds. The root catalog can
+    /// override the TTL announced in the manifest.
+    pub fn get_ttl(&self) -> CvmfsResult<u32> {
+        let catalog = match self.micro_catalog(self.get_root_hash()?) {
+            Some(micro_catalog) => micro_catalog,
+            None => self.retrieve_current_root_catalog()?,
+        };
+        Ok(catalog.ttl.unwrap_or(self.manifest.ttl))
+    }
+
+    pub fn retrieve_current_root_catalog(&self) -> CvmfsResult<Arc<Catalog>> {
+        self.retrieve_catalog(&self.current_tag()?.hash)
     }
 
     /// Recursively walk down the Catalogs and find the best fit for a path
-    pub fn retrieve_catalog_for_path(&mut self, needle_path: &str) -> CvmfsResult<&Catalog> {
-        let mut hash = String::from(self.get_root_hash()?);
+    pub fn retrieve_catalog_for_path(&self, needle_path: &str) -> CvmfsResult<Arc<Catalog>> {
+        self.retrieve_catalog_for_path_at(self.get_root_hash()?, needle_path)
+    }
+
+    /// Same as `retrieve_catalog_for_path`, starting from the root catalog of
+    /// any revision instead of the current one.
+    /// Resolved paths are remembered, and the walk starts from the catalog of the
+    /// closest resolved ancestor, since nested catalogs can only be deeper.
+    pub fn retrieve_catalog_for_path_at(
+        &self,
+        root_hash: &str,
+        needle_path: &str,
+    ) -> CvmfsResult<Arc<Catalog>> {
+        let key = revision_path_key(root_hash, needle_path);
+        let mut hash = {
+            let mut catalog_cache = self.catalog_cache.lock().map_err(|_| CvmfsError::Sync)?;
+            if let Some(hash) = catalog_cache.get(&key) {
+                drop(catalog_cache);
+                return self.retrieve_catalog(&hash);
+            }
+            Path::new(needle_path)
+                .ancestors()
+                .skip(1)
+                .filter_map(|ancestor| ancestor.to_str())
+                .take_while(|ancestor| *ancestor != "/")
+                .find_map(|ancestor| catalog_cache.get(&revision_path_key(root_hash, ancestor)))
+                .unwrap_or_else(|| Arc::from(root_hash))
+        };
         loop {
-            match self
-                .retrieve_catalog(&hash)?
-                .find_nested_for_path(needle_path)
-            {
-                Ok(None) => return self.retrieve_catalog(&hash),
-                Ok(Some(nested_reference)) => hash = nested_reference.catalog_hash.clone(),
-                Err(error) => return Err(error),
+            let catalog = self.retrieve_catalog(&hash)?;
+            match catalog.find_nested_for_path(needle_path)? {
+                None => {
+                    if let Ok(mut catalog_cache) = self.catalog_cache.lock() {
+                        catalog_cache.insert(key, hash);
+                    }
+                    return Ok(catalog);
+                }
+                Some(nested_reference) => {
+                    if !self.is_catalog_opened(&nested_reference.catalog_hash) {
+                        self.prefetch_sibling_catalogs(&catalog, &nested_reference)?;
+                    }
+                    hash = Arc::from(nested_reference.catalog_hash)
+                }
+            };
+        }
+    }
+
+    /// Downloads the next catalog of a resolution chain along with the nested
+    /// catalogs mounted next to it, which are likely to be resolved soon after
+    fn prefetch_sibling_catalogs(
+        &self,
+        parent: &Catalog,
+        next: &CatalogReference,
+    ) -> CvmfsResult<()> {
+        let parent_directory = Path::new(&next.root_path).parent();
+        let mut file_names = vec![next.catalog_hash.clone()];
+        file_names.extend(
+            parent
+                .list_nested()?
+                .into_iter()
+                .filter(|nested| {
+                    nested.catalog_hash != next.catalog_hash
+                        && Path::new(&nested.root_path).parent() == parent_directory
+                        && !self.is_catalog_opened(&nested.catalog_hash)
+                })
+                .map(|nested| nested.catalog_hash)
+                .take(MAX_PARALLEL_CATALOG_FETCHES - 1),
+        );
+        let file_names: Vec<String> = file_names
+            .iter()
+            .filter_map(|hash| {
+                compose_object_path(hash, CATALOG_ROOT_PREFIX)
+                    .ok()?
+                    .to_str()
+                    .map(String::from)
+            })
+            .collect();
+        self.fetcher.prefetch(&file_names);
+        Ok(())
+    }
+
+    /// Downloads every nested catalog below a path of the current revision,
+    /// a level of the catalog tree at a time with the catalogs of each level
+    /// fetched in parallel, so that a batch job walking the subtree does not
+    /// wait for them one by one. Returns the number of catalogs serving the
+    /// subtree, including the one the path belongs to.
+    pub fn preload_catalogs(&self, prefix: &str) -> CvmfsResult<usize> {
+        let prefix = normalize_path(prefix);
+        let prefix = prefix.trim_end_matches('/');
+        let is_below = |path: &str| {
+            prefix.is_empty()
+                || path
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        };
+        let mut level = vec![self.retrieve_catalog_for_path(prefix)?];
+        let mut preloaded = level.len();
+        while !level.is_empty() {
+            let mut nested = Vec::new();
+            for catalog in &level {
+                nested.extend(
+                    catalog
+                        .list_nested()?
+                        .into_iter()
+                        .filter(|reference| is_below(&reference.root_path)),
+                );
+            }
+            let file_names: Vec<String> = nested
+                .iter()
+                .filter_map(|reference| {
+                    compose_object_path(&reference.catalog_hash, CATALOG_ROOT_PREFIX)
+                        .ok()?
+                        .to_str()
+                        .map(String::from)
+                })
+                .collect();
+            log::debug!("Preloading {} catalogs below {}", file_names.len(), prefix);
+            self.fetcher
+                .prefetch_with_concurrency(&file_names, MAX_PARALLEL_CATALOG_FETCHES);
+            preloaded += nested.len();
+            level = nested
+                .iter()
+                .map(|reference| self.retrieve_catalog(&reference.catalog_hash))
+                .collect::<CvmfsResult<_>>()?;
+        }
+        Ok(preloaded)
+    }
+
+    /// Downloads objects into the cache with at most `concurrency` downloads
+    /// in flight. Failures are only logged, see `Fetcher::prefetch`.
+    pub fn prefetch_objects(&self, object_names: &[String], concurrency: usize) {
+        self.fetcher
+            .prefetch_with_concurrency(object_names, concurrency);
+    }
+
+    /// Fills the cache with the catalogs and files matched by a spec, see
+    /// `preload::preload`
+    pub fn preload(&self, spec: &PreloadSpec) -> CvmfsResult<PreloadSummary> {
+        preload::preload(self, spec, DEFAULT_PRELOAD_CONCURRENCY)
+    }
+
+    pub fn lookup(&self, path: &str) -> CvmfsResult<DirectoryEntry> {
+        self.lookup_at(self.get_root_hash()?, path)
+    }
+
+    /// Looks up a path in the revision with the given root catalog
+    pub fn lookup_at(&self, root_hash: &str, path: &str) -> CvmfsResult<DirectoryEntry> {
+        let started = Instant::now();
+        let result = self.find_at(root_hash, path);
+        metrics().observe_lookup(started.elapsed());
+        if let Err(e) = &result {
+            metrics().record_error(e);
+        }
+        result
+    }
+
+    fn find_at(&self, root_hash: &str, path: &str) -> CvmfsResult<DirectoryEntry> {
+        let path = if path == "/" { "" } else { path };
+        let key = revision_path_key(root_hash, path);
+        let cached = self
+            .lookup_cache
+            .lock()
+            .map_err(|_| CvmfsError::Sync)?
+            .get(&key);
+        if let Some(dirent) = cached {
+            return Ok(dirent);
+        }
+        let dirent = match self.find_top_level(root_hash, path)? {
+            Some(dirent) => dirent,
+            None => self
+                .retrieve_catalog_for_path_at(root_hash, path)?
+                .find_directory_entry(path)?,
+        };
+        if let Ok(mut lookup_cache) = self.lookup_cache.lock() {
+            lookup_cache.insert(key, dirent.clone());
+        }
+        Ok(dirent)
+    }
+
+    /// Statistics of the queries run against the opened catalogs, per root
+    /// prefix. Closing a catalog drops its counters.
+    pub fn get_performance_counters(&self) -> CvmfsResult<BTreeMap<String, PerformanceCounters>> {
+        let opened_catalogs = self.opened_catalogs.read().map_err(|_| CvmfsError::Sync)?;
+        let mut counters = BTreeMap::<String, PerformanceCounters>::new();
+        for opened in opened_catalogs.values() {
+            counters
+                .entry(opened.catalog.root_prefix.clone())
+                .or_default()
+                .merge(&opened.catalog.performance_counters());
+        }
+        Ok(counters)
+    }
+
+    /// Reports the memory held by the opened catalogs and the lookup caches
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            catalogs: self
+                .opened_catalogs
+                .read()
+                .map(|opened_catalogs| {
+                    opened_catalogs
+                        .values()
+                        .map(|opened| opened.catalog.database.memory_used())
+                        .sum()
+                })
+                .unwrap_or(0),
+            lookup_cache: self
+                .lookup_cache
+                .lock()
+                .map(|cache| {
+                    cache
+                        .iter()
+                        .map(|(_, dirent)| std::mem::size_of::<[u8; 16]>() + dirent.memory_size())
+                        .sum()
+                })
+                .unwrap_or(0),
+            catalog_cache: self
+                .catalog_cache
+                .lock()
+                .map(|cache| {
+                    cache
+                        .iter()
+                        .map(|(_, hash)| std::mem::size_of::<([u8; 16], Arc<str>)>() + hash.len())
+                        .sum()
+                })
+                .unwrap_or(0),
+            listing_cache: self
+                .listing_cache
+                .lock()
+                .map(|cache| {
+                    cache
+                        .iter()
+                        .map(|(_, entries)| {
+                            std::mem::size_of::<([u8; 16], Arc<[DirectoryEntry]>)>()
+                                + entries
+                                    .iter()
+                                    .map(DirectoryEntry::memory_size)
+                                    .sum::<usize>()
+                        })
+                        .sum()
+                })
+                .unwrap_or(0),
+            open_files: 0,
+        }
+    }
+
+    /// Releases memory if the usage is above the configured limits
+    pub fn enforce_memory_limits(&self) {
+        if self.memory_limits == MemoryLimits::default() {
+            return;
+        }
+        let usage = self.memory_usage();
+        if let Some(limit) = self.memory_limits.caches {
+            if usage.lookup_cache + usage.catalog_cache + usage.listing_cache > limit {
+                log::info!("Lookup caches over {} bytes, shrinking them", limit);
+                self.clear_caches();
+            }
+        }
+        if let Some(limit) = self.memory_limits.catalogs {
+            if usage.catalogs > limit {
+                log::info!("Catalogs over {} bytes, closing the oldest ones", limit);
+                let Ok(mut opened_catalogs) = self.opened_catalogs.write() else {
+                    return;
+                };
+                let mut used = usage.catalogs;
+                while used > limit {
+                    let Some(catalog) = self.close_oldest_catalog(&mut opened_catalogs) else {
+                        break;
+                    };
+                    used = used.saturating_sub(catalog.database.memory_used());
+                }
+            }
+        }
+    }
+
+    /// Empties the lookup caches, e.g. when a new revision is served
+    fn clear_caches(&self) {
+        if let Ok(mut lookup_cache) = self.lookup_cache.lock() {
+            lookup_cache.clear();
+        }
+        if let Ok(mut catalog_cache) = self.catalog_cache.lock() {
+            catalog_cache.clear();
+        }
+        if let Ok(mut listing_cache) = self.listing_cache.lock() {
+            listing_cache.clear();
+        }
+        if let Ok(mut prefetched_directories) = self.prefetched_directories.lock() {
+            prefetched_directories.clear();
+        }
+    }
+
+    /// Changes the number of directory entries kept in memory
+    pub fn set_lookup_cache_size(&mut self, size: usize) {
+        self.lookup_cache = Mutex::new(LruCache::new(size));
+    }
+
+    /// Changes the number of resolved catalog paths kept in memory
+    pub fn set_catalog_cache_size(&mut self, size: usize) {
+        self.catalog_cache = Mutex::new(LruCache::new(size));
+    }
+
+    /// Changes the number of directory listings kept in memory
+    pub fn set_listing_cache_size(&mut self, size: usize) {
+        self.listing_cache = Mutex::new(LruCache::new(size));
+    }
+
+    /// Looks up several paths at once, grouping them by the catalog serving them
+    /// so that every catalog is loaded, listed for its nested catalogs and
+    /// queried with a single statement. The results are returned in the same
+    /// order as the paths.
+    pub fn lookup_many(&self, paths: &[&str]) -> Vec<CvmfsResult<DirectoryEntry>> {
+        let root_hash = match self.get_root_hash() {
+            Ok(root_hash) => root_hash,
+            Err(e) => return paths.iter().map(|_| Err(e.clone())).collect(),
+        };
+        let mut results: Vec<Option<CvmfsResult<DirectoryEntry>>> =
+            paths.iter().map(|_| None).collect();
+        let mut nested_catalogs = HashMap::new();
+        let mut groups: HashMap<String, Vec<(usize, &str)>> = HashMap::new();
+        for (index, path) in paths.iter().enumerate() {
+            let path = if path.eq(&"/") { "" } else { path };
+            match self.find_catalog(root_hash, path, &mut nested_catalogs) {
+                Ok(catalog_hash) => groups.entry(catalog_hash).or_default().push((index, path)),
+                Err(e) => results[index] = Some(Err(e)),
+            }
+        }
+        for (catalog_hash, group) in groups {
+            let catalog = match self.retrieve_catalog(&catalog_hash) {
+                Ok(catalog) => catalog,
+                Err(e) => {
+                    for (index, _) in group {
+                        results[index] = Some(Err(e.clone()));
+                    }
+                    continue;
+                }
             };
+            let group_paths: Vec<&str> = group.iter().map(|(_, path)| *path).collect();
+            let entries = catalog.find_directory_entries(&group_paths);
+            for ((index, _), entry) in group.into_iter().zip(entries) {
+                results[index] = Some(entry);
+            }
         }
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or(Err(CvmfsError::FileNotFound)))
+            .collect()
     }
 
-    pub fn lookup(&mut self, path: &str) -> CvmfsResult<DirectoryEntry> {
-        let mut path = String::from(path);
-        if path.eq("/") {
-            path = String::new();
+    /// Hash of the catalog serving a path, walking down the nested catalogs
+    /// listed so far before listing those of the catalogs not seen yet
+    fn find_catalog(
+        &self,
+        root_hash: &str,
+        path: &str,
+        nested_catalogs: &mut HashMap<String, Vec<CatalogReference>>,
+    ) -> CvmfsResult<String> {
+        let mut hash = root_hash.to_string();
+        loop {
+            if !nested_catalogs.contains_key(&hash) {
+                let catalog_refs = self.retrieve_catalog(&hash)?.list_nested()?;
+                nested_catalogs.insert(hash.clone(), catalog_refs);
+            }
+            match Catalog::best_nested_match(&nested_catalogs[&hash], path) {
+                Some(nested_reference) => hash = nested_reference.catalog_hash.clone(),
+                None => return Ok(hash),
+            }
         }
-        let best_fit = self.retrieve_catalog_for_path(&path)?;
-        best_fit.find_directory_entry(&path)
     }
 
-    pub fn get_file(&mut self, path: &str) -> CvmfsResult<Box<dyn FileLike>> {
-        let directory_entry = self.lookup(path)?;
+    pub fn get_file(&self, path: &str) -> CvmfsResult<Box<dyn FileLike>> {
+        self.get_file_at(self.get_root_hash()?, path)
+    }
+
+    /// Retrieves a file of the revision with the given root catalog
+    pub fn get_file_at(&self, root_hash: &str, path: &str) -> CvmfsResult<Box<dyn FileLike>> {
+        let mut directory_entry = self.lookup_at(root_hash, path)?;
         if !directory_entry.is_file() {
             return Err(CvmfsError::NotAFile);
         }
-        self.retrieve_object(&directory_entry)
+        self.retrieve_catalog_for_path_at(root_hash, path)?
+            .load_chunks(&mut directory_entry)?;
+        self.retrieve_object(path, directory_entry)
+    }
+
+    /// Starts downloading in the background the small siblings of a file of the
+    /// revision with the given root catalog, if sibling prefetching is enabled
+    pub fn prefetch_siblings_at(&self, root_hash: &str, path: &str) -> CvmfsResult<()> {
+        let Some(settings) = self.sibling_prefetch.clone() else {
+            return Ok(());
+        };
+        let directory = path.rsplit_once('/').map_or("", |(parent, _)| parent);
+        let key = revision_path_key(root_hash, directory);
+        {
+            let mut prefetched_directories = self
+                .prefetched_directories
+                .lock()
+                .map_err(|_| CvmfsError::Sync)?;
+            if prefetched_directories.get(&key).is_some() {
+                return Ok(());
+            }
+            prefetched_directories.insert(key, ());
+        }
+        let object_names: Vec<String> = self
+            .map_directory_at(root_hash, directory, |dirent| {
+                if !dirent.is_file()
+                    || dirent.has_chunks()
+                    || dirent.is_external()
+                    || dirent.size > settings.max_file_size
+                {
+                    return None;
+                }
+                let object_path = compose_object_path(&dirent.content_hash_string()?, "").ok()?;
+                object_path.to_str().map(String::from)
+            })?
+            .into_iter()
+            .flatten()
+            .filter(|name| self.fetcher.cache.get(name).is_none())
+            .collect();
+        if object_names.is_empty() {
+            return Ok(());
+        }
+        log::debug!("Prefetching {} files of {}", object_names.len(), directory);
+        let fetcher = self.fetcher.clone();
+        thread::spawn(move || {
+            fetcher.prefetch_with_concurrency(&object_names, settings.concurrency)
+        });
+        Ok(())
     }
 
     /// List all the entries in a directory
-    pub fn list_directory(&mut self, path: &str) -> CvmfsResult<Vec<DirectoryEntry>> {
-        let dirent = self.lookup(path)?;
+    pub fn list_directory(&self, path: &str) -> CvmfsResult<Vec<DirectoryEntry>> {
+        self.list_directory_at(self.get_root_hash()?, path)
+    }
+
+    /// List all the entries in a directory of the revision with the given root catalog
+    pub fn list_directory_at(
+        &self,
+        root_hash: &str,
+        path: &str,
+    ) -> CvmfsResult<Vec<DirectoryEntry>> {
+        self.map_directory_at(root_hash, path, |dirent| dirent)
+    }
+
+    /// Maps the entries of a directory. Listings are cached per revision, so
+    /// directories listed over and over are only read once from the catalog.
+    pub fn map_directory_at<T>(
+        &self,
+        root_hash: &str,
+        path: &str,
+        f: impl FnMut(DirectoryEntry) -> T,
+    ) -> CvmfsResult<Vec<T>> {
+        let key = revision_path_key(root_hash, if path == "/" { "" } else { path });
+        let cached = self
+            .listing_cache
+            .lock()
+            .map_err(|_| CvmfsError::Sync)?
+            .get(&key);
+        if let Some(entries) = cached {
+            return Ok(entries.iter().cloned().map(f).collect());
+        }
+        let dirent = self.lookup_at(root_hash, path)?;
         if !dirent.is_directory() {
             return Err(CvmfsError::FileNotFound);
         }
-        let best_fit = self.retrieve_catalog_for_path(path)?;
-        best_fit.list_directory(path)
+        // the micro catalog may lack entries, so listings come from the root
+        let best_fit = self.retrieve_catalog_for_path_at(root_hash, path)?;
+        let entries: Arc<[DirectoryEntry]> = best_fit.map_directory(path, |dirent| dirent)?.into();
+        if let Ok(mut listing_cache) = self.listing_cache.lock() {
+            listing_cache.insert(key, entries.clone());
+        }
+        Ok(entries.iter().cloned().map(f).collect())
     }
 
-    pub fn get_statistics(&mut self) -> CvmfsResult<Statistics> {
+    pub fn get_statistics(&self) -> CvmfsResult<Statistics> {
         self.retrieve_current_root_catalog()?.get_statistics()
     }
+
+    /// Lists a directory straight from its catalog, bypassing the listing
+    /// cache, for the traversals that read each directory once
+    pub(crate) fn read_directory_at(
+        &self,
+        root_hash: &str,
+        path: &str,
+    ) -> CvmfsResult<Vec<DirectoryEntry>> {
+        let catalog_path = if path == "/" { "" } else { path };
+        self.retrieve_catalog_for_path_at(root_hash, catalog_path)?
+            .list_directory(path)
+    }
+
+    /// Changes from the revision of one tag to the one of another, see
+    /// `diff_revisions`
+    pub fn diff(&self, old_tag: &str, new_tag: &str) -> CvmfsResult<Vec<Change>> {
+        let old = self.get_tag_by_name(old_tag)?;
+        let new = self.get_tag_by_name(new_tag)?;
+        diff_revisions(self, &old.hash, &new.hash)
+    }
+
+    /// Writes a subtree of the current revision to a local directory or tar
+    /// archive, see `export::export`
+    pub fn export(&self, path: &str, target: &ExportTarget) -> CvmfsResult<ExportSummary> {
+        export::export(self, path, target)
+    }
+
+    /// Iterates over a path and everything below it, see `Walk`
+    pub fn walk(&self, path: &str) -> CvmfsResult<Walk<'_>> {
+        self.walk_at(self.get_root_hash()?, path)
+    }
+
+    /// Iterates over a path and everything below it in the revision with the
+    /// given root catalog
+    pub fn walk_at(&self, root_hash: &str, path: &str) -> CvmfsResult<Walk<'_>> {
+        let path = normalize_path(path);
+        let directory_entry = self.lookup_at(root_hash, &path)?;
+        Ok(Walk {
+            repository: self,
+            root_hash: root_hash.into(),
+            pending: vec![DirectoryEntryWrapper {
+                directory_entry,
+                path: path.into(),
+            }],
+            failed: None,
+        })
+    }
+}
+
+/// Depth first traversal of the entries of a revision, each directory before
+/// its contents and the contents sorted by name. Directories are listed as
+/// they are reached, opening the nested catalogs on demand, and bypass the
+/// listing cache so that a full traversal does not evict it.
+#[derive(Debug)]
+pub struct Walk<'a> {
+    repository: &'a Repository,
+    root_hash: String,
+    /// Entries to return, the next one last
+    pending: Vec<DirectoryEntryWrapper>,
+    /// Failure to list the last directory returned, reported next
+    failed: Option<CvmfsError>,
+}
+
+impl Walk<'_> {
+    fn list(&self, path: &str) -> CvmfsResult<Vec<DirectoryEntryWrapper>> {
+        let parent = if path == "/" { "" } else { path };
+        Ok(self
+            .repository
+            .read_directory_at(&self.root_hash, path)?
+            .into_iter()
+            .map(|directory_entry| DirectoryEntryWrapper {
+                path: format!("{}/{}", parent, directory_entry.name),
+                directory_entry,
+            })
+            .collect())
+    }
+}
+
+impl Iterator for Walk<'_> {
+    type Item = CvmfsResult<DirectoryEntryWrapper>;
+
+    /// Fails once for every directory that cannot be listed, skipping its
+    /// contents but not the rest of the traversal
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(error) = self.failed.take() {
+            return Some(Err(error));
+        }
+        let wrapper = self.pending.pop()?;
+        if wrapper.directory_entry.is_directory() {
+            match self.list(&wrapper.path) {
+                Ok(children) => self.pending.extend(children.into_iter().rev()),
+                Err(error) => self.failed = Some(error),
+            }
+        }
+        Some(Ok(wrapper))
+    }
+}
+
+/// Key of a path in a given revision, hashing the root catalog hash and the
+/// path together so that cache probes do not allocate
+fn revision_path_key(root_hash: &str, path: &str) -> [u8; 16] {
+    let mut context = md5::Context::new();
+    context.consume(root_hash.as_bytes());
+    context.consume([0u8]);
+    context.consume(path.as_bytes());
+    context.compute().0
 }
@@ -7,6 +7,12 @@ SELECT name, hash, revision, timestamp, channel, description \
 FROM tags \
 ORDER BY timestamp DESC";
 
+pub const SQL_QUERY_ALL_PAGED: &str = "\
+SELECT name, hash, revision, timestamp, channel, description \
+FROM tags \
+ORDER BY timestamp DESC \
+LIMIT ? OFFSET ?";
+
 pub const SQL_QUERY_NAME: &str = "\
 SELECT name, hash, revision, timestamp, channel, description \
 FROM tags \
@@ -22,10 +28,36 @@ LIMIT 1";
 pub const SQL_QUERY_DATE: &str = "\
 SELECT name, hash, revision, timestamp, channel, description \
 FROM tags \
-WHERE timestamp > ? \
-ORDER BY timestamp ASC \
+WHERE timestamp <= ? \
+ORDER BY timestamp DESC \
 LIMIT 1";
 
+pub const SQL_QUERY_RANGE: &str = "\
+SELECT name, hash, revision, timestamp, channel, description \
+FROM tags \
+WHERE timestamp BETWEEN ? AND ? \
+ORDER BY timestamp ASC, revision ASC";
+
+pub const SQL_QUERY_BRANCHES: &str = "\
+SELECT branch, parent, initial_revision \
+FROM branches \
+ORDER BY initial_revision ASC";
+
+pub const SQL_QUERY_BRANCH_TAGS: &str = "\
+SELECT name, hash, revision, timestamp, channel, description \
+FROM tags \
+WHERE branch = ? \
+ORDER BY revision DESC";
+
+pub const SQL_QUERY_NAME_BRANCH: &str = "\
+SELECT name, hash, revision, timestamp, channel, description \
+FROM tags \
+WHERE name = ? AND branch = ? \
+LIMIT 1";
+
+/// Name of the branch tags belong to when the repository does not use branches
+pub const DEFAULT_BRANCH: &str = "";
+
 #[derive(Debug, Clone)]
 pub struct RevisionTag {
     pub name: String,
@@ -34,6 +66,7 @@ pub struct RevisionTag {
     pub timestamp: u64,
     pub channel: i32,
     pub description: String,
+    pub branch: Option<String>,
 }
 
 impl RevisionTag {
@@ -45,6 +78,25 @@ impl RevisionTag {
             timestamp: row.get(3)?,
             channel: row.get(4)?,
             description: row.get(5)?,
+            branch: row.get::<_, Option<String>>(6).ok().flatten(),
+        })
+    }
+}
+
+/// Publishing branch of the repository, as stored in the history database
+#[derive(Debug, Clone, PartialEq)]
+pub struct Branch {
+    pub name: String,
+    pub parent: Option<String>,
+    pub initial_revision: i32,
+}
+
+impl Branch {
+    pub fn new(row: &Row) -> CvmfsResult<Self> {
+        Ok(Self {
+            name: row.get(0)?,
+            parent: row.get(1)?,
+            initial_revision: row.get(2)?,
         })
     }
 }
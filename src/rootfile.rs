@@ -1,6 +1,6 @@
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::str::Split;
+use std::io::{BufReader, Read};
+use std::str::Lines;
 
 use hex::ToHex;
 use sha1::{Digest, Sha1};
@@ -23,6 +23,7 @@ use crate::common::{CvmfsError, CvmfsResult};
 #[derive(Debug)]
 pub struct RootFile {
     checksum: Option<String>,
+    signature: Option<Vec<u8>>,
     contents: String,
 }
 
@@ -31,46 +32,94 @@ impl RootFile {
         self.checksum.is_some()
     }
 
-    pub fn lines(&self) -> Split<char> {
-        self.contents.split('\n')
+    /// Hex encoded hash of the key-value part, which is the message being signed
+    pub fn checksum(&self) -> Option<&str> {
+        self.checksum.as_deref()
+    }
+
+    /// Binary private-key signature following the checksum line
+    pub fn signature(&self) -> Option<&[u8]> {
+        self.signature.as_deref()
+    }
+
+    /// Key-value lines of the file, without line terminators
+    pub fn lines(&self) -> Lines<'_> {
+        self.contents.lines()
     }
 
     pub fn new(file: &File) -> CvmfsResult<Self> {
-        let mut reader = BufReader::new(file);
-        let mut buffer = String::new();
-        let mut contents = String::new();
-        let mut checksum: Option<String> = None;
-        loop {
-            buffer.clear();
-            let mut bytes_read = reader.read_line(&mut buffer)?;
-            if bytes_read == 0 {
+        let mut bytes = Vec::new();
+        BufReader::new(file).read_to_end(&mut bytes)?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Parses a root file, returning an error on malformed input instead of panicking
+    pub fn from_bytes(bytes: &[u8]) -> CvmfsResult<Self> {
+        let mut position = 0;
+        let mut separator = None;
+        while position < bytes.len() {
+            let line_end = bytes[position..]
+                .iter()
+                .position(|byte| *byte == b'\n')
+                .map(|offset| position + offset);
+            let line = &bytes[position..line_end.unwrap_or(bytes.len())];
+            if Self::trim_carriage_return(line) == b"--" {
+                separator = Some((position, line_end));
                 break;
             }
-            if buffer[..2].eq("--") {
-                buffer.clear();
-                bytes_read = reader.read_line(&mut buffer)?;
-                if bytes_read != 41 {
-                    return Err(CvmfsError::IO("Input does not have 41 bytes".to_string()));
-                }
-                checksum = Some(buffer[..40].into());
-                break;
-            } else {
-                contents.push_str(buffer.as_str())
+            match line_end {
+                Some(line_end) => position = line_end + 1,
+                None => position = bytes.len(),
             }
         }
+        let Some((contents_end, separator_end)) = separator else {
+            return Ok(Self {
+                checksum: None,
+                signature: None,
+                contents: Self::decode(bytes)?,
+            });
+        };
+        let contents = &bytes[..contents_end];
+        let signature_block = separator_end.map(|end| &bytes[end + 1..]).unwrap_or(&[]);
+        let checksum_end = signature_block
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .unwrap_or(signature_block.len());
+        let checksum = Self::decode(Self::trim_carriage_return(&signature_block[..checksum_end]))?;
+        if checksum.is_empty() {
+            return Err(CvmfsError::IncompleteRootFileSignature);
+        }
+        Self::verify_checksum(contents, &checksum)?;
+        let signature = signature_block
+            .get(checksum_end + 1..)
+            .filter(|signature| !signature.is_empty())
+            .map(|signature| signature.to_vec());
+        Ok(Self {
+            checksum: Some(checksum),
+            signature,
+            contents: Self::decode(contents)?,
+        })
+    }
 
-        if checksum.is_some() {
-            let mut hasher = Sha1::new();
-            hasher.update(contents.as_bytes());
-            let hash = &hasher.finalize()[..];
-            let signature: String = hash.encode_hex();
-            if signature.ne(checksum
-                .as_ref()
-                .ok_or(CvmfsError::InvalidRootFileSignature)?)
-            {
-                return Err(CvmfsError::InvalidRootFileSignature);
-            }
+    fn verify_checksum(contents: &[u8], checksum: &str) -> CvmfsResult<()> {
+        if checksum.len() != 40 || !checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(CvmfsError::InvalidRootFileSignature);
         }
-        Ok(Self { checksum, contents })
+        let mut hasher = Sha1::new();
+        hasher.update(contents);
+        let hash = &hasher.finalize()[..];
+        let signature: String = hash.encode_hex();
+        if !signature.eq_ignore_ascii_case(checksum) {
+            return Err(CvmfsError::InvalidRootFileSignature);
+        }
+        Ok(())
+    }
+
+    fn trim_carriage_return(line: &[u8]) -> &[u8] {
+        line.strip_suffix(b"\r").unwrap_or(line)
+    }
+
+    fn decode(bytes: &[u8]) -> CvmfsResult<String> {
+        String::from_utf8(bytes.to_vec()).map_err(|_| CvmfsError::ParseError)
     }
 }
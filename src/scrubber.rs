@@ -0,0 +1,268 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use openssl::sha::Sha256;
+
+use crate::cache::{is_partial_file, record_digest, stored_digest, Cache, QuarantineRecord};
+use crate::common::{CvmfsError, CvmfsResult};
+
+/// Pause between two passes over the cache by default
+pub const DEFAULT_SCRUB_INTERVAL: Duration = Duration::from_secs(24 * 3600);
+const SCRUB_CHUNK_SIZE: usize = 64 * 1024;
+/// Granularity of the sleeps, so that stopping the scrubber is quick
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScrubberConfig {
+    /// Bytes read from the cache per second at most
+    pub bytes_per_second: u64,
+    pub interval: Duration,
+}
+
+impl ScrubberConfig {
+    pub fn new(bytes_per_second: u64) -> Self {
+        Self {
+            bytes_per_second,
+            interval: DEFAULT_SCRUB_INTERVAL,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScrubOutcome {
+    /// The content matches the recorded digest
+    Valid,
+    /// No digest was recorded yet, the current one is taken as reference
+    Recorded,
+    /// The content changed since it was stored, the object was evicted
+    Evicted,
+    /// The digest cannot be recorded on this file system
+    Unverifiable,
+}
+
+/// Results of the objects checked so far
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScrubReport {
+    pub passes: u64,
+    pub scanned: u64,
+    pub bytes: u64,
+    pub recorded: u64,
+    pub evicted: u64,
+    pub unverifiable: u64,
+}
+
+impl ScrubReport {
+    fn add(&mut self, outcome: ScrubOutcome, bytes: u64) {
+        self.scanned += 1;
+        self.bytes += bytes;
+        match outcome {
+            ScrubOutcome::Valid => {}
+            ScrubOutcome::Recorded => self.recorded += 1,
+            ScrubOutcome::Evicted => self.evicted += 1,
+            ScrubOutcome::Unverifiable => self.unverifiable += 1,
+        }
+    }
+}
+
+/// Re-hashes the cached objects slowly, evicting the ones whose content no
+/// longer matches the digest recorded when they were stored, so that a flaky
+/// disk does not serve corrupt files forever. Evicted objects are moved to the
+/// quarantine and downloaded again on the next access.
+#[derive(Debug)]
+pub struct Scrubber {
+    cache: Cache,
+    config: ScrubberConfig,
+    stop: Arc<AtomicBool>,
+    report: Arc<Mutex<ScrubReport>>,
+}
+
+impl Scrubber {
+    pub fn new(cache: Cache, config: ScrubberConfig) -> CvmfsResult<Self> {
+        if config.bytes_per_second == 0 {
+            return Err(CvmfsError::InvalidConfiguration(
+                "the scrub rate must be positive".into(),
+            ));
+        }
+        Ok(Self {
+            cache,
+            config,
+            stop: Default::default(),
+            report: Default::default(),
+        })
+    }
+
+    pub fn report(&self) -> ScrubReport {
+        self.report.lock().map(|r| r.clone()).unwrap_or_default()
+    }
+
+    /// Checks every object of the primary and the fallback directories once
+    pub fn scrub_pass(&self) -> CvmfsResult<()> {
+        let directories =
+            std::iter::once(&self.cache.cache_directory).chain(&self.cache.fallback_directory);
+        let mut budget = RateLimit::new(self.config.bytes_per_second);
+        for directory in directories {
+            for object in Self::objects(Path::new(directory))? {
+                if self.stop.load(Ordering::Relaxed) {
+                    return Ok(());
+                }
+                match self.scrub_object(&object, &mut budget) {
+                    Ok((outcome, bytes)) => self
+                        .report
+                        .lock()
+                        .map_err(|_| CvmfsError::Sync)?
+                        .add(outcome, bytes),
+                    // the object may have been evicted in the meantime
+                    Err(e) => log::debug!("Could not scrub {:?}: {:?}", object, e),
+                }
+            }
+        }
+        self.report.lock().map_err(|_| CvmfsError::Sync)?.passes += 1;
+        Ok(())
+    }
+
+    fn objects(directory: &Path) -> CvmfsResult<Vec<PathBuf>> {
+        let data = directory.join("data");
+        if !data.is_dir() {
+            return Ok(vec![]);
+        }
+        let mut objects = Vec::new();
+        for prefix in fs::read_dir(data)? {
+            for entry in fs::read_dir(prefix?.path())? {
+                let path = entry?.path();
+                if !is_partial_file(&path) {
+                    objects.push(path);
+                }
+            }
+        }
+        Ok(objects)
+    }
+
+    fn scrub_object(
+        &self,
+        path: &Path,
+        budget: &mut RateLimit,
+    ) -> CvmfsResult<(ScrubOutcome, u64)> {
+        let mut file = File::open(path)?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0u8; SCRUB_CHUNK_SIZE];
+        let mut bytes = 0;
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+            bytes += read as u64;
+            budget.consume(read as u64, &self.stop);
+        }
+        let digest = hex::encode(hasher.finish());
+        let outcome = match stored_digest(path) {
+            Some(stored) if stored == digest => ScrubOutcome::Valid,
+            Some(stored) => {
+                log::warn!(
+                    "Evicting corrupt cached object {:?}: digest {} instead of {}",
+                    path,
+                    digest,
+                    stored
+                );
+                let url = format!("file://{}", path.display());
+                let record = QuarantineRecord::new(&url, &stored, &digest);
+                self.cache.quarantine_object(path, &record)?;
+                ScrubOutcome::Evicted
+            }
+            None => match record_digest(path, &digest) {
+                Ok(()) => ScrubOutcome::Recorded,
+                Err(_) => ScrubOutcome::Unverifiable,
+            },
+        };
+        Ok((outcome, bytes))
+    }
+
+    /// Scrubs the cache over and over in a background thread, until the
+    /// returned handle is stopped or dropped
+    pub fn spawn(self) -> ScrubberHandle {
+        let stop = self.stop.clone();
+        let report = self.report.clone();
+        let thread = thread::spawn(move || {
+            while !self.stop.load(Ordering::Relaxed) {
+                if let Err(e) = self.scrub_pass() {
+                    log::warn!("Cache scrubbing failed: {:?}", e);
+                }
+                log::info!("Cache scrubbing pass finished: {:?}", self.report());
+                sleep_unless_stopped(self.config.interval, &self.stop);
+            }
+        });
+        ScrubberHandle {
+            stop,
+            report,
+            thread: Some(thread),
+        }
+    }
+}
+
+/// Control over a scrubber running in the background
+#[derive(Debug)]
+pub struct ScrubberHandle {
+    stop: Arc<AtomicBool>,
+    report: Arc<Mutex<ScrubReport>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ScrubberHandle {
+    pub fn report(&self) -> ScrubReport {
+        self.report.lock().map(|r| r.clone()).unwrap_or_default()
+    }
+
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for ScrubberHandle {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+pub(crate) fn sleep_unless_stopped(duration: Duration, stop: &AtomicBool) {
+    let deadline = Instant::now() + duration;
+    while !stop.load(Ordering::Relaxed) {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return;
+        }
+        thread::sleep(remaining.min(STOP_POLL_INTERVAL));
+    }
+}
+
+/// Sleeps as needed to keep the bytes read under the configured rate
+#[derive(Debug)]
+struct RateLimit {
+    bytes_per_second: u64,
+    start: Instant,
+    consumed: u64,
+}
+
+impl RateLimit {
+    fn new(bytes_per_second: u64) -> Self {
+        Self {
+            bytes_per_second,
+            start: Instant::now(),
+            consumed: 0,
+        }
+    }
+
+    fn consume(&mut self, bytes: u64, stop: &AtomicBool) {
+        self.consumed += bytes;
+        let due = Duration::from_secs_f64(self.consumed as f64 / self.bytes_per_second as f64);
+        sleep_unless_stopped(due.saturating_sub(self.start.elapsed()), stop);
+    }
+}
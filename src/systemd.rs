@@ -0,0 +1,152 @@
+use std::env;
+use std::os::fd::{FromRawFd, RawFd};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram, UnixListener};
+use std::process;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::common::{CvmfsError, CvmfsResult};
+
+/// Name the binary is installed or linked under to act as the mount helper of
+/// `Type=cvmfs-rust` mount units
+pub const MOUNT_HELPER_NAME: &str = "mount.cvmfs-rust";
+pub const NOTIFY_SOCKET_VARIABLE: &str = "NOTIFY_SOCKET";
+pub const WATCHDOG_USEC_VARIABLE: &str = "WATCHDOG_USEC";
+pub const WATCHDOG_PID_VARIABLE: &str = "WATCHDOG_PID";
+pub const LISTEN_FDS_VARIABLE: &str = "LISTEN_FDS";
+pub const LISTEN_PID_VARIABLE: &str = "LISTEN_PID";
+pub const LISTEN_FDNAMES_VARIABLE: &str = "LISTEN_FDNAMES";
+/// First file descriptor passed by socket activation
+pub const LISTEN_FDS_START: RawFd = 3;
+
+/// Whether variables addressed to a process id are meant for this process.
+/// Unset means any process, as systemd does not always set it.
+fn is_for_process(pid: Option<&str>, own_pid: u32) -> bool {
+    pid.is_none_or(|pid| pid.parse() == Ok(own_pid))
+}
+
+/// Address of the notification socket, `@` standing for the abstract
+/// namespace
+pub fn notify_address(value: &str) -> CvmfsResult<SocketAddr> {
+    let address = match value.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name.as_bytes())?,
+        None if value.starts_with('/') => SocketAddr::from_pathname(value)?,
+        None => {
+            return Err(CvmfsError::InvalidConfiguration(format!(
+                "unsupported {}: {}",
+                NOTIFY_SOCKET_VARIABLE, value
+            )))
+        }
+    };
+    Ok(address)
+}
+
+/// Period of the watchdog pings: half of the timeout systemd enforces, so
+/// that a late ping does not get the mount restarted
+pub fn watchdog_interval(usec: Option<&str>, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    let usec: u64 = usec?.parse().ok().filter(|usec| *usec > 0)?;
+    is_for_process(pid, own_pid).then(|| Duration::from_micros(usec / 2))
+}
+
+/// Number of sockets passed by socket activation to this process
+pub fn listen_fds(fds: Option<&str>, pid: Option<&str>, own_pid: u32) -> usize {
+    match (fds.and_then(|fds| fds.parse().ok()), pid) {
+        (Some(fds), Some(_)) if is_for_process(pid, own_pid) => fds,
+        _ => 0,
+    }
+}
+
+/// Sends state changes to the service manager, as sd_notify(3) does
+#[derive(Debug)]
+pub struct Notifier {
+    socket: UnixDatagram,
+    address: SocketAddr,
+}
+
+impl Notifier {
+    /// The notifier of the service manager running this process, if any
+    pub fn from_env() -> CvmfsResult<Option<Self>> {
+        let Ok(value) = env::var(NOTIFY_SOCKET_VARIABLE) else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            socket: UnixDatagram::unbound()?,
+            address: notify_address(&value)?,
+        }))
+    }
+
+    pub fn notify(&self, state: &str) -> CvmfsResult<()> {
+        self.socket.send_to_addr(state.as_bytes(), &self.address)?;
+        Ok(())
+    }
+
+    /// Reports that the repository is mounted
+    pub fn ready(&self) -> CvmfsResult<()> {
+        self.notify(&format!("READY=1\nMAINPID={}", process::id()))
+    }
+
+    pub fn stopping(&self) -> CvmfsResult<()> {
+        self.notify("STOPPING=1")
+    }
+
+    pub fn status(&self, status: &str) -> CvmfsResult<()> {
+        self.notify(&format!("STATUS={}", status))
+    }
+
+    pub fn watchdog(&self) -> CvmfsResult<()> {
+        self.notify("WATCHDOG=1")
+    }
+
+    /// Pings the watchdog in a background thread while `alive` holds, if the
+    /// service manager asked for it
+    pub fn spawn_watchdog<F>(self: Arc<Self>, alive: F) -> Option<JoinHandle<()>>
+    where
+        F: Fn() -> bool + Send + 'static,
+    {
+        let interval = watchdog_interval(
+            env::var(WATCHDOG_USEC_VARIABLE).ok().as_deref(),
+            env::var(WATCHDOG_PID_VARIABLE).ok().as_deref(),
+            process::id(),
+        )?;
+        Some(thread::spawn(move || loop {
+            if alive() {
+                if let Err(e) = self.watchdog() {
+                    log::warn!("Could not ping the systemd watchdog: {:?}", e);
+                }
+            } else {
+                log::warn!("Health check failed, not pinging the systemd watchdog");
+            }
+            thread::sleep(interval);
+        }))
+    }
+}
+
+/// Takes the control socket passed by socket activation, if any. The
+/// variables are removed so that child processes do not take it too.
+pub fn take_control_listener() -> Option<UnixListener> {
+    let fds = listen_fds(
+        env::var(LISTEN_FDS_VARIABLE).ok().as_deref(),
+        env::var(LISTEN_PID_VARIABLE).ok().as_deref(),
+        process::id(),
+    );
+    for variable in [
+        LISTEN_FDS_VARIABLE,
+        LISTEN_PID_VARIABLE,
+        LISTEN_FDNAMES_VARIABLE,
+    ] {
+        env::remove_var(variable);
+    }
+    if fds == 0 {
+        return None;
+    }
+    if fds > 1 {
+        log::warn!("Only the first of the {} activated sockets is used", fds);
+    }
+    // SAFETY: systemd hands the listening socket over to this process
+    unsafe {
+        libc::fcntl(LISTEN_FDS_START, libc::F_SETFD, libc::FD_CLOEXEC);
+        Some(UnixListener::from_raw_fd(LISTEN_FDS_START))
+    }
+}
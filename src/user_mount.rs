@@ -0,0 +1,81 @@
+use std::env;
+use std::fs;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use crate::common::{CvmfsError, CvmfsResult};
+
+/// Setuid helpers mounting FUSE file systems on behalf of regular users,
+/// in order of preference
+pub const FUSERMOUNT_HELPERS: [&str; 2] = ["fusermount3", "fusermount"];
+pub const FUSE_CONF_PATH: &str = "/etc/fuse.conf";
+/// Options only honored for regular users if `user_allow_other` is set in fuse.conf
+pub const PRIVILEGED_FUSE_OPTIONS: [&str; 2] = ["allow_other", "allow_root"];
+
+/// Whether the process lacks the privileges to mount by itself. Root inside a
+/// user namespace counts as privileged, since it can mount there.
+pub fn is_unprivileged() -> bool {
+    unsafe { libc::geteuid() != 0 }
+}
+
+/// Per-user cache directory, `$XDG_CACHE_HOME/cvmfs` or else `~/.cache/cvmfs`
+pub fn user_cache_directory() -> Option<PathBuf> {
+    xdg_cache_directory(
+        env::var("XDG_CACHE_HOME").ok().as_deref(),
+        env::var("HOME").ok().as_deref(),
+    )
+}
+
+pub fn xdg_cache_directory(xdg_cache_home: Option<&str>, home: Option<&str>) -> Option<PathBuf> {
+    // relative values are invalid according to the XDG specification
+    match (xdg_cache_home, home) {
+        (Some(cache), _) if cache.starts_with('/') => Some(Path::new(cache).join("cvmfs")),
+        (_, Some(home)) if home.starts_with('/') => {
+            Some(Path::new(home).join(".cache").join("cvmfs"))
+        }
+        _ => None,
+    }
+}
+
+/// Finds the first setuid-root mount helper in the `PATH`
+pub fn find_fusermount() -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    FUSERMOUNT_HELPERS.iter().find_map(|helper| {
+        env::split_paths(&path)
+            .map(|directory| directory.join(helper))
+            .find(|candidate| is_setuid_root(candidate))
+    })
+}
+
+fn is_setuid_root(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| {
+        metadata.is_file() && metadata.uid() == 0 && metadata.permissions().mode() & 0o4000 != 0
+    })
+}
+
+/// Whether `user_allow_other` is enabled in the contents of fuse.conf
+pub fn parse_user_allow_other(fuse_conf: &str) -> bool {
+    fuse_conf
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .any(|line| line == "user_allow_other")
+}
+
+pub fn user_allow_other() -> bool {
+    fs::read_to_string(FUSE_CONF_PATH).is_ok_and(|conf| parse_user_allow_other(&conf))
+}
+
+/// Whether the mount helper accepts a FUSE option from a regular user
+pub fn is_user_fuse_option(option: &str, allow_other: bool) -> bool {
+    allow_other || !PRIVILEGED_FUSE_OPTIONS.contains(&option)
+}
+
+/// Checks that a regular user can mount, which needs the setuid helper
+pub fn check_user_mount() -> CvmfsResult<PathBuf> {
+    find_fusermount().ok_or_else(|| {
+        CvmfsError::InvalidConfiguration(format!(
+            "mounting as a regular user needs one of {} installed setuid root",
+            FUSERMOUNT_HELPERS.join(", ")
+        ))
+    })
+}
@@ -0,0 +1,132 @@
+use std::str::FromStr;
+
+use crate::common::{CvmfsError, CvmfsResult};
+
+/// What happens when an integrity check fails
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValidationMode {
+    /// The failure is returned as an error
+    Fatal,
+    /// The failure is logged and the operation goes on
+    Warn,
+    /// The check is not even performed
+    Ignore,
+}
+
+impl ValidationMode {
+    pub fn is_enabled(self) -> bool {
+        self != ValidationMode::Ignore
+    }
+
+    /// Applies the mode to the outcome of a check
+    pub fn apply(self, result: CvmfsResult<()>) -> CvmfsResult<()> {
+        match (self, result) {
+            (ValidationMode::Fatal, result) => result,
+            (ValidationMode::Warn, Err(e)) => {
+                log::warn!("Integrity check failed, going on anyway: {}", e);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FromStr for ValidationMode {
+    type Err = CvmfsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fatal" => Ok(ValidationMode::Fatal),
+            "warn" => Ok(ValidationMode::Warn),
+            "ignore" => Ok(ValidationMode::Ignore),
+            _ => Err(CvmfsError::InvalidConfiguration(format!(
+                "invalid validation mode {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Handling of the integrity checks of a repository. By default badly signed
+/// repositories are refused, object digests are not verified and an expired
+/// whitelist applies the expiry policy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationPolicy {
+    /// Whitelist signed by one of the repository master keys, listing the
+    /// certificate whose key signed the manifest
+    pub signature: ValidationMode,
+    /// Digest of the downloaded objects matching their name
+    pub content_hash: ValidationMode,
+    /// Whitelist not expired. When fatal, the `ExpiryPolicy` of the repository
+    /// decides between freezing the revision and failing the refresh.
+    pub whitelist_expiry: ValidationMode,
+}
+
+impl Default for ValidationPolicy {
+    fn default() -> Self {
+        Self {
+            signature: ValidationMode::Fatal,
+            content_hash: ValidationMode::Ignore,
+            whitelist_expiry: ValidationMode::Fatal,
+        }
+    }
+}
+
+impl ValidationPolicy {
+    /// Every failed check is fatal
+    pub fn strict() -> Self {
+        Self {
+            signature: ValidationMode::Fatal,
+            content_hash: ValidationMode::Fatal,
+            whitelist_expiry: ValidationMode::Fatal,
+        }
+    }
+
+    /// Every check is performed but failures are only logged
+    pub fn permissive() -> Self {
+        Self {
+            signature: ValidationMode::Warn,
+            content_hash: ValidationMode::Warn,
+            whitelist_expiry: ValidationMode::Warn,
+        }
+    }
+}
+
+/// Parses `strict`, `permissive` or `default`, optionally followed by
+/// comma separated overrides such as `strict,content_hash=warn`
+impl FromStr for ValidationPolicy {
+    type Err = CvmfsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut policy = Self::default();
+        for setting in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let Some((check, mode)) = setting.split_once('=') else {
+                policy = match setting {
+                    "strict" => Self::strict(),
+                    "permissive" => Self::permissive(),
+                    "default" => Self::default(),
+                    _ => {
+                        return Err(CvmfsError::InvalidConfiguration(format!(
+                            "unknown validation policy {}",
+                            setting
+                        )))
+                    }
+                };
+                continue;
+            };
+            let mode = mode.trim().parse()?;
+            match check.trim() {
+                "signature" => policy.signature = mode,
+                "content_hash" => policy.content_hash = mode,
+                "whitelist_expiry" => policy.whitelist_expiry = mode,
+                _ => {
+                    return Err(CvmfsError::InvalidConfiguration(format!(
+                        "unknown integrity check {}",
+                        check
+                    )))
+                }
+            }
+        }
+        Ok(policy)
+    }
+}
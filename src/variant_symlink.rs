@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+
+use crate::common::{CvmfsError, CvmfsResult};
+
+const PLACEHOLDER_START: &str = "$(";
+/// Separates the name of a placeholder from its default value
+const DEFAULT_SEPARATOR: &str = ":-";
+
+/// Values of the `$(NAME)` and `$(NAME:-default)` placeholders in the targets
+/// of variant symlinks. Names without a value of their own are looked up in
+/// the environment, and expand to the default or to nothing when unset there
+/// too, as in the reference client.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SymlinkVariables {
+    values: HashMap<String, String>,
+}
+
+impl SymlinkVariables {
+    /// Sets a variable from a `NAME=value` assignment
+    pub fn set_assignment(&mut self, assignment: &str) -> CvmfsResult<()> {
+        let (name, value) = assignment
+            .split_once('=')
+            .filter(|(name, _)| !name.is_empty())
+            .ok_or_else(|| {
+                CvmfsError::InvalidConfiguration(format!(
+                    "invalid symlink variable {}, expected NAME=value",
+                    assignment
+                ))
+            })?;
+        self.set(name, value);
+        Ok(())
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) {
+        self.values.insert(name.into(), value.into());
+    }
+
+    /// Value of a variable, falling back to the environment
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.values
+            .get(name)
+            .cloned()
+            .or_else(|| std::env::var(name).ok())
+    }
+
+    /// Replaces the placeholders of a symlink target. An unterminated
+    /// placeholder is kept as it is.
+    pub fn expand(&self, target: &str) -> String {
+        let mut expanded = String::with_capacity(target.len());
+        let mut rest = target;
+        while let Some(start) = rest.find(PLACEHOLDER_START) {
+            let placeholder = &rest[start + PLACEHOLDER_START.len()..];
+            let Some(end) = placeholder.find(')') else {
+                break;
+            };
+            expanded.push_str(&rest[..start]);
+            let (name, default) = match placeholder[..end].split_once(DEFAULT_SEPARATOR) {
+                Some((name, default)) => (name, default),
+                None => (&placeholder[..end], ""),
+            };
+            match self.get(name) {
+                Some(value) => expanded.push_str(&value),
+                None => expanded.push_str(default),
+            }
+            rest = &placeholder[end + 1..];
+        }
+        expanded.push_str(rest);
+        expanded
+    }
+}
@@ -0,0 +1,131 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+
+use crate::common::{CvmfsError, CvmfsResult};
+use crate::master_key::MasterKey;
+use crate::rootfile::RootFile;
+
+const TIMESTAMP_FORMAT: &str = "%Y%m%d%H%M%S";
+
+/// Behavior of a mounted repository once its whitelist expires
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ExpiryPolicy {
+    /// Keep serving the last verified revision from the cache, without updates
+    #[default]
+    ServeFromCache,
+    /// Fail the refresh so the mount can be torn down
+    Unmount,
+}
+
+/// Parses `serve-from-cache` or `unmount`
+impl FromStr for ExpiryPolicy {
+    type Err = CvmfsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "serve-from-cache" => Ok(ExpiryPolicy::ServeFromCache),
+            "unmount" => Ok(ExpiryPolicy::Unmount),
+            _ => Err(CvmfsError::InvalidConfiguration(format!(
+                "invalid whitelist expiry policy {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Wraps information from .cvmfswhitelist
+#[derive(Debug)]
+pub struct Whitelist {
+    pub root_file: RootFile,
+    pub last_modified: DateTime<Utc>,
+    pub expires: DateTime<Utc>,
+    pub repository_name: String,
+    pub fingerprints: Vec<String>,
+}
+
+impl Whitelist {
+    fn parse_timestamp(value: &str) -> CvmfsResult<DateTime<Utc>> {
+        Ok(
+            NaiveDateTime::parse_from_str(value.trim(), TIMESTAMP_FORMAT)
+                .map_err(|_| CvmfsError::InvalidTimestamp)?
+                .and_utc(),
+        )
+    }
+
+    pub fn new(root_file: RootFile) -> CvmfsResult<Self> {
+        let mut last_modified = None;
+        let mut expires = None;
+        let mut repository_name = String::new();
+        let mut fingerprints = Vec::new();
+        for line in root_file.lines() {
+            match line.chars().next() {
+                Some('0'..='9') if last_modified.is_none() => {
+                    last_modified = Some(Self::parse_timestamp(line)?)
+                }
+                // fingerprints may start with `E` too
+                Some(_) if line.contains(':') => {
+                    let fingerprint = line.split('#').next().unwrap_or(line).trim();
+                    fingerprints.push(fingerprint.into());
+                }
+                Some('E') => expires = Some(Self::parse_timestamp(&line[1..])?),
+                Some('N') => repository_name = line[1..].into(),
+                _ => {}
+            }
+        }
+        Ok(Self {
+            root_file,
+            last_modified: last_modified.ok_or(CvmfsError::ParseError)?,
+            expires: expires.ok_or(CvmfsError::ParseError)?,
+            repository_name,
+            fingerprints,
+        })
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires < Utc::now()
+    }
+
+    /// Validates the whitelist timestamps against the local clock, tolerating the
+    /// given clock skew. A whitelist created in the future means the local clock
+    /// is behind, which is reported as such instead of as an invalid whitelist.
+    pub fn validate_timestamps(&self, now: DateTime<Utc>, tolerance: TimeDelta) -> CvmfsResult<()> {
+        if self.last_modified - tolerance > now {
+            return Err(CvmfsError::ClockSkew(format!(
+                "the whitelist of {} was created on {} but the local time is {}",
+                self.repository_name, self.last_modified, now
+            )));
+        }
+        if self.expires + tolerance < now {
+            return Err(CvmfsError::WhitelistExpired);
+        }
+        Ok(())
+    }
+
+    /// Whether a certificate fingerprint is listed, ignoring the case
+    pub fn contains_fingerprint(&self, fingerprint: &str) -> bool {
+        self.fingerprints
+            .iter()
+            .any(|listed| listed.eq_ignore_ascii_case(fingerprint))
+    }
+
+    /// Checks that the whitelist was signed by one of the repository master keys
+    pub fn verify_signature(&self, master_keys: &[MasterKey]) -> CvmfsResult<()> {
+        let checksum = self
+            .root_file
+            .checksum()
+            .ok_or(CvmfsError::IncompleteRootFileSignature)?;
+        let signature = self
+            .root_file
+            .signature()
+            .ok_or(CvmfsError::IncompleteRootFileSignature)?;
+        if master_keys
+            .iter()
+            .any(|key| key.verify(signature, checksum.as_bytes()))
+        {
+            Ok(())
+        } else {
+            Err(CvmfsError::InvalidWhitelistSignature)
+        }
+    }
+}
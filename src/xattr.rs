@@ -0,0 +1,94 @@
+use crate::common::{CvmfsError, CvmfsResult};
+
+pub const SECURITY_CAPABILITY: &str = "security.capability";
+pub const SECURITY_SELINUX: &str = "security.selinux";
+pub const POSIX_ACL_ACCESS: &str = "system.posix_acl_access";
+pub const POSIX_ACL_DEFAULT: &str = "system.posix_acl_default";
+
+/// Namespace of an extended attribute, which decides how queries for it are
+/// answered before touching the repository
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum XattrClass {
+    /// `user.*`, served from the repository
+    User,
+    /// File capabilities, never set on repository files
+    Capability,
+    /// SELinux label, synthesized when a context is configured
+    Selinux,
+    /// POSIX ACLs, not supported at all
+    Acl,
+    /// Any other `security.*` attribute
+    Security,
+    /// Any other `system.*` attribute
+    System,
+    /// `trusted.*` and unknown namespaces
+    Other,
+}
+
+impl XattrClass {
+    pub fn of(name: &str) -> Self {
+        match name {
+            SECURITY_CAPABILITY => XattrClass::Capability,
+            SECURITY_SELINUX => XattrClass::Selinux,
+            POSIX_ACL_ACCESS | POSIX_ACL_DEFAULT => XattrClass::Acl,
+            _ if name.starts_with("user.") => XattrClass::User,
+            _ if name.starts_with("security.") => XattrClass::Security,
+            _ if name.starts_with("system.") => XattrClass::System,
+            _ => XattrClass::Other,
+        }
+    }
+}
+
+/// Answers to the `security.*` and `system.*` queries that kernels and
+/// container tooling issue for every file. A missing attribute is reported as
+/// ENODATA, a whole unsupported feature as EOPNOTSUPP, which callers such as
+/// `ls` or `cp -a` treat as final instead of retrying with fallbacks.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct XattrPolicy {
+    /// Context returned for `security.selinux`, like
+    /// `system_u:object_r:cvmfs_t:s0`. Without it SELinux labels the mount
+    /// from its own policy.
+    pub selinux_context: Option<String>,
+}
+
+impl XattrPolicy {
+    pub fn with_selinux_context(context: &str) -> CvmfsResult<Self> {
+        if context.split(':').count() < 3 || context.contains('\0') {
+            return Err(CvmfsError::InvalidConfiguration(format!(
+                "invalid SELinux context: {}",
+                context
+            )));
+        }
+        Ok(Self {
+            selinux_context: Some(context.into()),
+        })
+    }
+
+    /// Value of an attribute outside the `user.*` namespace, or the errno to
+    /// reply with. `None` means the repository has to be queried.
+    pub fn resolve(&self, name: &str) -> Option<Result<Vec<u8>, i32>> {
+        match XattrClass::of(name) {
+            XattrClass::User => None,
+            XattrClass::Selinux => Some(match &self.selinux_context {
+                Some(context) => {
+                    let mut value = context.clone().into_bytes();
+                    value.push(0);
+                    Ok(value)
+                }
+                None => Err(libc::EOPNOTSUPP),
+            }),
+            XattrClass::Acl | XattrClass::System => Some(Err(libc::EOPNOTSUPP)),
+            XattrClass::Capability | XattrClass::Security | XattrClass::Other => {
+                Some(Err(libc::ENODATA))
+            }
+        }
+    }
+
+    /// Attributes listed on every path besides the `user.*` ones
+    pub fn listed(&self) -> Vec<&'static str> {
+        match self.selinux_context {
+            Some(_) => vec![SECURITY_SELINUX],
+            None => vec![],
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use std::fs;
+use std::time::Duration;
+
+use chrono::DateTime;
+use cvmfs::access_log::{
+    AccessLog, AccessLogConfig, AccessLogTarget, AccessOperation, AccessRecord,
+};
+use cvmfs::common::CvmfsResult;
+
+fn record(path: &str) -> AccessRecord<'_> {
+    AccessRecord {
+        operation: AccessOperation::Open,
+        path,
+        size: 42,
+        cache_hit: true,
+        latency: Duration::from_micros(1500),
+        errno: None,
+    }
+}
+
+#[test]
+fn test_to_json() {
+    let time = DateTime::from_timestamp(0, 0).unwrap();
+    assert_eq!(
+        "{\"time\":\"1970-01-01T00:00:00+00:00\",\"op\":\"open\",\"path\":\"/a \\\"b\\\"\",\
+         \"size\":42,\"cache_hit\":true,\"latency_us\":1500,\"errno\":null}",
+        record("/a \"b\"").to_json(time)
+    );
+}
+
+#[test]
+fn test_parse_target() {
+    assert_eq!(Ok(AccessLogTarget::Syslog), "syslog".parse());
+    assert_eq!(
+        Ok(AccessLogTarget::File("/var/log/cvmfs.log".into())),
+        "/var/log/cvmfs.log".parse()
+    );
+    assert!("".parse::<AccessLogTarget>().is_err());
+}
+
+#[test]
+fn test_rate_limit() -> CvmfsResult<()> {
+    let path = std::env::temp_dir().join("cvmfs_access_log_rate.log");
+    let _ = fs::remove_file(&path);
+    let mut config = AccessLogConfig::new(AccessLogTarget::File(path.clone()));
+    config.max_records_per_second = Some(2);
+    let access_log = AccessLog::open(config)?;
+    for _ in 0..5 {
+        access_log.record(&record("/file"));
+    }
+    assert_eq!(2, fs::read_to_string(&path)?.lines().count());
+    Ok(())
+}
+
+#[test]
+fn test_rotation() -> CvmfsResult<()> {
+    let path = std::env::temp_dir().join("cvmfs_access_log_rotation.log");
+    let rotated = |index: usize| path.with_extension(format!("log.{}", index));
+    for file in [path.clone(), rotated(1), rotated(2), rotated(3)] {
+        let _ = fs::remove_file(file);
+    }
+    let mut config = AccessLogConfig::new(AccessLogTarget::File(path.clone()));
+    config.max_file_size = Some(1);
+    config.rotations = 2;
+    let access_log = AccessLog::open(config)?;
+    for file in ["/first", "/second", "/third", "/fourth"] {
+        access_log.record(&record(file));
+    }
+    assert!(fs::read_to_string(&path)?.contains("/fourth"));
+    assert!(fs::read_to_string(rotated(1))?.contains("/third"));
+    assert!(fs::read_to_string(rotated(2))?.contains("/second"));
+    assert!(!rotated(3).exists());
+    Ok(())
+}
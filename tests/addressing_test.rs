@@ -0,0 +1,137 @@
+//! Property checks of the path hashing and of the catalog lookups built on it.
+//! Failing cases are shrunk by proptest down to a minimal tree.
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use proptest::prelude::*;
+use sha1::{Digest, Sha1};
+
+use cvmfs::catalog::Catalog;
+use cvmfs::common::{path_md5, split_md5, CvmfsResult};
+
+mod common;
+use common::{grow_tree, parent_of, write_catalog, Tree};
+
+const CASES: u32 = 32;
+
+fn name() -> impl Strategy<Value = String> {
+    "[a-z0-9._-]{1,11}".prop_map(|name| {
+        // `.` and `..` are not names of entries
+        if name.chars().all(|c| c == '.') {
+            format!("{}x", name)
+        } else {
+            name
+        }
+    })
+}
+
+/// Tree of paths, whose files hold their own name
+fn tree() -> impl Strategy<Value = Tree> {
+    prop::collection::vec((any::<usize>(), name(), prop::bool::weighted(0.3)), 1..60).prop_map(
+        |entries| {
+            grow_tree(entries.into_iter().map(|(parent, name, is_directory)| {
+                let content = (!is_directory).then(|| name.clone().into_bytes());
+                (parent, name, content)
+            }))
+        },
+    )
+}
+
+/// Writes a catalog holding every path of the tree
+fn build_catalog(name: &str, tree: &Tree) -> CvmfsResult<PathBuf> {
+    let path = std::env::temp_dir().join(format!("cvmfs_addressing_{}.db", name));
+    write_catalog(&path, tree, "", &[], |content| {
+        hex::encode(Sha1::digest(content))
+    })?;
+    Ok(path)
+}
+
+proptest! {
+    #[test]
+    fn test_split_md5_is_little_endian(digest in any::<[u8; 16]>()) {
+        let hash = split_md5(&digest);
+        prop_assert_eq!(
+            i64::from_le_bytes(digest[..8].try_into().unwrap()),
+            hash.hash1
+        );
+        prop_assert_eq!(
+            i64::from_le_bytes(digest[8..].try_into().unwrap()),
+            hash.hash2
+        );
+        let mut round_trip = [0u8; 16];
+        round_trip[..8].copy_from_slice(&hash.hash1.to_le_bytes());
+        round_trip[8..].copy_from_slice(&hash.hash2.to_le_bytes());
+        prop_assert_eq!(digest, round_trip);
+    }
+
+    #[test]
+    fn test_parent_hash_round_trips(parent in "(/[a-z0-9._-]{1,11}){0,5}", child in name()) {
+        let path = format!("{}/{}", parent, child);
+        prop_assert_eq!(parent.as_str(), parent_of(&path));
+        prop_assert_eq!(path_md5(&parent), path_md5(parent_of(&path)));
+        prop_assert_ne!(path_md5(&parent), path_md5(&path));
+    }
+}
+
+proptest! {
+    // every case writes a catalog
+    #![proptest_config(ProptestConfig::with_cases(CASES))]
+
+    #[test]
+    fn test_lookup_round_trips(tree in tree()) {
+        let path = build_catalog("lookup", &tree)?;
+        let catalog = Catalog::new(path.to_str().unwrap().into(), "hash".into())?;
+        for (entry, content) in tree.iter().filter(|(entry, _)| !entry.is_empty()) {
+            let dirent = catalog.find_directory_entry(entry)?;
+            let hash = split_md5(&path_md5(entry));
+            let parent = split_md5(&path_md5(parent_of(entry)));
+            let found = dirent.path_hash();
+            prop_assert_eq!((hash.hash1, hash.hash2), (found.hash1, found.hash2));
+            let found = dirent.parent_hash();
+            prop_assert_eq!((parent.hash1, parent.hash2), (found.hash1, found.hash2));
+            prop_assert_eq!(entry.rsplit('/').next(), Some(dirent.name.as_str()));
+            prop_assert_eq!(content.is_none(), dirent.is_directory());
+            if let Some(content) = content {
+                prop_assert_eq!(content.len() as u64, dirent.size);
+            }
+            // trailing slashes and `.` components address the same entry
+            prop_assert_eq!(
+                &dirent.name,
+                &catalog.find_directory_entry(&format!("{}/./", entry))?.name
+            );
+        }
+        let missing = format!("{}/missing", tree.keys().last().unwrap());
+        prop_assert!(catalog.find_directory_entry(&missing).is_err());
+    }
+
+    #[test]
+    fn test_list_round_trips(tree in tree()) {
+        let path = build_catalog("list", &tree)?;
+        let catalog = Catalog::new(path.to_str().unwrap().into(), "hash".into())?;
+        for (directory, _) in tree.iter().filter(|(_, content)| content.is_none()) {
+            let expected: BTreeSet<&str> = tree
+                .keys()
+                .filter(|entry| !entry.is_empty() && parent_of(entry) == directory)
+                .filter_map(|entry| entry.rsplit('/').next())
+                .collect();
+            let listed =
+                catalog.list_directory(if directory.is_empty() { "/" } else { directory })?;
+            let names: Vec<&str> = listed.iter().map(|dirent| dirent.name.as_str()).collect();
+            prop_assert!(names.is_sorted(), "{:?}", names);
+            prop_assert_eq!(expected, names.into_iter().collect::<BTreeSet<_>>());
+            let hash = split_md5(&path_md5(directory));
+            for dirent in &listed {
+                let parent = dirent.parent_hash();
+                prop_assert_eq!((hash.hash1, hash.hash2), (parent.hash1, parent.hash2));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_split_md5_sign() {
+    // digests with the top bit set are stored as negative numbers
+    let hash = split_md5(&[0xff; 16]);
+    assert_eq!((-1, -1), (hash.hash1, hash.hash2));
+}
@@ -0,0 +1,62 @@
+use std::fs;
+
+use cvmfs::analytics::{Analytics, PathUsage};
+use cvmfs::common::CvmfsResult;
+
+#[test]
+fn test_subtree_of() {
+    let analytics = Analytics::default();
+    assert_eq!("/", analytics.subtree_of("/README"));
+    assert_eq!("/", analytics.subtree_of("/"));
+    assert_eq!("/sw", analytics.subtree_of("/sw/setup.sh"));
+    assert_eq!("/sw/x86_64", analytics.subtree_of("/sw/x86_64/gcc/bin/gcc"));
+    assert_eq!("/sw", Analytics::new(1).subtree_of("/sw/x86_64/gcc"));
+}
+
+#[test]
+fn test_report() -> CvmfsResult<()> {
+    let analytics = Analytics::default();
+    analytics.record_open("/sw/x86_64/lib.so", false);
+    analytics.record_read("/sw/x86_64/lib.so", 4096);
+    analytics.record_open("/sw/x86_64/lib.so", true);
+    analytics.record_read("/sw/x86_64/lib.so", 1024);
+    analytics.record_open("/sw/setup.sh", true);
+    analytics.record_open("/data/run1/events", false);
+    analytics.record_read("/data/run1/events", 100);
+
+    let report = analytics.report(2)?;
+    assert_eq!(
+        PathUsage {
+            opens: 4,
+            bytes: 5220,
+            cache_hits: 2
+        },
+        report.total
+    );
+    assert_eq!(3, report.tracked_paths);
+    let top: Vec<&str> = report.top_paths.iter().map(|(p, _)| p.as_str()).collect();
+    assert_eq!(vec!["/sw/x86_64/lib.so", "/data/run1/events"], top);
+    assert_eq!(0.5, report.top_paths[0].1.cache_efficiency());
+    let subtrees: Vec<&str> = report.subtrees.iter().map(|(p, _)| p.as_str()).collect();
+    assert_eq!(vec!["/sw/x86_64", "/data/run1", "/sw"], subtrees);
+    assert_eq!(1.0, report.subtrees[2].1.cache_efficiency());
+
+    let json = report.to_json();
+    assert!(json.starts_with("{\"total\":{\"path\":\"/\",\"opens\":4,\"bytes\":5220"));
+    assert!(json.contains("\"cache_efficiency\":0.500"));
+    assert!(!json.contains('\n'));
+    Ok(())
+}
+
+#[test]
+fn test_write_report() -> CvmfsResult<()> {
+    let path = std::env::temp_dir().join("cvmfs_analytics_test.json");
+    let analytics = Analytics::default();
+    assert_eq!(0.0, analytics.report(10)?.total.cache_efficiency());
+    analytics.record_open("/a", true);
+    analytics.write_report(&path, 10)?;
+    let written = fs::read_to_string(&path)?;
+    assert_eq!(analytics.report(10)?.to_json() + "\n", written);
+    fs::remove_file(path)?;
+    Ok(())
+}
@@ -0,0 +1,77 @@
+#![cfg(feature = "async")]
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+
+use cvmfs::async_fetcher::{runtime, AsyncFetcher};
+use cvmfs::common::{CvmfsError, CvmfsResult};
+use cvmfs::fetcher::Fetcher;
+
+mod common;
+use common::zlib_stored;
+
+#[test]
+fn test_retrieve_files() -> CvmfsResult<()> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut request = [0u8; 1024];
+            let length = stream.read(&mut request).unwrap_or_default();
+            let request = String::from_utf8_lossy(&request[..length]);
+            let path = request.split_whitespace().nth(1).unwrap_or_default();
+            let (status, body) = match path {
+                path if path.ends_with("missing") => ("404 Not Found", Vec::new()),
+                path => ("200 OK", zlib_stored(path.as_bytes())),
+            };
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                body.len()
+            );
+            let _ = stream.write_all(&body);
+        }
+    });
+
+    let directory = std::env::temp_dir().join("cvmfs_async_fetcher_test");
+    let _ = std::fs::remove_dir_all(&directory);
+    let fetcher = AsyncFetcher::new(Fetcher::new(
+        &format!("http://127.0.0.1:{}", port),
+        directory.to_str().unwrap(),
+        true,
+    )?);
+    std::fs::write(directory.join("data/00/cached"), "cached")?;
+    let file_names: Vec<String> = ["data/00/cached", "data/01/object", "data/02/missing"]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+    let results = fetcher.retrieve_files_blocking(&file_names, 2)?;
+    assert_eq!(
+        "cached",
+        std::fs::read_to_string(results[0].as_ref().unwrap())?
+    );
+    assert_eq!(
+        "/data/01/object",
+        std::fs::read_to_string(results[1].as_ref().unwrap())?
+    );
+    assert!(matches!(results[2], Err(CvmfsError::IO(_))));
+    let path = runtime()?.block_on(fetcher.retrieve_file("data/03/object"))?;
+    assert_eq!("/data/03/object", std::fs::read_to_string(path)?);
+    Ok(())
+}
+
+#[test]
+fn test_offline() -> CvmfsResult<()> {
+    let directory = std::env::temp_dir().join("cvmfs_async_offline_test");
+    let fetcher = AsyncFetcher::new(Fetcher::new(
+        "http://127.0.0.1:1",
+        directory.to_str().unwrap(),
+        true,
+    )?);
+    let result = runtime()?.block_on(fetcher.retrieve_file("data/00/missing"));
+    assert!(matches!(result, Err(CvmfsError::Offline(_))));
+    assert!(fetcher.fetcher().is_offline());
+    Ok(())
+}
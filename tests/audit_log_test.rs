@@ -0,0 +1,62 @@
+use chrono::{TimeZone, Utc};
+
+use cvmfs::audit_log::{AuditKind, AuditLog, AuditRecord};
+use cvmfs::common::{CvmfsError, CvmfsResult};
+
+#[test]
+fn test_audit_kind() {
+    assert_eq!(
+        AuditKind::Catalog,
+        AuditKind::of_object("data/60/0230b0ba7620426f2e898f1e1f43c5466efe59C")
+    );
+    assert_eq!(
+        AuditKind::Object,
+        AuditKind::of_object("data/60/0230b0ba7620426f2e898f1e1f43c5466efe59")
+    );
+}
+
+#[test]
+fn test_record_to_json() {
+    let time = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+    let record = AuditRecord {
+        kind: AuditKind::Manifest,
+        name: ".cvmfspublished",
+        hash: "abc",
+        source: "cvmfs-stratum-one.cern.ch",
+        error: None,
+    };
+    assert_eq!(
+        "{\"time\":\"2024-05-01T12:00:00+00:00\",\"kind\":\"manifest\",\"name\":\".cvmfspublished\",\"hash\":\"abc\",\"source\":\"cvmfs-stratum-one.cern.ch\",\"result\":\"ok\",\"error\":null}",
+        record.to_json(time)
+    );
+    let error = CvmfsError::InvalidWhitelistSignature;
+    let record = AuditRecord {
+        kind: AuditKind::Whitelist,
+        error: Some(&error),
+        ..record
+    };
+    assert!(record
+        .to_json(time)
+        .ends_with("\"result\":\"failed\",\"error\":\"Invalid whitelist signature\"}"));
+}
+
+#[test]
+fn test_append_only() -> CvmfsResult<()> {
+    let path = std::env::temp_dir().join("cvmfs_audit_log_test.log");
+    let _ = std::fs::remove_file(&path);
+    let record = AuditRecord {
+        kind: AuditKind::Object,
+        name: "data/ab/cdef",
+        hash: "abcdef",
+        source: "localhost",
+        error: None,
+    };
+    AuditLog::open(&path)?.record(&record);
+    AuditLog::open(&path)?.record(&record);
+    let contents = std::fs::read_to_string(&path)?;
+    assert_eq!(2, contents.lines().count());
+    assert!(contents
+        .lines()
+        .all(|line| line.contains("\"kind\":\"object\"")));
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use std::path::Path;
+
+use cvmfs::autofs::{helper_args, is_mounted, is_valid_key, map_entry};
+use cvmfs::common::CvmfsResult;
+
+#[test]
+fn test_valid_keys() {
+    assert!(is_valid_key("atlas"));
+    assert!(is_valid_key("sft.cern.ch"));
+    assert!(is_valid_key("alice-ocdb"));
+    assert!(!is_valid_key(""));
+    assert!(!is_valid_key(".git"));
+    assert!(!is_valid_key("a..b"));
+    assert!(!is_valid_key("a/b"));
+    assert!(!is_valid_key("a b"));
+}
+
+#[test]
+fn test_map_entry() -> CvmfsResult<()> {
+    assert_eq!(
+        "-fstype=cvmfs :atlas.cern.ch",
+        map_entry("atlas", "cern.ch", &[])?
+    );
+    assert_eq!(
+        "-fstype=cvmfs :sft.cern.ch",
+        map_entry("sft.cern.ch", "cern.ch", &[])?
+    );
+    let allowed = vec!["atlas".to_string(), "cms.cern.ch".to_string()];
+    assert!(map_entry("atlas.cern.ch", "cern.ch", &allowed).is_ok());
+    assert!(map_entry("cms", "cern.ch", &allowed).is_ok());
+    assert!(map_entry("lhcb", "cern.ch", &allowed).is_err());
+    assert!(map_entry(".hidden", "cern.ch", &[]).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_helper_args() -> CvmfsResult<()> {
+    let args: Vec<String> = "atlas.cern.ch /cvmfs/atlas.cern.ch -o rw,nodev,nosuid -n"
+        .split_whitespace()
+        .map(String::from)
+        .collect();
+    assert_eq!(
+        vec![
+            "http://cvmfs.example.org/cvmfs/@fqrn@",
+            "/cvmfs/atlas.cern.ch",
+            "/var/lib/cvmfs",
+            "--repository",
+            "atlas.cern.ch",
+            "--fuse-options",
+            "ro,nodev,nosuid",
+        ],
+        helper_args(
+            &args,
+            "http://cvmfs.example.org/cvmfs/@fqrn@",
+            Some("/var/lib/cvmfs")
+        )?
+    );
+    assert!(helper_args(&args[..1], "http://localhost", None).is_err());
+    assert!(helper_args(&args[..3], "http://localhost", None).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_is_mounted() {
+    let mountinfo = "22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw\n\
+                     45 22 0:40 / /cvmfs/atlas.cern.ch ro,nodev,nosuid shared:2 - fuse cernvmfs ro\n\
+                     46 22 0:41 / /mnt/with\\040space rw shared:3 - tmpfs tmpfs rw\n";
+    assert!(is_mounted(mountinfo, Path::new("/cvmfs/atlas.cern.ch")));
+    assert!(is_mounted(mountinfo, Path::new("/mnt/with space")));
+    assert!(!is_mounted(mountinfo, Path::new("/cvmfs/cms.cern.ch")));
+}
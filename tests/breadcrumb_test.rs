@@ -0,0 +1,37 @@
+use cvmfs::breadcrumb::Breadcrumb;
+use cvmfs::cache::Cache;
+use cvmfs::common::CvmfsResult;
+
+#[test]
+fn test_breadcrumb_round_trip() -> CvmfsResult<()> {
+    let breadcrumb: Breadcrumb =
+        "600230b0ba7620426f2e898f1e1f43c5466efe59T1700000000R42".parse()?;
+    assert_eq!(
+        "600230b0ba7620426f2e898f1e1f43c5466efe59",
+        breadcrumb.catalog_hash
+    );
+    assert_eq!(1700000000, breadcrumb.timestamp);
+    assert_eq!(42, breadcrumb.revision);
+    assert_eq!(
+        "600230b0ba7620426f2e898f1e1f43c5466efe59T1700000000R42",
+        breadcrumb.to_string()
+    );
+    assert!("garbage".parse::<Breadcrumb>().is_err());
+    Ok(())
+}
+
+#[test]
+fn test_breadcrumb_in_cache() -> CvmfsResult<()> {
+    let directory = std::env::temp_dir().join("cvmfs_breadcrumb_test");
+    std::fs::create_dir_all(&directory)?;
+    let cache = Cache::new(directory.to_string_lossy().into_owned())?;
+    let breadcrumb = Breadcrumb {
+        catalog_hash: "abc".into(),
+        timestamp: 10,
+        revision: 3,
+    };
+    cache.store_breadcrumb("test.cern.ch", &breadcrumb)?;
+    assert_eq!(Some(breadcrumb), cache.load_breadcrumb("test.cern.ch"));
+    assert_eq!(None, cache.load_breadcrumb("other.cern.ch"));
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use cvmfs::cache::Cache;
+use cvmfs::common::CvmfsResult;
+
+#[test]
+fn test_pinned_tag() -> CvmfsResult<()> {
+    let directory = std::env::temp_dir().join("cvmfs_pinned_tag_test");
+    std::fs::create_dir_all(&directory)?;
+    let cache = Cache::new(directory.to_string_lossy().into_owned())?;
+    cache.remove_pinned_tag("test.cern.ch")?;
+    assert_eq!(None, cache.load_pinned_tag("test.cern.ch"));
+    cache.store_pinned_tag("test.cern.ch", "generic-2")?;
+    assert_eq!(
+        Some("generic-2".to_string()),
+        cache.load_pinned_tag("test.cern.ch")
+    );
+    assert_eq!(None, cache.load_pinned_tag("other.cern.ch"));
+    cache.remove_pinned_tag("test.cern.ch")?;
+    assert_eq!(None, cache.load_pinned_tag("test.cern.ch"));
+    Ok(())
+}
+
+#[test]
+fn test_fallback_directory() -> CvmfsResult<()> {
+    let fallback = std::env::temp_dir().join("cvmfs_fallback_cache_test");
+    let _ = std::fs::remove_dir_all(&fallback);
+    // not even root can create directories in sysfs
+    let cache = Cache::with_fallback(
+        "/sys/cvmfs_cache_test".into(),
+        fallback.to_string_lossy().into_owned(),
+    )?;
+    cache.initialize()?;
+    assert!(cache.failover_status().active);
+    let stored = cache.store("data/ab/cdef", b"content")?;
+    assert!(stored.starts_with(&fallback));
+    assert_eq!(Some(stored), cache.get("data/ab/cdef"));
+    let status = cache.failover_status();
+    assert_eq!(1, status.primary_errors);
+    assert_eq!(1, status.fallback_writes);
+    assert!(Cache::new("/sys/cvmfs_cache_test".into())?
+        .initialize()
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_quarantine() -> CvmfsResult<()> {
+    use cvmfs::cache::QuarantineRecord;
+
+    let directory = std::env::temp_dir().join("cvmfs_quarantine_test");
+    let _ = std::fs::remove_dir_all(&directory);
+    let cache = Cache::new(directory.to_string_lossy().into_owned())?;
+    cache.initialize()?;
+    assert!(cache.quarantined_objects()?.is_empty());
+
+    let record = QuarantineRecord::new(
+        "http://localhost/cvmfs/repo/data/ab/cdef",
+        "abcdef",
+        "012345",
+    );
+    let downloaded = cache.quarantine_content("data/ab/cdef", b"corrupt", &record)?;
+    assert!(downloaded
+        .file_name()
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .ends_with("-abcdef"));
+    assert_eq!(b"corrupt".to_vec(), std::fs::read(&downloaded)?);
+    let metadata = std::fs::read_to_string(format!("{}.json", downloaded.display()))?;
+    assert!(metadata.contains("\"expected_hash\":\"abcdef\""));
+    assert!(metadata.contains("\"actual_hash\":\"012345\""));
+    assert!(metadata.contains("\"url\":\"http://localhost/cvmfs/repo/data/ab/cdef\""));
+
+    let cached = cache.store("data/12/3456", b"rotten")?;
+    let record = QuarantineRecord::new("file:///cache/data/12/3456", "123456", "789abc");
+    let moved = cache.quarantine_object(&cached, &record)?;
+    assert!(!cached.exists());
+    assert_eq!(b"rotten".to_vec(), std::fs::read(&moved)?);
+    assert_eq!(2, cache.quarantined_objects()?.len());
+
+    cache.evict()?;
+    assert_eq!(2, cache.quarantined_objects()?.len());
+    Ok(())
+}
+
+#[test]
+fn test_concurrent_stores() -> CvmfsResult<()> {
+    use cvmfs::cache::is_partial_file;
+
+    let directory = std::env::temp_dir().join("cvmfs_concurrent_stores_test");
+    let _ = std::fs::remove_dir_all(&directory);
+    let cache = Cache::new(directory.to_string_lossy().into_owned())?;
+    cache.initialize()?;
+    let content = vec![7u8; 1 << 20];
+    std::thread::scope(|scope| {
+        let writers: Vec<_> = (0..8)
+            .map(|_| scope.spawn(|| cache.store("data/ab/cdef", &content)))
+            .collect();
+        for writer in writers {
+            assert!(writer.join().unwrap().is_ok());
+        }
+    });
+    let stored = cache.get("data/ab/cdef").unwrap();
+    assert_eq!(content, std::fs::read(&stored)?);
+    // every writer renamed its own temporary file
+    for entry in std::fs::read_dir(stored.parent().unwrap())? {
+        assert!(!is_partial_file(&entry?.path()));
+    }
+    assert!(is_partial_file("data/ab/cdef.partial.1.0".as_ref()));
+    Ok(())
+}
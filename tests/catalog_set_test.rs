@@ -0,0 +1,30 @@
+use cvmfs::cache::Cache;
+use cvmfs::catalog_set::CatalogSet;
+use cvmfs::common::CvmfsResult;
+
+#[test]
+fn test_catalog_set_round_trip() -> CvmfsResult<()> {
+    let catalog_set = CatalogSet {
+        root_hash: "root".into(),
+        catalog_hashes: vec!["root".into(), "nested1".into(), "nested2".into()],
+    };
+    let parsed: CatalogSet = catalog_set.to_string().parse()?;
+    assert_eq!(catalog_set, parsed);
+    assert!("".parse::<CatalogSet>().is_err());
+    Ok(())
+}
+
+#[test]
+fn test_catalog_set_in_cache() -> CvmfsResult<()> {
+    let directory = std::env::temp_dir().join("cvmfs_catalog_set_test");
+    std::fs::create_dir_all(&directory)?;
+    let cache = Cache::new(directory.to_string_lossy().into_owned())?;
+    let catalog_set = CatalogSet {
+        root_hash: "root".into(),
+        catalog_hashes: vec!["nested".into()],
+    };
+    cache.store_catalog_set("test.cern.ch", &catalog_set)?;
+    assert_eq!(Some(catalog_set), cache.load_catalog_set("test.cern.ch"));
+    assert_eq!(None, cache.load_catalog_set("other.cern.ch"));
+    Ok(())
+}
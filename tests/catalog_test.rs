@@ -0,0 +1,189 @@
+use cvmfs::catalog::Catalog;
+use cvmfs::common::CvmfsResult;
+use rusqlite::Connection;
+
+#[test]
+fn test_minimal_catalog() -> CvmfsResult<()> {
+    let path = std::env::temp_dir().join("cvmfs_minimal_catalog.db");
+    let _ = std::fs::remove_file(&path);
+    Connection::open(&path)?.execute_batch(
+        "CREATE TABLE properties (key TEXT, value TEXT);
+         INSERT INTO properties VALUES ('revision', '3'), ('schema', '2.5');
+         CREATE TABLE catalog (md5path_1 INTEGER, md5path_2 INTEGER, parent_1 INTEGER, \
+         parent_2 INTEGER, hash BLOB, flags INTEGER, size INTEGER, mode INTEGER, \
+         mtime INTEGER, name TEXT, symlink TEXT);",
+    )?;
+    let catalog = Catalog::new(path.to_str().unwrap().into(), "hash".into())?;
+    assert!(!catalog.has_statistics);
+    assert!(!catalog.has_chunks);
+    assert!(!catalog.has_nested_catalogs);
+    assert_eq!(0, catalog.get_statistics()?.regular);
+    assert_eq!(0, catalog.nested_count()?);
+    assert!(catalog.list_nested()?.is_empty());
+    assert!(catalog.find_nested_for_path("/some/path")?.is_none());
+    assert!(catalog.list_directory("/")?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_legacy_catalog() -> CvmfsResult<()> {
+    let path = std::env::temp_dir().join("cvmfs_legacy_catalog.db");
+    let _ = std::fs::remove_file(&path);
+    Connection::open(&path)?.execute_batch(
+        "CREATE TABLE properties (key TEXT, value TEXT);
+         INSERT INTO properties VALUES ('revision', '12'), ('schema', '0.9');
+         CREATE TABLE catalog (md5path_1 INTEGER, md5path_2 INTEGER, parent_1 INTEGER, \
+         parent_2 INTEGER, inode INTEGER, hash BLOB, size INTEGER, mode INTEGER, \
+         mtime INTEGER, flags INTEGER, name TEXT);
+         CREATE TABLE nested_catalogs (path TEXT, sha1 TEXT);
+         INSERT INTO nested_catalogs VALUES ('/nested', 'abcdef');",
+    )?;
+    let catalog = Catalog::new(path.to_str().unwrap().into(), "hash".into())?;
+    assert!(catalog.is_legacy);
+    assert!(catalog.list_directory("/")?.is_empty());
+    let nested = catalog.list_nested()?;
+    assert_eq!(1, nested.len());
+    assert_eq!("/nested", nested[0].root_path);
+    assert_eq!(0, nested[0].catalog_size);
+    let nested = catalog.find_nested_for_path("/nested/file")?.unwrap();
+    assert_eq!("abcdef", nested.catalog_hash);
+    Ok(())
+}
+
+#[test]
+fn test_performance_counters() -> CvmfsResult<()> {
+    use cvmfs::catalog::CatalogQuery;
+    use std::time::Duration;
+
+    let path = std::env::temp_dir().join("cvmfs_counted_catalog.db");
+    let _ = std::fs::remove_file(&path);
+    Connection::open(&path)?.execute_batch(
+        "CREATE TABLE properties (key TEXT, value TEXT);
+         INSERT INTO properties VALUES ('revision', '3'), ('schema', '2.5');
+         CREATE TABLE catalog (md5path_1 INTEGER, md5path_2 INTEGER, parent_1 INTEGER, \
+         parent_2 INTEGER, hash BLOB, flags INTEGER, size INTEGER, mode INTEGER, \
+         mtime INTEGER, name TEXT, symlink TEXT);
+         CREATE TABLE nested_catalogs (path TEXT, sha1 TEXT);",
+    )?;
+    let mut catalog = Catalog::new(path.to_str().unwrap().into(), "hash".into())?;
+    assert_eq!(0, catalog.performance_counters().total_queries());
+    catalog.slow_query_threshold = Duration::ZERO;
+    assert!(catalog.find_directory_entry("/missing").is_err());
+    assert_eq!(2, catalog.find_directory_entries(&["/a", "/b"]).len());
+    assert!(catalog.list_directory("/")?.is_empty());
+    assert!(catalog.list_nested()?.is_empty());
+
+    let counters = catalog.performance_counters();
+    let find = counters.get(CatalogQuery::Find);
+    assert_eq!(3, find.count);
+    assert_eq!(3, find.slow);
+    assert!(find.max >= find.mean());
+    assert_eq!(1, counters.get(CatalogQuery::Listing).count);
+    assert_eq!(1, counters.get(CatalogQuery::Nested).count);
+    assert_eq!(0, counters.get(CatalogQuery::Chunks).count);
+    assert_eq!(5, counters.total_queries());
+
+    let mut merged = counters.clone();
+    merged.merge(&counters);
+    assert_eq!(10, merged.total_queries());
+    assert_eq!(find.max, merged.get(CatalogQuery::Find).max);
+    Ok(())
+}
+
+#[test]
+fn test_hardlinks() -> CvmfsResult<()> {
+    use cvmfs::common::{path_md5, split_md5};
+
+    let path = std::env::temp_dir().join("cvmfs_hardlinks_catalog.db");
+    let _ = std::fs::remove_file(&path);
+    let connection = Connection::open(&path)?;
+    connection.execute_batch(
+        "CREATE TABLE properties (key TEXT, value TEXT);
+         INSERT INTO properties VALUES ('revision', '3'), ('schema', '2.5');
+         CREATE TABLE catalog (md5path_1 INTEGER, md5path_2 INTEGER, parent_1 INTEGER, \
+         parent_2 INTEGER, hardlinks INTEGER, hash BLOB, flags INTEGER, size INTEGER, \
+         mode INTEGER, mtime INTEGER, name TEXT, symlink TEXT);",
+    )?;
+    let parent = split_md5(&path_md5("/dir"));
+    for (name, flags, hardlinks) in [
+        ("dir", 1, 0),
+        ("a", 4, (7i64 << 32) | 2),
+        ("b", 4, (7i64 << 32) | 2),
+        ("c", 4, 1),
+    ] {
+        let full_path = if name == "dir" {
+            "/dir".to_string()
+        } else {
+            format!("/dir/{}", name)
+        };
+        let hash = split_md5(&path_md5(&full_path));
+        connection.execute(
+            "INSERT INTO catalog VALUES (?, ?, ?, ?, ?, NULL, ?, 0, 420, 0, ?, NULL)",
+            rusqlite::params![
+                hash.hash1,
+                hash.hash2,
+                parent.hash1,
+                parent.hash2,
+                hardlinks,
+                flags,
+                name
+            ],
+        )?;
+    }
+    let catalog = Catalog::new(path.to_str().unwrap().into(), "hash".into())?;
+    let directory = catalog.find_directory_entry("/dir")?;
+    assert_eq!(0, directory.link_count);
+    assert_eq!(2, directory.nlink());
+    let a = catalog.find_directory_entry("/dir/a")?;
+    let b = catalog.find_directory_entry("/dir/b")?;
+    let c = catalog.find_directory_entry("/dir/c")?;
+    assert_eq!((2, 7), (a.nlink(), a.hardlink_group));
+    assert_eq!(a.inode(), b.inode());
+    assert_eq!((1, 0), (c.nlink(), c.hardlink_group));
+    assert_ne!(a.inode(), c.inode());
+    assert_eq!(c.md5_path_1 as u64, c.inode());
+    Ok(())
+}
+
+#[test]
+fn test_special_files() -> CvmfsResult<()> {
+    use cvmfs::cli::mode_string;
+    use cvmfs::common::{path_md5, split_md5};
+    use cvmfs::directory_entry::SpecialKind;
+
+    let path = std::env::temp_dir().join("cvmfs_special_catalog.db");
+    let _ = std::fs::remove_file(&path);
+    let connection = Connection::open(&path)?;
+    connection.execute_batch(
+        "CREATE TABLE properties (key TEXT, value TEXT);
+         INSERT INTO properties VALUES ('revision', '3'), ('schema', '2.5');
+         CREATE TABLE catalog (md5path_1 INTEGER, md5path_2 INTEGER, parent_1 INTEGER, \
+         parent_2 INTEGER, hash BLOB, flags INTEGER, size INTEGER, mode INTEGER, \
+         mtime INTEGER, name TEXT, symlink TEXT);",
+    )?;
+    // major 1, minor 3
+    let null_device = 0x103;
+    for (name, flags, size, mode) in [
+        ("null", 4 | 16, null_device, 0o020666),
+        ("pipe", 4 | 16, 0, 0o010644),
+        ("file", 4, 12, 0o100644),
+    ] {
+        let hash = split_md5(&path_md5(&format!("/{}", name)));
+        connection.execute(
+            "INSERT INTO catalog VALUES (?, ?, 0, 0, NULL, ?, ?, ?, 0, ?, NULL)",
+            rusqlite::params![hash.hash1, hash.hash2, flags, size, mode, name],
+        )?;
+    }
+    let catalog = Catalog::new(path.to_str().unwrap().into(), "hash".into())?;
+    let null = catalog.find_directory_entry("/null")?;
+    assert_eq!(Some(SpecialKind::CharDevice), null.special_kind());
+    assert_eq!((0, null_device), (null.size, null.rdev));
+    assert_eq!("crw-rw-rw-", mode_string(&null));
+    let pipe = catalog.find_directory_entry("/pipe")?;
+    assert_eq!(Some(SpecialKind::NamedPipe), pipe.special_kind());
+    assert_eq!("prw-r--r--", mode_string(&pipe));
+    let file = catalog.find_directory_entry("/file")?;
+    assert!(file.special_kind().is_none());
+    assert_eq!((12, 0), (file.size, file.rdev));
+    Ok(())
+}
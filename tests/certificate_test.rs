@@ -0,0 +1,38 @@
+use cvmfs::certificate::Certificate;
+use cvmfs::common::CvmfsResult;
+
+const MESSAGE: &[u8] = include_bytes!("fixtures/signed_message.txt");
+
+#[test]
+fn test_verify_rsa_signature() -> CvmfsResult<()> {
+    let certificate = Certificate::from_pem(include_bytes!("fixtures/rsa_certificate.pem"))?;
+    let signature = include_bytes!("fixtures/rsa_signature.bin");
+    assert!(certificate.verify(signature, MESSAGE));
+    assert!(!certificate.verify(signature, b"tampered message"));
+    Ok(())
+}
+
+#[test]
+fn test_verify_ecdsa_signature() -> CvmfsResult<()> {
+    let certificate = Certificate::from_pem(include_bytes!("fixtures/ecdsa_certificate.pem"))?;
+    let signature = include_bytes!("fixtures/ecdsa_signature.bin");
+    assert!(certificate.verify(signature, MESSAGE));
+    assert!(!certificate.verify(include_bytes!("fixtures/rsa_signature.bin"), MESSAGE));
+    Ok(())
+}
+
+#[test]
+fn test_fingerprint() -> CvmfsResult<()> {
+    let certificate = Certificate::from_pem(include_bytes!("fixtures/rsa_certificate.pem"))?;
+    assert_eq!(
+        "3F:E3:A4:E7:F0:B3:3F:0C:63:82:9C:6B:FD:20:31:AD:8E:4E:02:DB",
+        certificate.fingerprint()?
+    );
+    Ok(())
+}
+
+#[test]
+fn test_invalid_certificate() {
+    assert!(Certificate::from_pem(b"not a certificate").is_err());
+    assert!(Certificate::try_from(&b"garbage"[..]).is_err());
+}
@@ -0,0 +1,174 @@
+use clap::Parser;
+use cvmfs::cli::{mode_string, Cli, Command, RepositoryArgs};
+use cvmfs::directory_entry::DirectoryEntry;
+use cvmfs::mount_config::MountArgs;
+
+fn parse(line: &str) -> Result<Command, clap::Error> {
+    Cli::try_parse_from(std::iter::once("cvmfs").chain(line.split_whitespace()))
+        .map(Cli::into_command)
+}
+
+fn mount_args(line: &str) -> MountArgs {
+    MountArgs::parse_from(std::iter::once("mount").chain(line.split_whitespace()))
+}
+
+#[test]
+fn test_parse_mount() -> Result<(), clap::Error> {
+    // the subcommand can be left out when mounting
+    assert_eq!(
+        Command::Mount(Box::new(mount_args(
+            "http://localhost/cvmfs/repo /mnt --threads 2"
+        ))),
+        parse("http://localhost/cvmfs/repo /mnt --threads 2")?
+    );
+    assert_eq!(
+        Command::Mount(Box::new(mount_args("http://localhost/cvmfs/repo /mnt"))),
+        parse("mount http://localhost/cvmfs/repo /mnt")?
+    );
+    assert_eq!(
+        Command::MountHelper {
+            args: vec![
+                "atlas.cern.ch".into(),
+                "/cvmfs/atlas.cern.ch".into(),
+                "-o".into(),
+                "ro".into()
+            ]
+        },
+        parse("mount-helper atlas.cern.ch /cvmfs/atlas.cern.ch -o ro")?
+    );
+    assert_eq!(
+        Command::Automount {
+            key: "atlas".into()
+        },
+        parse("automount atlas")?
+    );
+    assert_eq!(
+        Command::Compare {
+            first: "http://a/cvmfs/repo".into(),
+            second: "http://b/cvmfs/repo".into(),
+            cache_directory: None,
+        },
+        parse("compare http://a/cvmfs/repo http://b/cvmfs/repo")?
+    );
+    for line in [
+        "",
+        "http://localhost/cvmfs/repo",
+        "automount",
+        "automount a b",
+        "compare http://a/cvmfs/repo",
+        "http://localhost/cvmfs/repo /mnt --unknown value",
+    ] {
+        assert!(parse(line).is_err(), "{}", line);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_parse_inspection() -> Result<(), clap::Error> {
+    let url = "http://localhost/cvmfs/repo";
+    let repository = RepositoryArgs {
+        cache_directory: "/tmp/cache".into(),
+        ..RepositoryArgs::new(url)
+    };
+    assert_eq!(
+        Command::Info(repository.clone()),
+        parse(&format!("info {} --cache-dir /tmp/cache", url))?
+    );
+    assert_eq!(
+        Command::List {
+            repository: repository.clone(),
+            path: "/".into(),
+            long: true,
+        },
+        parse(&format!("ls -l {} --cache-dir /tmp/cache", url))?
+    );
+    assert_eq!(
+        Command::Cat {
+            repository: RepositoryArgs {
+                revision: Some(3),
+                insecure: true,
+                ..repository.clone()
+            },
+            path: "/sw/README".into()
+        },
+        parse(&format!(
+            "cat {} /sw/README --cache-dir /tmp/cache --revision 3 --insecure",
+            url
+        ))?
+    );
+    assert_eq!(
+        Command::Stat {
+            repository: RepositoryArgs {
+                tag: Some("v1".into()),
+                ..repository
+            },
+            path: "/sw".into()
+        },
+        parse(&format!("stat --tag v1 {} /sw --cache-dir /tmp/cache", url))?
+    );
+    assert_eq!(
+        Command::Diff {
+            repository: RepositoryArgs {
+                insecure: true,
+                ..RepositoryArgs::new(url)
+            },
+            old: "v1".into(),
+            new: "v2".into()
+        },
+        parse(&format!("diff {} v1 --insecure v2", url))?
+    );
+    assert_eq!(
+        Command::Export {
+            repository: RepositoryArgs {
+                tag: Some("v1".into()),
+                ..RepositoryArgs::new(url)
+            },
+            path: "/sw".into(),
+            destination: "/tmp/sw.tar".into()
+        },
+        parse(&format!("export {} /sw /tmp/sw.tar --tag v1", url))?
+    );
+    assert_eq!(
+        Command::Preload {
+            repository: RepositoryArgs::new(url),
+            paths: vec!["/sw".into(), "/data".into()],
+            spec_file: Some("/etc/preload".into()),
+        },
+        parse(&format!("preload {} /sw --spec /etc/preload /data", url))?
+    );
+    assert_eq!(
+        Command::Preload {
+            repository: RepositoryArgs::new(url),
+            paths: Vec::new(),
+            spec_file: Some("/etc/preload".into()),
+        },
+        parse(&format!("preload {} --spec /etc/preload", url))?
+    );
+    for line in [
+        "preload http://localhost/cvmfs/repo",
+        "preload http://localhost/cvmfs/repo /sw --spec",
+        "ls http://localhost/cvmfs/repo --spec /etc/preload",
+        "diff http://localhost/cvmfs/repo v1",
+        "export http://localhost/cvmfs/repo /sw",
+        "info",
+        "info http://localhost/cvmfs/repo /sw",
+        "lookup http://localhost/cvmfs/repo",
+        "cat http://localhost/cvmfs/repo /a /b",
+        "tags http://localhost/cvmfs/repo -l",
+        "ls http://localhost/cvmfs/repo --revision latest",
+        "ls http://localhost/cvmfs/repo --tag v1 --revision 3",
+        "stat http://localhost/cvmfs/repo /sw --cache-dir",
+    ] {
+        assert!(parse(line).is_err(), "{}", line);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_mode_string() {
+    let mut entry = DirectoryEntry::virtual_directory("sw", 0);
+    assert_eq!("dr-xr-xr-x", mode_string(&entry));
+    entry.flags = 0;
+    entry.mode = 0o100644;
+    assert_eq!("-rw-r--r--", mode_string(&entry));
+}
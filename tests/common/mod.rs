@@ -0,0 +1,131 @@
+//! Helpers shared by the integration tests that build mock repositories. Each
+//! test crate only uses some of them.
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+
+use cvmfs::common::{path_md5, split_md5, CvmfsResult};
+
+/// Tables of a catalog, with the columns the client reads
+pub const CATALOG_SCHEMA: &str = "CREATE TABLE properties (key TEXT, value TEXT);
+     INSERT INTO properties VALUES ('revision', '1'), ('schema', '2.5');
+     CREATE TABLE catalog (md5path_1 INTEGER, md5path_2 INTEGER, parent_1 INTEGER, \
+     parent_2 INTEGER, hash BLOB, flags INTEGER, size INTEGER, mode INTEGER, \
+     mtime INTEGER, name TEXT, symlink TEXT);
+     CREATE TABLE nested_catalogs (path TEXT, sha1 TEXT);";
+
+/// Paths of a mock repository mapped to the content of the files, `None` for
+/// directories. The root is the empty path, as in the catalogs.
+pub type Tree = BTreeMap<String, Option<Vec<u8>>>;
+
+/// Tree grown by adding every entry, a name and its content, under one of the
+/// directories already in it, picked by the index modulo their number. Names
+/// already taken in a directory are skipped.
+pub fn grow_tree(entries: impl IntoIterator<Item = (usize, String, Option<Vec<u8>>)>) -> Tree {
+    let mut tree = BTreeMap::from([(String::new(), None)]);
+    for (parent, name, content) in entries {
+        let directories: Vec<&String> = tree
+            .iter()
+            .filter(|(_, content)| content.is_none())
+            .map(|(path, _)| path)
+            .collect();
+        let path = format!("{}/{}", directories[parent % directories.len()], name);
+        tree.entry(path).or_insert(content);
+    }
+    tree
+}
+
+/// Path of the parent of a path of the tree, the empty root for the top level
+pub fn parent_of(path: &str) -> &str {
+    &path[..path.rfind('/').unwrap_or(0)]
+}
+
+/// Writes the catalog of a tree, mounted at `root_prefix` (empty for the root
+/// catalog) with nested catalogs mounted at the given paths. Files point to
+/// the object hash returned for their content.
+pub fn write_catalog(
+    path: &Path,
+    tree: &Tree,
+    root_prefix: &str,
+    nested: &[(&str, String)],
+    mut object_hash: impl FnMut(&[u8]) -> String,
+) -> CvmfsResult<()> {
+    let _ = std::fs::remove_file(path);
+    let connection = Connection::open(path)?;
+    connection.execute_batch(CATALOG_SCHEMA)?;
+    if !root_prefix.is_empty() {
+        connection.execute(
+            "INSERT INTO properties VALUES ('root_prefix', ?)",
+            [root_prefix],
+        )?;
+    }
+    for (mountpoint, hash) in nested {
+        connection.execute(
+            "INSERT INTO nested_catalogs VALUES (?, ?)",
+            params![mountpoint, hash],
+        )?;
+    }
+    for (entry, content) in tree {
+        let hash = split_md5(&path_md5(entry));
+        // the root entry has no parent
+        let parent = match entry.rfind('/') {
+            Some(_) => split_md5(&path_md5(parent_of(entry))),
+            None => split_md5(&[0; 16]),
+        };
+        let (object, flags, size, mode) = match content {
+            Some(content) => {
+                let object = hex::decode(object_hash(content)).unwrap();
+                (Some(object), 4, content.len(), 0o100644)
+            }
+            None if !root_prefix.is_empty() && entry == root_prefix => {
+                (None, 1 | 32, 4096, 0o40755)
+            }
+            None if nested.iter().any(|(mountpoint, _)| mountpoint == entry) => {
+                (None, 1 | 2, 4096, 0o40755)
+            }
+            None => (None, 1, 4096, 0o40755),
+        };
+        connection.execute(
+            "INSERT INTO catalog VALUES (?, ?, ?, ?, ?, ?, ?, ?, 1700000000, ?, NULL)",
+            params![
+                hash.hash1,
+                hash.hash2,
+                parent.hash1,
+                parent.hash2,
+                object,
+                flags,
+                size as i64,
+                mode,
+                entry.rsplit('/').next().unwrap_or_default()
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// Zlib stream made of stored deflate blocks, as the objects of a repository
+/// are compressed, without depending on a compressor
+pub fn zlib_stored(content: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = content.chunks(u16::MAX as usize).collect();
+    for (index, block) in blocks.iter().enumerate() {
+        let length = block.len() as u16;
+        stream.push((index + 1 == blocks.len()) as u8);
+        stream.extend(length.to_le_bytes());
+        stream.extend((!length).to_le_bytes());
+        stream.extend(*block);
+    }
+    if blocks.is_empty() {
+        stream.extend([0x01, 0x00, 0x00, 0xff, 0xff]);
+    }
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in content {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    stream.extend(((b << 16) | a).to_be_bytes());
+    stream
+}
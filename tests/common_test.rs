@@ -0,0 +1,190 @@
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+
+use cvmfs::common::{compose_object_path, normalize_path, path_md5, CvmfsError, FileLike};
+
+#[test]
+fn test_file_read_at() -> std::io::Result<()> {
+    let path = std::env::temp_dir().join("cvmfs_read_at_test");
+    fs::write(&path, b"0123456789")?;
+    let mut file = File::open(&path)?;
+    file.seek(SeekFrom::Start(1))?;
+    let mut buffer = [0u8; 4];
+    assert_eq!(4, FileLike::read_at(&file, &mut buffer, 3).unwrap()?);
+    assert_eq!(b"3456", &buffer);
+    let mut buffer = [0u8; 4];
+    assert_eq!(2, FileLike::read_at(&file, &mut buffer, 8).unwrap()?);
+    assert_eq!(b"89", &buffer[..2]);
+    // the cursor did not move
+    let mut buffer = [0u8; 1];
+    file.read_exact(&mut buffer)?;
+    assert_eq!(b"1", &buffer);
+    Ok(())
+}
+
+#[test]
+fn test_normalize_path() {
+    assert_eq!("/software/bin", normalize_path("/software/bin"));
+    assert_eq!("", normalize_path(""));
+    assert_eq!("/", normalize_path("/"));
+    assert_eq!("/software/bin", normalize_path("/software//bin/"));
+    assert_eq!("/software/bin", normalize_path("/software/./bin/."));
+    assert_eq!("/", normalize_path("//"));
+    assert!(matches!(
+        normalize_path("/software"),
+        std::borrow::Cow::Borrowed(_)
+    ));
+}
+
+#[test]
+fn test_path_md5() {
+    assert_eq!(md5::compute("/software").0, path_md5("/software"));
+}
+
+#[test]
+fn test_chunked_file_read() -> cvmfs::common::CvmfsResult<()> {
+    use cvmfs::common::ChunkedFile;
+    use cvmfs::directory_entry::{Chunk, ContentHashTypes};
+    use cvmfs::fetcher::Fetcher;
+
+    let directory = std::env::temp_dir().join("cvmfs_chunked_file_test");
+    let cache_directory = directory.to_str().unwrap();
+    let fetcher = Fetcher::new("http://localhost.invalid", cache_directory, true)?;
+    let mut chunks = Vec::new();
+    // the second chunk is never fetched, so it does not need to be cached
+    for (index, content) in [&b"01234"[..], b"56789", b"abcde"].iter().enumerate() {
+        let path = format!("data/0{}/chunk", index);
+        if index != 1 {
+            fs::write(directory.join(&path), content)?;
+        }
+        chunks.push((
+            path,
+            Chunk {
+                offset: 5 * index as u64,
+                size: 5,
+                content_hash: format!("0{}chunk", index),
+                content_hash_type: ContentHashTypes::Sha1,
+            },
+        ));
+    }
+    chunks.reverse();
+    let mut file = ChunkedFile::new(chunks, 15, fetcher);
+    let mut buffer = [0u8; 4];
+    file.seek(SeekFrom::Start(1))?;
+    assert_eq!(4, file.read(&mut buffer)?);
+    assert_eq!(b"1234", &buffer);
+    file.seek(SeekFrom::Start(11))?;
+    assert_eq!(4, file.read(&mut buffer)?);
+    assert_eq!(b"bcde", &buffer);
+    assert_eq!(0, file.read(&mut buffer)?);
+    Ok(())
+}
+
+#[test]
+fn test_memory_file() -> std::io::Result<()> {
+    use cvmfs::common::{FileLike, MemoryFile};
+
+    let mut file = MemoryFile::new("data/00/object", b"0123456789"[..].into());
+    let mut buffer = [0u8; 4];
+    assert_eq!(2, FileLike::read_at(&file, &mut buffer, 8).unwrap()?);
+    assert_eq!(b"89", &buffer[..2]);
+    assert_eq!(0, FileLike::read_at(&file, &mut buffer, 20).unwrap()?);
+    file.seek(SeekFrom::End(-6))?;
+    assert_eq!(4, file.read(&mut buffer)?);
+    assert_eq!(b"4567", &buffer);
+    assert_eq!(2, file.read(&mut buffer)?);
+    assert_eq!(0, file.read(&mut buffer)?);
+    Ok(())
+}
+
+#[test]
+fn test_compose_object_path() {
+    assert_eq!(
+        Some("data/60/0230b0baC"),
+        compose_object_path("600230b0ba", "C")
+            .ok()
+            .as_deref()
+            .and_then(|p| p.to_str())
+    );
+    assert_eq!(
+        Err(CvmfsError::InvalidObjectHash("".into())),
+        compose_object_path("", "")
+    );
+    assert!(compose_object_path("6", "C").is_err());
+    assert!(compose_object_path("ñ0", "").is_err());
+}
+
+#[test]
+fn test_chunk_spans() -> cvmfs::common::CvmfsResult<()> {
+    use cvmfs::common::{ChunkSpan, ChunkedFile};
+    use cvmfs::directory_entry::{Chunk, ContentHashTypes};
+    use cvmfs::fetcher::Fetcher;
+
+    let directory = std::env::temp_dir().join("cvmfs_chunk_spans_test");
+    let fetcher = Fetcher::new(
+        "http://localhost.invalid",
+        directory.to_str().unwrap(),
+        true,
+    )?;
+    let file = |layout: &[(u64, u64)], size: u64| {
+        let chunks = layout
+            .iter()
+            .map(|&(offset, size)| {
+                let chunk = Chunk {
+                    offset,
+                    size,
+                    content_hash: format!("{:02x}", offset),
+                    content_hash_type: ContentHashTypes::Sha1,
+                };
+                (format!("data/{:02x}/chunk", offset), chunk)
+            })
+            .collect();
+        ChunkedFile::new(chunks, size, fetcher.clone()).with_read_ahead(0)
+    };
+    let span = |index, offset, len| ChunkSpan { index, offset, len };
+
+    // uneven chunks, given out of order
+    let mut uneven = file(&[(10, 20), (0, 3), (3, 7)], 30);
+    assert_eq!(vec![span(0, 0, 2)], uneven.spans(2));
+    assert_eq!(
+        vec![span(0, 0, 3), span(1, 0, 7), span(2, 0, 20)],
+        uneven.spans(100)
+    );
+    uneven.seek(SeekFrom::Start(2))?;
+    assert_eq!(
+        vec![span(0, 2, 1), span(1, 0, 7), span(2, 0, 2)],
+        uneven.spans(10)
+    );
+    // reads starting on a boundary begin with the next chunk
+    uneven.seek(SeekFrom::Start(10))?;
+    assert_eq!(vec![span(2, 0, 5)], uneven.spans(5));
+    uneven.seek(SeekFrom::Start(29))?;
+    assert_eq!(vec![span(2, 19, 1)], uneven.spans(4));
+    uneven.seek(SeekFrom::Start(30))?;
+    assert!(uneven.spans(4).is_empty());
+    uneven.seek(SeekFrom::Start(40))?;
+    assert!(uneven.spans(4).is_empty());
+    assert!(file(&[(0, 3)], 3).spans(0).is_empty());
+
+    // empty chunks are skipped and gaps end the read
+    let mut gaps = file(&[(0, 4), (4, 0), (4, 4), (12, 4)], 16);
+    assert_eq!(vec![span(0, 0, 4), span(2, 0, 4)], gaps.spans(16));
+    gaps.seek(SeekFrom::Start(9))?;
+    assert!(gaps.spans(4).is_empty());
+    gaps.seek(SeekFrom::Start(13))?;
+    assert_eq!(vec![span(3, 1, 3)], gaps.spans(4));
+
+    // chunks past the size of the file are cut at its end
+    assert_eq!(
+        vec![span(0, 0, 4), span(1, 0, 2)],
+        file(&[(0, 4), (4, 4)], 6).spans(8)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_errno() {
+    assert_eq!(libc::ENOENT, i32::from(CvmfsError::FileNotFound));
+    assert_eq!(libc::EIO, i32::from(CvmfsError::Offline("".into())));
+    assert_eq!(libc::EIO, i32::from(CvmfsError::Sync));
+}
@@ -0,0 +1,110 @@
+use std::fs;
+use std::path::Path;
+
+use cvmfs::common::CvmfsResult;
+use cvmfs::config::ClientConfig;
+use cvmfs::mount_config::MountConfig;
+
+#[test]
+fn test_parse() {
+    let config = ClientConfig::parse(
+        "# comment\n\
+         CVMFS_SERVER_URL=\"http://s1/cvmfs/@fqrn@\"\n\
+         export CVMFS_HTTP_PROXY='DIRECT'\n\
+         CVMFS_QUOTA_LIMIT=4000 # megabytes\n\
+         if [ -f /x ]; then\n\
+         not an assignment\n",
+    );
+    assert_eq!(Some("http://s1/cvmfs/@fqrn@"), config.server_url());
+    assert_eq!(Some("DIRECT"), config.get("CVMFS_HTTP_PROXY"));
+    assert_eq!(Some("4000"), config.get("CVMFS_QUOTA_LIMIT"));
+    assert_eq!(None, config.get("not an assignment"));
+    assert_eq!(None, ClientConfig::default().server_url());
+}
+
+#[test]
+fn test_layers() -> CvmfsResult<()> {
+    let directory = std::env::temp_dir().join("cvmfs_config_test");
+    let _ = fs::remove_dir_all(&directory);
+    let repositories = directory.join("repositories.d");
+    fs::create_dir_all(directory.join("domain.d"))?;
+    fs::create_dir_all(directory.join("config.d"))?;
+    fs::create_dir_all(repositories.join("sft.example.org"))?;
+    fs::write(
+        repositories.join("sft.example.org/server.conf"),
+        "CVMFS_STRATUM0=http://s0/cvmfs/sft.example.org\nCVMFS_KEYS_DIR=/etc/keys\n",
+    )?;
+    fs::write(
+        directory.join("default.conf"),
+        "CVMFS_DEFAULT_DOMAIN=example.org\nCVMFS_CACHE_BASE=/var/lib/cvmfs\nCVMFS_QUOTA_LIMIT=-1\n",
+    )?;
+    fs::write(directory.join("default.local"), "CVMFS_QUOTA_LIMIT=100\n")?;
+    fs::write(
+        directory.join("domain.d/example.org.conf"),
+        "CVMFS_HTTP_PROXY=squid:3128;DIRECT\nCVMFS_USE_GEOAPI=yes\n",
+    )?;
+    fs::write(
+        directory.join("config.d/sft.example.org.conf"),
+        "CVMFS_REPOSITORY_TAG=v1\n",
+    )?;
+    fs::write(
+        directory.join("config.d/sft.example.org.local"),
+        "CVMFS_CACHE_BASE=/scratch/cvmfs\n",
+    )?;
+
+    let client_config = ClientConfig::load(&directory, &repositories, "sft")?;
+    assert_eq!(
+        Some("http://s0/cvmfs/sft.example.org"),
+        client_config.server_url()
+    );
+    let mut config = MountConfig::new("http://localhost/cvmfs/sft", Path::new("/mnt"), "/tmp");
+    client_config.apply(&mut config)?;
+    assert_eq!("example.org", config.default_domain);
+    assert_eq!("/scratch/cvmfs", config.cache_directory);
+    assert_eq!(Some(100 << 20), config.cache_quota);
+    assert_eq!(Some("v1".to_string()), config.tag);
+    assert_eq!(Path::new("/etc/keys"), config.keys_directory);
+    assert!(config.use_geo_api);
+    assert_eq!(
+        vec![vec!["http://squid:3128"], vec!["DIRECT"]],
+        config.http_proxy.unwrap().groups
+    );
+
+    // the configuration of another repository only shares the defaults
+    let client_config = ClientConfig::load(&directory, &repositories, "atlas.cern.ch")?;
+    assert_eq!(None, client_config.server_url());
+    assert_eq!(
+        Some("/var/lib/cvmfs"),
+        client_config.get("CVMFS_CACHE_BASE")
+    );
+    Ok(())
+}
+
+#[test]
+fn test_config_option() -> CvmfsResult<()> {
+    let path = std::env::temp_dir().join("cvmfs_config_option_test.conf");
+    fs::write(
+        &path,
+        "CVMFS_CACHE_BASE=/var/lib/cvmfs\nCVMFS_QUOTA_LIMIT=-1\nCVMFS_REPOSITORY_TAG=v1\n",
+    )?;
+    let args = |line: &str| -> Vec<String> { line.split_whitespace().map(String::from).collect() };
+    let config = MountConfig::from_args(args(&format!(
+        "http://localhost/cvmfs/repo /mnt --config {}",
+        path.display()
+    )))?;
+    assert_eq!("/var/lib/cvmfs", config.cache_directory);
+    assert_eq!(None, config.cache_quota);
+    assert_eq!(Some("v1".to_string()), config.tag);
+    // the arguments override the configuration file
+    let config = MountConfig::from_args(args(&format!(
+        "http://localhost/cvmfs/repo /mnt /cache --config {} --tag v2",
+        path.display()
+    )))?;
+    assert_eq!("/cache", config.cache_directory);
+    assert_eq!(Some("v2".to_string()), config.tag);
+    assert!(MountConfig::from_args(args(
+        "http://localhost/cvmfs/repo /mnt --config /nonexistent/cvmfs.conf"
+    ))
+    .is_err());
+    Ok(())
+}
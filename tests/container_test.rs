@@ -0,0 +1,28 @@
+use cvmfs::common::CvmfsResult;
+use cvmfs::container::ImageReference;
+
+#[test]
+fn test_image_reference_defaults() -> CvmfsResult<()> {
+    let image = ImageReference::parse("centos")?;
+    assert_eq!("registry.hub.docker.com", image.registry);
+    assert_eq!("library/centos", image.repository);
+    assert_eq!(Some("latest".to_string()), image.tag);
+    assert_eq!(
+        "/registry.hub.docker.com/library/centos:latest",
+        image.repository_path()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_image_reference_with_registry() -> CvmfsResult<()> {
+    let image = ImageReference::parse("gitlab-registry.cern.ch:5000/atlas/athena:22.0")?;
+    assert_eq!("gitlab-registry.cern.ch:5000", image.registry);
+    assert_eq!("atlas/athena", image.repository);
+    assert_eq!(Some("22.0".to_string()), image.tag);
+    let image = ImageReference::parse("docker.io/user/image@sha256:abcd")?;
+    assert_eq!(None, image.tag);
+    assert_eq!("/docker.io/user/image@sha256:abcd", image.repository_path());
+    assert!(ImageReference::parse("bad image").is_err());
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use cvmfs::control::{socket_path, ControlCommand};
+
+#[test]
+fn test_parse_commands() {
+    assert_eq!(
+        ControlCommand::Pin("generic-2".into()),
+        ControlCommand::parse("pin generic-2").unwrap()
+    );
+    assert_eq!(
+        ControlCommand::Unpin,
+        ControlCommand::parse("unpin\n").unwrap()
+    );
+    assert_eq!(ControlCommand::Tag, ControlCommand::parse(" tag ").unwrap());
+    assert_eq!(
+        ControlCommand::Cache,
+        ControlCommand::parse("cache").unwrap()
+    );
+    assert_eq!(
+        ControlCommand::Status,
+        ControlCommand::parse("status").unwrap()
+    );
+    assert_eq!(
+        ControlCommand::Analytics(20),
+        ControlCommand::parse("analytics").unwrap()
+    );
+    assert_eq!(
+        ControlCommand::Analytics(5),
+        ControlCommand::parse("analytics 5").unwrap()
+    );
+    assert!(ControlCommand::parse("analytics many").is_err());
+    assert!(ControlCommand::parse("pin").is_err());
+    assert!(ControlCommand::parse("pin a b").is_err());
+    assert!(ControlCommand::parse("unpin now").is_err());
+    assert!(ControlCommand::parse("").is_err());
+}
+
+#[test]
+fn test_socket_path() {
+    assert_eq!(
+        "/tmp/cvmfs/cvmfs_io.atlas.cern.ch",
+        socket_path("/tmp/cvmfs", "atlas.cern.ch").to_str().unwrap()
+    );
+}
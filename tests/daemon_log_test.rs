@@ -0,0 +1,103 @@
+use std::fs;
+
+use log::{Level, LevelFilter, Log, Record};
+
+use cvmfs::common::CvmfsResult;
+use cvmfs::daemon_log::{DaemonLogger, LogConfig};
+use cvmfs::file_system::FUSE_LOG_TARGET;
+use cvmfs::mount_config::MountConfig;
+
+fn record(logger: &DaemonLogger, target: &str, line: u32, message: &str) {
+    logger.log(
+        &Record::builder()
+            .level(Level::Info)
+            .target(target)
+            .file(Some("src/file_system.rs"))
+            .line(Some(line))
+            .args(format_args!("{}", message))
+            .build(),
+    );
+}
+
+#[test]
+fn test_category_levels() -> CvmfsResult<()> {
+    let mut config = LogConfig::default();
+    config.set_levels("info,file_system=warn,fetcher=debug,reqwest=off")?;
+    assert_eq!(LevelFilter::Info, config.level);
+    assert_eq!(LevelFilter::Warn, config.level_of("cvmfs::file_system"));
+    assert_eq!(LevelFilter::Debug, config.level_of("cvmfs::fetcher"));
+    assert_eq!(LevelFilter::Off, config.level_of("reqwest::connect"));
+    assert_eq!(LevelFilter::Info, config.level_of("cvmfs::fetcher_pool"));
+    assert_eq!(LevelFilter::Info, config.level_of("cvmfs::repository"));
+    assert_eq!(LevelFilter::Debug, config.max_level());
+    assert!(config.set_levels("file_system=loud").is_err());
+
+    // the FUSE callbacks follow the file system unless set on their own
+    config.set_levels("file_system=debug")?;
+    assert_eq!(LevelFilter::Debug, config.level_of(FUSE_LOG_TARGET));
+    config.set_levels("file_system=debug,file_system::fuse=trace")?;
+    assert_eq!(LevelFilter::Trace, config.level_of(FUSE_LOG_TARGET));
+    assert_eq!(LevelFilter::Debug, config.level_of("cvmfs::file_system"));
+    Ok(())
+}
+
+#[test]
+fn test_rate_limit_and_rotation() -> CvmfsResult<()> {
+    let path = std::env::temp_dir().join("cvmfs_daemon_log_test.log");
+    for file in [path.clone(), path.with_extension("log.1")] {
+        let _ = fs::remove_file(file);
+    }
+    let mut config = LogConfig::default();
+    config.set_levels("info,file_system=warn,repository=info")?;
+    config.max_messages_per_second = Some(2);
+    config.file = Some(path.clone());
+    config.max_file_size = Some(4096);
+    config.rotations = 1;
+    let logger = DaemonLogger::new(config)?;
+    for _ in 0..10 {
+        record(&logger, "cvmfs::repository", 1, "Refreshing");
+        record(&logger, "cvmfs::file_system", 2, "Getting attributes");
+    }
+    record(&logger, "cvmfs::repository", 3, "Refreshed");
+    let contents = fs::read_to_string(&path)?;
+    assert_eq!(2, contents.matches("Refreshing").count());
+    assert_eq!(1, contents.matches("Refreshed").count());
+    assert!(!contents.contains("Getting attributes"));
+
+    let long = "x".repeat(1000);
+    for line in 0..10 {
+        record(&logger, "cvmfs::repository", 10 + line, &long);
+    }
+    assert!(fs::metadata(&path)?.len() <= 4096);
+    assert!(path.with_extension("log.1").exists());
+    Ok(())
+}
+
+#[test]
+fn test_log_options() -> CvmfsResult<()> {
+    let args = |extra: &[&str]| {
+        ["http://localhost/cvmfs/@fqrn@", "/cvmfs/atlas.cern.ch"]
+            .iter()
+            .chain(extra)
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>()
+    };
+    let config = MountConfig::from_args(args(&[
+        "--log-levels",
+        "warn,fetcher=info",
+        "--log-rate",
+        "10",
+        "--log-file",
+        "/var/log/cvmfs.log",
+        "--log-max-size",
+        "1048576",
+        "--log-rotations",
+        "5",
+    ]))?;
+    assert_eq!(LevelFilter::Warn, config.log.level);
+    assert_eq!(Some(10), config.log.max_messages_per_second);
+    assert_eq!(Some(1048576), config.log.max_file_size);
+    assert_eq!(5, config.log.rotations);
+    assert!(MountConfig::from_args(args(&["--log-max-size", "10"])).is_err());
+    Ok(())
+}
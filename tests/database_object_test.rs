@@ -0,0 +1,82 @@
+use cvmfs::common::CvmfsResult;
+use cvmfs::database_object::{DatabaseObject, SqliteTuning, TempStore, MAX_POOLED_CONNECTIONS};
+use rusqlite::Connection;
+
+fn pragma(database: &DatabaseObject, name: &str) -> CvmfsResult<i64> {
+    let mut statement = database.create_prepared_statement(&format!("PRAGMA {}", name))?;
+    Ok(statement.query_row([], |row| row.get(0))?)
+}
+
+#[test]
+fn test_sqlite_tuning() -> CvmfsResult<()> {
+    let path = std::env::temp_dir().join("cvmfs_sqlite_tuning.db");
+    let _ = std::fs::remove_file(&path);
+    Connection::open(&path)?.execute_batch("CREATE TABLE properties (key TEXT, value TEXT);")?;
+    let path = path.to_str().unwrap();
+
+    let database = DatabaseObject::new(path)?;
+    assert_eq!(-8192, pragma(&database, "cache_size")?);
+    assert_eq!(TempStore::Memory as i64, pragma(&database, "temp_store")?);
+    assert_eq!(1, pragma(&database, "query_only")?);
+
+    let tuning = SqliteTuning {
+        cache_size_kib: 1024,
+        temp_store: TempStore::File,
+        query_only: false,
+    };
+    let database = DatabaseObject::with_tuning(path, &tuning)?;
+    assert_eq!(-1024, pragma(&database, "cache_size")?);
+    assert_eq!(TempStore::File as i64, pragma(&database, "temp_store")?);
+    assert_eq!(0, pragma(&database, "query_only")?);
+    Ok(())
+}
+
+#[test]
+fn test_memory_used() -> CvmfsResult<()> {
+    let path = std::env::temp_dir().join("cvmfs_memory_used.db");
+    let _ = std::fs::remove_file(&path);
+    let connection = Connection::open(&path)?;
+    connection.execute_batch("CREATE TABLE properties (key TEXT, value TEXT);")?;
+    for i in 0..1000 {
+        connection.execute(
+            "INSERT INTO properties VALUES (?, ?)",
+            [i.to_string(), "x".repeat(100)],
+        )?;
+    }
+    let database = DatabaseObject::new(path.to_str().unwrap())?;
+    let before = database.memory_used();
+    assert_eq!(1000, database.read_properties_table()?.len());
+    assert!(database.memory_used() > before);
+    Ok(())
+}
+
+#[test]
+fn test_concurrent_connections() -> CvmfsResult<()> {
+    let path = std::env::temp_dir().join("cvmfs_connection_pool.db");
+    let _ = std::fs::remove_file(&path);
+    let connection = Connection::open(&path)?;
+    connection.execute_batch(
+        "CREATE TABLE properties (key TEXT, value TEXT);
+         INSERT INTO properties VALUES ('revision', '42');",
+    )?;
+    let database = DatabaseObject::new(path.to_str().unwrap())?;
+    std::thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                let value: String = database
+                    .with_connection(|connection| {
+                        Ok(connection.query_row(
+                            "SELECT value FROM properties WHERE key = 'revision'",
+                            [],
+                            |row| row.get(0),
+                        )?)
+                    })
+                    .unwrap();
+                assert_eq!("42", value);
+            });
+        }
+    });
+    let pooled = database.pooled_connections();
+    assert!((1..=MAX_POOLED_CONNECTIONS).contains(&pooled));
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use cvmfs::common::{CvmfsError, CvmfsResult};
+use cvmfs::directory_entry::{Chunk, ContentHashTypes, DirectoryEntry, Flags};
+use cvmfs::variant_symlink::SymlinkVariables;
+
+fn chunked_file(size: u64, chunks: &[(u64, u64)]) -> DirectoryEntry {
+    let mut dirent = DirectoryEntry::virtual_directory("file", 0);
+    dirent.flags = Flags::File as u32 | Flags::FileChunk as u32;
+    dirent.size = size;
+    dirent.chunks = chunks
+        .iter()
+        .map(|&(offset, size)| Chunk {
+            offset,
+            size,
+            content_hash: "0123".into(),
+            content_hash_type: ContentHashTypes::Sha1,
+        })
+        .collect();
+    dirent
+}
+
+#[test]
+fn test_check_chunks() -> CvmfsResult<()> {
+    chunked_file(0, &[]).check_chunks()?;
+    chunked_file(30, &[(0, 10), (10, 20)]).check_chunks()?;
+    for dirent in [
+        chunked_file(30, &[]),
+        chunked_file(30, &[(0, 10)]),
+        chunked_file(30, &[(0, 10), (15, 15)]),
+        chunked_file(30, &[(0, 20), (10, 20)]),
+        chunked_file(30, &[(5, 25)]),
+        chunked_file(20, &[(0, 10), (10, 20)]),
+    ] {
+        assert!(
+            matches!(dirent.check_chunks(), Err(CvmfsError::CorruptChunks(_))),
+            "{:?}",
+            dirent.chunks
+        );
+    }
+    assert_eq!(libc::EIO, i32::from(CvmfsError::CorruptChunks("".into())));
+    Ok(())
+}
+
+#[test]
+fn test_expand_symlink() {
+    let mut variables = SymlinkVariables::default();
+    variables.set("VARIANT_TEST_ARCH", "x86_64");
+    let mut dirent = DirectoryEntry::virtual_directory("link", 0);
+    dirent.flags = Flags::Link as u32;
+    dirent.symlink = Some("/opt/$(VARIANT_TEST_ARCH)/bin".into());
+    dirent.size = 24;
+    dirent.expand_symlink(&variables);
+    assert_eq!(Some("/opt/x86_64/bin"), dirent.symlink.as_deref());
+    assert_eq!(15, dirent.size);
+
+    // only symlinks are expanded
+    let mut dirent = DirectoryEntry::virtual_directory("dir", 0);
+    dirent.symlink = Some("$(VARIANT_TEST_ARCH)".into());
+    dirent.expand_symlink(&variables);
+    assert_eq!(Some("$(VARIANT_TEST_ARCH)"), dirent.symlink.as_deref());
+}
@@ -0,0 +1,455 @@
+use cvmfs::fetcher::{expected_digest, DigestAlgorithm};
+
+mod common;
+use common::zlib_stored;
+
+#[test]
+fn test_expected_digest() {
+    let (algorithm, hash) =
+        expected_digest("data/60/0230b0ba7620426f2e898f1e1f43c5466efe59C").unwrap();
+    assert_eq!(DigestAlgorithm::Sha1, algorithm);
+    assert_eq!("600230b0ba7620426f2e898f1e1f43c5466efe59", hash);
+    let sha256 = "ab".repeat(32);
+    let (algorithm, hash) =
+        expected_digest(&format!("data/{}/{}", &sha256[..2], &sha256[2..])).unwrap();
+    assert_eq!(DigestAlgorithm::Sha256, algorithm);
+    assert_eq!(sha256, hash);
+    let (algorithm, hash) =
+        expected_digest("data/60/0230b0ba7620426f2e898f1e1f43c5466efe59-rmd160").unwrap();
+    assert_eq!(DigestAlgorithm::Ripemd160, algorithm);
+    assert_eq!("600230b0ba7620426f2e898f1e1f43c5466efe59", hash);
+    let (algorithm, _) =
+        expected_digest("data/60/0230b0ba7620426f2e898f1e1f43c5466efe59-shake128C").unwrap();
+    assert_eq!(DigestAlgorithm::Shake128, algorithm);
+    assert!(expected_digest("data/60/0230b0ba7620426f2e898f1e1f43c5466efe59-md5").is_none());
+    assert!(expected_digest(".cvmfspublished").is_none());
+    assert!(expected_digest("data/60/0230").is_none());
+}
+
+#[test]
+fn test_digest_algorithms() {
+    assert_eq!(
+        Some("da39a3ee5e6b4b0d3255bfef95601890afd80709".to_string()),
+        DigestAlgorithm::Sha1.digest(b"")
+    );
+    assert_eq!(
+        Some("7f9c2ba4e88f827d616045507605853ed73b8093".to_string()),
+        DigestAlgorithm::Shake128.digest(b"")
+    );
+    // only available when OpenSSL loads its legacy provider
+    if let Some(digest) = DigestAlgorithm::Ripemd160.digest(b"") {
+        assert_eq!("9c1185a5c5e9fc54612808977ee8f548b2258d31", digest);
+    }
+}
+
+#[test]
+fn test_prefetch_skips_cached_files() -> cvmfs::common::CvmfsResult<()> {
+    use cvmfs::fetcher::Fetcher;
+
+    let directory = std::env::temp_dir().join("cvmfs_prefetch_test");
+    let fetcher = Fetcher::new(
+        "http://localhost.invalid",
+        directory.to_str().unwrap(),
+        true,
+    )?;
+    let file_names: Vec<String> = (0..5).map(|i| format!("data/0{}/cached", i)).collect();
+    for file_name in &file_names {
+        std::fs::write(directory.join(file_name), file_name)?;
+    }
+    fetcher.prefetch_with_concurrency(&file_names, 2);
+    fetcher.prefetch_with_concurrency(&[], 0);
+    for file_name in &file_names {
+        assert_eq!(
+            file_name.as_str(),
+            std::fs::read_to_string(directory.join(file_name))?
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_unreachable_server() -> cvmfs::common::CvmfsResult<()> {
+    use cvmfs::fetcher::Fetcher;
+
+    let directory = std::env::temp_dir().join("cvmfs_unreachable_test");
+    let fetcher = Fetcher::new("http://127.0.0.1:1", directory.to_str().unwrap(), true)?;
+    let error = fetcher.retrieve_raw_file(".cvmfspublished").unwrap_err();
+    assert!(error.is_unreachable(), "{:?}", error);
+    assert_eq!(libc::EIO, i32::from(error));
+    Ok(())
+}
+
+#[test]
+fn test_shared_cache_deduplicates_downloads() -> cvmfs::common::CvmfsResult<()> {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use cvmfs::cache::Cache;
+    use cvmfs::fetcher::Fetcher;
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+    let requests = Arc::new(AtomicUsize::new(0));
+    let served = requests.clone();
+    std::thread::spawn(move || {
+        let body = zlib_stored(b"common base content");
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request);
+            served.fetch_add(1, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(500));
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            let _ = stream.write_all(&body);
+        }
+    });
+
+    let directory = std::env::temp_dir().join("cvmfs_dedup_test");
+    let _ = std::fs::remove_dir_all(&directory);
+    let cache = Cache::new(directory.to_str().unwrap().into())?;
+    cache.initialize()?;
+    let fetchers: Vec<Fetcher> = ["first.cern.ch", "second.cern.ch"]
+        .iter()
+        .map(|fqrn| {
+            Fetcher::with_cache(
+                &format!("http://127.0.0.1:{}/{}", port, fqrn),
+                cache.clone(),
+            )
+        })
+        .collect();
+    let deduplicated = Fetcher::deduplicated_downloads();
+    std::thread::scope(|scope| {
+        for i in 0..4 {
+            let fetcher = &fetchers[i % 2];
+            scope.spawn(move || {
+                let mut content = String::new();
+                fetcher
+                    .retrieve_object("data/ab/cdef0123")
+                    .unwrap()
+                    .read_to_string(&mut content)
+                    .unwrap();
+                assert_eq!("common base content", content);
+            });
+        }
+    });
+    assert_eq!(1, requests.load(Ordering::SeqCst));
+    assert!(Fetcher::deduplicated_downloads() >= deduplicated + 3);
+    Ok(())
+}
+
+#[test]
+fn test_mirror_failover() -> cvmfs::common::CvmfsResult<()> {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    use cvmfs::cache::Cache;
+    use cvmfs::fetcher::Fetcher;
+    use cvmfs::mirrors::MirrorSet;
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut request = [0u8; 1024];
+            let length = stream.read(&mut request).unwrap_or_default();
+            let request = String::from_utf8_lossy(&request[..length]);
+            let path = request.split_whitespace().nth(1).unwrap_or_default();
+            let (status, body) = match path {
+                // the mirror still missing the object
+                path if path.starts_with("/behind/") => ("404 Not Found", Vec::new()),
+                path => ("200 OK", zlib_stored(path.as_bytes())),
+            };
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                body.len()
+            );
+            let _ = stream.write_all(&body);
+        }
+    });
+
+    let directory = std::env::temp_dir().join("cvmfs_mirror_test");
+    let _ = std::fs::remove_dir_all(&directory);
+    let cache = Cache::new(directory.to_str().unwrap().into())?;
+    cache.initialize()?;
+    let mirrors = MirrorSet::new(vec![
+        "http://127.0.0.1:1/down".into(),
+        format!("http://127.0.0.1:{}/behind", port),
+        format!("http://127.0.0.1:{}/up", port),
+    ])?
+    .with_backoff(Duration::from_secs(60), Duration::from_secs(60));
+    let fetcher = Fetcher::with_mirror_set(mirrors, cache);
+    assert_eq!("http://127.0.0.1:1/down", fetcher.source);
+    for i in 0..6 {
+        let mut content = String::new();
+        fetcher
+            .retrieve_object(&format!("data/0{}/object", i))?
+            .read_to_string(&mut content)?;
+        assert_eq!(format!("/up/data/0{}/object", i), content);
+    }
+    let status = fetcher.mirror_status();
+    // the unreachable mirror is only tried until it is backed off from
+    assert!(!status[0].healthy);
+    assert_eq!((1, 0), (status[0].failures, status[0].successes));
+    // missing objects do not make a mirror unhealthy
+    assert!(status[1].healthy);
+    assert_eq!(0, status[1].failures);
+    assert_eq!(6, status[2].successes);
+    Ok(())
+}
+
+#[test]
+fn test_geo_api() -> cvmfs::common::CvmfsResult<()> {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    use cvmfs::cache::Cache;
+    use cvmfs::common::CvmfsError;
+    use cvmfs::fetcher::{parse_geo_reply, Fetcher};
+
+    assert_eq!(vec![1, 2, 0], parse_geo_reply("2,3,1\n", 3)?);
+    for reply in ["", "1,2", "1,2,4", "1,1,2", "0,1,2", "a,b,c", "1,2,3,1"] {
+        assert!(
+            matches!(
+                parse_geo_reply(reply, 3),
+                Err(CvmfsError::InvalidGeoApiReply(_))
+            ),
+            "{}",
+            reply
+        );
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut request = [0u8; 1024];
+            let length = stream.read(&mut request).unwrap_or_default();
+            let request = String::from_utf8_lossy(&request[..length]);
+            let path = request.split_whitespace().nth(1).unwrap_or_default();
+            let (status, body) = match path {
+                "/s1/cvmfs/repo/api/v1.0/geo/x/127.0.0.1,127.0.0.1,127.0.0.1" => {
+                    ("200 OK", "3,1,2")
+                }
+                _ => ("404 Not Found", ""),
+            };
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+        }
+    });
+
+    let directory = std::env::temp_dir().join("cvmfs_geo_api_test");
+    let _ = std::fs::remove_dir_all(&directory);
+    let cache = Cache::new(directory.to_str().unwrap().into())?;
+    cache.initialize()?;
+    let urls: Vec<String> = ["s1", "s2", "s3"]
+        .iter()
+        .map(|server| format!("http://127.0.0.1:{}/{}/cvmfs/repo", port, server))
+        .collect();
+    let urls: Vec<&str> = urls.iter().map(String::as_str).collect();
+    let fetcher = Fetcher::with_mirrors(&urls, cache)?;
+    // the mirrors without a GeoAPI are skipped
+    assert_eq!(vec![2, 0, 1], fetcher.geo_order()?);
+    fetcher.sort_mirrors_by_geo()?;
+    assert_eq!(Some(vec![2, 0, 1]), fetcher.mirrors.ranking());
+    assert_eq!(vec![2, 0, 1], fetcher.mirrors.order());
+    Ok(())
+}
+
+#[test]
+fn test_offline_mode() -> cvmfs::common::CvmfsResult<()> {
+    use std::io::Read;
+    use std::time::Duration;
+
+    use cvmfs::common::CvmfsError;
+    use cvmfs::fetcher::Fetcher;
+
+    let directory = std::env::temp_dir().join("cvmfs_offline_test");
+    let _ = std::fs::remove_dir_all(&directory);
+    let mut fetcher = Fetcher::new("http://127.0.0.1:1", directory.to_str().unwrap(), true)?;
+    std::fs::write(directory.join("data/00/cached"), "cached")?;
+    assert!(!fetcher.is_offline());
+    let error = fetcher.retrieve_object("data/01/missing").unwrap_err();
+    assert!(matches!(error, CvmfsError::Offline(_)), "{:?}", error);
+    assert!(error.is_unreachable());
+    assert_eq!(libc::EIO, i32::from(error));
+    // cached objects are still served
+    assert!(fetcher.is_offline());
+    let mut content = String::new();
+    fetcher
+        .retrieve_object("data/00/cached")?
+        .read_to_string(&mut content)?;
+    assert_eq!("cached", content);
+    // the network is tried again once the retry interval elapsed
+    fetcher.offline_retry_interval = Duration::ZERO;
+    assert!(!fetcher.is_offline());
+    // the disconnected mode is shared by the clones
+    fetcher.clone().set_offline(true);
+    assert!(fetcher.is_offline());
+    assert!(matches!(
+        fetcher.retrieve_raw_file(".cvmfspublished"),
+        Err(CvmfsError::Offline(_))
+    ));
+    fetcher.set_offline(false);
+    assert!(!fetcher.is_offline());
+    Ok(())
+}
+
+#[test]
+fn test_chunked_file_read_ahead() -> cvmfs::common::CvmfsResult<()> {
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use std::net::TcpListener;
+
+    use cvmfs::common::ChunkedFile;
+    use cvmfs::directory_entry::{Chunk, ContentHashTypes};
+    use cvmfs::fetcher::Fetcher;
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut request = [0u8; 1024];
+            let length = stream.read(&mut request).unwrap_or_default();
+            let request = String::from_utf8_lossy(&request[..length]);
+            let path = request.split_whitespace().nth(1).unwrap_or_default();
+            // chunk contents are the last 5 characters of their path
+            let body = zlib_stored(&path.as_bytes()[path.len() - 5..]);
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            let _ = stream.write_all(&body);
+        }
+    });
+
+    let directory = std::env::temp_dir().join("cvmfs_read_ahead_test");
+    let _ = std::fs::remove_dir_all(&directory);
+    let fetcher = Fetcher::new(
+        &format!("http://127.0.0.1:{}", port),
+        directory.to_str().unwrap(),
+        true,
+    )?;
+    let chunks: Vec<_> = ["01234", "56789", "abcde", "fghij"]
+        .iter()
+        .enumerate()
+        .map(|(index, content)| {
+            let chunk = Chunk {
+                offset: 5 * index as u64,
+                size: 5,
+                content_hash: format!("0{}{}", index, content),
+                content_hash_type: ContentHashTypes::Sha1,
+            };
+            (format!("data/0{}/{}", index, content), chunk)
+        })
+        .collect();
+    let mut file = ChunkedFile::new(chunks.clone(), 20, fetcher.clone()).with_read_ahead(2);
+    let mut buffer = [0u8; 2];
+    file.read_exact(&mut buffer)?;
+    assert_eq!(b"01", &buffer);
+    // the read started fetching the following chunks in the background
+    let started = std::time::Instant::now();
+    while fetcher.cache.get("data/01/56789").is_none()
+        || fetcher.cache.get("data/02/abcde").is_none()
+    {
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(fetcher.cache.get("data/03/fghij").is_none());
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    assert_eq!("23456789abcdefghij", content);
+
+    // random reads do not prefetch
+    let _ = std::fs::remove_dir_all(&directory);
+    fetcher.cache.initialize()?;
+    let mut file = ChunkedFile::new(chunks, 20, fetcher.clone());
+    file.seek(SeekFrom::Start(11))?;
+    file.read_exact(&mut buffer)?;
+    assert_eq!(b"bc", &buffer);
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert!(fetcher.cache.get("data/03/fghij").is_none());
+    Ok(())
+}
+
+#[test]
+fn test_external_data() -> cvmfs::common::CvmfsResult<()> {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    use cvmfs::common::CvmfsError;
+    use cvmfs::fetcher::Fetcher;
+    use cvmfs::validation::ValidationMode;
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut request = [0u8; 1024];
+            let length = stream.read(&mut request).unwrap_or_default();
+            let request = String::from_utf8_lossy(&request[..length]);
+            // served uncompressed, by path
+            let body = request.split_whitespace().nth(1).unwrap_or_default();
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+        }
+    });
+
+    let directory = std::env::temp_dir().join("cvmfs_external_test");
+    let _ = std::fs::remove_dir_all(&directory);
+    let mut fetcher = Fetcher::new(
+        "http://127.0.0.1:1/repository",
+        directory.to_str().unwrap(),
+        true,
+    )?;
+    fetcher.content_validation = ValidationMode::Fatal;
+    let external = fetcher.for_external_data(&[&format!("http://127.0.0.1:{}/external", port)])?;
+    assert_eq!(ValidationMode::Fatal, external.content_validation);
+    let hash = DigestAlgorithm::Sha1
+        .digest(b"/external/sw/data.bin")
+        .unwrap();
+    let object_name = format!("data/{}/{}E", &hash[..2], &hash[2..]);
+    let mut content = String::new();
+    external
+        .retrieve_external("/sw/data.bin", &object_name)?
+        .read_to_string(&mut content)?;
+    assert_eq!("/external/sw/data.bin", content);
+    assert!(fetcher.cache.get(&object_name).is_some());
+    // the repository servers are not involved
+    assert!(!fetcher.is_offline());
+
+    let error = external
+        .retrieve_external(
+            "/sw/other.bin",
+            &object_name.replace(&hash[2..], &"0".repeat(38)),
+        )
+        .unwrap_err();
+    assert!(
+        matches!(error, CvmfsError::ContentHashMismatch(_)),
+        "{:?}",
+        error
+    );
+    Ok(())
+}
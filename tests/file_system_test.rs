@@ -0,0 +1,77 @@
+use cvmfs::catalog::Statistics;
+use cvmfs::file_system::{catalogue_counters, tag_xattrs, VirtualPath};
+use cvmfs::revision_tag::RevisionTag;
+
+#[test]
+fn test_parse_current_path() {
+    assert_eq!(VirtualPath::Current("/"), VirtualPath::parse("/"));
+    assert_eq!(
+        VirtualPath::Current("/software/bin"),
+        VirtualPath::parse("/software/bin")
+    );
+    assert_eq!(
+        VirtualPath::Current("/.cvmfs/snapshotsfoo"),
+        VirtualPath::parse("/.cvmfs/snapshotsfoo")
+    );
+}
+
+#[test]
+fn test_parse_snapshot_path() {
+    assert_eq!(
+        VirtualPath::Directory("/.cvmfs"),
+        VirtualPath::parse("/.cvmfs")
+    );
+    assert_eq!(
+        VirtualPath::Directory("/.cvmfs/snapshots"),
+        VirtualPath::parse("/.cvmfs/snapshots")
+    );
+    assert_eq!(
+        VirtualPath::Snapshot {
+            tag: "generic-1",
+            path: "/"
+        },
+        VirtualPath::parse("/.cvmfs/snapshots/generic-1")
+    );
+    assert_eq!(
+        VirtualPath::Snapshot {
+            tag: "generic-1",
+            path: "/software/bin"
+        },
+        VirtualPath::parse("/.cvmfs/snapshots/generic-1/software/bin")
+    );
+}
+
+#[test]
+fn test_tag_xattrs() {
+    let tag = RevisionTag {
+        name: "generic-2".into(),
+        hash: "abc".into(),
+        revision: 2,
+        timestamp: 1700000000,
+        channel: 0,
+        description: "nightly".into(),
+        branch: None,
+    };
+    let xattrs = tag_xattrs(&tag);
+    assert!(xattrs.contains(&("user.tag", "generic-2".to_string())));
+    assert!(xattrs.contains(&("user.tag_timestamp", "1700000000".to_string())));
+    assert!(xattrs.contains(&("user.tag_channel", "0".to_string())));
+    assert!(xattrs.contains(&("user.tag_description", "nightly".to_string())));
+}
+
+#[test]
+fn test_catalogue_counters() {
+    let statistics = Statistics {
+        regular: 12,
+        dir: 3,
+        chunks: 40,
+        file_size: 4096,
+        ..Default::default()
+    };
+    let counters = catalogue_counters(&statistics);
+    assert!(counters.starts_with("regular: 12\n"));
+    assert!(counters.contains("\ndir: 3\n"));
+    assert!(counters.contains("\nchunks: 40\n"));
+    assert!(counters.contains("\nfile_size: 4096\n"));
+    assert!(counters.ends_with("external_file_size: 0\n"));
+}
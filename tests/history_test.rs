@@ -0,0 +1,232 @@
+use std::path::PathBuf;
+
+use cvmfs::common::CvmfsResult;
+use cvmfs::history::{ExportFormat, History};
+use cvmfs::revision_tag::RevisionTag;
+use rusqlite::Connection;
+
+/// Creates a history database with one tag per revision, one hour apart
+fn create_history(name: &str, revisions: u32) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("cvmfs_history_test_{}.db", name));
+    let _ = std::fs::remove_file(&path);
+    let connection = Connection::open(&path).expect("Failure creating the history");
+    connection
+        .execute_batch(
+            "CREATE TABLE properties (key TEXT, value TEXT);
+             INSERT INTO properties VALUES ('schema', '1.0'), ('fqrn', 'test.cern.ch');
+             CREATE TABLE tags (name TEXT, hash TEXT, revision INTEGER, timestamp INTEGER,
+                                channel INTEGER, description TEXT);",
+        )
+        .expect("Failure creating the history schema");
+    for revision in 1..=revisions {
+        connection
+            .execute(
+                "INSERT INTO tags VALUES (?, ?, ?, ?, 0, ?)",
+                rusqlite::params![
+                    format!("generic-{}", revision),
+                    format!("hash{}", revision),
+                    revision,
+                    1_700_000_000 + 3600 * revision as i64,
+                    format!("revision {}", revision),
+                ],
+            )
+            .expect("Failure inserting a tag");
+    }
+    path
+}
+
+/// Adds the branches table of newer history schemas with a `devel` branch
+fn add_branches(path: &PathBuf) {
+    let connection = Connection::open(path).expect("Failure opening the history");
+    connection
+        .execute_batch(
+            "ALTER TABLE tags ADD COLUMN branch TEXT DEFAULT '';
+             CREATE TABLE branches (branch TEXT, parent TEXT, initial_revision INTEGER);
+             INSERT INTO branches VALUES ('', NULL, 1), ('devel', '', 2);
+             UPDATE tags SET branch = 'devel' WHERE revision = 3;",
+        )
+        .expect("Failure adding the branches");
+}
+
+#[test]
+fn test_list_tags() -> CvmfsResult<()> {
+    let path = create_history("list_tags", 3);
+    let history = History::new(path.to_str().unwrap())?;
+    let tags = history.list_tags()?;
+    assert_eq!(
+        vec![3, 2, 1],
+        tags.iter().map(|tag| tag.revision).collect::<Vec<_>>()
+    );
+    assert_eq!("generic-3", tags[0].name);
+    Ok(())
+}
+
+#[test]
+fn test_iterate_tags() -> CvmfsResult<()> {
+    let path = create_history("iterate_tags", 250);
+    let history = History::new(path.to_str().unwrap())?;
+    let tags = history.tags().collect::<CvmfsResult<Vec<_>>>()?;
+    assert_eq!(250, tags.len());
+    assert_eq!(250, tags[0].revision);
+    assert_eq!(1, tags[249].revision);
+    Ok(())
+}
+
+#[test]
+fn test_branches() -> CvmfsResult<()> {
+    let path = create_history("branches", 3);
+    add_branches(&path);
+    let history = History::new(path.to_str().unwrap())?;
+    assert!(history.has_branches);
+    let branches = history.list_branches()?;
+    assert_eq!(2, branches.len());
+    assert_eq!("devel", branches[1].name);
+    assert_eq!(Some("".to_string()), branches[1].parent);
+    let devel_tags = history.list_tags_by_branch("devel")?;
+    assert_eq!(1, devel_tags.len());
+    assert_eq!(Some("devel".to_string()), devel_tags[0].branch);
+    assert_eq!(2, history.list_tags_by_branch("")?.len());
+    assert_eq!(3, history.list_tags()?.len());
+    let tag = history
+        .get_tag_by_name_and_branch("generic-3", "devel")?
+        .unwrap();
+    assert_eq!(Some("devel".to_string()), tag.branch);
+    assert!(history
+        .get_tag_by_name_and_branch("generic-3", "")?
+        .is_none());
+    assert!(history
+        .get_tag_by_name_and_branch("generic-1", "")?
+        .is_some());
+    Ok(())
+}
+
+#[test]
+fn test_without_branches() -> CvmfsResult<()> {
+    let path = create_history("without_branches", 2);
+    let history = History::new(path.to_str().unwrap())?;
+    assert!(!history.has_branches);
+    assert!(history.list_branches()?.is_empty());
+    assert_eq!(2, history.list_tags_by_branch("")?.len());
+    assert!(history.list_tags_by_branch("devel")?.is_empty());
+    assert_eq!(None, history.list_tags()?[0].branch);
+    assert!(history
+        .get_tag_by_name_and_branch("generic-1", "")?
+        .is_some());
+    assert!(history
+        .get_tag_by_name_and_branch("generic-1", "devel")?
+        .is_none());
+    Ok(())
+}
+
+#[test]
+fn test_get_tag_by_date() -> CvmfsResult<()> {
+    let path = create_history("by_date", 3);
+    let history = History::new(path.to_str().unwrap())?;
+    let second = 1_700_000_000 + 3600 * 2;
+    let tag = history.get_tag_by_date(second)?.unwrap();
+    assert_eq!(2, tag.revision);
+    let tag = history.get_tag_by_date(second + 1800)?.unwrap();
+    assert_eq!(2, tag.revision);
+    let tag = history.get_tag_by_date(second * 2)?.unwrap();
+    assert_eq!(3, tag.revision);
+    assert!(history.get_tag_by_date(1_700_000_000)?.is_none());
+    Ok(())
+}
+
+#[test]
+fn test_tags_between() -> CvmfsResult<()> {
+    let path = create_history("between", 5);
+    let history = History::new(path.to_str().unwrap())?;
+    let revisions =
+        |tags: Vec<RevisionTag>| -> Vec<i32> { tags.into_iter().map(|tag| tag.revision).collect() };
+    assert_eq!(
+        vec![2, 3, 4],
+        revisions(history.tags_between("generic-2", "generic-4")?)
+    );
+    assert_eq!(
+        vec![2, 3, 4],
+        revisions(history.tags_between("generic-4", "generic-2")?)
+    );
+    assert_eq!(
+        vec![3],
+        revisions(history.tags_between("generic-3", "generic-3")?)
+    );
+    assert!(history.tags_between("generic-1", "missing").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_export_json() -> CvmfsResult<()> {
+    let path = create_history("export_json", 2);
+    add_branches(&path);
+    let history = History::new(path.to_str().unwrap())?;
+    let mut output = Vec::new();
+    history.export(&mut output, ExportFormat::Json)?;
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.starts_with("{\"fqrn\":"));
+    assert!(output.contains(
+        "{\"name\":\"generic-2\",\"hash\":\"hash2\",\"revision\":2,\"timestamp\":1700007200,\"channel\":0,\"description\":\"revision 2\",\"branch\":\"\"}"
+    ));
+    assert!(output.contains("{\"name\":\"devel\",\"parent\":\"\",\"initial_revision\":2}"));
+    assert!(output.contains("{\"name\":\"\",\"parent\":null,\"initial_revision\":1}"));
+    Ok(())
+}
+
+#[test]
+fn test_export_csv() -> CvmfsResult<()> {
+    let path = create_history("export_csv", 2);
+    let history = History::new(path.to_str().unwrap())?;
+    let mut output = Vec::new();
+    history.export(&mut output, ExportFormat::Csv)?;
+    let output = String::from_utf8(output).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        vec![
+            "kind,name,hash,revision,timestamp,channel,description,branch,parent",
+            "tag,generic-2,hash2,2,1700007200,0,revision 2,,",
+            "tag,generic-1,hash1,1,1700003600,0,revision 1,,",
+        ],
+        lines
+    );
+    Ok(())
+}
+
+fn set_schema(path: &PathBuf, schema: &str) {
+    Connection::open(path)
+        .expect("Failure opening the history")
+        .execute(
+            "UPDATE properties SET value = ? WHERE key = 'schema'",
+            [schema],
+        )
+        .expect("Failure changing the schema");
+}
+
+#[test]
+fn test_unsupported_schema() {
+    let path = create_history("schema", 1);
+    for schema in ["2.0", "0.9", "", "1.x"] {
+        set_schema(&path, schema);
+        assert_eq!(
+            cvmfs::common::CvmfsError::UnsupportedHistorySchema(schema.into()),
+            History::new(path.to_str().unwrap()).unwrap_err()
+        );
+    }
+}
+
+#[test]
+fn test_newer_minor_schema() -> CvmfsResult<()> {
+    let path = create_history("minor_schema", 2);
+    set_schema(&path, "1.3");
+    Connection::open(&path)
+        .expect("Failure opening the history")
+        .execute_batch(
+            "ALTER TABLE tags ADD COLUMN size INTEGER DEFAULT 0;
+             CREATE TABLE recycle_bin (hash TEXT, flags INTEGER);",
+        )
+        .expect("Failure extending the schema");
+    let history = History::new(path.to_str().unwrap())?;
+    assert_eq!("1.3", history.schema);
+    assert_eq!(2, history.list_tags()?.len());
+    assert_eq!("hash1", history.get_tag_by_name("generic-1")?.unwrap().hash);
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use cvmfs::libcvmfs::{cache_directory_option, parse_options, resolve_symlink};
+
+#[test]
+fn test_parse_options() {
+    let options = parse_options("repo_name=atlas.cern.ch,url=http://host/cvmfs/atlas\\,x,nofiles");
+    assert_eq!("atlas.cern.ch", options["repo_name"]);
+    assert_eq!("http://host/cvmfs/atlas,x", options["url"]);
+    assert_eq!("", options["nofiles"]);
+    assert!(parse_options("").is_empty());
+}
+
+#[test]
+fn test_cache_directory_option() {
+    let options = parse_options("url=http://host/cvmfs/atlas,cachedir=/var/cache/cvmfs");
+    assert_eq!(
+        Some("/var/cache/cvmfs"),
+        cache_directory_option(&options).map(String::as_str)
+    );
+    let options = parse_options("cache_directory=/tmp/cache");
+    assert_eq!(
+        Some("/tmp/cache"),
+        cache_directory_option(&options).map(String::as_str)
+    );
+    assert!(cache_directory_option(&parse_options("url=x")).is_none());
+}
+
+#[test]
+fn test_resolve_symlink() {
+    let fqrn = "atlas.cern.ch";
+    let resolve = |link, target| resolve_symlink(fqrn, link, target);
+    assert_eq!(Some("/a/c".into()), resolve("/a/b", "c"));
+    assert_eq!(Some("/c/d".into()), resolve("/a/b", "../c/./d"));
+    assert_eq!(
+        Some("/x/y".into()),
+        resolve("/a/b", "/cvmfs/atlas.cern.ch/x/y")
+    );
+    assert_eq!(Some("/".into()), resolve("/a", "/cvmfs/atlas.cern.ch"));
+    assert_eq!(None, resolve("/a", "../../outside"));
+    assert_eq!(None, resolve("/a", "/cvmfs/atlas.cern.ch.other/x"));
+    assert_eq!(None, resolve("/a", "/usr/lib"));
+}
@@ -0,0 +1,38 @@
+use cvmfs::lru::LruCache;
+
+#[test]
+fn test_eviction_order() {
+    let mut cache = LruCache::new(2);
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    assert_eq!(Some(1), cache.get(&"a"));
+    cache.insert("c", 3);
+    assert_eq!(2, cache.len());
+    assert_eq!(None, cache.get(&"b"));
+    assert_eq!(Some(1), cache.get(&"a"));
+    assert_eq!(Some(3), cache.get(&"c"));
+}
+
+#[test]
+fn test_update_and_clear() {
+    let mut cache = LruCache::new(2);
+    cache.insert("a", 1);
+    cache.insert("a", 2);
+    assert_eq!(1, cache.len());
+    assert_eq!(Some(2), cache.get(&"a"));
+    cache.clear();
+    assert!(cache.is_empty());
+    let mut disabled = LruCache::new(0);
+    disabled.insert("a", 1);
+    assert_eq!(None, disabled.get(&"a"));
+}
+
+#[test]
+fn test_iter_keeps_recency() {
+    let mut cache = LruCache::new(2);
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    assert_eq!(3, cache.iter().map(|(_, value)| value).sum::<i32>());
+    cache.insert("c", 3);
+    assert_eq!(None, cache.get(&"a"));
+}
@@ -0,0 +1,107 @@
+use std::fs::{self, File};
+use std::path::PathBuf;
+
+use cvmfs::common::{CvmfsError, CvmfsResult};
+use cvmfs::directory_entry::ContentHashTypes;
+use cvmfs::manifest::Manifest;
+use cvmfs::rootfile::RootFile;
+
+fn write_fixture(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("cvmfs_manifest_test_{}", name));
+    fs::write(&path, contents).expect("Failure writing the fixture");
+    path
+}
+
+#[test]
+fn test_extended_keys() -> CvmfsResult<()> {
+    let path = write_fixture(
+        "extended",
+        "C600230b0ba7620426f2e898f1e1f43c5466efe59\n\
+         B1024\n\
+         Rd41d8cd98f00b204e9800998ecf8427e\n\
+         D240\n\
+         S42\n\
+         Nboss.cern.ch\n\
+         Mc8a0b6d8d29d3c1f0f7b5cd6e6e0a0ed1c1d4b8b\n\
+         Y2f4c0b6d8d29d3c1f0f7b5cd6e6e0a0ed1c1d4b8\n\
+         Zextra\n\
+         Gno\n\
+         Ayes\n",
+    );
+    let manifest = Manifest::new(RootFile::new(&File::open(&path)?)?)?;
+    assert_eq!(42, manifest.revision);
+    assert_eq!(240, manifest.ttl);
+    assert_eq!("boss.cern.ch", manifest.repository_name);
+    assert!(manifest.has_meta_info());
+    assert_eq!(
+        Some("2f4c0b6d8d29d3c1f0f7b5cd6e6e0a0ed1c1d4b8".to_string()),
+        manifest.reflog_hash
+    );
+    assert_eq!("extra", manifest.unknown_keys[&'Z']);
+    assert!(!manifest.garbage_collectable);
+    assert!(manifest.allows_alternative_name);
+    assert_eq!(ContentHashTypes::Sha1, manifest.hash_algorithm());
+    Ok(())
+}
+
+#[test]
+fn test_hash_algorithm() -> CvmfsResult<()> {
+    let path = write_fixture(
+        "shake128",
+        "C600230b0ba7620426f2e898f1e1f43c5466efe59-shake128\n\
+         Rd41d8cd98f00b204e9800998ecf8427e\n\
+         D240\n\
+         S43\n\
+         Nboss.cern.ch\n",
+    );
+    let manifest = Manifest::new(RootFile::new(&File::open(&path)?)?)?;
+    assert_eq!(ContentHashTypes::Shake128, manifest.hash_algorithm());
+    Ok(())
+}
+
+#[test]
+fn test_content_hash_types() {
+    assert_eq!(
+        ContentHashTypes::Sha1,
+        ContentHashTypes::from_hash("600230b0ba7620426f2e898f1e1f43c5466efe59C")
+    );
+    assert_eq!(
+        ContentHashTypes::Ripemd160,
+        ContentHashTypes::from_hash("600230b0ba7620426f2e898f1e1f43c5466efe59-rmd160")
+    );
+    assert_eq!(
+        ContentHashTypes::Shake128,
+        ContentHashTypes::from_hash("600230b0ba7620426f2e898f1e1f43c5466efe59-shake128C")
+    );
+    assert_eq!(
+        ContentHashTypes::Unknown,
+        ContentHashTypes::from_hash("600230b0ba7620426f2e898f1e1f43c5466efe59-md5")
+    );
+    assert_eq!(ContentHashTypes::Shake128, ContentHashTypes::from(3));
+    assert_eq!(
+        "-shake128",
+        ContentHashTypes::hash_suffix(&ContentHashTypes::Shake128)
+    );
+}
+
+#[test]
+fn test_malformed_manifest() -> CvmfsResult<()> {
+    let path = write_fixture(
+        "malformed",
+        "C600230b0ba7620426f2e898f1e1f43c5466efe59\n\
+         Gmaybe\n",
+    );
+    let error = Manifest::new(RootFile::new(&File::open(&path)?)?).unwrap_err();
+    assert!(
+        matches!(error, CvmfsError::InvalidManifest(_)),
+        "{:?}",
+        error
+    );
+    assert_eq!(libc::EIO, i32::from(error));
+    let path = write_fixture(
+        "multibyte",
+        "C600230b0ba7620426f2e898f1e1f43c5466efe59\nñx\n",
+    );
+    assert!(Manifest::new(RootFile::new(&File::open(&path)?)?).is_ok());
+    Ok(())
+}
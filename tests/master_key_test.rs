@@ -0,0 +1,41 @@
+use std::fs;
+use std::path::Path;
+
+use cvmfs::common::{CvmfsError, CvmfsResult};
+use cvmfs::master_key::MasterKey;
+use cvmfs::rootfile::RootFile;
+use cvmfs::whitelist::Whitelist;
+
+const MASTER_KEY: &[u8] = include_bytes!("fixtures/master_key.pub");
+
+#[test]
+fn test_load_keys_by_domain() -> CvmfsResult<()> {
+    let keys_directory = std::env::temp_dir().join("cvmfs_master_key_test");
+    fs::create_dir_all(keys_directory.join("cern.ch"))?;
+    fs::write(
+        keys_directory.join("cern.ch").join("cern-it1.cern.ch.pub"),
+        MASTER_KEY,
+    )?;
+    fs::write(keys_directory.join("cern.ch").join("README"), "not a key")?;
+    let keys = MasterKey::load_for_repository(&keys_directory, "atlas.cern.ch")?;
+    assert_eq!(1, keys.len());
+    assert!(matches!(
+        MasterKey::load_for_repository(&keys_directory, "repo.egi.eu"),
+        Err(CvmfsError::MasterKeyNotFound(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_verify_whitelist_signature() -> CvmfsResult<()> {
+    let key = MasterKey::from_pem(MASTER_KEY, Path::new("master_key.pub"))?;
+    let whitelist = Whitelist::new(RootFile::from_bytes(include_bytes!("fixtures/whitelist"))?)?;
+    whitelist.verify_signature(&[key])?;
+    let unsigned = Whitelist::new(RootFile::from_bytes(b"20240101120000\nE20990101120000\n")?)?;
+    let key = MasterKey::from_pem(MASTER_KEY, Path::new("master_key.pub"))?;
+    assert_eq!(
+        Err(CvmfsError::IncompleteRootFileSignature),
+        unsigned.verify_signature(&[key])
+    );
+    Ok(())
+}
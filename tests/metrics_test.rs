@@ -0,0 +1,66 @@
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use cvmfs::common::{CvmfsError, CvmfsResult};
+use cvmfs::metrics::{metrics, spawn_endpoint, Histogram, LATENCY_BUCKETS};
+
+#[test]
+fn test_histogram() {
+    let mut histogram = Histogram::default();
+    histogram.observe(Duration::from_micros(50));
+    histogram.observe(Duration::from_millis(3));
+    histogram.observe(Duration::from_secs(60));
+    assert_eq!(3, histogram.count());
+    assert_eq!(10, LATENCY_BUCKETS.len());
+}
+
+#[test]
+fn test_render() -> CvmfsResult<()> {
+    let lookups = metrics().lookups();
+    let errors = metrics().errors("FileNotFound");
+    metrics().record_download(100);
+    metrics().record_cache_lookup(true);
+    metrics().record_cache_lookup(false);
+    metrics().observe_lookup(Duration::from_millis(2));
+    metrics().record_error(&CvmfsError::FileNotFound);
+    metrics().record_error(&CvmfsError::IO("broken pipe".into()));
+    drop(metrics().time_request("getattr"));
+    assert!(metrics().lookups() > lookups);
+    assert!(metrics().errors("FileNotFound") > errors);
+    assert!(metrics().errors("IO") > 0);
+
+    let text = metrics().render();
+    for line in [
+        "# TYPE cvmfs_downloads_total counter",
+        "# TYPE cvmfs_open_files gauge",
+        "# TYPE cvmfs_lookup_duration_seconds histogram",
+        "cvmfs_lookup_duration_seconds_bucket{le=\"+Inf\"}",
+        "cvmfs_lookup_duration_seconds_count ",
+        "cvmfs_fuse_request_duration_seconds_bucket{operation=\"getattr\",le=\"0.0001\"}",
+        "cvmfs_fuse_request_duration_seconds_sum{operation=\"getattr\"}",
+        "cvmfs_errors_total{type=\"FileNotFound\"}",
+        "cvmfs_errors_total{type=\"IO\"}",
+    ] {
+        assert!(text.contains(line), "{}", line);
+    }
+
+    let path = std::env::temp_dir().join(format!("cvmfs-metrics-{}.prom", std::process::id()));
+    metrics().write_file(&path)?;
+    assert!(std::fs::read_to_string(&path)?.contains("cvmfs_cache_hits_total"));
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn test_endpoint() -> CvmfsResult<()> {
+    let address = spawn_endpoint("127.0.0.1:0")?;
+    let mut stream = TcpStream::connect(address)?;
+    stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("text/plain; version=0.0.4"));
+    assert!(response.contains("cvmfs_downloads_total"));
+    Ok(())
+}
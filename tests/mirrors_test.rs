@@ -0,0 +1,67 @@
+use std::thread;
+use std::time::Duration;
+
+use cvmfs::common::CvmfsResult;
+use cvmfs::mirrors::MirrorSet;
+
+fn mirrors(count: usize) -> CvmfsResult<MirrorSet> {
+    MirrorSet::new(
+        (0..count)
+            .map(|i| format!("http://s1-{}/cvmfs", i))
+            .collect(),
+    )
+}
+
+#[test]
+fn test_round_robin() -> CvmfsResult<()> {
+    let mirrors = mirrors(3)?;
+    assert_eq!(vec![0, 1, 2], mirrors.order());
+    assert_eq!(vec![1, 2, 0], mirrors.order());
+    assert_eq!(vec![2, 0, 1], mirrors.order());
+    assert_eq!(vec![0, 1, 2], mirrors.order());
+    assert!(MirrorSet::new(Vec::new()).is_err());
+    assert_eq!(vec![0], MirrorSet::single("http://s1/cvmfs".into()).order());
+    Ok(())
+}
+
+#[test]
+fn test_backoff() -> CvmfsResult<()> {
+    let mirrors = mirrors(3)?.with_backoff(Duration::from_millis(100), Duration::from_secs(1));
+    mirrors.record_failure(1);
+    mirrors.record_failure(0);
+    mirrors.record_failure(0);
+    // backed off mirrors go last, the one recovering soonest first
+    assert_eq!(vec![2, 1, 0], mirrors.order());
+    assert_eq!(vec![2, 1, 0], mirrors.order());
+    let status = mirrors.status();
+    assert_eq!("http://s1-0/cvmfs", status[0].url);
+    assert_eq!((false, 2), (status[0].healthy, status[0].failures));
+    assert!(status[2].healthy);
+
+    thread::sleep(Duration::from_millis(150));
+    assert_eq!(vec![2, 1, 0], mirrors.order());
+    assert!(mirrors.status()[1].healthy);
+    mirrors.record_success(0);
+    let status = mirrors.status();
+    assert_eq!(
+        (true, 0, 1),
+        (status[0].healthy, status[0].failures, status[0].successes)
+    );
+    assert_eq!(vec![0, 1, 2], mirrors.order());
+    Ok(())
+}
+
+#[test]
+fn test_ranking() -> CvmfsResult<()> {
+    let mirrors = mirrors(3)?;
+    assert!(mirrors.ranking().is_none());
+    assert!(mirrors.set_ranking(vec![2, 0]).is_err());
+    assert!(mirrors.set_ranking(vec![2, 0, 0]).is_err());
+    mirrors.set_ranking(vec![2, 0, 1])?;
+    // ranked mirrors are always tried from the first one
+    assert_eq!(vec![2, 0, 1], mirrors.order());
+    assert_eq!(vec![2, 0, 1], mirrors.order());
+    mirrors.record_failure(2);
+    assert_eq!(vec![0, 1, 2], mirrors.order());
+    Ok(())
+}
@@ -0,0 +1,226 @@
+use std::path::Path;
+use std::time::Duration;
+
+use cvmfs::access_log::AccessLogTarget;
+use cvmfs::common::{CvmfsError, CvmfsResult, DEFAULT_CHUNK_READ_AHEAD};
+use cvmfs::mount_config::{default_cache_directory, MountConfig, DEFAULT_FUSE_THREADS};
+use cvmfs::validation::ValidationPolicy;
+
+fn args(line: &str) -> Vec<String> {
+    line.split_whitespace().map(String::from).collect()
+}
+
+#[test]
+fn test_from_args() -> CvmfsResult<()> {
+    let config = MountConfig::from_args(args("http://localhost/cvmfs/repo /mnt"))?;
+    assert_eq!("http://localhost/cvmfs/repo", config.repository_url);
+    assert_eq!(Path::new("/mnt"), config.mount_point);
+    assert_eq!(default_cache_directory(), config.cache_directory);
+    assert_eq!(DEFAULT_FUSE_THREADS, config.threads);
+    assert!(config.sibling_prefetch.is_none());
+    assert_eq!(ValidationPolicy::default(), config.validation);
+    assert!(config.access_log.is_none());
+    assert!(config.fallback_cache_directory.is_none());
+    assert!(config.analytics_report.is_none());
+    assert!(config.http_proxy.is_none());
+    assert!(config.revision.is_none());
+    assert_eq!(DEFAULT_CHUNK_READ_AHEAD, config.chunk_read_ahead);
+
+    let mut config = MountConfig::from_args(args(
+        "--threads 8 http://localhost/cvmfs/repo /mnt /var/cache --tag v1 --subpath /sw \
+         --fuse-options allow_other,ro --prefetch-siblings 4096 --prefetch-concurrency 2 --validation strict \
+         --access-log-rate 100 --access-log /var/log/cvmfs.log --fallback-cache-dir /scratch \
+         --selinux-context system_u:object_r:cvmfs_t:s0 --max-staleness 86400 \
+         --scrub-interval 3600 --scrub-rate 1048576 --audit-log /var/log/cvmfs-audit.log \
+         --analytics /var/log/cvmfs-usage.json --http-proxy squid:3128;DIRECT \
+         --cache-quota 1073741824 --chunk-read-ahead 16 --max-catalogs 64 \
+         --catalog-memory-limit 33554432",
+    ))?;
+    assert_eq!("/var/cache", config.cache_directory);
+    assert_eq!(8, config.threads);
+    assert_eq!(Some("v1".to_string()), config.tag);
+    assert_eq!(Some("/sw".to_string()), config.subpath);
+    assert_eq!(
+        vec!["fsname=cernvmfs", "allow_other", "ro"],
+        config.fuse_options
+    );
+    config.unprivileged = false;
+    assert_eq!(6, config.fuse_args().len());
+    let prefetch = config.sibling_prefetch.unwrap();
+    assert_eq!(4096, prefetch.max_file_size);
+    assert_eq!(2, prefetch.concurrency);
+    assert_eq!(ValidationPolicy::strict(), config.validation);
+    let access_log = config.access_log.unwrap();
+    assert_eq!(
+        AccessLogTarget::File("/var/log/cvmfs.log".into()),
+        access_log.target
+    );
+    assert_eq!(Some(100), access_log.max_records_per_second);
+    assert_eq!(
+        Some("/scratch".to_string()),
+        config.fallback_cache_directory
+    );
+    assert_eq!(
+        Some("system_u:object_r:cvmfs_t:s0".to_string()),
+        config.xattr_policy.selinux_context
+    );
+    assert_eq!(Some(86400), config.max_staleness);
+    let scrub = config.scrub.unwrap();
+    assert_eq!(1048576, scrub.bytes_per_second);
+    assert_eq!(Duration::from_secs(3600), scrub.interval);
+    assert_eq!(
+        Some(Path::new("/var/log/cvmfs-audit.log").into()),
+        config.audit_log
+    );
+    assert_eq!(
+        Some(Path::new("/var/log/cvmfs-usage.json").into()),
+        config.analytics_report
+    );
+    assert_eq!(
+        vec![vec!["http://squid:3128"], vec!["DIRECT"]],
+        config.http_proxy.unwrap().groups
+    );
+    assert_eq!(Some(1 << 30), config.cache_quota);
+    assert_eq!(16, config.chunk_read_ahead);
+    assert_eq!(64, config.max_opened_catalogs);
+    assert_eq!(Some(32 << 20), config.catalog_memory_limit);
+    Ok(())
+}
+
+#[test]
+fn test_insecure() -> CvmfsResult<()> {
+    use cvmfs::validation::ValidationMode;
+    use cvmfs::whitelist::ExpiryPolicy;
+
+    let config = MountConfig::from_args(args("http://localhost/cvmfs/repo /mnt"))?;
+    assert_eq!(ValidationMode::Fatal, config.validation.signature);
+    let config = MountConfig::from_args(args(
+        "--insecure http://localhost/cvmfs/repo /mnt --validation strict --threads 2",
+    ))?;
+    assert_eq!(ValidationMode::Ignore, config.validation.signature);
+    assert_eq!(ValidationMode::Fatal, config.validation.content_hash);
+    assert_eq!(2, config.threads);
+    assert!(!config.offline);
+    assert_eq!(ExpiryPolicy::ServeFromCache, config.whitelist_expiry_policy);
+    let config = MountConfig::from_args(args(
+        "http://localhost/cvmfs/repo /mnt --whitelist-expiry-policy unmount",
+    ))?;
+    assert_eq!(ExpiryPolicy::Unmount, config.whitelist_expiry_policy);
+    assert!(config.auto_refresh);
+    let config = MountConfig::from_args(args(
+        "--offline http://localhost/cvmfs/repo /mnt --no-auto-refresh",
+    ))?;
+    assert!(config.offline);
+    assert!(!config.auto_refresh);
+    assert!(!config.use_geo_api);
+    assert!(config.external_url.is_none());
+    assert!(config.metrics_file.is_none());
+    let config = MountConfig::from_args(args(
+        "http://localhost/cvmfs/repo /mnt --revision 3 --external-url http://ext/@fqrn@ --use-geoapi \
+         --metrics-file /var/lib/cvmfs.prom --metrics-listen 127.0.0.1:9100 \
+         --symlink-variable ORG=cern --symlink-variable EMPTY=",
+    ))?;
+    assert_eq!(Some("cern".into()), config.symlink_variables.get("ORG"));
+    assert_eq!(Some("".into()), config.symlink_variables.get("EMPTY"));
+    assert!(config.use_geo_api);
+    assert_eq!(
+        Some(Path::new("/var/lib/cvmfs.prom").into()),
+        config.metrics_file
+    );
+    assert_eq!(Some("127.0.0.1:9100".to_string()), config.metrics_address);
+    assert_eq!(Some(3), config.revision);
+    assert_eq!(Some("http://ext/@fqrn@".to_string()), config.external_url);
+    Ok(())
+}
+
+#[test]
+fn test_invalid_args() {
+    for line in [
+        "http://localhost/cvmfs/repo",
+        "http://localhost/cvmfs/repo /mnt /cache extra",
+        "http://localhost/cvmfs/repo /mnt --threads many",
+        "http://localhost/cvmfs/repo /mnt --unknown value",
+        "http://localhost/cvmfs/repo /mnt --prefetch-concurrency 2",
+        "http://localhost/cvmfs/repo /mnt --tag",
+        "http://localhost/cvmfs/repo /mnt --validation lenient",
+        "http://localhost/cvmfs/repo /mnt --access-log-rate 10",
+        "http://localhost/cvmfs/repo /mnt --selinux-context cvmfs_t",
+        "http://localhost/cvmfs/repo /mnt --max-staleness -1",
+        "http://localhost/cvmfs/repo /mnt --scrub-interval 60",
+        "http://localhost/cvmfs/repo /mnt --whitelist-expiry-policy never",
+        "http://localhost/cvmfs/repo /mnt --cache-quota 1G",
+        "http://localhost/cvmfs/repo /mnt --revision latest",
+        "http://localhost/cvmfs/repo /mnt --revision 3 --tag v1",
+    ] {
+        assert!(
+            matches!(
+                MountConfig::from_args(args(line)),
+                Err(CvmfsError::InvalidConfiguration(_))
+            ),
+            "{}",
+            line
+        );
+    }
+}
+
+#[test]
+fn test_validate() -> CvmfsResult<()> {
+    let temp_dir = std::env::temp_dir();
+    let mut config = MountConfig::new("http://localhost/cvmfs/repo", &temp_dir, "/tmp/cvmfs");
+    config.unprivileged = false;
+    config.validate()?;
+    config.threads = 0;
+    assert!(matches!(
+        config.validate(),
+        Err(CvmfsError::InvalidConfiguration(_))
+    ));
+    config.threads = 1;
+    config.subpath = Some("sw/releases".into());
+    assert!(matches!(
+        config.validate(),
+        Err(CvmfsError::InvalidConfiguration(_))
+    ));
+    config.subpath = Some("/sw/releases".into());
+    config.validate()?;
+    config.mount_point = "/nonexistent/mount/point".into();
+    assert!(matches!(
+        config.validate(),
+        Err(CvmfsError::InvalidMountPoint(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_server_url_templates() -> CvmfsResult<()> {
+    use cvmfs::mount_config::{derive_fqrn, expand_server_url};
+
+    assert_eq!("atlas.cern.ch", derive_fqrn("atlas", "cern.ch"));
+    assert_eq!("sft.cern.ch", derive_fqrn("sft.cern.ch", "example.org"));
+    assert_eq!(
+        "http://host/cvmfs/atlas.cern.ch/atlas",
+        expand_server_url("http://host/cvmfs/@fqrn@/@org@", "atlas.cern.ch")
+    );
+
+    let mut config = MountConfig::from_args(args(
+        "http://a/cvmfs/@fqrn@;http://b/cvmfs/@fqrn@ /cvmfs/alice",
+    ))?;
+    assert_eq!(Some("alice.cern.ch".to_string()), config.fqrn());
+    assert_eq!("http://a/cvmfs/alice.cern.ch", config.server_url()?);
+    assert_eq!(
+        vec![
+            "http://a/cvmfs/alice.cern.ch",
+            "http://b/cvmfs/alice.cern.ch"
+        ],
+        config.server_urls()?
+    );
+    config.repository_name = Some("lhcb".into());
+    config.default_domain = "example.org".into();
+    assert_eq!("http://a/cvmfs/lhcb.example.org", config.server_url()?);
+
+    let config = MountConfig::from_args(args("http://a/cvmfs/@fqrn@ /"))?;
+    assert!(matches!(
+        config.server_url(),
+        Err(CvmfsError::InvalidConfiguration(_))
+    ));
+    Ok(())
+}
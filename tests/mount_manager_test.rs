@@ -0,0 +1,36 @@
+use std::path::Path;
+
+use cvmfs::common::{CvmfsError, CvmfsResult};
+use cvmfs::mount_manager::{MountManager, MountSpec};
+
+#[test]
+fn test_invalid_mount_points() {
+    let manager = MountManager::new();
+    let spec = MountSpec::new("http://localhost/cvmfs/repo", Path::new("relative"), "/tmp");
+    assert!(matches!(
+        manager.mount(spec),
+        Err(CvmfsError::InvalidMountPoint(_))
+    ));
+    let spec = MountSpec::new(
+        "http://localhost/cvmfs/repo",
+        Path::new("/nonexistent/mount/point"),
+        "/tmp",
+    );
+    assert!(matches!(
+        manager.mount(spec),
+        Err(CvmfsError::InvalidMountPoint(_))
+    ));
+}
+
+#[test]
+fn test_unmount_is_idempotent() -> CvmfsResult<()> {
+    let manager = MountManager::new();
+    manager.unmount(Path::new("/nonexistent/mount/point"))?;
+    assert!(manager.list()?.is_empty());
+    assert!(matches!(
+        manager.health_check(Path::new("/nonexistent/mount/point")),
+        Err(CvmfsError::MountNotFound(_))
+    ));
+    assert!(manager.health_check_all()?.is_empty());
+    Ok(())
+}
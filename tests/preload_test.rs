@@ -0,0 +1,35 @@
+use cvmfs::common::CvmfsResult;
+use cvmfs::preload::PreloadSpec;
+
+#[test]
+fn test_parse_spec() -> CvmfsResult<()> {
+    let spec = PreloadSpec::parse(
+        "# software of the batch nodes
+         /sw/gcc/
+         /sw/python/3.*   # every minor version
+
+         ! /sw/python/*/test
+         /",
+    )?;
+    assert_eq!(vec!["/sw/gcc", "/sw/python/3.*", "/"], spec.include);
+    assert_eq!(vec!["/sw/python/*/test"], spec.exclude);
+    assert!(PreloadSpec::parse("sw/gcc").is_err());
+    assert_eq!(
+        vec!["/sw", "/data"],
+        PreloadSpec::from_paths(&["/sw/".into(), "/data".into()])?.include
+    );
+    Ok(())
+}
+
+#[test]
+fn test_is_excluded() -> CvmfsResult<()> {
+    let spec = PreloadSpec::parse("!/sw/python/*/test\n!/data/?.log")?;
+    assert!(spec.is_excluded("/sw/python/3.11/test"));
+    assert!(spec.is_excluded("/sw/python/3.11/test/unit.py"));
+    assert!(spec.is_excluded("/data/a.log"));
+    assert!(!spec.is_excluded("/data/ab.log"));
+    assert!(!spec.is_excluded("/sw/python/3.11"));
+    assert!(!spec.is_excluded("/sw/python/3.11/tests"));
+    assert!(!spec.is_excluded("/sw/gcc/test"));
+    Ok(())
+}
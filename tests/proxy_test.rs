@@ -0,0 +1,72 @@
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::time::Duration;
+
+use cvmfs::common::{CvmfsError, CvmfsResult};
+use cvmfs::proxy::{ProxyChain, ProxyConfig, DIRECT};
+
+#[test]
+fn test_parse() -> CvmfsResult<()> {
+    let config = ProxyConfig::parse("http://squid1:3128|squid2:3128; http://squid3:3128 ;direct")?;
+    assert_eq!(
+        vec![
+            vec!["http://squid1:3128", "http://squid2:3128"],
+            vec!["http://squid3:3128"],
+            vec![DIRECT],
+        ],
+        config.groups
+    );
+    assert_eq!(vec![vec![DIRECT]], ProxyConfig::parse("DIRECT")?.groups);
+    assert!(matches!(
+        ProxyConfig::parse(" ; |"),
+        Err(CvmfsError::InvalidConfiguration(_))
+    ));
+    assert!(ProxyConfig::parse("auto;DIRECT").is_err());
+    Ok(())
+}
+
+/// Proxy answering every request with the url it was asked for
+fn spawn_proxy() -> CvmfsResult<String> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let address = format!("127.0.0.1:{}", listener.local_addr()?.port());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut request = [0u8; 1024];
+            let length = stream.read(&mut request).unwrap_or_default();
+            let request = String::from_utf8_lossy(&request[..length]);
+            let url = request.split_whitespace().nth(1).unwrap_or_default();
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                url.len(),
+                url
+            );
+        }
+    });
+    Ok(address)
+}
+
+#[test]
+fn test_failover_to_next_group() -> CvmfsResult<()> {
+    let proxy = spawn_proxy()?;
+    let config = ProxyConfig::parse(&format!("127.0.0.1:1|127.0.0.1:2;{}", proxy))?;
+    let chain = ProxyChain::new(&config)?.with_backoff(Duration::from_secs(60));
+    for i in 0..3 {
+        let url = format!("http://stratum1.invalid/cvmfs/data/0{}", i);
+        assert_eq!(url.as_bytes(), chain.get(&url)?);
+    }
+    let status = chain.status();
+    assert_eq!(format!("http://{}", proxy), status[2].url);
+    // the first group is only tried until both proxies are backed off from
+    assert_eq!(
+        vec![(false, 1), (false, 1), (true, 0)],
+        status
+            .iter()
+            .map(|proxy| (proxy.healthy, proxy.failures))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(3, status[2].successes);
+    assert!(ProxyChain::new(&ProxyConfig { groups: Vec::new() }).is_err());
+    Ok(())
+}
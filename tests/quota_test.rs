@@ -0,0 +1,79 @@
+use std::path::Path;
+
+use cvmfs::cache::Cache;
+use cvmfs::common::CvmfsResult;
+use cvmfs::quota::QUOTA_DATABASE;
+
+fn object(index: usize) -> String {
+    format!("data/0{}/object{}", index % 4, index)
+}
+
+fn is_cached(directory: &Path, index: usize) -> bool {
+    directory.join(object(index)).exists()
+}
+
+#[test]
+fn test_evicts_least_recently_used() -> CvmfsResult<()> {
+    let directory = std::env::temp_dir().join("cvmfs_quota_test");
+    let _ = std::fs::remove_dir_all(&directory);
+    let cache = Cache::with_quota(directory.to_string_lossy().into_owned(), 1000)?;
+    cache.initialize()?;
+    for index in 0..10 {
+        cache.store(&object(index), &[0; 100])?;
+    }
+    // metadata of the cache is not accounted
+    cache.store_pinned_tag("quota.cern.ch", "generic-2")?;
+    let quota = cache.quota().unwrap();
+    assert_eq!(1000, quota.size());
+    assert!((0..10).all(|index| is_cached(&directory, index)));
+
+    assert!(cache.get(&object(0)).is_some());
+    cache.store(&object(10), &[0; 100])?;
+    // cleaned up to half of the limit, sparing the object just used
+    assert_eq!(500, quota.size());
+    for index in [0, 7, 8, 9, 10] {
+        assert!(is_cached(&directory, index), "{}", index);
+    }
+    for index in 1..7 {
+        assert!(!is_cached(&directory, index), "{}", index);
+    }
+    assert!(cache.load_pinned_tag("quota.cern.ch").is_some());
+
+    // storing an object again only accounts its new size
+    cache.store(&object(10), &[0; 50])?;
+    assert_eq!(450, quota.size());
+    Ok(())
+}
+
+#[test]
+fn test_index_persistence() -> CvmfsResult<()> {
+    let directory = std::env::temp_dir().join("cvmfs_quota_persistence_test");
+    let _ = std::fs::remove_dir_all(&directory);
+    let cache_directory = directory.to_string_lossy().into_owned();
+    {
+        let cache = Cache::with_quota(cache_directory.clone(), 1 << 20)?;
+        cache.initialize()?;
+        for index in 0..4 {
+            cache.store(&object(index), &[0; 300])?;
+        }
+        // least recently used from now on
+        assert!(cache.get(&object(3)).is_some());
+    }
+    let cache = Cache::with_quota(cache_directory.clone(), 1 << 20)?;
+    assert_eq!(1200, cache.quota().unwrap().size());
+    assert!(cache.get(&object(1)).is_some());
+    assert_eq!(600, cache.quota().unwrap().cleanup(600)?);
+    assert!(is_cached(&directory, 1) && is_cached(&directory, 3));
+    assert!(!is_cached(&directory, 0) && !is_cached(&directory, 2));
+    drop(cache);
+
+    // a lost index is rebuilt from the cached objects
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(directory.join(format!("{}{}", QUOTA_DATABASE, suffix)));
+    }
+    let cache = Cache::with_quota(cache_directory, 1 << 20)?;
+    assert_eq!(600, cache.quota().unwrap().size());
+    cache.evict()?;
+    assert_eq!(0, cache.quota().unwrap().size());
+    Ok(())
+}
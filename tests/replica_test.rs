@@ -0,0 +1,86 @@
+use chrono::DateTime;
+use cvmfs::replica::{DivergentTag, ReplicaComparison, ReplicaStatus};
+use cvmfs::revision_tag::RevisionTag;
+
+fn tag(name: &str, hash: &str, revision: i32) -> RevisionTag {
+    RevisionTag {
+        name: name.into(),
+        hash: hash.into(),
+        revision,
+        timestamp: 0,
+        channel: 0,
+        description: String::new(),
+        branch: None,
+    }
+}
+
+fn status(url: &str, revision: u32, root_catalog: &str, tags: Vec<RevisionTag>) -> ReplicaStatus {
+    ReplicaStatus {
+        url: url.into(),
+        fqrn: "test.cern.ch".into(),
+        revision,
+        root_catalog: root_catalog.into(),
+        last_modified: DateTime::from_timestamp(1_700_000_000 + 60 * revision as i64, 0).unwrap(),
+        last_replication: None,
+        replicating: false,
+        tags,
+    }
+}
+
+#[test]
+fn test_in_sync() {
+    let tags = vec![tag("v1", "hash1", 1), tag("v2", "hash2", 2)];
+    let comparison = ReplicaComparison::new(
+        status("http://stratum0", 2, "hash2", tags.clone()),
+        status("http://stratum1", 2, "hash2", tags),
+    );
+    assert!(comparison.is_in_sync());
+    assert_eq!(0, comparison.revision_lag());
+    assert!(comparison.to_string().ends_with("In sync\n"));
+}
+
+#[test]
+fn test_lagging_replica() {
+    let comparison = ReplicaComparison::new(
+        status(
+            "http://stratum0",
+            3,
+            "hash3",
+            vec![
+                tag("v1", "hash1", 1),
+                tag("v2", "other", 2),
+                tag("v3", "hash3", 3),
+            ],
+        ),
+        status(
+            "http://stratum1",
+            2,
+            "hash2",
+            vec![tag("v1", "hash1", 1), tag("v2", "hash2", 2)],
+        ),
+    );
+    assert!(!comparison.is_in_sync());
+    assert_eq!(1, comparison.revision_lag());
+    assert_eq!(60, comparison.time_lag().num_seconds());
+    assert!(!comparison.root_catalog_diverged());
+    assert_eq!(vec!["v3".to_string()], comparison.missing_in_second);
+    assert!(comparison.missing_in_first.is_empty());
+    assert_eq!(
+        vec![DivergentTag {
+            name: "v2".into(),
+            first_hash: "other".into(),
+            second_hash: "hash2".into(),
+        }],
+        comparison.divergent_tags
+    );
+}
+
+#[test]
+fn test_diverged_root_catalog() {
+    let comparison = ReplicaComparison::new(
+        status("http://stratum0", 2, "hash2", vec![]),
+        status("http://stratum1", 2, "forked", vec![]),
+    );
+    assert!(comparison.root_catalog_diverged());
+    assert!(!comparison.is_in_sync());
+}
@@ -13,10 +13,32 @@ fn setup() {
 #[test]
 fn test_initialization() -> CvmfsResult<()> {
     setup();
-    let fetcher = Fetcher::new("http://cvmfs-stratum-one.cern.ch/opt/boss", TEST_CACHE_PATH, true)?;
-    let mut repo = Repository::new(fetcher)?;
-    assert_eq!(0, repo.opened_catalogs.len());
+    let fetcher = Fetcher::new(
+        "http://cvmfs-stratum-one.cern.ch/opt/boss",
+        TEST_CACHE_PATH,
+        true,
+    )?;
+    let repo = Repository::new(fetcher)?;
+    assert!(repo.opened_catalog_hashes().is_empty());
     assert_eq!("boss.cern.ch", repo.fqrn);
     repo.retrieve_current_root_catalog()?;
     Ok(())
 }
+
+#[test]
+fn test_lookup_many() -> CvmfsResult<()> {
+    setup();
+    let fetcher = Fetcher::new(
+        "http://cvmfs-stratum-one.cern.ch/opt/boss",
+        TEST_CACHE_PATH,
+        true,
+    )?;
+    let repo = Repository::new(fetcher)?;
+    let results = repo.lookup_many(&["/", "/nonexistent_path"]);
+    assert_eq!(2, results.len());
+    assert!(results[0]
+        .as_ref()
+        .is_ok_and(|dirent| dirent.is_directory()));
+    assert!(results[1].is_err());
+    Ok(())
+}
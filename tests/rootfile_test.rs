@@ -0,0 +1,58 @@
+use cvmfs::common::{CvmfsError, CvmfsResult};
+use cvmfs::rootfile::RootFile;
+
+const CONTENTS: &str = "Cabc\nS12\nNtest.cern.ch\n";
+const CONTENTS_SHA1: &str = "fc175347b4ee70b56a48c04b783f8474d4be6ebe";
+
+#[test]
+fn test_unsigned_without_trailing_newline() -> CvmfsResult<()> {
+    let root_file = RootFile::from_bytes(b"Cabc\nS12")?;
+    assert!(!root_file.has_signature());
+    assert_eq!(None, root_file.signature());
+    assert_eq!(vec!["Cabc", "S12"], root_file.lines().collect::<Vec<_>>());
+    Ok(())
+}
+
+#[test]
+fn test_short_lines_and_crlf() -> CvmfsResult<()> {
+    let root_file = RootFile::from_bytes(b"C\r\n\n-\r\nS1\r\n")?;
+    assert_eq!(
+        vec!["C", "", "-", "S1"],
+        root_file.lines().collect::<Vec<_>>()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_signed_file() -> CvmfsResult<()> {
+    let mut bytes = CONTENTS.as_bytes().to_vec();
+    bytes.extend_from_slice(format!("--\r\n{}\n", CONTENTS_SHA1).as_bytes());
+    bytes.extend_from_slice(&[0u8, 1, 2, 3, b'\n', 255]);
+    let root_file = RootFile::from_bytes(&bytes)?;
+    assert!(root_file.has_signature());
+    assert_eq!(Some(CONTENTS_SHA1), root_file.checksum());
+    assert_eq!(Some(&[0u8, 1, 2, 3, b'\n', 255][..]), root_file.signature());
+    assert_eq!(3, root_file.lines().count());
+    Ok(())
+}
+
+#[test]
+fn test_malformed_signatures() {
+    assert_eq!(
+        Some(CvmfsError::IncompleteRootFileSignature),
+        RootFile::from_bytes(b"Cabc\n--").err()
+    );
+    assert_eq!(
+        Some(CvmfsError::InvalidRootFileSignature),
+        RootFile::from_bytes(b"Cabc\n--\nshort\n").err()
+    );
+    let tampered = format!("Cabd\nS12\nNtest.cern.ch\n--\n{}\n", CONTENTS_SHA1);
+    assert_eq!(
+        Some(CvmfsError::InvalidRootFileSignature),
+        RootFile::from_bytes(tampered.as_bytes()).err()
+    );
+    assert_eq!(
+        Some(CvmfsError::ParseError),
+        RootFile::from_bytes(&[0xff, 0xfe, b'\n']).err()
+    );
+}
@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use cvmfs::cache::{content_digest, record_digest, stored_digest, Cache};
+use cvmfs::common::{CvmfsError, CvmfsResult};
+use cvmfs::scrubber::{ScrubReport, Scrubber, ScrubberConfig};
+
+#[test]
+fn test_scrub_pass() -> CvmfsResult<()> {
+    let directory = std::env::temp_dir().join("cvmfs_scrubber_test");
+    let _ = std::fs::remove_dir_all(&directory);
+    let mut cache = Cache::new(directory.to_string_lossy().into_owned())?;
+    cache.record_digests = true;
+    cache.initialize()?;
+    let valid = cache.store("data/ab/valid", b"valid content")?;
+    let corrupt = cache.store("data/cd/corrupt", b"original content")?;
+    if stored_digest(&valid).is_none() {
+        // user extended attributes are not supported by the temporary directory
+        return Ok(());
+    }
+    assert_eq!(
+        Some(content_digest(b"valid content")),
+        stored_digest(&valid)
+    );
+    std::fs::write(&corrupt, b"flipped content")?;
+    cache.record_digests = false;
+    let unrecorded = cache.store("data/ef/unrecorded", b"more content")?;
+    assert_eq!(None, stored_digest(&unrecorded));
+
+    let scrubber_cache = cache.clone();
+    let scrubber = Scrubber::new(cache, ScrubberConfig::new(1 << 30))?;
+    scrubber.scrub_pass()?;
+    assert_eq!(
+        ScrubReport {
+            passes: 1,
+            scanned: 3,
+            bytes: 40,
+            recorded: 1,
+            evicted: 1,
+            unverifiable: 0,
+        },
+        scrubber.report()
+    );
+    assert!(valid.exists());
+    assert!(!corrupt.exists());
+    let quarantined = scrubber_cache.quarantined_objects()?;
+    assert_eq!(1, quarantined.len());
+    assert_eq!(b"flipped content".to_vec(), std::fs::read(&quarantined[0])?);
+    assert_eq!(
+        Some(content_digest(b"more content")),
+        stored_digest(&unrecorded)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_record_digest() -> CvmfsResult<()> {
+    let path = std::env::temp_dir().join("cvmfs_record_digest_test");
+    std::fs::write(&path, b"content")?;
+    if record_digest(&path, &content_digest(b"content")).is_ok() {
+        assert_eq!(Some(content_digest(b"content")), stored_digest(&path));
+    }
+    assert_eq!(64, content_digest(b"").len());
+    Ok(())
+}
+
+#[test]
+fn test_scrubber_handle() -> CvmfsResult<()> {
+    let directory = std::env::temp_dir().join("cvmfs_scrubber_handle_test");
+    let cache = Cache::new(directory.to_string_lossy().into_owned())?;
+    cache.initialize()?;
+    let mut config = ScrubberConfig::new(1024);
+    config.interval = Duration::from_secs(3600);
+    let mut handle = Scrubber::new(cache.clone(), config)?.spawn();
+    handle.stop();
+    assert!(matches!(
+        Scrubber::new(cache, ScrubberConfig::new(0)),
+        Err(CvmfsError::InvalidConfiguration(_))
+    ));
+    Ok(())
+}
@@ -0,0 +1,870 @@
+//! Hammers the file system operations of a mock repository from many threads,
+//! checking that they neither deadlock, leak descriptors nor corrupt data. The
+//! operations are called the way the FUSE threads call them, so that no mount
+//! is needed.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use fuse_mt::{FilesystemMT, RequestInfo};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rusqlite::{params, Connection};
+use sha1::{Digest, Sha1};
+
+use cvmfs::common::CvmfsResult;
+use cvmfs::fetcher::Fetcher;
+use cvmfs::file_system::CernvmFileSystem;
+use cvmfs::manifest::Manifest;
+use cvmfs::repository::{Repository, TrustSettings};
+use cvmfs::rootfile::RootFile;
+use cvmfs::validation::{ValidationMode, ValidationPolicy};
+
+mod common;
+use common::{grow_tree, write_catalog, zlib_stored, Tree};
+
+const FQRN: &str = "stress.cern.ch";
+const THREADS: usize = 16;
+const OPERATIONS_PER_THREAD: usize = 400;
+const DEADLOCK_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Files served by a running mock server, which a test can change to publish
+/// a new revision
+type ServedFiles = Arc<RwLock<HashMap<String, Vec<u8>>>>;
+
+/// Compressed objects of the mock repository, keyed by their url path
+#[derive(Default)]
+struct MockServer {
+    files: HashMap<String, Vec<u8>>,
+}
+
+impl MockServer {
+    /// Adds a content addressed object, returning its hash
+    fn add_object(&mut self, content: &[u8], suffix: &str) -> String {
+        let compressed = zlib_stored(content);
+        let hash = hex::encode(Sha1::digest(&compressed));
+        self.files.insert(
+            format!("/data/{}/{}{}", &hash[..2], &hash[2..], suffix),
+            compressed,
+        );
+        hash
+    }
+
+    /// Serves the files over HTTP, returning the url of the repository and
+    /// the files being served
+    fn serve(self) -> CvmfsResult<(String, ServedFiles)> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let files = Arc::new(RwLock::new(self.files));
+        let served = files.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let files = files.clone();
+                thread::spawn(move || {
+                    let mut request = [0u8; 4096];
+                    let read = stream.read(&mut request).unwrap_or(0);
+                    let request = String::from_utf8_lossy(&request[..read]);
+                    let path = request.split_whitespace().nth(1).unwrap_or("/");
+                    let body = files.read().unwrap().get(path).cloned();
+                    let status = if body.is_some() {
+                        "200 OK"
+                    } else {
+                        "404 Not Found"
+                    };
+                    let body = body.unwrap_or_default();
+                    let _ = write!(
+                        stream,
+                        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        status,
+                        body.len()
+                    );
+                    let _ = stream.write_all(&body);
+                });
+            }
+        });
+        Ok((url, served))
+    }
+}
+
+/// Random tree of the mock repository
+fn random_tree(rng: &mut StdRng) -> Tree {
+    grow_tree((0..120).map(|index| {
+        let parent = rng.gen();
+        let content = rng.gen_bool(0.75).then(|| {
+            let length = match rng.gen_range(0..10) {
+                0 => 0,
+                1 => rng.gen_range(65_536..200_000),
+                _ => rng.gen_range(1..8192),
+            };
+            (0..length).map(|_| rng.gen()).collect()
+        });
+        (parent, format!("entry{}", index), content)
+    }))
+}
+
+fn temporary_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("cvmfs_stress_{}", name));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+/// Catalog database of a tree, stored as an object with the given suffix
+fn build_catalog(
+    server: &mut MockServer,
+    tree: &Tree,
+    name: &str,
+    suffix: &str,
+) -> CvmfsResult<String> {
+    build_nested_catalog(server, tree, "", &[], name, suffix)
+}
+
+/// Same as `build_catalog`, for the catalog mounted at `root_prefix` (empty
+/// for the root catalog) with nested catalogs mounted at the given paths
+fn build_nested_catalog(
+    server: &mut MockServer,
+    tree: &Tree,
+    root_prefix: &str,
+    nested: &[(&str, String)],
+    name: &str,
+    suffix: &str,
+) -> CvmfsResult<String> {
+    let path = temporary_path(&format!("{}_catalog.db", name));
+    write_catalog(&path, tree, root_prefix, nested, |content| {
+        server.add_object(content, "")
+    })?;
+    Ok(server.add_object(&std::fs::read(&path)?, suffix))
+}
+
+/// History with a tag per root catalog, numbered as revisions from 1
+fn build_history(
+    server: &mut MockServer,
+    tags: &[(&str, String)],
+    name: &str,
+) -> CvmfsResult<String> {
+    let path = temporary_path(&format!("{}_history.db", name));
+    let connection = Connection::open(&path)?;
+    connection.execute_batch(&format!(
+        "CREATE TABLE properties (key TEXT, value TEXT);
+         INSERT INTO properties VALUES ('schema', '1.0'), ('fqrn', '{}');
+         CREATE TABLE tags (name TEXT, hash TEXT, revision INTEGER, timestamp INTEGER,
+                            channel INTEGER, description TEXT);",
+        FQRN
+    ))?;
+    for (revision, (tag, root_catalog)) in tags.iter().enumerate() {
+        connection.execute(
+            "INSERT INTO tags VALUES (?, ?, ?, 1700000000, 0, 'stress')",
+            params![tag, root_catalog, revision + 1],
+        )?;
+    }
+    drop(connection);
+    Ok(server.add_object(&std::fs::read(&path)?, "H"))
+}
+
+/// Mock repository served over HTTP, with a tag per tree and the last one
+/// as the current revision. The temporary files are named after each test,
+/// so that tests run in parallel.
+fn mock_repository(trees: &[(&str, &Tree)], name: &str) -> CvmfsResult<Repository> {
+    serve_repository(trees, name, false)
+}
+
+/// Same as `mock_repository`, optionally announcing a micro catalog with the
+/// top level entries of the current revision
+fn serve_repository(
+    trees: &[(&str, &Tree)],
+    name: &str,
+    micro_catalog: bool,
+) -> CvmfsResult<Repository> {
+    let mut server = MockServer::default();
+    let mut tags = Vec::new();
+    for (tag, tree) in trees {
+        let catalog_name = format!("{}_{}", name, tag);
+        tags.push((*tag, build_catalog(&mut server, tree, &catalog_name, "C")?));
+    }
+    let mut micro_catalog_line = String::new();
+    if let (true, Some((_, tree))) = (micro_catalog, trees.last()) {
+        let top_level: Tree = tree
+            .iter()
+            .filter(|(path, _)| path.rfind('/').unwrap_or(0) == 0)
+            .map(|(path, content)| (path.clone(), content.clone()))
+            .collect();
+        let micro_name = format!("{}_micro", name);
+        let hash = build_catalog(&mut server, &top_level, &micro_name, "L")?;
+        micro_catalog_line = format!("L{}\n", hash);
+    }
+    start_repository(server, &tags, &micro_catalog_line, name)
+}
+
+/// Manifest of a revision of the mock repository
+fn build_manifest(root_catalog: &str, history: &str, revision: u32, lines: &str) -> Vec<u8> {
+    format!(
+        "C{}\nB0\nRd41d8cd98f00b204e9800998ecf8427e\nD240\nS{}\nN{}\nH{}\n{}T{}\nX0000000000000000000000000000000000000000\n",
+        root_catalog,
+        revision,
+        FQRN,
+        history,
+        lines,
+        chrono::Utc::now().timestamp_millis()
+    )
+    .into_bytes()
+}
+
+/// Serves the history and the manifest of the given root catalogs, the last
+/// one as the current revision, along with the objects already added
+fn start_repository(
+    server: MockServer,
+    tags: &[(&str, String)],
+    manifest_lines: &str,
+    name: &str,
+) -> CvmfsResult<Repository> {
+    Ok(start_shared_repository(server, tags, manifest_lines, name)?.0)
+}
+
+/// Same as `start_repository`, also returning the files being served
+fn start_shared_repository(
+    mut server: MockServer,
+    tags: &[(&str, String)],
+    manifest_lines: &str,
+    name: &str,
+) -> CvmfsResult<(Repository, ServedFiles)> {
+    let root_catalog = tags
+        .last()
+        .map(|(_, hash)| hash.clone())
+        .unwrap_or_default();
+    let history = build_history(&mut server, tags, name)?;
+    server.files.insert(
+        "/.cvmfspublished".into(),
+        build_manifest(&root_catalog, &history, 1, manifest_lines),
+    );
+    let (url, files) = server.serve()?;
+
+    let cache_directory = std::env::temp_dir().join(format!("cvmfs_stress_{}_cache", name));
+    let _ = std::fs::remove_dir_all(&cache_directory);
+    let trust = TrustSettings {
+        validation: ValidationPolicy {
+            signature: ValidationMode::Ignore,
+            content_hash: ValidationMode::Fatal,
+            whitelist_expiry: ValidationMode::Ignore,
+        },
+        ..Default::default()
+    };
+    let fetcher = Fetcher::new(&url, cache_directory.to_str().unwrap(), true)?;
+    Ok((Repository::with_trust(fetcher, trust)?, files))
+}
+
+/// File system mounted on a mock repository with a single revision
+fn mock_file_system(tree: &Tree, name: &str) -> CvmfsResult<CernvmFileSystem> {
+    CernvmFileSystem::new(mock_repository(&[("trunk", tree)], name)?)
+}
+
+fn request() -> RequestInfo {
+    RequestInfo {
+        unique: 0,
+        uid: 0,
+        gid: 0,
+        pid: 0,
+    }
+}
+
+fn open_descriptors() -> usize {
+    std::fs::read_dir("/proc/self/fd").map_or(0, |entries| entries.count())
+}
+
+/// Runs one random operation against the file system, checking its outcome
+fn random_operation(
+    file_system: &CernvmFileSystem,
+    tree: &Tree,
+    paths: &[&String],
+    rng: &mut StdRng,
+) {
+    let entry = paths[rng.gen_range(0..paths.len())];
+    let path = if entry.is_empty() { "/" } else { entry };
+    let fuse_path = Path::new(path);
+    let (_, attributes) = file_system
+        .getattr(request(), fuse_path, None)
+        .unwrap_or_else(|e| panic!("getattr {}: {}", path, e));
+    match &tree[entry] {
+        Some(content) => {
+            assert_eq!(content.len() as u64, attributes.size, "{}", path);
+            let handle = file_system
+                .open(request(), fuse_path, 0)
+                .unwrap_or_else(|e| panic!("open {}: {}", path, e))
+                .0;
+            for _ in 0..rng.gen_range(1..4) {
+                let offset = rng.gen_range(0..=content.len());
+                let mut buffer = vec![0u8; rng.gen_range(1..16384)];
+                let read = file_system
+                    .read_into(path, handle, offset as u64, &mut buffer)
+                    .unwrap_or_else(|e| panic!("read {}: {}", path, e));
+                let expected = &content[offset..(offset + buffer.len()).min(content.len())];
+                assert_eq!(expected, &buffer[..read], "{} at {}", path, offset);
+            }
+            file_system
+                .release(request(), fuse_path, handle, 0, 0, false)
+                .unwrap_or_else(|e| panic!("release {}: {}", path, e));
+        }
+        None => {
+            let handle = file_system
+                .opendir(request(), fuse_path, 0)
+                .unwrap_or_else(|e| panic!("opendir {}: {}", path, e))
+                .0;
+            let mut listed: Vec<String> = file_system
+                .readdir(request(), fuse_path, handle)
+                .unwrap_or_else(|e| panic!("readdir {}: {}", path, e))
+                .into_iter()
+                .map(|entry| entry.name.to_string_lossy().into_owned())
+                .filter(|name| name != "." && name != "..")
+                .collect();
+            listed.sort();
+            let mut expected: Vec<String> = tree
+                .keys()
+                .filter(|child| {
+                    !child.is_empty() && child.rfind('/').map(|s| &child[..s]) == Some(entry)
+                })
+                .map(|child| child.rsplit('/').next().unwrap().to_string())
+                .collect();
+            expected.sort();
+            assert_eq!(expected, listed, "{}", path);
+            file_system
+                .releasedir(request(), fuse_path, handle, 0)
+                .unwrap_or_else(|e| panic!("releasedir {}: {}", path, e));
+        }
+    }
+}
+
+#[test]
+fn test_concurrent_operations() -> CvmfsResult<()> {
+    let tree = Arc::new(random_tree(&mut StdRng::seed_from_u64(7)));
+    let file_system = Arc::new(mock_file_system(&tree, "operations")?);
+    let paths: Vec<&String> = tree.keys().collect();
+    // a first sequential pass downloads everything, so that the descriptors
+    // of the caches are all opened before counting them
+    let mut rng = StdRng::seed_from_u64(0);
+    for _ in 0..paths.len() * 4 {
+        random_operation(&file_system, &tree, &paths, &mut rng);
+    }
+    Fetcher::flush_write_back();
+    let descriptors = open_descriptors();
+
+    let (sender, receiver) = mpsc::channel();
+    for thread_index in 0..THREADS {
+        let file_system = file_system.clone();
+        let tree = tree.clone();
+        let sender = sender.clone();
+        thread::spawn(move || {
+            let paths: Vec<&String> = tree.keys().collect();
+            let mut rng = StdRng::seed_from_u64(thread_index as u64 + 1);
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                for _ in 0..OPERATIONS_PER_THREAD {
+                    random_operation(&file_system, &tree, &paths, &mut rng);
+                }
+            }));
+            let _ = sender.send(result.is_ok());
+        });
+    }
+    drop(sender);
+    for _ in 0..THREADS {
+        let succeeded = receiver
+            .recv_timeout(DEADLOCK_TIMEOUT)
+            .expect("The operations did not finish, they are probably deadlocked");
+        assert!(succeeded, "An operation failed, see the panic above");
+    }
+
+    assert_eq!(0, file_system.memory_usage()?.open_files);
+    assert!(
+        open_descriptors() <= descriptors,
+        "{} descriptors open, {} before the concurrent operations",
+        open_descriptors(),
+        descriptors
+    );
+    Ok(())
+}
+
+#[test]
+fn test_walk() -> CvmfsResult<()> {
+    let tree = random_tree(&mut StdRng::seed_from_u64(1));
+    let file_system = mock_file_system(&tree, "walk")?;
+    let repository = file_system.repository();
+    let repository = repository.read().unwrap();
+
+    let walked = repository
+        .walk("/")?
+        .map(|wrapper| wrapper.map(|wrapper| wrapper.path))
+        .collect::<CvmfsResult<Vec<_>>>()?;
+    let mut expected: Vec<String> = tree.keys().skip(1).cloned().collect();
+    expected.insert(0, "/".into());
+    assert_eq!(expected.len(), walked.len());
+    let mut sorted = walked.clone();
+    sorted.sort();
+    assert_eq!(expected, sorted);
+    // every directory comes before its contents
+    for (index, path) in walked.iter().enumerate().skip(1) {
+        let parent = &path[..path.rfind('/').unwrap()];
+        let parent = if parent.is_empty() { "/" } else { parent };
+        assert!(
+            walked[..index].iter().any(|walked| walked == parent),
+            "{}",
+            path
+        );
+    }
+
+    let (directory, _) = tree
+        .iter()
+        .find(|(path, content)| !path.is_empty() && content.is_none())
+        .unwrap();
+    for wrapper in repository.walk(directory)? {
+        assert!(wrapper?.path.starts_with(directory.as_str()));
+    }
+    assert!(repository.walk("/missing").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_diff() -> CvmfsResult<()> {
+    use cvmfs::diff::ChangeKind;
+
+    let old = random_tree(&mut StdRng::seed_from_u64(2));
+    let mut new = old.clone();
+    let (modified, _) = old
+        .iter()
+        .find(|(_, content)| content.as_ref().is_some_and(|content| !content.is_empty()))
+        .unwrap();
+    new.insert(modified.clone(), Some(b"modified".to_vec()));
+    let (removed, _) = old
+        .iter()
+        .rev()
+        .find(|(path, _)| {
+            !old.keys()
+                .any(|other| other.starts_with(&format!("{}/", path)))
+        })
+        .unwrap();
+    new.remove(removed);
+    new.insert("/added".into(), None);
+    new.insert("/added/file".into(), Some(b"new".to_vec()));
+    let repository = mock_repository(&[("v1", &old), ("v2", &new)], "diff")?;
+
+    let tags: Vec<(String, i32)> = repository
+        .list_tags()?
+        .into_iter()
+        .map(|tag| (tag.name, tag.revision))
+        .collect();
+    assert_eq!(vec![("v1".to_string(), 1), ("v2".to_string(), 2)], tags);
+    assert!(repository.diff("v1", "v1")?.is_empty());
+    let changes = repository.diff("v1", "v2")?;
+    let summary: Vec<(ChangeKind, &str)> = changes
+        .iter()
+        .map(|change| (change.kind, change.path.as_str()))
+        .collect();
+    assert!(summary.contains(&(ChangeKind::Added, "/added")));
+    assert!(summary.contains(&(ChangeKind::Added, "/added/file")));
+    assert!(summary.contains(&(ChangeKind::Removed, removed.as_str())));
+    assert!(summary.contains(&(ChangeKind::Modified, modified.as_str())));
+    assert_eq!(4, summary.len(), "{:?}", summary);
+    let change = changes
+        .iter()
+        .find(|change| &change.path == modified)
+        .unwrap();
+    assert!(change.fields.contains(&"content"));
+    assert!(change
+        .to_string()
+        .starts_with(&format!("M {} (content", modified)));
+    assert!(repository.diff("v1", "missing").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_export() -> CvmfsResult<()> {
+    use cvmfs::export::ExportTarget;
+
+    let tree = random_tree(&mut StdRng::seed_from_u64(3));
+    let repository = mock_repository(&[("v1", &tree)], "export")?;
+    let files = tree.values().filter(|content| content.is_some()).count() as u64;
+    let bytes: u64 = tree
+        .values()
+        .flatten()
+        .map(|content| content.len() as u64)
+        .sum();
+
+    let destination = std::env::temp_dir().join("cvmfs_stress_export");
+    let _ = std::fs::remove_dir_all(&destination);
+    let summary = repository.export("/", &ExportTarget::Directory(destination.clone()))?;
+    assert_eq!(files, summary.files);
+    assert_eq!(tree.len() as u64 - files, summary.directories);
+    assert_eq!(bytes, summary.bytes);
+    for (path, content) in &tree {
+        let local_path = destination.join(path.trim_start_matches('/'));
+        match content {
+            Some(content) => assert_eq!(content, &std::fs::read(&local_path)?, "{}", path),
+            None => assert!(local_path.is_dir(), "{}", path),
+        }
+    }
+    // exporting again replaces the files
+    repository.export("/", &ExportTarget::Directory(destination.clone()))?;
+
+    let archive = temporary_path("export.tar");
+    let summary = repository.export("/", &ExportTarget::from_path(&archive))?;
+    assert_eq!(files, summary.files);
+    let length = std::fs::metadata(&archive)?.len();
+    assert_eq!(0, length % 512);
+    assert!(length >= bytes + 1024);
+    assert!(repository
+        .export("/missing", &ExportTarget::Directory(destination))
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_micro_catalog() -> CvmfsResult<()> {
+    let tree = random_tree(&mut StdRng::seed_from_u64(4));
+    let repository = serve_repository(&[("v1", &tree)], "micro", true)?;
+    let root_hash = repository.manifest.root_catalog.clone();
+    let root_catalog = std::env::temp_dir()
+        .join("cvmfs_stress_micro_cache")
+        .join(format!("data/{}/{}C", &root_hash[..2], &root_hash[2..]));
+    let top_level: Vec<&String> = tree
+        .keys()
+        .filter(|path| !path.is_empty() && path.rfind('/') == Some(0))
+        .collect();
+
+    // the top level is looked up without downloading the root catalog
+    assert!(repository.lookup("/")?.is_directory());
+    for path in &top_level {
+        assert_eq!(
+            tree[*path].is_none(),
+            repository.lookup(path)?.is_directory()
+        );
+    }
+    assert!(!root_catalog.exists());
+
+    // listings need the root catalog, which then answers for everything
+    let mut listed: Vec<String> = repository
+        .list_directory("/")?
+        .into_iter()
+        .map(|entry| format!("/{}", entry.name))
+        .collect();
+    listed.sort();
+    assert_eq!(top_level, listed.iter().collect::<Vec<_>>());
+    assert!(root_catalog.exists());
+    let (deep, _) = tree
+        .iter()
+        .find(|(path, _)| path.rfind('/').unwrap_or(0) > 0)
+        .unwrap();
+    repository.lookup(deep)?;
+    assert!(repository.lookup("/missing").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_micro_catalog_fallback() -> CvmfsResult<()> {
+    let tree = random_tree(&mut StdRng::seed_from_u64(8));
+    let mut top_level: Tree = tree
+        .iter()
+        .filter(|(path, _)| path.rfind('/').unwrap_or(0) == 0)
+        .map(|(path, content)| (path.clone(), content.clone()))
+        .collect();
+    let only_in_root = top_level
+        .keys()
+        .find(|path| !path.is_empty())
+        .unwrap()
+        .clone();
+    top_level.remove(&only_in_root);
+    let mut server = MockServer::default();
+    let root_hash = build_catalog(&mut server, &tree, "fallback", "C")?;
+    let micro_hash = build_catalog(&mut server, &top_level, "fallback_micro", "L")?;
+    let repository = start_repository(
+        server,
+        &[("v1", root_hash.clone())],
+        &format!("L{}\n", micro_hash),
+        "fallback",
+    )?;
+    let root_catalog = std::env::temp_dir()
+        .join("cvmfs_stress_fallback_cache")
+        .join(format!("data/{}/{}C", &root_hash[..2], &root_hash[2..]));
+
+    // an entry missing from the micro catalog is found in the root catalog
+    assert!(repository.lookup("/")?.is_directory());
+    assert!(!root_catalog.exists());
+    assert_eq!(
+        tree[&only_in_root].is_none(),
+        repository.lookup(&only_in_root)?.is_directory()
+    );
+    assert!(root_catalog.exists());
+    Ok(())
+}
+
+#[test]
+fn test_failed_refresh() -> CvmfsResult<()> {
+    let first = random_tree(&mut StdRng::seed_from_u64(5));
+    let second = random_tree(&mut StdRng::seed_from_u64(6));
+    let mut server = MockServer::default();
+    let first_catalog = build_catalog(&mut server, &first, "refresh_v1", "C")?;
+    let second_catalog = build_catalog(&mut server, &second, "refresh_v2", "C")?;
+    // the history published along the new revision lacks its tag
+    let stale_history = build_history(
+        &mut server,
+        &[("v1", first_catalog.clone())],
+        "refresh_stale",
+    )?;
+    let broken_manifest = build_manifest(&second_catalog, &stale_history, 2, "");
+    let (mut repository, files) =
+        start_shared_repository(server, &[("v1", first_catalog.clone())], "", "refresh")?;
+    files
+        .write()
+        .unwrap()
+        .insert("/.cvmfspublished".into(), broken_manifest);
+    assert!(repository.refresh().is_err());
+    assert_eq!(1, repository.manifest.revision);
+    assert_eq!(first_catalog, repository.get_root_hash()?.to_string());
+    assert_eq!(
+        Some(1),
+        repository
+            .cache()
+            .load_breadcrumb(FQRN)
+            .map(|breadcrumb| breadcrumb.revision)
+    );
+    // the rejected manifest did not replace the cached one
+    let cached_manifest = repository.cache().get(".cvmfspublished").unwrap();
+    let cached_manifest = Manifest::new(RootFile::from_bytes(&std::fs::read(cached_manifest)?)?)?;
+    assert_eq!(first_catalog, cached_manifest.root_catalog);
+    let (path, _) = first.iter().find(|(_, content)| content.is_some()).unwrap();
+    repository.lookup(path)?;
+
+    // the revision is switched to once it is published properly
+    let mut update = MockServer::default();
+    let history = build_history(
+        &mut update,
+        &[("v1", first_catalog), ("v2", second_catalog.clone())],
+        "refresh_fixed",
+    )?;
+    update.files.insert(
+        "/.cvmfspublished".into(),
+        build_manifest(&second_catalog, &history, 2, ""),
+    );
+    files.write().unwrap().extend(update.files);
+    // the revision is downloaded without switching to it
+    let prepared = repository.prepare_refresh();
+    assert!(prepared.is_ok());
+    assert_eq!(1, repository.manifest.revision);
+    assert!(repository.apply_refresh(prepared)?);
+    assert_eq!(second_catalog, repository.get_root_hash()?.to_string());
+    assert_eq!(2, repository.get_revision_number()?);
+    assert_eq!(
+        Some(2),
+        repository
+            .cache()
+            .load_breadcrumb(FQRN)
+            .map(|breadcrumb| breadcrumb.revision)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_open_across_revisions() -> CvmfsResult<()> {
+    let tree = |content: &[u8]| -> Tree {
+        BTreeMap::from([
+            (String::new(), None),
+            ("/file".into(), Some(content.to_vec())),
+        ])
+    };
+    let mut server = MockServer::default();
+    let first = build_catalog(&mut server, &tree(b"first"), "reopen_v1", "C")?;
+    let second = build_catalog(&mut server, &tree(b"second revision"), "reopen_v2", "C")?;
+    let (repository, files) =
+        start_shared_repository(server, &[("v1", first.clone())], "", "reopen")?;
+    let file_system = CernvmFileSystem::new(repository)?;
+    let path = Path::new("/file");
+    let read = |handle| -> CvmfsResult<Vec<u8>> {
+        let mut buffer = vec![0u8; 64];
+        let read = file_system
+            .read_into("/file", handle, 0, &mut buffer)
+            .map_err(|e| format!("read: {}", e))?;
+        buffer.truncate(read);
+        Ok(buffer)
+    };
+    let old_handle = file_system
+        .open(request(), path, 0)
+        .map_err(|e| format!("open: {}", e))?
+        .0;
+
+    let mut update = MockServer::default();
+    let history = build_history(
+        &mut update,
+        &[("v1", first), ("v2", second.clone())],
+        "reopen",
+    )?;
+    update.files.insert(
+        "/.cvmfspublished".into(),
+        build_manifest(&second, &history, 2, ""),
+    );
+    files.write().unwrap().extend(update.files);
+    assert!(file_system.repository().write().unwrap().refresh()?);
+
+    // the new revision is opened next to the file still read by the old handle
+    let new_handle = file_system
+        .open(request(), path, 0)
+        .map_err(|e| format!("open: {}", e))?
+        .0;
+    assert_ne!(old_handle, new_handle);
+    assert_eq!(b"first".to_vec(), read(old_handle)?);
+    assert_eq!(b"second revision".to_vec(), read(new_handle)?);
+    assert_eq!(2, file_system.memory_usage()?.open_files);
+    for handle in [old_handle, new_handle] {
+        file_system
+            .release(request(), path, handle, 0, 0, false)
+            .map_err(|e| format!("release: {}", e))?;
+    }
+    assert_eq!(0, file_system.memory_usage()?.open_files);
+    Ok(())
+}
+
+#[test]
+fn test_preload_catalogs() -> CvmfsResult<()> {
+    let tree = |paths: &[&str]| -> Tree {
+        paths
+            .iter()
+            .map(|path| {
+                let content = path.contains('.').then(|| path.as_bytes().to_vec());
+                (path.to_string(), content)
+            })
+            .collect()
+    };
+    let mut server = MockServer::default();
+    let deep = build_nested_catalog(
+        &mut server,
+        &tree(&["/a/b", "/a/b/deep.txt"]),
+        "/a/b",
+        &[],
+        "preload_deep",
+        "C",
+    )?;
+    let a = build_nested_catalog(
+        &mut server,
+        &tree(&["/a", "/a/b", "/a/a.txt"]),
+        "/a",
+        &[("/a/b", deep.clone())],
+        "preload_a",
+        "C",
+    )?;
+    let other = build_nested_catalog(
+        &mut server,
+        &tree(&["/ab", "/ab/other.txt"]),
+        "/ab",
+        &[],
+        "preload_other",
+        "C",
+    )?;
+    let root = build_nested_catalog(
+        &mut server,
+        &tree(&["", "/a", "/ab", "/root.txt"]),
+        "",
+        &[("/a", a.clone()), ("/ab", other.clone())],
+        "preload_root",
+        "C",
+    )?;
+    let repository = start_repository(server, &[("v1", root)], "", "preload")?;
+    let is_cached = |hash: &str| {
+        std::env::temp_dir()
+            .join("cvmfs_stress_preload_cache")
+            .join(format!("data/{}/{}C", &hash[..2], &hash[2..]))
+            .exists()
+    };
+
+    // /ab is not below /a, although it shares its prefix
+    assert_eq!(2, repository.preload_catalogs("/a/")?);
+    assert!(is_cached(&a) && is_cached(&deep));
+    assert_eq!(1, repository.preload_catalogs("/a/b")?);
+    assert_eq!(4, repository.preload_catalogs("/")?);
+    assert!(is_cached(&other));
+    let mut content = Vec::new();
+    repository
+        .get_file("/a/b/deep.txt")?
+        .read_to_end(&mut content)?;
+    assert_eq!(b"/a/b/deep.txt".to_vec(), content);
+    assert_eq!(1, repository.preload_catalogs("/missing")?);
+    Ok(())
+}
+
+#[test]
+fn test_lookup_many() -> CvmfsResult<()> {
+    let tree = random_tree(&mut StdRng::seed_from_u64(9));
+    let (mountpoint, _) = tree
+        .iter()
+        .find(|(path, content)| !path.is_empty() && content.is_none())
+        .unwrap();
+    let (outer, inner): (Tree, Tree) = tree
+        .clone()
+        .into_iter()
+        .partition(|(path, _)| !path.starts_with(mountpoint.as_str()) || path == mountpoint);
+    let mut inner = inner;
+    inner.insert(mountpoint.clone(), None);
+    let mut server = MockServer::default();
+    let nested = build_nested_catalog(&mut server, &inner, mountpoint, &[], "many_nested", "C")?;
+    let root = build_nested_catalog(
+        &mut server,
+        &outer,
+        "",
+        &[(mountpoint.as_str(), nested)],
+        "many_root",
+        "C",
+    )?;
+    let repository = start_repository(server, &[("v1", root)], "", "many")?;
+
+    // every entry is found in the catalog serving it, in the order asked
+    let mut paths: Vec<&str> = tree.keys().map(String::as_str).collect();
+    paths.push("/missing");
+    paths.reverse();
+    let results = repository.lookup_many(&paths);
+    assert_eq!(paths.len(), results.len());
+    assert!(results[0].is_err());
+    for (path, result) in paths.iter().zip(&results).skip(1) {
+        let dirent = result.as_ref().map_err(|e| format!("{}: {}", path, e))?;
+        assert_eq!(path.rsplit('/').next(), Some(dirent.name.as_str()));
+        assert_eq!(tree[*path].is_none(), dirent.is_directory());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_preload() -> CvmfsResult<()> {
+    use cvmfs::preload::PreloadSpec;
+
+    let tree = random_tree(&mut StdRng::seed_from_u64(5));
+    let repository = mock_repository(&[("v1", &tree)], "preload_files")?;
+    let (excluded, _) = tree
+        .iter()
+        .find(|(path, content)| path.rfind('/') == Some(0) && content.is_none())
+        .unwrap();
+    let spec = PreloadSpec::parse(&format!("/entry*\n!{}", excluded))?;
+    let summary = repository.preload(&spec)?;
+    let expected: Vec<&Vec<u8>> = tree
+        .iter()
+        .filter(|(path, _)| !path.starts_with(&format!("{}/", excluded)))
+        .filter_map(|(_, content)| content.as_ref())
+        .collect();
+    assert_eq!(expected.len() as u64, summary.files);
+    assert_eq!(
+        expected
+            .iter()
+            .map(|content| content.len() as u64)
+            .sum::<u64>(),
+        summary.bytes
+    );
+    assert!(summary.missing.is_empty());
+    assert_eq!(0, summary.cached);
+
+    let summary = repository.preload(&PreloadSpec::from_paths(&["/".into()])?)?;
+    assert_eq!(tree.values().flatten().count() as u64, summary.files);
+    assert!(summary.cached > 0 && summary.cached < summary.objects);
+    assert!(summary.missing.is_empty());
+    let summary = repository.preload(&PreloadSpec::from_paths(&["/".into()])?)?;
+    assert_eq!(summary.objects, summary.cached);
+    assert!(repository
+        .preload(&PreloadSpec::from_paths(&["/missing".into()])?)
+        .is_err());
+    Ok(())
+}
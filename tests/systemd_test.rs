@@ -0,0 +1,45 @@
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+
+use cvmfs::common::CvmfsResult;
+use cvmfs::systemd::{listen_fds, notify_address, watchdog_interval};
+
+#[test]
+fn test_watchdog_interval() {
+    assert_eq!(
+        Some(Duration::from_secs(15)),
+        watchdog_interval(Some("30000000"), None, 42)
+    );
+    assert_eq!(
+        Some(Duration::from_secs(15)),
+        watchdog_interval(Some("30000000"), Some("42"), 42)
+    );
+    assert_eq!(None, watchdog_interval(Some("30000000"), Some("43"), 42));
+    assert_eq!(None, watchdog_interval(Some("0"), None, 42));
+    assert_eq!(None, watchdog_interval(None, None, 42));
+}
+
+#[test]
+fn test_listen_fds() {
+    assert_eq!(1, listen_fds(Some("1"), Some("42"), 42));
+    assert_eq!(0, listen_fds(Some("1"), Some("43"), 42));
+    assert_eq!(0, listen_fds(Some("1"), None, 42));
+    assert_eq!(0, listen_fds(None, Some("42"), 42));
+    assert_eq!(0, listen_fds(Some("x"), Some("42"), 42));
+}
+
+#[test]
+fn test_notify_address() -> CvmfsResult<()> {
+    let path = std::env::temp_dir().join("cvmfs_systemd_test_notify");
+    let _ = std::fs::remove_file(&path);
+    let socket = UnixDatagram::bind(&path)?;
+    let address = notify_address(path.to_str().unwrap())?;
+    let sender = UnixDatagram::unbound()?;
+    sender.send_to_addr(b"READY=1", &address)?;
+    let mut buffer = [0u8; 16];
+    let read = socket.recv(&mut buffer)?;
+    assert_eq!(b"READY=1", &buffer[..read]);
+    assert!(notify_address("@cvmfs/notify").is_ok());
+    assert!(notify_address("relative/path").is_err());
+    Ok(())
+}
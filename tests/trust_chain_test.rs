@@ -0,0 +1,499 @@
+//! Verification of the manifest signature through the whole trust chain, over
+//! a mock repository signed with keys generated on the fly.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+
+use openssl::asn1::Asn1Time;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::rsa::{Padding, Rsa};
+use openssl::sign::Signer;
+use openssl::x509::{X509NameBuilder, X509};
+use rusqlite::Connection;
+use sha1::{Digest, Sha1};
+
+use cvmfs::certificate::Certificate;
+use cvmfs::common::{CvmfsError, CvmfsResult};
+use cvmfs::fetcher::Fetcher;
+use cvmfs::manifest::Manifest;
+use cvmfs::repository::{Repository, TrustSettings};
+use cvmfs::rootfile::RootFile;
+use cvmfs::validation::{ValidationMode, ValidationPolicy};
+use cvmfs::whitelist::ExpiryPolicy;
+
+mod common;
+use common::zlib_stored;
+
+const FQRN: &str = "trust.cern.ch";
+
+fn rsa_key() -> PKey<Private> {
+    PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap()
+}
+
+fn self_signed_certificate(key: &PKey<Private>) -> Vec<u8> {
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", FQRN).unwrap();
+    let name = name.build();
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder.set_pubkey(key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(30).unwrap())
+        .unwrap();
+    builder.sign(key, MessageDigest::sha256()).unwrap();
+    builder.build().to_pem().unwrap()
+}
+
+/// Root file with its checksum line, followed by the signature of the
+/// checksum made by `sign`
+fn signed_root_file(contents: &str, sign: impl Fn(&[u8]) -> Vec<u8>) -> Vec<u8> {
+    let checksum = hex::encode(Sha1::digest(contents.as_bytes()));
+    let mut root_file = format!("{}--\n{}\n", contents, checksum).into_bytes();
+    root_file.extend(sign(checksum.as_bytes()));
+    root_file
+}
+
+/// Signature of the manifest, made with the key of the certificate
+fn certificate_signature(key: &PKey<Private>, message: &[u8]) -> Vec<u8> {
+    let mut signer = Signer::new(MessageDigest::sha1(), key).unwrap();
+    signer.update(message).unwrap();
+    signer.sign_to_vec().unwrap()
+}
+
+/// Signature of the whitelist, the checksum encrypted with the master key
+fn master_key_signature(key: &PKey<Private>, message: &[u8]) -> Vec<u8> {
+    let rsa = key.rsa().unwrap();
+    let mut signature = vec![0; rsa.size() as usize];
+    let length = rsa
+        .private_encrypt(message, &mut signature, Padding::PKCS1)
+        .unwrap();
+    signature.truncate(length);
+    signature
+}
+
+/// Keys signing the mock repository
+struct Signers {
+    master_key: PKey<Private>,
+    certificate_key: PKey<Private>,
+    /// Key the manifest is actually signed with
+    manifest_key: PKey<Private>,
+    /// Whether the whitelist lists the certificate
+    listed: bool,
+    /// Days until the whitelist expires
+    expires_in: i64,
+    /// Repository named in the whitelist
+    whitelist_name: &'static str,
+}
+
+impl Signers {
+    fn valid() -> Self {
+        let certificate_key = rsa_key();
+        Self {
+            master_key: rsa_key(),
+            manifest_key: certificate_key.clone(),
+            certificate_key,
+            listed: true,
+            expires_in: 30,
+            whitelist_name: FQRN,
+        }
+    }
+}
+
+fn add_object(files: &mut HashMap<String, Vec<u8>>, content: &[u8], suffix: &str) -> String {
+    let compressed = zlib_stored(content);
+    let hash = hex::encode(Sha1::digest(&compressed));
+    files.insert(
+        format!("/data/{}/{}{}", &hash[..2], &hash[2..], suffix),
+        compressed,
+    );
+    hash
+}
+
+fn database(name: &str, schema: &str) -> CvmfsResult<Vec<u8>> {
+    let path = std::env::temp_dir().join(format!("cvmfs_trust_chain_{}.db", name));
+    let _ = std::fs::remove_file(&path);
+    Connection::open(&path)?.execute_batch(schema)?;
+    Ok(std::fs::read(&path)?)
+}
+
+/// Serves a repository signed by the given keys, returning its url and the
+/// keys directory holding its master key
+fn serve(name: &str, signers: &Signers) -> CvmfsResult<(String, PathBuf)> {
+    let mut files = HashMap::new();
+    let catalog = database(
+        &format!("{}_catalog", name),
+        "CREATE TABLE properties (key TEXT, value TEXT);
+         INSERT INTO properties VALUES ('revision', '1'), ('schema', '2.5');
+         CREATE TABLE catalog (md5path_1 INTEGER, md5path_2 INTEGER, parent_1 INTEGER, \
+         parent_2 INTEGER, hash BLOB, flags INTEGER, size INTEGER, mode INTEGER, \
+         mtime INTEGER, name TEXT, symlink TEXT);",
+    )?;
+    let root_catalog = add_object(&mut files, &catalog, "C");
+    let history = database(
+        &format!("{}_history", name),
+        &format!(
+            "CREATE TABLE properties (key TEXT, value TEXT);
+             INSERT INTO properties VALUES ('schema', '1.0'), ('fqrn', '{}');
+             CREATE TABLE tags (name TEXT, hash TEXT, revision INTEGER, timestamp INTEGER,
+                                channel INTEGER, description TEXT);
+             INSERT INTO tags VALUES ('trunk', '{}', 1, 1700000000, 0, 'trust');",
+            FQRN, root_catalog
+        ),
+    )?;
+    let history = add_object(&mut files, &history, "H");
+    let certificate_pem = self_signed_certificate(&signers.certificate_key);
+    let certificate = add_object(&mut files, &certificate_pem, "X");
+    let fingerprint = Certificate::from_pem(&certificate_pem)?.fingerprint()?;
+
+    let manifest = format!(
+        "C{}\nB0\nRd41d8cd98f00b204e9800998ecf8427e\nD240\nS1\nN{}\nH{}\nT{}\nX{}\n",
+        root_catalog,
+        FQRN,
+        history,
+        chrono::Utc::now().timestamp(),
+        certificate
+    );
+    files.insert(
+        "/.cvmfspublished".into(),
+        signed_root_file(&manifest, |checksum| {
+            certificate_signature(&signers.manifest_key, checksum)
+        }),
+    );
+    let whitelist = format!(
+        "20240101000000\nE{}\nN{}\n{}\n",
+        (chrono::Utc::now() + chrono::TimeDelta::days(signers.expires_in)).format("%Y%m%d%H%M%S"),
+        signers.whitelist_name,
+        if signers.listed {
+            fingerprint
+        } else {
+            "00:11:22:33:44:55:66:77:88:99:AA:BB:CC:DD:EE:FF:00:11:22:33".into()
+        }
+    );
+    files.insert(
+        "/.cvmfswhitelist".into(),
+        signed_root_file(&whitelist, |checksum| {
+            master_key_signature(&signers.master_key, checksum)
+        }),
+    );
+
+    let keys_directory = std::env::temp_dir().join(format!("cvmfs_trust_chain_{}_keys", name));
+    let _ = std::fs::remove_dir_all(&keys_directory);
+    std::fs::create_dir_all(&keys_directory)?;
+    std::fs::write(
+        keys_directory.join(format!("{}.pub", FQRN)),
+        signers.master_key.public_key_to_pem().unwrap(),
+    )?;
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}", listener.local_addr()?);
+    let files = Arc::new(files);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut request = [0u8; 4096];
+            let read = stream.read(&mut request).unwrap_or(0);
+            let request = String::from_utf8_lossy(&request[..read]);
+            let path = request.split_whitespace().nth(1).unwrap_or("/");
+            let (status, body) = match files.get(path) {
+                Some(body) => ("200 OK", body.as_slice()),
+                None => ("404 Not Found", &[][..]),
+            };
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                body.len()
+            );
+            let _ = stream.write_all(body);
+        }
+    });
+    Ok((url, keys_directory))
+}
+
+fn open_repository(name: &str, signers: &Signers) -> CvmfsResult<Repository> {
+    open_repository_with(name, signers, |_| {})
+}
+
+/// Opens the repository served for the signers, with trust settings adjusted
+/// on top of the keys of the mock server
+fn open_repository_with(
+    name: &str,
+    signers: &Signers,
+    adjust: impl FnOnce(&mut TrustSettings),
+) -> CvmfsResult<Repository> {
+    let (url, keys_directory) = serve(name, signers)?;
+    let cache = std::env::temp_dir().join(format!("cvmfs_trust_chain_{}_cache", name));
+    let _ = std::fs::remove_dir_all(&cache);
+    let mut trust = TrustSettings {
+        keys_directory,
+        ..Default::default()
+    };
+    adjust(&mut trust);
+    Repository::with_trust(Fetcher::new(&url, cache.to_str().unwrap(), true)?, trust)
+}
+
+#[test]
+fn test_manifest_signature() -> CvmfsResult<()> {
+    let key = rsa_key();
+    let certificate = Certificate::from_pem(&self_signed_certificate(&key))?;
+    let contents = "C600230b0ba7620426f2e898f1e1f43c5466efe59\nNtrust.cern.ch\nS1\n";
+    let manifest = |bytes: Vec<u8>| Manifest::new(RootFile::from_bytes(&bytes)?);
+
+    let signed = manifest(signed_root_file(contents, |checksum| {
+        certificate_signature(&key, checksum)
+    }))?;
+    signed.verify_signature(&certificate)?;
+    let forged = manifest(signed_root_file(contents, |checksum| {
+        certificate_signature(&rsa_key(), checksum)
+    }))?;
+    assert!(matches!(
+        forged.verify_signature(&certificate),
+        Err(CvmfsError::InvalidManifestSignature)
+    ));
+    let unsigned = manifest(contents.as_bytes().to_vec())?;
+    assert!(matches!(
+        unsigned.verify_signature(&certificate),
+        Err(CvmfsError::IncompleteRootFileSignature)
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_trusted_repository() -> CvmfsResult<()> {
+    let repository = open_repository("trusted", &Signers::valid())?;
+    assert_eq!(
+        ValidationMode::Fatal,
+        repository.validation_policy().signature
+    );
+    assert!(repository.certificate_hash().is_some());
+    Ok(())
+}
+
+#[test]
+fn test_untrusted_repositories() -> CvmfsResult<()> {
+    let forged = Signers {
+        manifest_key: rsa_key(),
+        ..Signers::valid()
+    };
+    let result = open_repository("forged", &forged).map(|_| ());
+    assert!(
+        matches!(result, Err(CvmfsError::InvalidManifestSignature)),
+        "{:?}",
+        result
+    );
+    // the escape hatch skips the whole chain
+    let repository = open_repository_with("forged_insecure", &forged, |trust| {
+        trust.validation = ValidationPolicy {
+            signature: ValidationMode::Ignore,
+            ..Default::default()
+        }
+    })?;
+    assert!(repository.certificate_hash().is_none());
+
+    let result = open_repository(
+        "unlisted",
+        &Signers {
+            listed: false,
+            ..Signers::valid()
+        },
+    )
+    .map(|_| ());
+    assert!(
+        matches!(result, Err(CvmfsError::UntrustedCertificate(_))),
+        "{:?}",
+        result
+    );
+
+    let result = open_repository_with("unknown_master_key", &Signers::valid(), |trust| {
+        trust.keys_directory = std::env::temp_dir().join("cvmfs_trust_chain_no_keys")
+    });
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[test]
+fn test_repository_name_mismatch() -> CvmfsResult<()> {
+    let result = open_repository(
+        "other_whitelist",
+        &Signers {
+            whitelist_name: "other.cern.ch",
+            ..Signers::valid()
+        },
+    )
+    .map(|_| ());
+    assert!(
+        matches!(result, Err(CvmfsError::RepositoryNameMismatch(_))),
+        "{:?}",
+        result
+    );
+
+    // a validly signed repository mounted under another name
+    let result = open_repository_with("other_mount", &Signers::valid(), |trust| {
+        trust.expected_fqrn = Some("other.cern.ch".into())
+    })
+    .map(|_| ());
+    assert!(
+        matches!(result, Err(CvmfsError::RepositoryNameMismatch(_))),
+        "{:?}",
+        result
+    );
+    open_repository_with("same_mount", &Signers::valid(), |trust| {
+        trust.expected_fqrn = Some(FQRN.into())
+    })?;
+    Ok(())
+}
+
+#[test]
+fn test_expired_whitelist() -> CvmfsResult<()> {
+    let expired = Signers {
+        expires_in: -1,
+        ..Signers::valid()
+    };
+    let repository = open_repository("expired", &expired)?;
+    assert!(repository.degraded);
+    let result = open_repository_with("expired_unmount", &expired, |trust| {
+        trust.whitelist_expiry_policy = ExpiryPolicy::Unmount
+    })
+    .map(|_| ());
+    assert!(matches!(result, Err(CvmfsError::WhitelistExpired)));
+
+    let repository = open_repository_with("renewed", &Signers::valid(), |trust| {
+        trust.whitelist_expiry_policy = ExpiryPolicy::Unmount
+    })?;
+    assert!(!repository.degraded);
+    Ok(())
+}
+
+#[test]
+fn test_refresh_schedule() -> CvmfsResult<()> {
+    use std::time::Duration;
+
+    let mut repository = open_repository("refresh", &Signers::valid())?;
+    // the manifest announces a TTL of 240 seconds
+    let wait = repository.time_to_refresh();
+    assert!(wait > Duration::from_secs(230) && wait <= Duration::from_secs(240));
+    assert!(!repository.refresh_if_due()?);
+    // the same revision is still published
+    assert!(!repository.refresh()?);
+    assert_eq!(1, repository.manifest.revision);
+    assert!(repository.time_to_refresh() > Duration::from_secs(230));
+    Ok(())
+}
+
+#[test]
+fn test_catalog_eviction() -> CvmfsResult<()> {
+    use cvmfs::common::compose_object_path;
+
+    let mut repository = open_repository("eviction", &Signers::valid())?;
+    repository.max_opened_catalogs = 2;
+    let root_hash = repository.get_root_hash()?.to_string();
+    repository.retrieve_catalog(&root_hash)?;
+    // copies of the root catalog stand for nested ones
+    let cache_directory = PathBuf::from(&repository.cache().cache_directory);
+    let root_catalog = cache_directory.join(compose_object_path(&root_hash, "C")?);
+    let hashes: Vec<String> = (1..=3).map(|i| format!("{:02x}", i).repeat(20)).collect();
+    for hash in &hashes {
+        std::fs::copy(
+            &root_catalog,
+            cache_directory.join(compose_object_path(hash, "C")?),
+        )?;
+    }
+    repository.retrieve_catalog(&hashes[0])?;
+    repository.retrieve_catalog(&hashes[1])?;
+    // the root catalog is never closed
+    assert_eq!(2, repository.opened_catalog_hashes().len());
+    assert!(repository.is_catalog_opened(&root_hash));
+    assert!(repository.is_catalog_opened(&hashes[1]));
+    // closed catalogs are opened again when needed
+    repository.retrieve_catalog(&hashes[0])?;
+    assert!(repository.is_catalog_opened(&hashes[0]));
+    assert!(!repository.is_catalog_opened(&hashes[1]));
+
+    repository.max_opened_catalogs = 3;
+    repository.retrieve_catalog(&hashes[1])?;
+    repository.retrieve_catalog(&hashes[0])?;
+    repository.memory_limits.catalogs = Some(1);
+    repository.retrieve_catalog(&hashes[2])?;
+    assert_eq!(2, repository.opened_catalog_hashes().len());
+    assert!(repository.is_catalog_opened(&root_hash));
+    assert!(repository.is_catalog_opened(&hashes[2]));
+    Ok(())
+}
+
+#[test]
+fn test_attribute_cache() -> CvmfsResult<()> {
+    use std::path::Path;
+
+    use cvmfs::file_system::CernvmFileSystem;
+    use fuse_mt::{FilesystemMT, RequestInfo};
+
+    let request = RequestInfo {
+        unique: 0,
+        uid: 0,
+        gid: 0,
+        pid: 0,
+    };
+    let file_system = CernvmFileSystem::new(open_repository("attributes", &Signers::valid())?)?;
+    let missing = Path::new("/missing");
+    assert!(file_system.getattr(request, missing, None).is_err());
+    let repository = file_system.repository();
+    {
+        let mut repository = repository.write().unwrap();
+        repository.close_catalogs();
+        repository.set_lookup_cache_size(16);
+        repository.set_catalog_cache_size(16);
+    }
+    // the failed lookup is answered without going through the catalogs again
+    assert!(file_system.getattr(request, missing, None).is_err());
+    assert!(file_system.access(request, missing, 0).is_err());
+    assert!(repository
+        .read()
+        .unwrap()
+        .opened_catalog_hashes()
+        .is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_concurrent_reads() -> CvmfsResult<()> {
+    use std::path::Path;
+
+    use cvmfs::file_system::CernvmFileSystem;
+    use fuse_mt::{FilesystemMT, RequestInfo};
+
+    let request = RequestInfo {
+        unique: 0,
+        uid: 0,
+        gid: 0,
+        pid: 0,
+    };
+    let file_system = CernvmFileSystem::new(open_repository("concurrent", &Signers::valid())?)?;
+    let missing = Path::new("/missing");
+    let repository = file_system.repository();
+    // lookups and statistics only share the repository with the other readers
+    let _reader = repository.read().unwrap();
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                scope.spawn(|| {
+                    assert!(file_system.getattr(request, missing, None).is_err());
+                    file_system.statfs(request, Path::new("/")).map(|_| ())
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(Ok(()), handle.join().unwrap());
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+use cvmfs::user_mount::{is_user_fuse_option, parse_user_allow_other, xdg_cache_directory};
+
+#[test]
+fn test_xdg_cache_directory() {
+    assert_eq!(
+        Some(PathBuf::from("/home/user/.xdg/cvmfs")),
+        xdg_cache_directory(Some("/home/user/.xdg"), Some("/home/user"))
+    );
+    assert_eq!(
+        Some(PathBuf::from("/home/user/.cache/cvmfs")),
+        xdg_cache_directory(None, Some("/home/user"))
+    );
+    assert_eq!(
+        Some(PathBuf::from("/home/user/.cache/cvmfs")),
+        xdg_cache_directory(Some("relative"), Some("/home/user"))
+    );
+    assert_eq!(None, xdg_cache_directory(None, None));
+}
+
+#[test]
+fn test_parse_user_allow_other() {
+    assert!(parse_user_allow_other(
+        "# mount_max = 1000\nuser_allow_other\n"
+    ));
+    assert!(parse_user_allow_other("  user_allow_other # for cvmfs\n"));
+    assert!(!parse_user_allow_other("#user_allow_other\n"));
+    assert!(!parse_user_allow_other(""));
+}
+
+#[test]
+fn test_is_user_fuse_option() {
+    assert!(is_user_fuse_option("ro", false));
+    assert!(is_user_fuse_option("fsname=cernvmfs", false));
+    assert!(!is_user_fuse_option("allow_other", false));
+    assert!(!is_user_fuse_option("allow_root", false));
+    assert!(is_user_fuse_option("allow_other", true));
+}
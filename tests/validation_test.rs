@@ -0,0 +1,27 @@
+use cvmfs::common::{CvmfsError, CvmfsResult};
+use cvmfs::validation::{ValidationMode, ValidationPolicy};
+
+#[test]
+fn test_apply() {
+    let failure = || Err(CvmfsError::ContentHashMismatch("object".into()));
+    assert_eq!(failure(), ValidationMode::Fatal.apply(failure()));
+    assert_eq!(Ok(()), ValidationMode::Warn.apply(failure()));
+    assert_eq!(Ok(()), ValidationMode::Ignore.apply(failure()));
+    assert!(!ValidationMode::Ignore.is_enabled());
+}
+
+#[test]
+fn test_parse_policy() -> CvmfsResult<()> {
+    assert_eq!(ValidationPolicy::strict(), "strict".parse()?);
+    assert_eq!(ValidationPolicy::permissive(), "permissive".parse()?);
+    let policy: ValidationPolicy = "strict,content_hash=warn".parse()?;
+    assert_eq!(ValidationMode::Fatal, policy.signature);
+    assert_eq!(ValidationMode::Warn, policy.content_hash);
+    let policy: ValidationPolicy = "whitelist_expiry=ignore".parse()?;
+    assert_eq!(ValidationMode::Ignore, policy.whitelist_expiry);
+    assert_eq!(ValidationPolicy::default().signature, policy.signature);
+    for invalid in ["lenient", "signature=maybe", "digest=fatal"] {
+        assert!(invalid.parse::<ValidationPolicy>().is_err(), "{}", invalid);
+    }
+    Ok(())
+}
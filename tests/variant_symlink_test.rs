@@ -0,0 +1,37 @@
+use cvmfs::common::CvmfsResult;
+use cvmfs::variant_symlink::SymlinkVariables;
+
+#[test]
+fn test_expand() -> CvmfsResult<()> {
+    let mut variables = SymlinkVariables::default();
+    variables.set_assignment("VARIANT_TEST_ORG=atlas")?;
+    variables.set_assignment("VARIANT_TEST_EMPTY=")?;
+    assert_eq!(
+        "/sw/atlas/lib",
+        variables.expand("/sw/$(VARIANT_TEST_ORG)/lib")
+    );
+    assert_eq!(
+        "atlas-atlas",
+        variables.expand("$(VARIANT_TEST_ORG)-$(VARIANT_TEST_ORG)")
+    );
+    assert_eq!("plain/target", variables.expand("plain/target"));
+    // unset variables expand to their default, or to nothing
+    assert_eq!("/sw/v2", variables.expand("/sw/$(VARIANT_TEST_UNSET:-v2)"));
+    assert_eq!("/sw/", variables.expand("/sw/$(VARIANT_TEST_UNSET)"));
+    assert_eq!("atlas", variables.expand("$(VARIANT_TEST_ORG:-cms)"));
+    assert_eq!("", variables.expand("$(VARIANT_TEST_EMPTY:-cms)"));
+    // unterminated placeholders are kept
+    assert_eq!("/sw/$(ORG", variables.expand("/sw/$(ORG"));
+    assert!(variables.set_assignment("=value").is_err());
+    assert!(variables.set_assignment("NAME").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_environment() {
+    std::env::set_var("VARIANT_TEST_FROM_ENV", "env");
+    let mut variables = SymlinkVariables::default();
+    assert_eq!("/env", variables.expand("/$(VARIANT_TEST_FROM_ENV)"));
+    variables.set("VARIANT_TEST_FROM_ENV", "override");
+    assert_eq!("/override", variables.expand("/$(VARIANT_TEST_FROM_ENV)"));
+}
@@ -0,0 +1,62 @@
+use chrono::TimeDelta;
+use cvmfs::common::{CvmfsError, CvmfsResult};
+use cvmfs::rootfile::RootFile;
+use cvmfs::whitelist::{ExpiryPolicy, Whitelist};
+
+#[test]
+fn test_parse_whitelist() -> CvmfsResult<()> {
+    let root_file = RootFile::from_bytes(
+        b"20240101120000\n\
+          E20990101120000\n\
+          Natlas.cern.ch\n\
+          1A:2B:3C:4D:5E:6F:70:81:92:A3:B4:C5:D6:E7:F8:09:1A:2B:3C:4D # comment\n\
+          E1:2B:3C:4D:5E:6F:70:81:92:A3:B4:C5:D6:E7:F8:09:1A:2B:3C:4D\n",
+    )?;
+    let whitelist = Whitelist::new(root_file)?;
+    assert_eq!("atlas.cern.ch", whitelist.repository_name);
+    assert_eq!(2, whitelist.fingerprints.len());
+    assert!(whitelist
+        .contains_fingerprint("E1:2B:3C:4D:5E:6F:70:81:92:A3:B4:C5:D6:E7:F8:09:1A:2B:3C:4D"));
+    assert_eq!(
+        "1A:2B:3C:4D:5E:6F:70:81:92:A3:B4:C5:D6:E7:F8:09:1A:2B:3C:4D",
+        whitelist.fingerprints[0]
+    );
+    assert!(whitelist
+        .contains_fingerprint("1a:2b:3c:4d:5e:6f:70:81:92:a3:b4:c5:d6:e7:f8:09:1a:2b:3c:4d"));
+    assert!(!whitelist
+        .contains_fingerprint("3F:E3:A4:E7:F0:B3:3F:0C:63:82:9C:6B:FD:20:31:AD:8E:4E:02:DB"));
+    assert!(!whitelist.is_expired());
+    Ok(())
+}
+
+#[test]
+fn test_expired_whitelist() -> CvmfsResult<()> {
+    let root_file = RootFile::from_bytes(b"20200101120000\nE20200201120000\nNatlas.cern.ch\n")?;
+    assert!(Whitelist::new(root_file)?.is_expired());
+    let root_file = RootFile::from_bytes(b"20200101120000\nNatlas.cern.ch\n")?;
+    assert!(Whitelist::new(root_file).is_err());
+    assert_eq!(ExpiryPolicy::Unmount, "unmount".parse()?);
+    assert_eq!(ExpiryPolicy::ServeFromCache, "serve-from-cache".parse()?);
+    assert!("ignore".parse::<ExpiryPolicy>().is_err());
+    Ok(())
+}
+
+#[test]
+fn test_clock_skew_tolerance() -> CvmfsResult<()> {
+    let root_file = RootFile::from_bytes(b"20240101120000\nE20240201120000\nNatlas.cern.ch\n")?;
+    let whitelist = Whitelist::new(root_file)?;
+    let tolerance = TimeDelta::minutes(5);
+    let created = whitelist.last_modified;
+    whitelist.validate_timestamps(created - TimeDelta::minutes(2), tolerance)?;
+    assert!(matches!(
+        whitelist.validate_timestamps(created - TimeDelta::hours(1), tolerance),
+        Err(CvmfsError::ClockSkew(_))
+    ));
+    let expiry = whitelist.expires;
+    whitelist.validate_timestamps(expiry + TimeDelta::minutes(2), tolerance)?;
+    assert_eq!(
+        Err(CvmfsError::WhitelistExpired),
+        whitelist.validate_timestamps(expiry + TimeDelta::hours(1), tolerance)
+    );
+    Ok(())
+}
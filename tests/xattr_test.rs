@@ -0,0 +1,56 @@
+use cvmfs::common::{CvmfsError, CvmfsResult};
+use cvmfs::xattr::{XattrClass, XattrPolicy, SECURITY_SELINUX};
+
+#[test]
+fn test_xattr_class() {
+    assert_eq!(XattrClass::User, XattrClass::of("user.tag"));
+    assert_eq!(
+        XattrClass::Capability,
+        XattrClass::of("security.capability")
+    );
+    assert_eq!(XattrClass::Selinux, XattrClass::of("security.selinux"));
+    assert_eq!(XattrClass::Acl, XattrClass::of("system.posix_acl_access"));
+    assert_eq!(XattrClass::Acl, XattrClass::of("system.posix_acl_default"));
+    assert_eq!(XattrClass::Security, XattrClass::of("security.ima"));
+    assert_eq!(XattrClass::System, XattrClass::of("system.nfs4_acl"));
+    assert_eq!(XattrClass::Other, XattrClass::of("trusted.overlay.opaque"));
+}
+
+#[test]
+fn test_default_policy() {
+    let policy = XattrPolicy::default();
+    assert_eq!(None, policy.resolve("user.tag"));
+    assert_eq!(
+        Some(Err(libc::ENODATA)),
+        policy.resolve("security.capability")
+    );
+    assert_eq!(
+        Some(Err(libc::EOPNOTSUPP)),
+        policy.resolve("security.selinux")
+    );
+    assert_eq!(
+        Some(Err(libc::EOPNOTSUPP)),
+        policy.resolve("system.posix_acl_access")
+    );
+    assert_eq!(Some(Err(libc::ENODATA)), policy.resolve("trusted.foo"));
+    assert!(policy.listed().is_empty());
+}
+
+#[test]
+fn test_selinux_context() -> CvmfsResult<()> {
+    let policy = XattrPolicy::with_selinux_context("system_u:object_r:cvmfs_t:s0")?;
+    assert_eq!(
+        Some(Ok(b"system_u:object_r:cvmfs_t:s0\0".to_vec())),
+        policy.resolve("security.selinux")
+    );
+    assert_eq!(
+        Some(Err(libc::ENODATA)),
+        policy.resolve("security.capability")
+    );
+    assert_eq!(vec![SECURITY_SELINUX], policy.listed());
+    assert!(matches!(
+        XattrPolicy::with_selinux_context("cvmfs_t"),
+        Err(CvmfsError::InvalidConfiguration(_))
+    ));
+    Ok(())
+}